@@ -0,0 +1,127 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use dcs_tetrad::dcs::{self, DcsWorldObject, DcsWorldUnit, Loggable, RowStamp};
+use dcs_tetrad::kinematics::MotionTracker;
+use mlua::Lua;
+use std::io::Write;
+
+const NUM_UNITS: usize = 2000;
+const NUM_BALLISTICS: usize = 500;
+
+// Stand-in for DCS's `Export.LoGetWorldObjects`, returning tables shaped like
+// the real ones.
+const FAKE_EXPORT: &str = r#"
+local function make_object(i, with_names)
+    local obj = {
+        Name = "F-16C_50",
+        Country = 2,
+        Coalition = "Enemies",
+        CoalitionID = 2,
+        LatLongAlt = { Lat = 41.0 + i * 1e-4, Long = 41.5 - i * 1e-4, Alt = 1000.0 + i },
+        Heading = 1.2,
+        Pitch = 0.05,
+        Bank = -0.1,
+        Position = { x = -2500.0 + i, y = 1000.0 + i, z = 6000.0 - i },
+        Type = { level1 = with_names and 1 or 4, level2 = 1, level3 = 1, level4 = 1 },
+    }
+    if with_names then
+        obj.UnitName = "Unit #" .. i
+        obj.GroupName = "Group #" .. math.floor(i / 4)
+    end
+    return obj
+end
+
+local units = {}
+local ballistics = {}
+for i = 1, NUM_UNITS do
+    units[16777216 + i] = make_object(i, true)
+end
+for i = 1, NUM_BALLISTICS do
+    ballistics[33554432 + i] = make_object(i, false)
+end
+
+Export = {
+    LoGetWorldObjects = function(kind)
+        if kind == "ballistic" then
+            return ballistics
+        end
+        return units
+    end,
+}
+"#;
+
+fn make_lua() -> Lua {
+    let lua = Lua::new();
+    lua.globals().set("NUM_UNITS", NUM_UNITS).unwrap();
+    lua.globals().set("NUM_BALLISTICS", NUM_BALLISTICS).unwrap();
+    lua.load(FAKE_EXPORT).exec().unwrap();
+    lua
+}
+
+fn serialize_frame(units: &[DcsWorldUnit], ballistics: &[DcsWorldObject]) -> Vec<u8> {
+    let motion = MotionTracker::default();
+    let stamp = RowStamp {
+        frame_count: 100,
+        frame_time: 12.5,
+        real_time: 13.0,
+        epoch_ms: 1_700_000_000_000,
+        session_id: "00000000-0000-4000-8000-000000000000",
+    };
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(Vec::new());
+    for unit in units {
+        unit.log_as_csv(&stamp, &motion, None, &mut writer).unwrap();
+    }
+    for obj in ballistics {
+        obj.log_as_csv(&stamp, &motion, None, &mut writer).unwrap();
+    }
+    writer.into_inner().unwrap()
+}
+
+fn bench_extraction(c: &mut Criterion) {
+    let lua = make_lua();
+    c.bench_function("extract units", |b| {
+        b.iter(|| black_box(dcs::get_unit_objects(&lua).unwrap()))
+    });
+    c.bench_function("extract ballistics", |b| {
+        b.iter(|| black_box(dcs::get_ballistics_objects(&lua).unwrap()))
+    });
+    let mut pool = dcs::ObjectPool::default();
+    c.bench_function("extract units (pooled)", |b| {
+        b.iter(|| black_box(pool.get_unit_objects(&lua).unwrap()))
+    });
+}
+
+fn bench_serialization(c: &mut Criterion) {
+    let lua = make_lua();
+    let units = dcs::get_unit_objects(&lua).unwrap();
+    let ballistics = dcs::get_ballistics_objects(&lua).unwrap();
+    c.bench_function("serialize frame", |b| {
+        b.iter(|| black_box(serialize_frame(&units, &ballistics)))
+    });
+}
+
+fn bench_compression(c: &mut Criterion) {
+    let lua = make_lua();
+    let units = dcs::get_unit_objects(&lua).unwrap();
+    let ballistics = dcs::get_ballistics_objects(&lua).unwrap();
+    let data = serialize_frame(&units, &ballistics);
+    c.bench_function("compress frame", |b| {
+        b.iter_batched(
+            || zstd::stream::write::Encoder::new(Vec::new(), 10).unwrap(),
+            |mut encoder| {
+                encoder.write_all(&data).unwrap();
+                black_box(encoder.finish().unwrap())
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_extraction,
+    bench_serialization,
+    bench_compression
+);
+criterion_main!(benches);
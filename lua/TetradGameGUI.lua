@@ -0,0 +1,123 @@
+-- Generated by `cargo run --bin tetrad-hookgen` from src/callbacks.rs, do not edit.
+-- Adds routing for every DCS GameGUI callback tetrad listens to.
+return function(callbacks)
+    function callbacks.onSimulationStart()
+        if TETRAD.lib then
+            TETRAD.lib.on_simulation_start()
+        end
+    end
+
+    function callbacks.onSimulationPause()
+        if TETRAD.lib then
+            TETRAD.lib.on_simulation_pause()
+        end
+    end
+
+    function callbacks.onSimulationResume()
+        if TETRAD.lib then
+            TETRAD.lib.on_simulation_resume()
+        end
+    end
+
+    function callbacks.onGameEvent(eventName, arg1, arg2, arg3, arg4, arg5, arg6, arg7)
+        if TETRAD.lib then
+            TETRAD.lib.on_game_event(eventName, arg1, arg2, arg3, arg4, arg5, arg6, arg7)
+        end
+    end
+
+    function callbacks.onNetConnect(localPlayerID)
+        if TETRAD.lib then
+            TETRAD.lib.on_net_connect(localPlayerID)
+        end
+    end
+
+    function callbacks.onNetMissionChanged(newMissionName)
+        if TETRAD.lib then
+            TETRAD.lib.on_net_mission_changed(newMissionName)
+        end
+    end
+
+    function callbacks.onNetDisconnect(reason, errCode)
+        if TETRAD.lib then
+            TETRAD.lib.on_net_disconnect(reason, errCode)
+        end
+    end
+
+    function callbacks.onPlayerConnect(id)
+        if TETRAD.lib then
+            TETRAD.lib.on_player_connect(id)
+        end
+    end
+
+    function callbacks.onPlayerDisconnect(id, errCode)
+        if TETRAD.lib then
+            TETRAD.lib.on_player_disconnect(id, errCode)
+        end
+    end
+
+    function callbacks.onPlayerStart(id)
+        if TETRAD.lib then
+            TETRAD.lib.on_player_start(id)
+        end
+    end
+
+    function callbacks.onPlayerStop(id)
+        if TETRAD.lib then
+            TETRAD.lib.on_player_stop(id)
+        end
+    end
+
+    function callbacks.onPlayerChangeSlot(id)
+        if TETRAD.lib then
+            TETRAD.lib.on_player_change_slot(id)
+        end
+    end
+
+    function callbacks.onPlayerTryConnect(addr, ucid, name, playerID)
+        if TETRAD.lib then
+            TETRAD.lib.on_player_try_connect(addr, ucid, name, playerID)
+        end
+    end
+
+    function callbacks.onPlayerTrySendChat(playerID, msg, all)
+        if TETRAD.lib then
+            TETRAD.lib.on_player_try_send_chat(playerID, msg, all)
+        end
+    end
+
+    function callbacks.onPlayerTryChangeSlot(playerID, side, slotID)
+        if TETRAD.lib then
+            TETRAD.lib.on_player_try_change_slot(playerID, side, slotID)
+        end
+    end
+
+    function callbacks.onChatMessage(message, from)
+        if TETRAD.lib then
+            TETRAD.lib.on_chat_message(message, from)
+        end
+    end
+
+    function callbacks.onShowChatAll()
+        if TETRAD.lib then
+            TETRAD.lib.on_show_chat_all()
+        end
+    end
+
+    function callbacks.onShowChatTeam()
+        if TETRAD.lib then
+            TETRAD.lib.on_show_chat_team()
+        end
+    end
+
+    function callbacks.onShowChatRead()
+        if TETRAD.lib then
+            TETRAD.lib.on_show_chat_read()
+        end
+    end
+
+    function callbacks.onRadioCommand(commandMessage)
+        if TETRAD.lib then
+            TETRAD.lib.on_radio_command(commandMessage)
+        end
+    end
+end
@@ -6,6 +6,15 @@ end
 
 local tetradCallbacks = {}
 TETRAD = {}
+local missionLoadBeginTime = nil
+-- Left out of the config dump, dcs.log gets shared. `alerts` holds the
+-- webhook URLs.
+local secretConfigKeys = {
+    encryption_key = true,
+    upload_secret_key = true,
+    mqtt_password = true,
+    alerts = true,
+}
 local function onMissionLoadEnd()
     writeLog(log.INFO, "On Mission load end!")
     -- Let DCS know where to find the DLLs
@@ -28,9 +37,19 @@ local function onMissionLoadEnd()
         writeLog(log.INFO, "`Config/tetrad-config.lua` not found (" .. tostring(err) .. ")")
     end
     tetrad_config.write_dir = lfs.writedir()
+    if missionLoadBeginTime then
+        tetrad_config.mission_load_duration = DCS.getRealTime() - missionLoadBeginTime
+    end
     writeLog(log.INFO, "Tetrad config follows: ")
     for k, v in pairs(tetrad_config) do
-        writeLog(log.INFO, k .. " = " .. tostring(v))
+        if not secretConfigKeys[k] then
+            local value = tostring(v)
+            if k == "upload_url" then
+                -- drop any user:password@
+                value = (string.gsub(value, "://[^/]*@", "://"))
+            end
+            writeLog(log.INFO, k .. " = " .. value)
+        end
     end
     writeLog(log.INFO, "End of Tetrad config")
 
@@ -46,29 +65,35 @@ local function onMissionLoadEnd()
 end
 
 do
+    function tetradCallbacks.onMissionLoadBegin()
+        missionLoadBeginTime = DCS.getRealTime()
+    end
+
     function tetradCallbacks.onMissionLoadEnd()
         local status, err = pcall(onMissionLoadEnd)
         if not status then
-            writeLog(log.INFO, "error starting library: " .. tostring(err))
+            writeLog(log.ERROR, "error starting library: " .. tostring(err))
         end
     end
 
     function tetradCallbacks.onSimulationStop()
-        TETRAD.lib.stop()
+        if TETRAD.lib then
+            TETRAD.lib.stop()
+        end
         TETRAD.lib = nil
         TETRAD = {}
         package.loaded['dcs_tetrad'] = nil
     end
 
     function tetradCallbacks.onSimulationFrame()
-        TETRAD.lib.on_frame_begin()
-    end
-
-    function tetradCallbacks.onPlayerConnect(id)
+        if TETRAD.lib then
+            TETRAD.lib.on_frame_begin()
+        end
     end
 
-    function tetradCallbacks.onPlayerDisconnect(id, err_code)
-    end
+    -- everything else is routed by the generated TetradGameGUI.lua
+    local addRouting = dofile(tetrad_config.lua_path .. [[TetradGameGUI.lua]])
+    addRouting(tetradCallbacks)
 
     DCS.setUserCallbacks(tetradCallbacks)
     writeLog(log.INFO, "Set up Tetrad hook callbacks.")
@@ -0,0 +1,178 @@
+// The `alerts` config rules, checked in the monitor thread. Each rule watches
+// one metric over one-second windows of the active mission, and goes off once
+// the metric has stayed past its threshold for the rule's duration. It goes
+// off again only after the condition has cleared.
+use crate::config::{AlertAction, AlertMetric, AlertRule, Comparison};
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+/// Real seconds of frames the metrics are measured over.
+const WINDOW: f64 = 1.0;
+
+/// An alert whose action has to be taken on the DCS thread (chat and event
+/// log), see `Monitor::take_alerts`.
+#[derive(Debug)]
+pub struct Alert {
+    pub action: AlertAction,
+    pub game_time: f64,
+    pub real_time: f64,
+    pub message: String,
+}
+
+/// What the monitor knows about one frame.
+pub struct AlertSample {
+    pub real_time: f64,
+    pub game_time: f64,
+    pub real_frame_time: f64,
+    pub lib_time: f64,
+    pub units: i32,
+    pub ballistics: i32,
+    pub players: i32,
+}
+
+#[derive(Debug, Default)]
+struct Window {
+    start: f64,
+    frames: u32,
+    max_frame_time: f64,
+    total_lib_time: f64,
+}
+
+#[derive(Debug)]
+struct RuleState {
+    rule: AlertRule,
+    /// Real time the condition started holding.
+    since: Option<f64>,
+    firing: bool,
+}
+
+#[derive(Debug)]
+pub struct AlertEngine {
+    rules: Vec<RuleState>,
+    window: Option<Window>,
+    tx: Sender<Alert>,
+}
+
+fn metric_value(metric: AlertMetric, window: &Window, elapsed: f64, sample: &AlertSample) -> f64 {
+    match metric {
+        AlertMetric::Fps => window.frames as f64 / elapsed,
+        AlertMetric::FrameTimeMs => window.max_frame_time * 1000.0,
+        AlertMetric::LibTimeMs => window.total_lib_time / window.frames as f64 * 1000.0,
+        AlertMetric::Units => sample.units as f64,
+        AlertMetric::Ballistics => sample.ballistics as f64,
+        AlertMetric::Players => sample.players as f64,
+    }
+}
+
+fn post_to_discord(url: String, message: String) {
+    std::thread::spawn(move || {
+        let result = ureq::post(&url)
+            .timeout(Duration::from_secs(10))
+            .send_json(serde_json::json!({ "content": message }));
+        if let Err(e) = result {
+            log::warn!("Couldn't post alert to Discord: {}", e);
+        }
+    });
+}
+
+impl AlertEngine {
+    pub fn new(rules: &[AlertRule], tx: Sender<Alert>) -> Self {
+        Self {
+            rules: rules
+                .iter()
+                .map(|rule| RuleState {
+                    rule: rule.clone(),
+                    since: None,
+                    firing: false,
+                })
+                .collect(),
+            window: None,
+            tx,
+        }
+    }
+
+    /// Takes one frame of the active mission, and checks the rules whenever a
+    /// window is complete.
+    pub fn update(&mut self, sample: &AlertSample) {
+        let window = self.window.get_or_insert_with(|| Window {
+            start: sample.real_time,
+            ..Default::default()
+        });
+        window.frames += 1;
+        window.max_frame_time = window.max_frame_time.max(sample.real_frame_time);
+        window.total_lib_time += sample.lib_time;
+        let elapsed = sample.real_time - window.start;
+        if elapsed < WINDOW {
+            return;
+        }
+        let window = self.window.take().unwrap();
+        for state in self.rules.iter_mut() {
+            let value = metric_value(state.rule.metric, &window, elapsed, sample);
+            state.check(value, sample, &self.tx);
+        }
+    }
+
+    /// Forgets the current window and how long the conditions have held, for
+    /// when the mission pauses.
+    pub fn interrupt(&mut self) {
+        self.window = None;
+        for state in self.rules.iter_mut() {
+            state.since = None;
+        }
+    }
+}
+
+impl RuleState {
+    fn check(&mut self, value: f64, sample: &AlertSample, tx: &Sender<Alert>) {
+        let rule = &self.rule;
+        let holds = match rule.comparison {
+            Comparison::Above => value > rule.threshold,
+            Comparison::Below => value < rule.threshold,
+        };
+        if !holds {
+            self.since = None;
+            if self.firing {
+                self.firing = false;
+                log::info!(
+                    "Alert {} cleared: {} is {:.1}",
+                    rule.name,
+                    rule.metric.name(),
+                    value
+                );
+            }
+            return;
+        }
+        let since = *self.since.get_or_insert(sample.real_time);
+        if self.firing || sample.real_time - since < rule.duration {
+            return;
+        }
+        self.firing = true;
+        let message = format!(
+            "Alert {}: {} {:.1} {} {} for {:.0} s",
+            rule.name,
+            rule.metric.name(),
+            value,
+            match rule.comparison {
+                Comparison::Above => "above",
+                Comparison::Below => "below",
+            },
+            rule.threshold,
+            sample.real_time - since + WINDOW,
+        );
+        log::warn!("{}", message);
+        match rule.action {
+            AlertAction::Log => {}
+            AlertAction::Discord => post_to_discord(rule.webhook_url.clone(), message),
+            AlertAction::Chat | AlertAction::Event => {
+                let alert = Alert {
+                    action: rule.action,
+                    game_time: sample.game_time,
+                    real_time: sample.real_time,
+                    message,
+                };
+                // the monitor may already be shutting down
+                let _ = tx.send(alert);
+            }
+        }
+    }
+}
@@ -0,0 +1,51 @@
+// Artillery barrages can put 10k+ shells in the air at once, and logging each
+// of them every frame is what slows tetrad down just when the frame times are
+// most interesting. Past `ballistics_log_cap`, the object logs leave the shells
+// out and the frame log gets one summary of where they are instead.
+use crate::dcs::DcsWorldObject;
+
+/// Where the ballistics of one frame are: how many, their centroid and the
+/// latitude/longitude box around them, in degrees and meters.
+#[derive(Debug, Clone)]
+pub struct BallisticsAggregate {
+    pub count: usize,
+    pub lat: f64,
+    pub lon: f64,
+    pub alt: f64,
+    pub south: f64,
+    pub west: f64,
+    pub north: f64,
+    pub east: f64,
+}
+
+impl BallisticsAggregate {
+    /// None without any ballistics.
+    pub fn compute(ballistics: &[DcsWorldObject]) -> Option<Self> {
+        let (lat, lon, _) = ballistics.first()?.lat_lon_alt();
+        let mut aggregate = Self {
+            count: ballistics.len(),
+            lat: 0.0,
+            lon: 0.0,
+            alt: 0.0,
+            south: lat,
+            west: lon,
+            north: lat,
+            east: lon,
+        };
+        for object in ballistics {
+            let (lat, lon, alt) = object.lat_lon_alt();
+            aggregate.lat += lat;
+            aggregate.lon += lon;
+            aggregate.alt += alt;
+            aggregate.south = aggregate.south.min(lat);
+            aggregate.west = aggregate.west.min(lon);
+            aggregate.north = aggregate.north.max(lat);
+            aggregate.east = aggregate.east.max(lon);
+        }
+        let n = ballistics.len() as f64;
+        aggregate.lat /= n;
+        aggregate.lon /= n;
+        aggregate.alt /= n;
+        Some(aggregate)
+    }
+}
@@ -0,0 +1,849 @@
+// Command line tool for tetrad's CSV logs, compressed (`.csv.zstd`) or not.
+// Run without arguments for usage.
+use arrow::array::{ArrayRef, Float64Builder, StringBuilder};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use once_cell::sync::OnceCell;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tetrad_data::csv_log;
+use tetrad_data::encrypted;
+use tetrad_data::recover::{self, Boundary};
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+const USAGE: &str = "\
+usage: tetrad-cli <command> [options] <file>
+
+Object logs written with `object_log_dictionary` need --dict <dictionary>
+to be read by any command.
+
+commands:
+  decompress <file> [-o <out>]
+      Write the plain CSV, to <file> without `.zstd` by default.
+  recover <file> [-o <out>]
+      Save what can still be read from a log cut off by a crash (e.g. a
+      `.csv.zstd.tmp` or `.bin.zstd.tmp` file) as a complete file, to <file>
+      without `.tmp` by default, or `<name>.recovered.csv.zstd`.
+  decrypt [-k <key>] [-o <out>] <file>
+      Decrypt a log written with `encryption_key`, to <file> without `.enc`
+      (and `.tmp`) by default. The key is the 64 hex digits from the config,
+      or the TETRAD_ENCRYPTION_KEY environment variable without -k.
+  train-dict [--size <KB>] [-o <out>] <object log>...
+      Train a zstd dictionary on the rows of some object logs, to
+      `objects.dict` by default (112 KB). Put it in the Saved Games
+      directory and set `object_log_dictionary` to compress later object
+      logs with it.
+  tail [-n <rows>] [-f] <file>
+      Print the header and the last rows (10 by default). With -f, keep
+      printing rows as they're written, e.g. while a mission is running.
+  filter [-w <condition>]... [-c <columns>] [-o <out>] <file>
+      Print the rows matching every condition, like `units>100`,
+      `t_game>=600` or `type=Ballistic` (operators =, !=, <, <=, >, >=).
+      -c keeps only the listed columns, e.g. `-c t_game,units`.
+  convert --to <csv|parquet> [-o <out>] <file>
+      Convert to plain CSV or Parquet. Columns whose values are all numbers
+      become Float64 in Parquet, everything else strings.
+  stats <file>
+      Count, min, mean, percentiles and max of every numeric column, plus
+      the frame rate when there's a `t_game` column.
+  heatmap [--cell <degrees>] [--frames <frame log>] [--slow-ms <ms>]
+          [-o <prefix>] <object log>
+      Count object samples in a lat/lon grid (0.1 degree cells by default)
+      and write one CSV matrix per coalition, north at the top. With the
+      session's frame log, also write a `.slow.csv` matrix counting only
+      frames of at least --slow-ms game time (100 by default).
+";
+
+const FOLLOW_INTERVAL: Duration = Duration::from_secs(1);
+const PARQUET_ROW_GROUP_SIZE: usize = 64 * 1024;
+
+struct Args {
+    positional: Vec<String>,
+    options: Vec<(String, String)>,
+    flags: Vec<String>,
+}
+
+impl Args {
+    /// Options that take a value; anything else starting with `-` is a flag.
+    const WITH_VALUE: &'static [&'static str] = &[
+        "-o",
+        "-k",
+        "-n",
+        "-w",
+        "-c",
+        "--to",
+        "--cell",
+        "--frames",
+        "--slow-ms",
+        "--size",
+        "--dict",
+    ];
+
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self> {
+        let mut parsed = Self {
+            positional: Vec::new(),
+            options: Vec::new(),
+            flags: Vec::new(),
+        };
+        while let Some(arg) = args.next() {
+            if Self::WITH_VALUE.contains(&arg.as_str()) {
+                let value = args.next().ok_or(format!("{} needs a value", arg))?;
+                parsed.options.push((arg, value));
+            } else if arg.starts_with('-') && arg.len() > 1 {
+                parsed.flags.push(arg);
+            } else {
+                parsed.positional.push(arg);
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn option(&self, name: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .rev()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn all(&self, name: &str) -> impl Iterator<Item = &str> {
+        let name = name.to_string();
+        self.options
+            .iter()
+            .filter(move |(n, _)| *n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|f| f == name)
+    }
+
+    fn input(&self) -> Result<&Path> {
+        match self.positional.as_slice() {
+            [path] => Ok(Path::new(path)),
+            [] => Err("no input file given".into()),
+            _ => Err(format!("expected one input file, got {:?}", self.positional).into()),
+        }
+    }
+}
+
+fn is_compressed(path: &Path) -> bool {
+    path.extension()
+        .map_or(false, |e| e == "zstd" || e == "zst")
+}
+
+/// `x.csv.zstd` -> `x.<extension>`.
+fn output_path(input: &Path, extension: &str) -> PathBuf {
+    let mut path = input.to_path_buf();
+    if is_compressed(&path) {
+        path.set_extension("");
+    }
+    path.set_extension(extension)
+}
+
+// Given with --dict.
+static DICTIONARY: OnceCell<Vec<u8>> = OnceCell::new();
+
+// Rows of the sample logs `train-dict` reads at most, plenty for a dictionary.
+const MAX_DICTIONARY_SAMPLES: usize = 100_000;
+
+fn open(path: &Path) -> Result<Box<dyn Read>> {
+    let file = BufReader::new(File::open(path)?);
+    if is_compressed(path) {
+        let decoder = match DICTIONARY.get() {
+            Some(dictionary) => zstd::stream::read::Decoder::with_dictionary(file, dictionary)?,
+            None => zstd::stream::read::Decoder::with_buffer(file)?,
+        };
+        Ok(Box::new(decoder))
+    } else {
+        Ok(Box::new(file))
+    }
+}
+
+fn reader(path: &Path) -> Result<csv::Reader<Box<dyn Read>>> {
+    Ok(csv::ReaderBuilder::new()
+        .flexible(true)
+        .comment(Some(b'#'))
+        .from_reader(open(path)?))
+}
+
+fn create(path: Option<&str>) -> Result<Box<dyn Write>> {
+    Ok(match path {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(std::io::stdout().lock())),
+    })
+}
+
+fn decompress(args: &Args) -> Result<()> {
+    let input = args.input()?;
+    let output = match args.option("-o") {
+        Some(path) => PathBuf::from(path),
+        None if is_compressed(input) => output_path(input, "csv"),
+        None => return Err(format!("{:?} isn't compressed", input).into()),
+    };
+    let mut out = BufWriter::new(File::create(&output)?);
+    let bytes = std::io::copy(&mut open(input)?, &mut out)?;
+    out.flush()?;
+    eprintln!("Wrote {} bytes to {:?}", bytes, output);
+    Ok(())
+}
+
+/// `x.csv.zstd.tmp` -> `x.csv.zstd`, unless that's taken, in which case (or
+/// without `.tmp`) `x.recovered.csv.zstd`.
+fn recovered_path(input: &Path) -> PathBuf {
+    let name = input.file_name().unwrap_or_default().to_string_lossy();
+    let name = name.strip_suffix(".tmp").unwrap_or(&name);
+    let path = input.with_file_name(name);
+    if path != input && !path.exists() {
+        return path;
+    }
+    for extension in [".csv.zstd", ".bin.zstd"] {
+        if let Some(stem) = name.strip_suffix(extension) {
+            return input.with_file_name(format!("{}.recovered{}", stem, extension));
+        }
+    }
+    input.with_file_name(format!("{}.recovered", name))
+}
+
+fn train_dict(args: &Args) -> Result<()> {
+    if args.positional.is_empty() {
+        return Err("no object logs given".into());
+    }
+    let size_kb: usize = args.option("--size").unwrap_or("112").parse()?;
+    let output = PathBuf::from(args.option("-o").unwrap_or("objects.dict"));
+    let mut samples = Vec::new();
+    'files: for path in &args.positional {
+        let mut input = BufReader::new(open(Path::new(path))?);
+        loop {
+            let mut row = Vec::new();
+            match input.read_until(b'\n', &mut row) {
+                Ok(0) => break,
+                Ok(_) => samples.push(row),
+                // keep the rows of a log that was cut off
+                Err(_) => break,
+            }
+            if samples.len() >= MAX_DICTIONARY_SAMPLES {
+                break 'files;
+            }
+        }
+    }
+    let dictionary = zstd::dict::from_samples(&samples, size_kb * 1024)?;
+    std::fs::write(&output, &dictionary)?;
+    eprintln!(
+        "Trained a {} byte dictionary on {} rows, wrote {:?}",
+        dictionary.len(),
+        samples.len(),
+        output
+    );
+    Ok(())
+}
+
+fn recover(args: &Args) -> Result<()> {
+    let input = args.input()?;
+    let output = match args.option("-o") {
+        Some(path) => PathBuf::from(path),
+        None => recovered_path(input),
+    };
+    let name = input.file_name().unwrap_or_default().to_string_lossy();
+    let out = BufWriter::new(File::create(&output)?);
+    let recovery = recover::recover(
+        BufReader::new(File::open(input)?),
+        out,
+        Boundary::for_file_name(&name),
+    )?;
+    if recovery.truncated {
+        eprintln!(
+            "{:?} was cut off, left out an incomplete {} bytes at the end",
+            input, recovery.dropped_bytes
+        );
+    } else {
+        eprintln!("{:?} was complete", input);
+    }
+    eprintln!(
+        "Wrote {} bytes (uncompressed) to {:?}",
+        recovery.bytes, output
+    );
+    Ok(())
+}
+
+fn decrypt(args: &Args) -> Result<()> {
+    let input = args.input()?;
+    let hex = match args.option("-k") {
+        Some(hex) => hex.to_string(),
+        None => std::env::var(encrypted::KEY_VARIABLE)
+            .map_err(|_| format!("no key given with -k or {}", encrypted::KEY_VARIABLE))?,
+    };
+    let key = encrypted::parse_key(&hex).map_err(|e| format!("key {}", e))?;
+    let output = match args.option("-o") {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let name = input.file_name().unwrap_or_default().to_string_lossy();
+            let name = name.strip_suffix(".tmp").unwrap_or(&name);
+            match name.strip_suffix(".enc") {
+                Some(name) => input.with_file_name(name),
+                None => input.with_file_name(format!("{}.decrypted", name)),
+            }
+        }
+    };
+    let mut out = BufWriter::new(File::create(&output)?);
+    let complete = encrypted::decrypt(BufReader::new(File::open(input)?), &mut out, &key)?;
+    out.flush()?;
+    eprintln!("Wrote {:?}", output);
+    if !complete {
+        eprintln!(
+            "{:?} was cut off, use `recover` on the output to make it readable",
+            input
+        );
+    }
+    Ok(())
+}
+
+/// Rows of a file that may still be being written. The end of an unfinished
+/// zstd stream doesn't decode, so whatever came before it is returned and the
+/// error ignored.
+fn read_complete_rows(path: &Path) -> Result<(csv::StringRecord, Vec<csv::StringRecord>)> {
+    let mut reader = reader(path)?;
+    let header = reader.headers().cloned().unwrap_or_default();
+    let mut rows = Vec::new();
+    for row in reader.records() {
+        match row {
+            Ok(row) => rows.push(row),
+            Err(_) => break,
+        }
+    }
+    Ok((header, rows))
+}
+
+fn tail(args: &Args) -> Result<()> {
+    let input = args.input()?;
+    let count: usize = args.option("-n").unwrap_or("10").parse()?;
+    let mut out = csv::Writer::from_writer(std::io::stdout().lock());
+
+    let follow = args.flag("-f");
+    // While the file is being written its last row may be cut off, so when
+    // following it's held back until the next one shows up.
+    let complete = |rows: &[csv::StringRecord]| {
+        if follow {
+            rows.len().saturating_sub(1)
+        } else {
+            rows.len()
+        }
+    };
+
+    let (header, rows) = read_complete_rows(input)?;
+    let mut printed = complete(&rows);
+    out.write_record(&header)?;
+    for row in &rows[printed.saturating_sub(count)..printed] {
+        out.write_record(row)?;
+    }
+    out.flush()?;
+    if !follow {
+        return Ok(());
+    }
+
+    loop {
+        std::thread::sleep(FOLLOW_INTERVAL);
+        let (_, rows) = read_complete_rows(input)?;
+        let end = complete(&rows);
+        if end > printed {
+            for row in &rows[printed..end] {
+                out.write_record(row)?;
+            }
+            out.flush()?;
+            printed = end;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+struct Condition {
+    column: usize,
+    op: Operator,
+    value: String,
+}
+
+impl Condition {
+    fn parse(text: &str, header: &csv::StringRecord) -> Result<Self> {
+        // the leftmost operator wins, and `<=` beats `<` at the same spot
+        const OPERATORS: [(&str, Operator); 6] = [
+            ("!=", Operator::Ne),
+            ("<=", Operator::Le),
+            (">=", Operator::Ge),
+            ("=", Operator::Eq),
+            ("<", Operator::Lt),
+            (">", Operator::Gt),
+        ];
+        let (pos, symbol, op) = OPERATORS
+            .iter()
+            .filter_map(|(symbol, op)| text.find(symbol).map(|pos| (pos, *symbol, *op)))
+            .min_by_key(|(pos, symbol, _)| (*pos, usize::MAX - symbol.len()))
+            .ok_or(format!("no operator in condition {:?}", text))?;
+        let name = text[..pos].trim();
+        let column = header
+            .iter()
+            .position(|h| h == name)
+            .ok_or(format!("no column named {:?}", name))?;
+        Ok(Self {
+            column,
+            op,
+            value: text[pos + symbol.len()..].trim().to_string(),
+        })
+    }
+
+    fn matches(&self, row: &csv::StringRecord) -> bool {
+        let Some(field) = row.get(self.column) else {
+            return false;
+        };
+        let ordering = match (field.parse::<f64>(), self.value.parse::<f64>()) {
+            (Ok(a), Ok(b)) => a.partial_cmp(&b),
+            _ => Some(field.cmp(self.value.as_str())),
+        };
+        let Some(ordering) = ordering else {
+            return self.op == Operator::Ne;
+        };
+        match self.op {
+            Operator::Eq => ordering.is_eq(),
+            Operator::Ne => ordering.is_ne(),
+            Operator::Lt => ordering.is_lt(),
+            Operator::Le => ordering.is_le(),
+            Operator::Gt => ordering.is_gt(),
+            Operator::Ge => ordering.is_ge(),
+        }
+    }
+}
+
+fn filter(args: &Args) -> Result<()> {
+    let mut reader = reader(args.input()?)?;
+    let header = reader.headers()?.clone();
+    let conditions = args
+        .all("-w")
+        .map(|c| Condition::parse(c, &header))
+        .collect::<Result<Vec<_>>>()?;
+    let columns: Vec<usize> = match args.option("-c") {
+        Some(names) => names
+            .split(',')
+            .map(|name| {
+                header
+                    .iter()
+                    .position(|h| h == name.trim())
+                    .ok_or_else(|| format!("no column named {:?}", name).into())
+            })
+            .collect::<Result<_>>()?,
+        None => (0..header.len()).collect(),
+    };
+    let select = |row: &csv::StringRecord| -> csv::StringRecord {
+        columns.iter().map(|i| row.get(*i).unwrap_or("")).collect()
+    };
+
+    let mut out = csv::Writer::from_writer(create(args.option("-o"))?);
+    out.write_record(&select(&header))?;
+    for row in reader.records() {
+        let row = row?;
+        if conditions.iter().all(|c| c.matches(&row)) {
+            out.write_record(&select(&row))?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn write_parquet(
+    header: &csv::StringRecord,
+    rows: &[csv::StringRecord],
+    output: &Path,
+) -> Result<()> {
+    let numeric: Vec<bool> = (0..header.len())
+        .map(|i| {
+            rows.iter()
+                .filter_map(|row| row.get(i))
+                .filter(|field| !field.is_empty())
+                .all(|field| field.parse::<f64>().is_ok())
+        })
+        .collect();
+    let fields = header
+        .iter()
+        .zip(numeric.iter())
+        .map(|(name, numeric)| {
+            let data_type = if *numeric {
+                DataType::Float64
+            } else {
+                DataType::Utf8
+            };
+            Field::new(name, data_type, true)
+        })
+        .collect();
+    let schema = Arc::new(Schema::new(fields));
+    let props = WriterProperties::builder()
+        .set_compression(Compression::ZSTD)
+        .set_max_row_group_size(PARQUET_ROW_GROUP_SIZE)
+        .build();
+    let mut writer = ArrowWriter::try_new(File::create(output)?, schema.clone(), Some(props))?;
+
+    for chunk in rows.chunks(PARQUET_ROW_GROUP_SIZE) {
+        let columns: Vec<ArrayRef> = numeric
+            .iter()
+            .enumerate()
+            .map(|(i, numeric)| -> ArrayRef {
+                let fields = chunk
+                    .iter()
+                    .map(|row| row.get(i).filter(|field| !field.is_empty()));
+                if *numeric {
+                    let mut builder = Float64Builder::new();
+                    for field in fields {
+                        builder.append_option(field.and_then(|f| f.parse().ok()));
+                    }
+                    Arc::new(builder.finish())
+                } else {
+                    let mut builder = StringBuilder::new();
+                    for field in fields {
+                        builder.append_option(field);
+                    }
+                    Arc::new(builder.finish())
+                }
+            })
+            .collect();
+        writer.write(&RecordBatch::try_new(schema.clone(), columns)?)?;
+    }
+    writer.close()?;
+    Ok(())
+}
+
+fn convert(args: &Args) -> Result<()> {
+    let input = args.input()?;
+    let to = args.option("--to").ok_or("--to is required")?;
+    let output = args
+        .option("-o")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| output_path(input, to));
+    let mut reader = reader(input)?;
+    let header = reader.headers()?.clone();
+    match to {
+        "csv" => {
+            let mut out = csv::Writer::from_path(&output)?;
+            out.write_record(&header)?;
+            for row in reader.records() {
+                out.write_record(&row?)?;
+            }
+            out.flush()?;
+        }
+        "parquet" => {
+            let rows = reader.records().collect::<csv::Result<Vec<_>>>()?;
+            write_parquet(&header, &rows, &output)?;
+        }
+        _ => return Err(format!("can't convert to {:?}, only csv and parquet", to).into()),
+    }
+    eprintln!("Wrote {:?}", output);
+    Ok(())
+}
+
+/// Nearest-rank percentile of sorted values.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil().max(1.0) as usize;
+    sorted[rank.min(sorted.len()) - 1]
+}
+
+fn print_stats_row(name: &str, values: &mut [f64]) {
+    values.sort_by(|a, b| a.total_cmp(b));
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    println!(
+        "{:<16} {:>10} {:>12.4} {:>12.4} {:>12.4} {:>12.4} {:>12.4} {:>12.4}",
+        name,
+        values.len(),
+        values[0],
+        mean,
+        percentile(values, 50.0),
+        percentile(values, 95.0),
+        percentile(values, 99.0),
+        values[values.len() - 1]
+    );
+}
+
+fn stats(args: &Args) -> Result<()> {
+    let mut reader = reader(args.input()?)?;
+    let header = reader.headers()?.clone();
+    let mut columns: Vec<Option<Vec<f64>>> = vec![Some(Vec::new()); header.len()];
+    for row in reader.records() {
+        let row = row?;
+        for (column, field) in columns.iter_mut().zip(row.iter()) {
+            if field.is_empty() {
+                continue;
+            }
+            // a single non-number makes it a text column
+            match field.parse::<f64>() {
+                Ok(value) => {
+                    if let Some(values) = column.as_mut() {
+                        values.push(value);
+                    }
+                }
+                Err(_) => *column = None,
+            }
+        }
+    }
+
+    println!(
+        "{:<16} {:>10} {:>12} {:>12} {:>12} {:>12} {:>12} {:>12}",
+        "column", "count", "min", "mean", "p50", "p95", "p99", "max"
+    );
+    for (name, column) in header.iter().zip(columns.iter_mut()) {
+        if let Some(values) = column.as_mut().filter(|v| !v.is_empty()) {
+            print_stats_row(name, values);
+        }
+    }
+
+    let t_game = header
+        .iter()
+        .position(|h| h == "t_game")
+        .and_then(|i| columns[i].as_ref());
+    if let Some(t_game) = t_game {
+        // frame times from consecutive game times, leaving out pauses
+        let mut frame_times: Vec<f64> = t_game
+            .windows(2)
+            .map(|w| w[1] - w[0])
+            .filter(|dt| *dt > 0.0)
+            .collect();
+        if !frame_times.is_empty() {
+            let total: f64 = frame_times.iter().sum();
+            println!();
+            println!(
+                "{} frames over {:.0} s of game time, average {:.1} FPS",
+                frame_times.len(),
+                total,
+                frame_times.len() as f64 / total
+            );
+            for t in frame_times.iter_mut() {
+                *t *= 1000.0;
+            }
+            print_stats_row("frame_ms", &mut frame_times);
+        }
+    }
+    Ok(())
+}
+
+/// Object samples in one grid cell, over all frames and over slow ones.
+#[derive(Debug, Default, Clone, Copy)]
+struct CellCount {
+    all: u64,
+    slow: u64,
+}
+
+fn coalition_label(coalition_id: i32) -> String {
+    match coalition_id {
+        0 => "neutral".to_string(),
+        1 => "red".to_string(),
+        2 => "blue".to_string(),
+        id => format!("coalition{}", id),
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// Game-time frame lengths in a frame log, in seconds, by frame count.
+fn frame_times(path: &Path) -> Result<HashMap<i32, f64>> {
+    let mut times = HashMap::new();
+    let mut last_t_game = None;
+    for row in csv_log::read_frames(path)? {
+        let Ok(row) = row else {
+            break;
+        };
+        if let Some(last) = last_t_game.replace(row.t_game) {
+            times.insert(row.frame_count, row.t_game - last);
+        }
+    }
+    Ok(times)
+}
+
+/// The grid cell a position falls in, counted in cells from 0 N 0 E.
+fn grid_cell(lat: f64, lon: f64, cell_size: f64) -> (i32, i32) {
+    (
+        (lat / cell_size).floor() as i32,
+        (lon / cell_size).floor() as i32,
+    )
+}
+
+fn write_heatmap(
+    path: &Path,
+    cells: &HashMap<(i32, i32), CellCount>,
+    lat_range: (i32, i32),
+    lon_range: (i32, i32),
+    cell_size: f64,
+    count: impl Fn(&CellCount) -> u64,
+) -> Result<()> {
+    let mut out = csv::Writer::from_path(path)?;
+    let center = |i: i32| format!("{:.4}", (i as f64 + 0.5) * cell_size);
+    let mut header = vec!["lat\\lon".to_string()];
+    header.extend((lon_range.0..=lon_range.1).map(center));
+    out.write_record(&header)?;
+    // north at the top, like a map
+    for lat in (lat_range.0..=lat_range.1).rev() {
+        let mut row = vec![center(lat)];
+        row.extend((lon_range.0..=lon_range.1).map(|lon| {
+            let n = cells.get(&(lat, lon)).map_or(0, &count);
+            n.to_string()
+        }));
+        out.write_record(&row)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn heatmap(args: &Args) -> Result<()> {
+    let input = args.input()?;
+    let cell_size: f64 = args.option("--cell").unwrap_or("0.1").parse()?;
+    if cell_size <= 0.0 {
+        return Err("--cell has to be positive".into());
+    }
+    let slow_threshold = args.option("--slow-ms").unwrap_or("100").parse::<f64>()? / 1000.0;
+    let frame_times = args
+        .option("--frames")
+        .map(Path::new)
+        .map(frame_times)
+        .transpose()?;
+    let prefix = args
+        .option("-o")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| output_path(input, "heatmap"));
+
+    let mut cells: BTreeMap<i32, HashMap<(i32, i32), CellCount>> = BTreeMap::new();
+    let mut lat_range = (i32::MAX, i32::MIN);
+    let mut lon_range = (i32::MAX, i32::MIN);
+    let mut samples = 0u64;
+    for row in csv_log::read_objects(input)? {
+        let Ok(row) = row else {
+            break;
+        };
+        let (lat, lon) = grid_cell(row.lat, row.lon, cell_size);
+        lat_range = (lat_range.0.min(lat), lat_range.1.max(lat));
+        lon_range = (lon_range.0.min(lon), lon_range.1.max(lon));
+        let is_slow = frame_times
+            .as_ref()
+            .and_then(|times| times.get(&row.frame_count))
+            .map_or(false, |dt| *dt >= slow_threshold);
+        let cell = cells
+            .entry(row.coalition_id)
+            .or_default()
+            .entry((lat, lon))
+            .or_default();
+        cell.all += 1;
+        if is_slow {
+            cell.slow += 1;
+        }
+        samples += 1;
+    }
+    if samples == 0 {
+        return Err(format!("no objects in {:?}", input).into());
+    }
+
+    for (coalition_id, cells) in cells.iter() {
+        let label = coalition_label(*coalition_id);
+        let path = with_suffix(&prefix, &format!(".{}.csv", label));
+        write_heatmap(&path, cells, lat_range, lon_range, cell_size, |c| c.all)?;
+        eprintln!("Wrote {:?}", path);
+        if frame_times.is_some() {
+            let path = with_suffix(&prefix, &format!(".{}.slow.csv", label));
+            write_heatmap(&path, cells, lat_range, lon_range, cell_size, |c| c.slow)?;
+            eprintln!("Wrote {:?}", path);
+        }
+    }
+    eprintln!(
+        "{} object samples in a {} x {} grid of {} degree cells",
+        samples,
+        lat_range.1 - lat_range.0 + 1,
+        lon_range.1 - lon_range.0 + 1,
+        cell_size
+    );
+    Ok(())
+}
+
+fn run() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+    let Some(command) = args.next() else {
+        print!("{}", USAGE);
+        return Ok(());
+    };
+    let args = Args::parse(args)?;
+    if let Some(path) = args.option("--dict") {
+        DICTIONARY.set(std::fs::read(path)?).unwrap();
+    }
+    match command.as_str() {
+        "decompress" => decompress(&args),
+        "recover" => recover(&args),
+        "decrypt" => decrypt(&args),
+        "train-dict" => train_dict(&args),
+        "tail" => tail(&args),
+        "filter" => filter(&args),
+        "convert" => convert(&args),
+        "stats" => stats(&args),
+        "heatmap" => heatmap(&args),
+        "help" | "-h" | "--help" => {
+            print!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(format!("unknown command {:?}\n\n{}", command, USAGE).into()),
+    }
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("tetrad-cli: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions_fall_in_the_cell_below_them() {
+        assert_eq!(grid_cell(42.05, 41.99, 0.1), (420, 419));
+        assert_eq!(grid_cell(0.0, 0.0, 0.5), (0, 0));
+        // south and west of zero round down too, not toward zero
+        assert_eq!(grid_cell(-0.05, -41.25, 0.1), (-1, -413));
+        assert_eq!(grid_cell(35.9, 36.1, 1.0), (35, 36));
+    }
+
+    #[test]
+    fn heatmap_has_north_at_the_top() {
+        let cells = HashMap::from([
+            ((420, 410), CellCount { all: 3, slow: 1 }),
+            ((421, 411), CellCount { all: 5, slow: 0 }),
+        ]);
+        let path = std::env::temp_dir().join("tetrad-cli-test-heatmap.csv");
+        write_heatmap(&path, &cells, (420, 421), (410, 411), 0.1, |c| c.all).unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            written.lines().collect::<Vec<_>>(),
+            ["lat\\lon,41.0500,41.1500", "42.1500,0,5", "42.0500,3,0"]
+        );
+    }
+
+    #[test]
+    fn coalition_labels() {
+        assert_eq!(coalition_label(1), "red");
+        assert_eq!(coalition_label(2), "blue");
+        assert_eq!(coalition_label(7), "coalition7");
+    }
+}
@@ -0,0 +1,57 @@
+// The GUI. Spawned by the tetrad library whenever `enable_gui` is on, and fed
+// frame data over stdin or a shared-memory ring buffer. Settings changes go
+// back to the library over stdout.
+//
+// `tetrad-gui --replay <session>` instead shows a recorded session offline.
+#![windows_subsystem = "windows"]
+use dcs_tetrad::{gui, gui_ipc};
+
+const REPLAY_ARG: &str = "--replay";
+
+fn main() {
+    fern::Dispatch::new()
+        .format(|out, message, record| {
+            out.finish(format_args!(
+                "[{}][tetrad-gui][{}] {}",
+                chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+                record.level(),
+                message
+            ))
+        })
+        .level(log::LevelFilter::Info)
+        .chain(std::io::stderr())
+        .apply()
+        .unwrap();
+    log_panics::init();
+
+    let ctx = gui::Context::default();
+    let args: Vec<String> = std::env::args().collect();
+    if let [_, flag, path] = args.as_slice() {
+        if flag == REPLAY_ARG {
+            if let Err(e) = gui::run_replay(std::path::Path::new(path), ctx) {
+                log::error!("Couldn't open session {}: {}", path, e);
+                std::process::exit(1);
+            }
+            return;
+        }
+    }
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let repaint_ctx = ctx.clone();
+    let shm_name = match args.as_slice() {
+        [_, flag, name] if flag == gui_ipc::SHM_ARG => Some(name.clone()),
+        _ => None,
+    };
+    std::thread::spawn(move || {
+        let on_message = || repaint_ctx.request_repaint();
+        match shm_name {
+            Some(name) => gui_ipc::receive_shared_memory(&name, tx, on_message),
+            None => gui_ipc::receive_messages(std::io::stdin(), tx, on_message),
+        }
+    });
+
+    let (client_tx, client_rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || gui_ipc::send_client_messages(client_rx, std::io::stdout()));
+
+    gui::run_standalone(rx, client_tx, ctx);
+}
@@ -0,0 +1,11 @@
+// Regenerates `lua/TetradGameGUI.lua` from the callback table in
+// src/callbacks.rs. Run from the repository root after changing that table.
+use dcs_tetrad::callbacks;
+
+fn main() {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "lua/TetradGameGUI.lua".to_string());
+    std::fs::write(&path, callbacks::generate_hook_lua()).expect("Couldn't write hook file");
+    println!("Wrote {}", path);
+}
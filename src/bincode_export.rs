@@ -0,0 +1,146 @@
+// Compact binary output for the frame and object logs, enabled by listing
+// "bincode" in `export_formats`. Much cheaper to write than CSV and smaller
+// after compression, since floats stay binary. The format, and a reader for
+// it, are in `tetrad_data::bincode_log`.
+use crate::io_thread::ThreadedFile;
+use crate::recorder::ObjectKind;
+use crate::sink::{Checkpoint, Frame, Sink, SinkResult};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::io::Write;
+use tetrad_data::bincode_log::{
+    FrameRecord, LogHeader, LogKind, ObjectFrame, ObjectRecord, FORMAT_VERSION, MAGIC,
+};
+
+struct RecordWriter {
+    file: ThreadedFile,
+    buf: Vec<u8>,
+}
+
+impl RecordWriter {
+    fn new(file: ThreadedFile, kind: LogKind, mission_name: &str) -> bincode::Result<Self> {
+        let mut writer = Self {
+            file,
+            buf: Vec::new(),
+        };
+        writer.write(&LogHeader {
+            magic: MAGIC,
+            version: FORMAT_VERSION,
+            kind,
+            mission_name: mission_name.to_string(),
+            tetrad_version: env!("CARGO_PKG_VERSION").to_string(),
+        })?;
+        Ok(writer)
+    }
+
+    fn write<T: Serialize>(&mut self, record: &T) -> bincode::Result<()> {
+        self.buf.clear();
+        bincode::serialize_into(&mut self.buf, record)?;
+        self.file
+            .write_all(&(self.buf.len() as u32).to_le_bytes())?;
+        self.file.write_all(&self.buf)?;
+        Ok(())
+    }
+
+    fn checkpoint(&mut self) -> std::io::Result<()> {
+        self.file.checkpoint()
+    }
+
+    fn finish(mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+pub struct BincodeSink {
+    frame_writer: Option<RecordWriter>,
+    object_writer: Option<RecordWriter>,
+}
+
+impl BincodeSink {
+    pub fn new(
+        frame_file: Option<ThreadedFile>,
+        object_file: Option<ThreadedFile>,
+        mission_name: &str,
+    ) -> bincode::Result<Self> {
+        Ok(Self {
+            frame_writer: frame_file
+                .map(|file| RecordWriter::new(file, LogKind::Frames, mission_name))
+                .transpose()?,
+            object_writer: object_file
+                .map(|file| RecordWriter::new(file, LogKind::Objects, mission_name))
+                .transpose()?,
+        })
+    }
+}
+
+impl Sink for BincodeSink {
+    fn name(&self) -> &'static str {
+        "bincode"
+    }
+
+    fn rotates(&self) -> bool {
+        true
+    }
+
+    fn write_frame(&mut self, frame: &Frame) -> SinkResult {
+        if let Some(writer) = self.frame_writer.as_mut() {
+            writer.write(&FrameRecord {
+                frame_count: frame.frame_count,
+                t_game: frame.game_time,
+                t_real: frame.real_time,
+                units: frame.num_units as u32,
+                ballistics: frame.num_ballistics as u32,
+                sys_cpu: frame.sys_time.0,
+                sys_wall: frame.sys_time.1,
+                proc_cpu: frame.proc_time.0,
+                lib_time: frame.lib_time,
+                working_set: frame.memory.working_set,
+                private_bytes: frame.memory.private_bytes,
+                commit: frame.memory.commit,
+            })?;
+        }
+        if let Some(writer) = self.object_writer.as_mut().filter(|_| frame.log_objects) {
+            let units = frame.units.iter().map(|unit| ObjectRecord {
+                kind: ObjectKind::Unit,
+                unit_name: Cow::Borrowed(unit.unit_name()),
+                group_name: Cow::Borrowed(unit.group_name()),
+                object: Cow::Borrowed(unit.object()),
+                motion: frame.motion.get(unit.object().id()),
+            });
+            let ballistics = frame.ballistics.iter().map(|object| ObjectRecord {
+                kind: ObjectKind::Ballistic,
+                unit_name: Cow::Borrowed(""),
+                group_name: Cow::Borrowed(""),
+                object: Cow::Borrowed(object),
+                motion: frame.motion.get(object.id()),
+            });
+            writer.write(&ObjectFrame {
+                frame_count: frame.frame_count,
+                t_game: frame.game_time,
+                t_real: frame.real_time,
+                objects: units.chain(ballistics).collect(),
+            })?;
+        }
+        Ok(())
+    }
+
+    fn checkpoint(&mut self) -> SinkResult {
+        for writer in [self.frame_writer.as_mut(), self.object_writer.as_mut()]
+            .into_iter()
+            .flatten()
+        {
+            writer.checkpoint()?;
+        }
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> SinkResult {
+        if let Some(writer) = self.frame_writer {
+            writer.finish()?;
+        }
+        if let Some(writer) = self.object_writer {
+            writer.finish()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,142 @@
+// The full set of DCS GameGUI user callbacks (`DCS.setUserCallbacks`) and how
+// each one reaches tetrad. `lua/TetradGameGUI.lua` is generated from this
+// table by the `tetrad-hookgen` binary, so adding a callback here is enough to
+// wire it up on both sides.
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Handler {
+    /// Drives the library's lifecycle, wired by hand in `hook.lua`.
+    Lifecycle,
+    /// Has its own export in lib.rs.
+    Dedicated,
+    /// Recorded as a generic callback event.
+    Generic,
+}
+
+#[derive(Debug)]
+pub struct Callback {
+    pub name: &'static str,
+    pub args: &'static [&'static str],
+    /// Index of the argument holding a player id, if there is one.
+    pub player_arg: Option<usize>,
+    pub handler: Handler,
+}
+
+const fn callback(
+    name: &'static str,
+    args: &'static [&'static str],
+    player_arg: Option<usize>,
+    handler: Handler,
+) -> Callback {
+    Callback {
+        name,
+        args,
+        player_arg,
+        handler,
+    }
+}
+
+use Handler::*;
+
+// Left out on purpose: onMissionLoadProgress, onShowMessage and
+// onRadioMessage, which fire many times a second during loading or busy
+// missions and would flood the event log, and onTriggerMessage, which the
+// stats overlay's own `trigger.action.outText` would feed back into it.
+pub const CALLBACKS: &[Callback] = &[
+    callback("onMissionLoadBegin", &[], None, Lifecycle),
+    callback("onMissionLoadEnd", &[], None, Lifecycle),
+    callback("onSimulationStart", &[], None, Generic),
+    callback("onSimulationStop", &[], None, Lifecycle),
+    callback("onSimulationFrame", &[], None, Lifecycle),
+    callback("onSimulationPause", &[], None, Dedicated),
+    callback("onSimulationResume", &[], None, Dedicated),
+    callback(
+        "onGameEvent",
+        &[
+            "eventName",
+            "arg1",
+            "arg2",
+            "arg3",
+            "arg4",
+            "arg5",
+            "arg6",
+            "arg7",
+        ],
+        Some(1),
+        Dedicated,
+    ),
+    callback("onNetConnect", &["localPlayerID"], Some(0), Generic),
+    callback("onNetMissionChanged", &["newMissionName"], None, Generic),
+    callback("onNetDisconnect", &["reason", "errCode"], None, Generic),
+    callback("onPlayerConnect", &["id"], Some(0), Dedicated),
+    callback("onPlayerDisconnect", &["id", "errCode"], Some(0), Dedicated),
+    callback("onPlayerStart", &["id"], Some(0), Generic),
+    callback("onPlayerStop", &["id"], Some(0), Generic),
+    callback("onPlayerChangeSlot", &["id"], Some(0), Dedicated),
+    callback(
+        "onPlayerTryConnect",
+        &["addr", "ucid", "name", "playerID"],
+        Some(3),
+        Dedicated,
+    ),
+    callback(
+        "onPlayerTrySendChat",
+        &["playerID", "msg", "all"],
+        Some(0),
+        Dedicated,
+    ),
+    callback(
+        "onPlayerTryChangeSlot",
+        &["playerID", "side", "slotID"],
+        Some(0),
+        Generic,
+    ),
+    callback("onChatMessage", &["message", "from"], Some(1), Dedicated),
+    callback("onShowChatAll", &[], None, Generic),
+    callback("onShowChatTeam", &[], None, Generic),
+    callback("onShowChatRead", &[], None, Generic),
+    callback("onRadioCommand", &["commandMessage"], None, Generic),
+];
+
+impl Callback {
+    /// Name of the library export the callback is routed to, e.g.
+    /// `onPlayerTryConnect` -> `on_player_try_connect`.
+    pub fn export_name(&self) -> String {
+        let mut s = String::new();
+        for c in self.name.chars() {
+            if c.is_ascii_uppercase() {
+                s.push('_');
+                s.push(c.to_ascii_lowercase());
+            } else {
+                s.push(c);
+            }
+        }
+        s
+    }
+}
+
+/// Source of `lua/TetradGameGUI.lua`. The generated functions never return a
+/// value, so tetrad can't accidentally veto `onPlayerTry*` callbacks.
+pub fn generate_hook_lua() -> String {
+    let mut lua = String::new();
+    lua.push_str(
+        "-- Generated by `cargo run --bin tetrad-hookgen` from src/callbacks.rs, do not edit.\n",
+    );
+    lua.push_str("-- Adds routing for every DCS GameGUI callback tetrad listens to.\n");
+    lua.push_str("return function(callbacks)\n");
+    let routed = CALLBACKS.iter().filter(|cb| cb.handler != Lifecycle);
+    for (i, cb) in routed.enumerate() {
+        if i > 0 {
+            lua.push('\n');
+        }
+        let args = cb.args.join(", ");
+        writeln!(lua, "    function callbacks.{}({})", cb.name, args).unwrap();
+        lua.push_str("        if TETRAD.lib then\n");
+        writeln!(lua, "            TETRAD.lib.{}({})", cb.export_name(), args).unwrap();
+        lua.push_str("        end\n");
+        lua.push_str("    end\n");
+    }
+    lua.push_str("end\n");
+    lua
+}
@@ -0,0 +1,172 @@
+// Helicopter logistics events. DCS has no scripting events for sling loads or
+// troop transport, so a small script in the mission environment watches cargo
+// objects for lifts and gives mission scripts (CTLD and friends) a way to
+// report troop movements. Tetrad collects the queued events from the hook.
+use crate::dcs;
+use crate::events::EventKind;
+use mlua::Lua;
+
+// Runs inside a_do_script([[...]]), so it must not contain a closing long
+// bracket.
+const MISSION_SCRIPT: &str = r#"
+if not tetrad_cargo then
+    tetrad_cargo = { queue = {}, lifted = {} }
+    local tc = tetrad_cargo
+    local LIFT_HEIGHT = 2
+    local ATTRIBUTION_RANGE = 60
+
+    local function describe(unit)
+        if unit and unit:isExist() then
+            return unit:getName(), unit:getPlayerName() or ""
+        end
+        return "", ""
+    end
+
+    local function push(kind, unit, player, cargo, p)
+        tc.queue[#tc.queue + 1] = table.concat(
+            { kind, timer.getTime(), unit, player, cargo, p.x, p.z, p.y }, "\t")
+    end
+
+    local function nearestHelicopter(p)
+        local best, bestDist = nil, ATTRIBUTION_RANGE
+        for _, side in pairs(coalition.side) do
+            for _, group in pairs(coalition.getGroups(side, Group.Category.HELICOPTER) or {}) do
+                for _, unit in pairs(group:getUnits() or {}) do
+                    local up = unit:getPoint()
+                    local d = math.sqrt((up.x - p.x) ^ 2 + (up.z - p.z) ^ 2)
+                    if d < bestDist then
+                        best, bestDist = unit, d
+                    end
+                end
+            end
+        end
+        return best
+    end
+
+    local function poll()
+        for _, side in pairs(coalition.side) do
+            for _, obj in pairs(coalition.getStaticObjects(side) or {}) do
+                if obj:isExist() and obj:getCategory() == Object.Category.CARGO then
+                    local name = obj:getName()
+                    local p = obj:getPoint()
+                    local agl = p.y - land.getHeight({ x = p.x, y = p.z })
+                    local carrier = tc.lifted[name]
+                    if not carrier and agl > LIFT_HEIGHT then
+                        local unit, player = describe(nearestHelicopter(p))
+                        tc.lifted[name] = { unit = unit, player = player }
+                        push("sling_load_pickup", unit, player, name, p)
+                    elseif carrier and agl <= LIFT_HEIGHT then
+                        tc.lifted[name] = nil
+                        push("sling_load_drop", carrier.unit, carrier.player, name, p)
+                    end
+                end
+            end
+        end
+    end
+
+    local function report(kind, name, troops)
+        local unit = Unit.getByName(name)
+        local _, player = describe(unit)
+        local p = unit and unit:isExist() and unit:getPoint() or { x = 0, y = 0, z = 0 }
+        push(kind, name, player, troops or "", p)
+    end
+
+    function tc.troops_embarked(unitName, troops)
+        report("troops_embark", unitName, troops)
+    end
+
+    function tc.troops_disembarked(unitName, troops)
+        report("troops_disembark", unitName, troops)
+    end
+
+    function tc.drain()
+        local s = table.concat(tc.queue, "\n")
+        tc.queue = {}
+        return s
+    end
+
+    timer.scheduleFunction(function(_, t)
+        local ok, err = pcall(poll)
+        if not ok then
+            env.error("tetrad cargo poll failed: " .. tostring(err))
+        end
+        return t + 1
+    end, nil, timer.getTime() + 1)
+end
+"#;
+
+#[derive(Debug, Clone)]
+pub struct CargoEvent {
+    pub kind: EventKind,
+    pub t_game: f64,
+    pub unit_name: String,
+    pub player_name: String,
+    /// Cargo object name for sling loads, free-form troop description for
+    /// troop transport.
+    pub cargo: String,
+    pub x: f64,
+    pub z: f64,
+    pub alt: f64,
+}
+
+impl CargoEvent {
+    fn parse(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [kind, t_game, unit_name, player_name, cargo, x, z, alt] = fields[..] else {
+            return None;
+        };
+        let kind = match kind {
+            "sling_load_pickup" => EventKind::SlingLoadPickup,
+            "sling_load_drop" => EventKind::SlingLoadDrop,
+            "troops_embark" => EventKind::TroopsEmbark,
+            "troops_disembark" => EventKind::TroopsDisembark,
+            _ => return None,
+        };
+        Some(Self {
+            kind,
+            t_game: t_game.parse().ok()?,
+            unit_name: unit_name.to_string(),
+            player_name: player_name.to_string(),
+            cargo: cargo.to_string(),
+            x: x.parse().ok()?,
+            z: z.parse().ok()?,
+            alt: alt.parse().ok()?,
+        })
+    }
+
+    pub fn details(&self) -> String {
+        format!(
+            "unit={} cargo={} x={:.0} z={:.0} alt={:.0}",
+            self.unit_name, self.cargo, self.x, self.z, self.alt
+        )
+    }
+}
+
+/// Sets up the cargo watcher in the mission scripting environment. Safe to
+/// call more than once per mission.
+pub fn install(lua: &Lua) -> mlua::Result<()> {
+    dcs::run_in_mission(lua, MISSION_SCRIPT)
+}
+
+/// Takes all cargo events queued in the mission since the last call.
+pub fn drain(lua: &Lua) -> Vec<CargoEvent> {
+    let code = "return tetrad_cargo and tetrad_cargo.drain() or ''";
+    let queued = match dcs::eval_in_mission(lua, code) {
+        Ok(Some(queued)) => queued,
+        Ok(None) => return Vec::new(),
+        Err(e) => {
+            log::warn!("Couldn't read the cargo events from the mission: {}", e);
+            return Vec::new();
+        }
+    };
+    queued
+        .lines()
+        .filter_map(|line| {
+            let event = CargoEvent::parse(line);
+            if event.is_none() {
+                log::warn!("Couldn't parse cargo event {:?}", line);
+            }
+            event
+        })
+        .collect()
+}
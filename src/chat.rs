@@ -0,0 +1,81 @@
+// In-game chat: the periodic server stats broadcast, `-tetrad` commands, and
+// the chat log.
+use chrono::{Local, NaiveTime};
+use serde::Serialize;
+
+/// A row of the chat log.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatRecord {
+    pub t_game: f64,
+    pub t_real: f64,
+    pub player_id: i32,
+    pub player_name: String,
+    /// "all", "coalition", or empty when DCS didn't say (e.g. the host).
+    pub scope: String,
+    pub message: String,
+}
+
+/// Time until the next daily restart at `restart_time` ("HH:MM", local time),
+/// formatted like "2h 05m".
+pub fn time_until_restart(restart_time: &str) -> Option<String> {
+    let restart = NaiveTime::parse_from_str(restart_time, "%H:%M").ok()?;
+    let now = Local::now().time();
+    let mut remaining = restart - now;
+    if remaining < chrono::Duration::zero() {
+        remaining = remaining + chrono::Duration::days(1);
+    }
+    Some(format!(
+        "{}h {:02}m",
+        remaining.num_hours(),
+        remaining.num_minutes() % 60
+    ))
+}
+
+/// Fills in the `{fps}`, `{players}`, `{mission}` and `{next_restart}`
+/// placeholders of the configured broadcast template.
+pub fn format_broadcast(
+    template: &str,
+    fps: f64,
+    num_players: usize,
+    mission_name: &str,
+    restart_time: &str,
+) -> String {
+    let next_restart = time_until_restart(restart_time).unwrap_or_else(|| "unknown".to_string());
+    template
+        .replace("{fps}", &format!("{:.0}", fps))
+        .replace("{players}", &num_players.to_string())
+        .replace("{mission}", mission_name)
+        .replace("{next_restart}", &next_restart)
+}
+
+/// Chat messages starting with this are commands for tetrad, e.g.
+/// `-tetrad fps`.
+pub const COMMAND_PREFIX: &str = "-tetrad";
+
+pub const COMMAND_HELP: &str = "Tetrad commands: -tetrad status, -tetrad fps, -tetrad objects";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Status,
+    Fps,
+    Objects,
+    Help,
+}
+
+impl Command {
+    /// The command in a chat message, or None if it isn't addressed to
+    /// tetrad. A bare `-tetrad` is `status`; anything unknown gets the help.
+    pub fn parse(message: &str) -> Option<Self> {
+        let mut words = message.split_whitespace();
+        if !words.next()?.eq_ignore_ascii_case(COMMAND_PREFIX) {
+            return None;
+        }
+        let command = match words.next().map(|w| w.to_ascii_lowercase()).as_deref() {
+            None | Some("status") => Self::Status,
+            Some("fps") => Self::Fps,
+            Some("objects") => Self::Objects,
+            Some(_) => Self::Help,
+        };
+        Some(command)
+    }
+}
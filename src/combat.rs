@@ -0,0 +1,172 @@
+// Weapon shots, hits and kills, for a lightweight debrief next to the object
+// log. The GameGUI `onGameEvent` callback only reports kills, and without unit
+// names, so a world event handler in the mission environment queues the
+// combat events and tetrad collects them from the hook, like the cargo events.
+use crate::dcs;
+use crate::events::EventKind;
+use mlua::Lua;
+
+// Runs inside a_do_script([[...]]), so it must not contain a closing long
+// bracket.
+const MISSION_SCRIPT: &str = r#"
+if not tetrad_combat then
+    tetrad_combat = { queue = {} }
+    local tc = tetrad_combat
+
+    -- S_EVENT_KILL is missing from older DCS versions
+    local kinds = {}
+    local function addKind(id, kind)
+        if id then
+            kinds[id] = kind
+        end
+    end
+    addKind(world.event.S_EVENT_SHOT, "shot")
+    addKind(world.event.S_EVENT_SHOOTING_START, "shot")
+    addKind(world.event.S_EVENT_HIT, "hit")
+    addKind(world.event.S_EVENT_KILL, "unit_kill")
+
+    local function describe(obj)
+        if not obj or not obj:isExist() then
+            return "", "", ""
+        end
+        local player = obj.getPlayerName and obj:getPlayerName() or ""
+        return obj:getName() or "", player, obj:getTypeName() or ""
+    end
+
+    local function weaponName(event)
+        if event.weapon_name then
+            return event.weapon_name
+        end
+        if event.weapon and event.weapon:isExist() then
+            return event.weapon:getTypeName()
+        end
+        return ""
+    end
+
+    local function format(kind, event)
+        local shooter, shooterPlayer, shooterType = describe(event.initiator)
+        local target, targetPlayer, targetType = describe(event.target)
+        return table.concat({ kind, event.time or timer.getTime(), shooter, shooterPlayer,
+            shooterType, target, targetPlayer, targetType, weaponName(event) }, "\t")
+    end
+
+    function tc:onEvent(event)
+        local kind = kinds[event.id]
+        if not kind then
+            return
+        end
+        local ok, line = pcall(format, kind, event)
+        if ok then
+            tc.queue[#tc.queue + 1] = line
+        else
+            env.error("tetrad combat event failed: " .. tostring(line))
+        end
+    end
+
+    function tc.drain()
+        local s = table.concat(tc.queue, "\n")
+        tc.queue = {}
+        return s
+    end
+
+    world.addEventHandler(tc)
+end
+"#;
+
+/// One side of a combat event. All fields are empty when DCS didn't say, e.g.
+/// for the target of a shot.
+#[derive(Debug, Clone, Default)]
+pub struct Combatant {
+    pub unit_name: String,
+    pub player_name: String,
+    pub type_name: String,
+}
+
+impl Combatant {
+    fn describe(&self) -> String {
+        if self.unit_name.is_empty() {
+            return "".to_string();
+        }
+        format!("{} ({})", self.unit_name, self.type_name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CombatEvent {
+    pub kind: EventKind,
+    pub t_game: f64,
+    pub shooter: Combatant,
+    pub target: Combatant,
+    pub weapon: String,
+}
+
+impl CombatEvent {
+    fn parse(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [kind, t_game, shooter, shooter_player, shooter_type, target, target_player, target_type, weapon] =
+            fields[..]
+        else {
+            return None;
+        };
+        let kind = match kind {
+            "shot" => EventKind::Shot,
+            "hit" => EventKind::Hit,
+            "unit_kill" => EventKind::UnitKill,
+            _ => return None,
+        };
+        Some(Self {
+            kind,
+            t_game: t_game.parse().ok()?,
+            shooter: Combatant {
+                unit_name: shooter.to_string(),
+                player_name: shooter_player.to_string(),
+                type_name: shooter_type.to_string(),
+            },
+            target: Combatant {
+                unit_name: target.to_string(),
+                player_name: target_player.to_string(),
+                type_name: target_type.to_string(),
+            },
+            weapon: weapon.to_string(),
+        })
+    }
+
+    pub fn details(&self) -> String {
+        format!(
+            "shooter={} target={} target_player={} weapon={}",
+            self.shooter.describe(),
+            self.target.describe(),
+            self.target.player_name,
+            self.weapon
+        )
+    }
+}
+
+/// Sets up the world event handler in the mission scripting environment. Safe
+/// to call more than once per mission.
+pub fn install(lua: &Lua) -> mlua::Result<()> {
+    dcs::run_in_mission(lua, MISSION_SCRIPT)
+}
+
+/// Takes all combat events queued in the mission since the last call.
+pub fn drain(lua: &Lua) -> Vec<CombatEvent> {
+    let code = "return tetrad_combat and tetrad_combat.drain() or ''";
+    let queued = match dcs::eval_in_mission(lua, code) {
+        Ok(Some(queued)) => queued,
+        Ok(None) => return Vec::new(),
+        Err(e) => {
+            log::warn!("Couldn't read the combat events from the mission: {}", e);
+            return Vec::new();
+        }
+    };
+    queued
+        .lines()
+        .filter_map(|line| {
+            let event = CombatEvent::parse(line);
+            if event.is_none() {
+                log::warn!("Couldn't parse combat event {:?}", line);
+            }
+            event
+        })
+        .collect()
+}
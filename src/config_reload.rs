@@ -0,0 +1,116 @@
+// Picks up edits to `Config/tetrad-config.lua` and `tetrad.toml` while a
+// mission is running. The Lua file is evaluated in an empty environment, like
+// the hook does at mission start, the TOML overrides for the mission are
+// layered on top, and the caller applies whatever can change mid-session.
+use crate::config::{Config, ConfigError, ConfigErrors};
+use mlua::{Lua, LuaSerdeExt, Table};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+pub type ConfigResult = Result<Config, Box<dyn std::error::Error>>;
+
+pub struct ConfigWatcher {
+    lua_path: PathBuf,
+    toml_path: PathBuf,
+    write_dir: String,
+    mission_name: String,
+    modified: [Option<SystemTime>; 2],
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Turns the config table into a `Config`, reporting every key with a value of
+/// the wrong type or an unknown option by name rather than stopping at the
+/// first one. Keys tetrad doesn't know are returned, since they're usually
+/// typos.
+pub fn from_lua_table(lua: &Lua, table: Table) -> mlua::Result<(Config, Vec<String>)> {
+    let known_keys = Config::known_keys();
+    let mut errors = Vec::new();
+    let mut unknown_keys = Vec::new();
+    for pair in table.clone().pairs::<String, mlua::Value>() {
+        let (key, value) = pair?;
+        if !known_keys.contains(&key) {
+            unknown_keys.push(key);
+            continue;
+        }
+        // deserialize each key on its own so the error can name it
+        let single = lua.create_table()?;
+        single.set(key.as_str(), value)?;
+        if let Err(e) = lua.from_value::<Config>(mlua::Value::Table(single)) {
+            errors.push(ConfigError {
+                key,
+                message: e.to_string(),
+            });
+        }
+    }
+    if !errors.is_empty() {
+        return Err(mlua::Error::external(ConfigErrors(errors)));
+    }
+    let config: Config = lua.from_value(mlua::Value::Table(table))?;
+    config.validate().map_err(mlua::Error::external)?;
+    Ok((config, unknown_keys))
+}
+
+fn read_config(lua: &Lua, path: &Path, write_dir: &str) -> mlua::Result<Config> {
+    let code = std::fs::read_to_string(path).map_err(mlua::Error::external)?;
+    let env = lua.create_table()?;
+    lua.load(&code)
+        .set_name("tetrad-config.lua")?
+        .set_environment(env.clone())?
+        .exec()?;
+    // the hook fills in write_dir, the file itself doesn't have it
+    env.set("write_dir", write_dir)?;
+    let (config, unknown_keys) = from_lua_table(lua, env)?;
+    for key in unknown_keys {
+        log::warn!("Unknown key `{}` in tetrad-config.lua, ignoring it", key);
+    }
+    Ok(config)
+}
+
+/// Layers the sections of `tetrad.toml` that apply to `mission_name` over
+/// `config`. A missing file leaves it unchanged.
+pub fn apply_overrides(config: Config, mission_name: &str) -> ConfigResult {
+    let path = config.overrides_file();
+    if !path.exists() {
+        return Ok(config);
+    }
+    let text = std::fs::read_to_string(&path)?;
+    let config = config.with_overrides(&text, mission_name)?;
+    config.validate()?;
+    Ok(config)
+}
+
+impl ConfigWatcher {
+    pub fn new(config: &Config, mission_name: &str) -> Self {
+        let lua_path = config.config_file();
+        let toml_path = config.overrides_file();
+        let modified = [modified_time(&lua_path), modified_time(&toml_path)];
+        Self {
+            lua_path,
+            toml_path,
+            write_dir: config.write_dir.clone(),
+            mission_name: mission_name.to_string(),
+            modified,
+        }
+    }
+
+    /// Re-reads the config if either file changed since the last call.
+    pub fn poll(&mut self, lua: &Lua) -> Option<ConfigResult> {
+        let modified = [
+            modified_time(&self.lua_path),
+            modified_time(&self.toml_path),
+        ];
+        if modified[0].is_none() || modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        log::debug!("Config files changed, reloading");
+        Some(
+            read_config(lua, &self.lua_path, &self.write_dir)
+                .map_err(Into::into)
+                .and_then(|config| apply_overrides(config, &self.mission_name)),
+        )
+    }
+}
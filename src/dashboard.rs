@@ -0,0 +1,157 @@
+// Small web dashboard for headless servers: unit counts, FPS and a frame time
+// sparkline, served over plain HTTP on `dashboard_port`. The page polls
+// `/data`, which the worker keeps up to date from the same per-frame data the
+// GUI gets.
+use crate::sink::{Frame, Sink, SinkResult};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+// Frames shown in the sparkline.
+const HISTORY_LEN: usize = 600;
+// Frames averaged for the FPS readout.
+const FPS_FRAMES: usize = 60;
+
+const PAGE: &str = include_str!("dashboard.html");
+
+#[derive(Default, Serialize)]
+struct DashboardData {
+    mission: String,
+    game_time: f64,
+    units: usize,
+    ballistics: usize,
+    fps: f64,
+    /// Real time per frame in ms, oldest first.
+    frame_times: VecDeque<f64>,
+}
+
+type SharedData = Arc<Mutex<DashboardData>>;
+
+pub struct DashboardServer {
+    data: SharedData,
+    is_running: Arc<AtomicBool>,
+    last_real_time: Option<f64>,
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &str) {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    if let Err(e) = stream.write_all(response.as_bytes()) {
+        log::debug!("Couldn't answer dashboard request: {}", e);
+    }
+}
+
+fn handle_request(mut stream: TcpStream, data: &SharedData) {
+    let setup = stream
+        .set_nonblocking(false)
+        .and_then(|_| stream.set_read_timeout(Some(REQUEST_TIMEOUT)))
+        .and_then(|_| stream.set_write_timeout(Some(REQUEST_TIMEOUT)));
+    if let Err(e) = setup {
+        log::warn!("Couldn't set up dashboard connection: {}", e);
+        return;
+    }
+    let mut request_line = String::new();
+    let Ok(reader) = stream.try_clone() else {
+        return;
+    };
+    if BufReader::new(reader).read_line(&mut request_line).is_err() {
+        return;
+    }
+    let path = request_line.split_whitespace().nth(1).unwrap_or("");
+    match path {
+        "/" | "/index.html" => respond(&mut stream, "200 OK", "text/html; charset=utf-8", PAGE),
+        "/data" => {
+            let json = serde_json::to_string(&*data.lock().unwrap()).unwrap_or_default();
+            respond(&mut stream, "200 OK", "application/json", &json)
+        }
+        _ => respond(&mut stream, "404 Not Found", "text/plain", "Not found"),
+    }
+}
+
+fn serve(listener: TcpListener, data: SharedData, is_running: Arc<AtomicBool>) {
+    while is_running.load(Ordering::SeqCst) {
+        match listener.accept() {
+            Ok((stream, _)) => handle_request(stream, &data),
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                std::thread::sleep(ACCEPT_POLL_INTERVAL)
+            }
+            Err(e) => {
+                log::error!("Dashboard listener failed: {}", e);
+                break;
+            }
+        }
+    }
+    log::debug!("Dashboard stopped");
+}
+
+impl DashboardServer {
+    pub fn start(address: &str, port: u16, mission_name: &str) -> std::io::Result<Self> {
+        let listener = TcpListener::bind((address, port))?;
+        listener.set_nonblocking(true)?;
+        log::info!("Serving dashboard on http://{}", listener.local_addr()?);
+
+        let data = SharedData::new(Mutex::new(DashboardData {
+            mission: mission_name.to_string(),
+            ..Default::default()
+        }));
+        let is_running = Arc::new(AtomicBool::new(true));
+        let serve_data = data.clone();
+        let serve_is_running = is_running.clone();
+        std::thread::spawn(move || serve(listener, serve_data, serve_is_running));
+        Ok(Self {
+            data,
+            is_running,
+            last_real_time: None,
+        })
+    }
+}
+
+impl Sink for DashboardServer {
+    fn name(&self) -> &'static str {
+        "dashboard"
+    }
+
+    fn write_frame(&mut self, frame: &Frame) -> SinkResult {
+        let mut data = self.data.lock().unwrap();
+        data.game_time = frame.game_time;
+        data.units = frame.num_units;
+        data.ballistics = frame.num_ballistics;
+        if let Some(last) = self.last_real_time {
+            if data.frame_times.len() >= HISTORY_LEN {
+                data.frame_times.pop_front();
+            }
+            data.frame_times
+                .push_back((frame.real_time - last) * 1000.0);
+            let recent = data.frame_times.len().min(FPS_FRAMES);
+            let total_ms: f64 = data.frame_times.iter().rev().take(recent).sum();
+            data.fps = if total_ms > 0.0 {
+                1000.0 * recent as f64 / total_ms
+            } else {
+                0.0
+            };
+        }
+        self.last_real_time = Some(frame.real_time);
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> SinkResult {
+        Ok(())
+    }
+}
+
+impl Drop for DashboardServer {
+    fn drop(&mut self) {
+        self.is_running.store(false, Ordering::SeqCst);
+    }
+}
@@ -1,110 +1,43 @@
+use crate::kinematics::MotionTracker;
 use mlua::prelude::{LuaFunction, LuaTable};
 use mlua::Lua;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
 use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-struct LatLonAlt {
-    lat: f64,
-    lon: f64,
-    alt: f64,
-}
+pub use tetrad_data::{DcsWorldObject, DcsWorldUnit, Motion, ObjectCategory};
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-struct DcsPosition {
-    x: f64,
-    y: f64,
-    z: f64,
-}
+// A broken mod can produce a malformed table every frame, so the warning about
+// skipping them is repeated at most this often.
+const DROP_WARNING_INTERVAL: Duration = Duration::from_secs(60);
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct DcsWorldObject {
-    id: i32,
-    name: String,
-    country: i32,
-    coalition: String,
-    coalition_id: i32,
-    lat_lon_alt: LatLonAlt,
-    heading: f64,
-    pitch: f64,
-    bank: f64,
-    position: DcsPosition,
-}
+static DROPPED_OBJECTS: AtomicU64 = AtomicU64::new(0);
+static LAST_DROP_WARNING: Mutex<Option<Instant>> = Mutex::new(None);
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct DcsWorldUnit {
-    object: DcsWorldObject,
-    unit_name: String,
-    group_name: String,
+/// The frame an object log row belongs to.
+pub struct RowStamp<'a> {
+    pub frame_count: i32,
+    pub frame_time: f64,
+    pub real_time: f64,
+    /// Wall-clock time of the frame, UTC milliseconds since the epoch.
+    pub epoch_ms: i64,
+    pub session_id: &'a str,
 }
 
+/// Something written to the object log. `columns` are the ones picked with
+/// `object_log_columns`, or `None` for all of them.
 pub trait Loggable {
     fn log_as_csv<W: Write>(
         &self,
-        frame_count: i32,
-        frame_time: f64,
-        real_time: f64,
+        stamp: &RowStamp,
+        motion: &MotionTracker,
+        columns: Option<&[&str]>,
         writer: &mut csv::Writer<W>,
-    );
-}
-
-impl<'lua> DcsWorldObject {
-    pub fn from_lua_with_id(id: i32, table: &LuaTable<'lua>) -> mlua::Result<Self> {
-        let lat_lon_alt = match table.get("LatLongAlt").unwrap() {
-            mlua::Value::Table(t) => t,
-            _ => panic!(""),
-        };
-
-        let position = match table.get("Position").unwrap() {
-            mlua::Value::Table(t) => t,
-            _ => panic!(""),
-        };
-
-        let lat_lon_alt = LatLonAlt {
-            lat: lat_lon_alt.get("Lat").unwrap(),
-            lon: lat_lon_alt.get("Long").unwrap(),
-            alt: lat_lon_alt.get("Alt").unwrap(),
-        };
-
-        let pos = DcsPosition {
-            x: position.get("x").unwrap(),
-            y: position.get("y").unwrap(),
-            z: position.get("z").unwrap(),
-        };
-
-        Ok(Self {
-            id: id,
-            name: table.get("Name").unwrap(),
-            country: table.get("Country").unwrap(),
-            coalition: table.get("Coalition").unwrap(),
-            coalition_id: table.get("CoalitionID").unwrap(),
-            lat_lon_alt: lat_lon_alt,
-            heading: table.get("Heading").unwrap(),
-            pitch: table.get("Pitch").unwrap(),
-            bank: table.get("Bank").unwrap(),
-            position: pos,
-        })
-    }
-}
-
-impl<'lua> DcsWorldUnit {
-    pub fn from_lua_with_id(id: i32, table: LuaTable<'lua>) -> mlua::Result<Self> {
-        let unit_name: String = match table.get("UnitName") {
-            Err(_e) => "NoName".to_string(),
-            Ok(val) => val,
-        };
-
-        let group_name: String = match table.get("GroupName") {
-            Err(_e) => "NoName".to_string(),
-            Ok(val) => val,
-        };
-
-        Ok(Self {
-            object: DcsWorldObject::from_lua_with_id(id, &table).unwrap(),
-            unit_name: unit_name,
-            group_name: group_name,
-        })
-    }
+    ) -> csv::Result<()>;
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -116,97 +49,591 @@ struct FrameObjectRecord<'a> {
     group_name: &'a str,
 }
 
+fn write_object_row<W: Write>(
+    writer: &mut csv::Writer<W>,
+    stamp: &RowStamp,
+    (unit_name, group_name): (&str, &str),
+    object: &DcsWorldObject,
+    motion: Motion,
+    columns: Option<&[&str]>,
+) -> csv::Result<()> {
+    let Some(columns) = columns else {
+        let record = FrameObjectRecord {
+            frame_count: stamp.frame_count,
+            frame_time: stamp.frame_time,
+            real_time: stamp.real_time,
+            unit_name,
+            group_name,
+        };
+        return writer.serialize((record, object, motion, stamp.epoch_ms, stamp.session_id));
+    };
+    let (lat, lon, alt) = object.lat_lon_alt();
+    let (heading, pitch, bank) = object.attitude();
+    let (x, y, z) = object.position();
+    for column in columns {
+        let value = match *column {
+            "frame_count" => stamp.frame_count.to_string(),
+            "t_game" => stamp.frame_time.to_string(),
+            "t_real" => stamp.real_time.to_string(),
+            "unit_name" => unit_name.to_string(),
+            "group_name" => group_name.to_string(),
+            "id" => object.id().to_string(),
+            "name" => object.name().to_string(),
+            "country" => object.country().to_string(),
+            "coalition" => object.coalition().to_string(),
+            "coalition_id" => object.coalition_id().to_string(),
+            "lat" => lat.to_string(),
+            "lon" => lon.to_string(),
+            "alt" => alt.to_string(),
+            "heading" => heading.to_string(),
+            "pitch" => pitch.to_string(),
+            "bank" => bank.to_string(),
+            "x" => x.to_string(),
+            "y" => y.to_string(),
+            "z" => z.to_string(),
+            "category" => object.category().as_str().to_string(),
+            "speed" => motion.speed.to_string(),
+            "vertical_speed" => motion.vertical_speed.to_string(),
+            "heading_rate" => motion.heading_rate.to_string(),
+            "epoch_ms" => stamp.epoch_ms.to_string(),
+            "session_id" => stamp.session_id.to_string(),
+            _ => String::new(),
+        };
+        writer.write_field(value)?;
+    }
+    writer.write_record(None::<&[u8]>)
+}
+
 impl Loggable for DcsWorldObject {
     fn log_as_csv<W: Write>(
         &self,
-        frame_count: i32,
-        frame_time: f64,
-        real_time: f64,
+        stamp: &RowStamp,
+        motion: &MotionTracker,
+        columns: Option<&[&str]>,
         writer: &mut csv::Writer<W>,
-    ) {
-        writer
-            .serialize((
-                FrameObjectRecord {
-                    frame_count,
-                    frame_time,
-                    real_time,
-                    unit_name: "",
-                    group_name: "",
-                },
-                self,
-            ))
-            .unwrap();
+    ) -> csv::Result<()> {
+        let motion = motion.get(self.id());
+        write_object_row(writer, stamp, ("", ""), self, motion, columns)
     }
 }
 
 impl Loggable for DcsWorldUnit {
     fn log_as_csv<W: Write>(
         &self,
-        frame_count: i32,
-        frame_time: f64,
-        real_time: f64,
+        stamp: &RowStamp,
+        motion: &MotionTracker,
+        columns: Option<&[&str]>,
         writer: &mut csv::Writer<W>,
-    ) {
-        writer
-            .serialize((
-                FrameObjectRecord {
-                    frame_count,
-                    frame_time,
-                    real_time,
-                    unit_name: self.unit_name.as_str(),
-                    group_name: self.group_name.as_str(),
-                },
-                &self.object,
-            ))
-            .unwrap();
+    ) -> csv::Result<()> {
+        let names = (self.unit_name(), self.group_name());
+        let motion = motion.get(self.object().id());
+        write_object_row(writer, stamp, names, self.object(), motion, columns)
     }
 }
 
-pub fn get_model_time(lua: &Lua) -> f64 {
-    let export: LuaTable = lua.globals().get("Export").unwrap();
-    let get_model_time: LuaFunction = export.get("LoGetModelTime").unwrap();
-    get_model_time.call::<_, f64>(()).unwrap()
+pub fn get_model_time(lua: &Lua) -> mlua::Result<f64> {
+    let export: LuaTable = lua.globals().get("Export")?;
+    let get_model_time: LuaFunction = export.get("LoGetModelTime")?;
+    get_model_time.call::<_, f64>(())
 }
 
-pub fn is_paused(lua: &Lua) -> bool {
-    let dcs: LuaTable = lua.globals().get("DCS").unwrap();
-    let get_pause: LuaFunction = dcs.get("getPause").unwrap();
-    get_pause.call::<_, bool>(()).unwrap()
+pub fn is_paused(lua: &Lua) -> mlua::Result<bool> {
+    let dcs: LuaTable = lua.globals().get("DCS")?;
+    let get_pause: LuaFunction = dcs.get("getPause")?;
+    get_pause.call::<_, bool>(())
 }
 
-pub fn get_lo_get_world_objects(lua: &Lua) -> LuaFunction {
-    let export: LuaTable = lua.globals().get("Export").unwrap();
-    export.get("LoGetWorldObjects").unwrap()
+pub fn get_lo_get_world_objects(lua: &Lua) -> mlua::Result<LuaFunction> {
+    let export: LuaTable = lua.globals().get("Export")?;
+    export.get("LoGetWorldObjects")
 }
 
-pub fn get_ballistics_objects(lua: &Lua) -> Vec<DcsWorldObject> {
-    let lo_get_world_objects = get_lo_get_world_objects(lua);
-    let table = lo_get_world_objects
-        .call::<_, LuaTable>("ballistic")
-        .unwrap();
-    let mut v: Vec<DcsWorldObject> = Vec::new();
-    for pair in table.pairs::<i32, LuaTable>() {
-        let (key, value) = pair.unwrap();
-        v.push(DcsWorldObject::from_lua_with_id(key, &value).unwrap());
+/// Counts an object that couldn't be read and leaves it out of the frame.
+fn skip_malformed(kind: &str, error: mlua::Error) {
+    let dropped = DROPPED_OBJECTS.fetch_add(1, Ordering::Relaxed) + 1;
+    let mut last_warning = LAST_DROP_WARNING.lock().unwrap();
+    if last_warning.map_or(true, |t| t.elapsed() >= DROP_WARNING_INTERVAL) {
+        *last_warning = Some(Instant::now());
+        log::warn!(
+            "Skipping malformed {} ({} dropped this session): {}",
+            kind,
+            dropped,
+            error
+        );
     }
-    log::trace!("got {} ballistics elements", v.len());
-    v
 }
 
-pub fn get_unit_objects(lua: &Lua) -> Vec<DcsWorldUnit> {
-    let lo_get_world_objects = get_lo_get_world_objects(lua);
-    let table = lo_get_world_objects.call::<_, LuaTable>(()).unwrap();
-    let mut v: Vec<DcsWorldUnit> = Vec::new();
+/// Malformed objects left out since the last `reset_dropped_objects`.
+pub fn dropped_objects() -> u64 {
+    DROPPED_OBJECTS.load(Ordering::Relaxed)
+}
+
+pub fn reset_dropped_objects() {
+    DROPPED_OBJECTS.store(0, Ordering::Relaxed);
+    *LAST_DROP_WARNING.lock().unwrap() = None;
+}
+
+/// Something `LoGetWorldObjects` returns, which can be read in place.
+trait WorldObject: Default {
+    const KIND: &'static str;
+    fn id(&self) -> i32;
+    fn read_from_lua(&mut self, id: i32, table: &LuaTable) -> mlua::Result<()>;
+    fn read_motion_from_lua(&mut self, table: &LuaTable) -> mlua::Result<()>;
+}
+
+impl WorldObject for DcsWorldObject {
+    const KIND: &'static str = "ballistics object";
+    fn id(&self) -> i32 {
+        DcsWorldObject::id(self)
+    }
+    fn read_from_lua(&mut self, id: i32, table: &LuaTable) -> mlua::Result<()> {
+        self.update_from_lua(id, table)
+    }
+    fn read_motion_from_lua(&mut self, table: &LuaTable) -> mlua::Result<()> {
+        self.update_motion_from_lua(table)
+    }
+}
+
+impl WorldObject for DcsWorldUnit {
+    const KIND: &'static str = "unit";
+    fn id(&self) -> i32 {
+        self.object().id()
+    }
+    fn read_from_lua(&mut self, id: i32, table: &LuaTable) -> mlua::Result<()> {
+        self.update_from_lua(id, table)
+    }
+    fn read_motion_from_lua(&mut self, table: &LuaTable) -> mlua::Result<()> {
+        self.object.update_motion_from_lua(table)
+    }
+}
+
+/// Moves the copy of object `id` from a previous frame to `v[index]`, if
+/// there is one later in `v`. `positions` maps ids to where they are in `v`,
+/// and is only built once the objects come in a different order than last
+/// time.
+fn find_previous<T: WorldObject>(
+    v: &mut [T],
+    index: usize,
+    id: i32,
+    positions: &mut Option<HashMap<i32, usize>>,
+) -> bool {
+    if index == v.len() {
+        return false;
+    }
+    if v[index].id() == id {
+        return true;
+    }
+    let positions =
+        positions.get_or_insert_with(|| (index..v.len()).map(|i| (v[i].id(), i)).collect());
+    match positions.get(&id) {
+        // entries go stale as elements get overwritten, so double check
+        Some(&j) if j > index && j < v.len() && v[j].id() == id => {
+            positions.insert(v[index].id(), j);
+            v.swap(index, j);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Reads the objects in `table` into `v`, overwriting the elements already
+/// there so their allocations get reused. Objects that were already in `v`
+/// only get their position and attitude read; everything else about an
+/// object stays the same for its lifetime.
+fn read_objects_into<T: WorldObject>(table: LuaTable, v: &mut Vec<T>) {
+    let mut len = 0;
+    let mut positions = None;
     for pair in table.pairs::<i32, LuaTable>() {
-        let (key, value) = pair.unwrap();
-        v.push(DcsWorldUnit::from_lua_with_id(key, value).unwrap());
+        let (id, value) = match pair {
+            Ok(pair) => pair,
+            Err(e) => {
+                skip_malformed(T::KIND, e);
+                continue;
+            }
+        };
+        let is_known = find_previous(v, len, id, &mut positions);
+        if len == v.len() {
+            v.push(T::default());
+        }
+        let result = if is_known {
+            v[len].read_motion_from_lua(&value)
+        } else {
+            v[len].read_from_lua(id, &value)
+        };
+        match result {
+            Ok(()) => len += 1,
+            Err(e) => skip_malformed(T::KIND, e),
+        }
     }
+    v.truncate(len);
+}
+
+fn read_ballistics_into(lua: &Lua, v: &mut Vec<DcsWorldObject>) -> mlua::Result<()> {
+    let lo_get_world_objects = get_lo_get_world_objects(lua)?;
+    read_objects_into(lo_get_world_objects.call::<_, LuaTable>("ballistic")?, v);
+    log::trace!("got {} ballistics elements", v.len());
+    Ok(())
+}
+
+fn read_units_into(lua: &Lua, v: &mut Vec<DcsWorldUnit>) -> mlua::Result<()> {
+    let lo_get_world_objects = get_lo_get_world_objects(lua)?;
+    read_objects_into(lo_get_world_objects.call::<_, LuaTable>(())?, v);
     log::trace!("got {} unit elements", v.len());
-    v
+    Ok(())
+}
+
+pub fn get_ballistics_objects(lua: &Lua) -> mlua::Result<Vec<DcsWorldObject>> {
+    let mut v = Vec::new();
+    read_ballistics_into(lua, &mut v)?;
+    Ok(v)
+}
+
+pub fn get_unit_objects(lua: &Lua) -> mlua::Result<Vec<DcsWorldUnit>> {
+    let mut v = Vec::new();
+    read_units_into(lua, &mut v)?;
+    Ok(v)
+}
+
+// Frames handed out recently. The worker and GUI usually let go of a frame
+// within a few more, at which point its buffers can be refilled.
+const POOL_SIZE: usize = 8;
+
+/// Recycles the object lists handed out each frame, so extraction on the DCS
+/// thread reuses the vectors and the strings in them instead of allocating
+/// everything from scratch.
+#[derive(Default)]
+pub struct ObjectPool {
+    units: VecDeque<Arc<Vec<DcsWorldUnit>>>,
+    ballistics: VecDeque<Arc<Vec<DcsWorldObject>>>,
+}
+
+/// Takes a buffer nobody else holds any more, or a new one.
+fn take_free<T>(pool: &mut VecDeque<Arc<Vec<T>>>) -> Arc<Vec<T>> {
+    match pool.iter().position(|v| Arc::strong_count(v) == 1) {
+        Some(i) => pool.remove(i).unwrap(),
+        None => Arc::default(),
+    }
+}
+
+fn give_back<T>(pool: &mut VecDeque<Arc<Vec<T>>>, v: &Arc<Vec<T>>) {
+    if pool.len() == POOL_SIZE {
+        pool.pop_front();
+    }
+    pool.push_back(v.clone());
+}
+
+impl ObjectPool {
+    pub fn get_ballistics_objects(&mut self, lua: &Lua) -> mlua::Result<Arc<Vec<DcsWorldObject>>> {
+        let mut v = take_free(&mut self.ballistics);
+        read_ballistics_into(lua, Arc::make_mut(&mut v))?;
+        give_back(&mut self.ballistics, &v);
+        Ok(v)
+    }
+
+    pub fn get_unit_objects(&mut self, lua: &Lua) -> mlua::Result<Arc<Vec<DcsWorldUnit>>> {
+        let mut v = take_free(&mut self.units);
+        read_units_into(lua, Arc::make_mut(&mut v))?;
+        give_back(&mut self.units, &v);
+        Ok(v)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PlayerInfo {
+    pub id: i32,
+    pub name: String,
+    pub ucid: String,
+    pub side: i32,
+    pub slot: String,
+    pub ping: i32,
+}
+
+pub fn value_to_string(value: mlua::Value) -> String {
+    match value {
+        mlua::Value::Boolean(b) => b.to_string(),
+        mlua::Value::String(s) => s.to_str().unwrap_or("").to_string(),
+        mlua::Value::Integer(i) => i.to_string(),
+        mlua::Value::Number(n) => n.to_string(),
+        _ => "".to_string(),
+    }
+}
+
+impl<'lua> PlayerInfo {
+    pub fn from_lua(table: &LuaTable<'lua>) -> mlua::Result<Self> {
+        Ok(Self {
+            id: table.get("id")?,
+            name: table.get("name").unwrap_or_else(|_| "NoName".to_string()),
+            ucid: table.get("ucid").unwrap_or_default(),
+            side: table.get("side").unwrap_or(0),
+            // slot ids are usually strings, but some are plain numbers
+            slot: value_to_string(table.get("slot")?),
+            ping: table.get("ping").unwrap_or(0),
+        })
+    }
+}
+
+/// None if there's no player `id`, e.g. one who has just left.
+pub fn get_player_info(lua: &Lua, id: i32) -> mlua::Result<Option<PlayerInfo>> {
+    let net: LuaTable = lua.globals().get("net")?;
+    let get_player_info: LuaFunction = net.get("get_player_info")?;
+    match get_player_info.call::<_, Option<LuaTable>>(id)? {
+        Some(table) => PlayerInfo::from_lua(&table).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Looks up one of the `net.ERR_*` style constants, which not every DCS
+/// version defines.
+pub fn get_net_constant(lua: &Lua, name: &str) -> Option<i32> {
+    let net: LuaTable = lua.globals().get("net").ok()?;
+    net.get::<_, Option<i32>>(name).ok()?
+}
+
+/// Aircraft/unit type occupying a multiplayer slot, empty for spectators.
+pub fn get_unit_type(lua: &Lua, slot: &str) -> mlua::Result<String> {
+    if slot.is_empty() {
+        return Ok("".to_string());
+    }
+    let dcs: LuaTable = lua.globals().get("DCS")?;
+    let get_unit_type: LuaFunction = dcs.get("getUnitType")?;
+    Ok(get_unit_type
+        .call::<_, Option<String>>(slot)?
+        .unwrap_or_default())
+}
+
+/// Name of the unit with mission id `id`, empty if DCS doesn't know it.
+pub fn get_unit_name(lua: &Lua, id: &str) -> mlua::Result<String> {
+    let dcs: LuaTable = lua.globals().get("DCS")?;
+    let get_unit_property: LuaFunction = dcs.get("getUnitProperty")?;
+    let Ok(property) = dcs.get::<_, i32>("UNIT_NAME") else {
+        return Ok("".to_string());
+    };
+    Ok(get_unit_property
+        .call::<_, Option<String>>((id, property))?
+        .unwrap_or_default())
+}
+
+/// Everyone connected, the server included. Players that can't be read are
+/// left out.
+pub fn get_players(lua: &Lua) -> mlua::Result<Vec<PlayerInfo>> {
+    let net: LuaTable = lua.globals().get("net")?;
+    let get_player_list: LuaFunction = net.get("get_player_list")?;
+    let ids = get_player_list.call::<_, Vec<i32>>(())?;
+    let mut v = Vec::with_capacity(ids.len());
+    for id in ids {
+        match get_player_info(lua, id) {
+            Ok(Some(info)) => v.push(info),
+            // left since the list was made
+            Ok(None) => {}
+            Err(e) => log::warn!("Skipping player {}: {}", id, e),
+        }
+    }
+    log::trace!("got {} players", v.len());
+    Ok(v)
+}
+
+pub fn send_chat(lua: &Lua, message: &str) -> mlua::Result<()> {
+    let net: LuaTable = lua.globals().get("net")?;
+    let send_chat: LuaFunction = net.get("send_chat")?;
+    send_chat.call::<_, ()>((message, true))
+}
+
+/// Sends a chat message that only player `id` sees.
+pub fn send_chat_to(lua: &Lua, message: &str, id: i32) -> mlua::Result<()> {
+    let net: LuaTable = lua.globals().get("net")?;
+    let send_chat_to: LuaFunction = net.get("send_chat_to")?;
+    send_chat_to.call::<_, ()>((message, id))
 }
 
-pub fn get_mission_name(lua: &Lua) -> String {
-    let dcs: LuaTable = lua.globals().get("DCS").unwrap();
-    let get_mission_name: LuaFunction = dcs.get("getMissionName").unwrap();
-    get_mission_name.call::<_, String>(()).unwrap()
+/// Runs `code` in the mission scripting environment.
+pub fn run_in_mission(lua: &Lua, code: &str) -> mlua::Result<()> {
+    let net: LuaTable = lua.globals().get("net")?;
+    let dostring_in: LuaFunction = net.get("dostring_in")?;
+    let wrapped = format!("a_do_script([[{}]])", code);
+    dostring_in.call::<_, ()>(("mission", wrapped))
+}
+
+/// Evaluates `code` in the server's mission state and returns its result as a
+/// string, if it produced one.
+pub fn eval_in_mission(lua: &Lua, code: &str) -> mlua::Result<Option<String>> {
+    let net: LuaTable = lua.globals().get("net")?;
+    let dostring_in: LuaFunction = net.get("dostring_in")?;
+    dostring_in.call::<_, Option<String>>(("server", code))
+}
+
+pub fn get_mission_name(lua: &Lua) -> mlua::Result<String> {
+    let dcs: LuaTable = lua.globals().get("DCS")?;
+    let get_mission_name: LuaFunction = dcs.get("getMissionName")?;
+    get_mission_name.call::<_, String>(())
+}
+
+/// The map the mission is set on, e.g. "Caucasus".
+pub fn get_theatre(lua: &Lua) -> Option<String> {
+    let dcs: LuaTable = lua.globals().get("DCS").ok()?;
+    let get_current_mission: LuaFunction = dcs.get("getCurrentMission").ok()?;
+    let current: LuaTable = get_current_mission.call(()).ok()?;
+    let mission: LuaTable = current.get("mission").ok()?;
+    mission.get("theatre").ok()
+}
+
+/// Whether the mission runs in multiplayer, on this machine or another.
+pub fn is_multiplayer(lua: &Lua) -> bool {
+    call_dcs_flag(lua, "isMultiplayer")
+}
+
+/// Whether this DCS instance hosts the mission, as a server or as the player
+/// hosting it.
+pub fn is_server(lua: &Lua) -> bool {
+    call_dcs_flag(lua, "isServer")
+}
+
+/// The server's settings: max players, pause options, the `advanced` table
+/// and so on. From `net.get_server_settings()` where DCS has it, otherwise
+/// from `Config/serverSettings.lua` in the Saved Games folder.
+pub fn get_server_settings<'lua>(lua: &'lua Lua, write_dir: &str) -> Option<LuaTable<'lua>> {
+    let net: LuaTable = lua.globals().get("net").ok()?;
+    if let Ok(get_server_settings) = net.get::<_, LuaFunction>("get_server_settings") {
+        return get_server_settings.call(()).ok();
+    }
+    let path = Path::new(write_dir)
+        .join("Config")
+        .join("serverSettings.lua");
+    let code = std::fs::read_to_string(path).ok()?;
+    let env = lua.create_table().ok()?;
+    lua.load(&code)
+        .set_name("serverSettings.lua")
+        .and_then(|chunk| chunk.set_environment(env.clone()))
+        .and_then(|chunk| chunk.exec())
+        .ok()?;
+    env.get("cfg").ok()
+}
+
+fn call_dcs_flag(lua: &Lua, name: &str) -> bool {
+    let Ok(dcs) = lua.globals().get::<_, LuaTable>("DCS") else {
+        return false;
+    };
+    dcs.get::<_, LuaFunction>(name)
+        .and_then(|f| f.call::<_, bool>(()))
+        .unwrap_or(false)
+}
+
+/// The version of DCS that's running, e.g. "2.8.3.37556".
+pub fn get_dcs_version(lua: &Lua) -> Option<String> {
+    lua.globals().get("__DCS_VERSION__").ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(id: i32) -> DcsWorldObject {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "name": "",
+            "country": 0,
+            "coalition": "",
+            "coalition_id": 0,
+            "lat_lon_alt": { "lat": 0.0, "lon": 0.0, "alt": 0.0 },
+            "heading": 0.0,
+            "pitch": 0.0,
+            "bank": 0.0,
+            "position": { "x": 0.0, "y": 0.0, "z": 0.0 },
+            "category": "other",
+        }))
+        .unwrap()
+    }
+
+    fn ids(v: &[DcsWorldObject]) -> Vec<i32> {
+        v.iter().map(DcsWorldObject::id).collect()
+    }
+
+    #[test]
+    fn find_previous_in_the_same_order() {
+        let mut v = vec![object(10), object(20)];
+        let mut positions = None;
+        assert!(find_previous(&mut v, 0, 10, &mut positions));
+        assert!(find_previous(&mut v, 1, 20, &mut positions));
+        assert!(!find_previous(&mut v, 2, 30, &mut positions));
+        assert!(positions.is_none());
+    }
+
+    #[test]
+    fn find_previous_moves_objects_into_place() {
+        let mut v = vec![object(10), object(20), object(30)];
+        let mut positions = None;
+        assert!(find_previous(&mut v, 0, 30, &mut positions));
+        assert_eq!(ids(&v), [30, 20, 10]);
+        // 10 was swapped to where 30 was
+        assert!(find_previous(&mut v, 1, 10, &mut positions));
+        assert_eq!(ids(&v), [30, 10, 20]);
+        assert!(!find_previous(&mut v, 2, 40, &mut positions));
+    }
+
+    #[test]
+    fn find_previous_ignores_objects_already_placed() {
+        let mut v = vec![object(10), object(20)];
+        let mut positions = None;
+        assert!(!find_previous(&mut v, 1, 10, &mut positions));
+        assert_eq!(ids(&v), [10, 20]);
+    }
+
+    fn world_objects<'lua>(lua: &'lua Lua, code: &str) -> LuaTable<'lua> {
+        lua.load(
+            r#"
+            function object(name, x)
+                return {
+                    Name = name, Country = 2, Coalition = "Enemies", CoalitionID = 1,
+                    LatLongAlt = { Lat = 42.0, Long = 41.0, Alt = 100.0 },
+                    Position = { x = x, y = 100.0, z = 0.0 },
+                    Heading = 0.0, Pitch = 0.0, Bank = 0.0,
+                }
+            end
+            "#,
+        )
+        .exec()
+        .unwrap();
+        lua.load(code).eval().unwrap()
+    }
+
+    #[test]
+    fn read_objects_into_only_reads_motion_of_known_objects() {
+        let lua = Lua::new();
+        let mut v: Vec<DcsWorldObject> = Vec::new();
+        read_objects_into(
+            world_objects(
+                &lua,
+                r#"return { object("Su-27", 0), object("MiG-29S", 0) }"#,
+            ),
+            &mut v,
+        );
+        assert_eq!(ids(&v), [1, 2]);
+
+        // a known object keeps what it was first read with, a new one is read
+        // in full and ones that went away are dropped
+        read_objects_into(
+            world_objects(
+                &lua,
+                r#"return { object("renamed", 5), [3] = object("Tu-22M3", 0) }"#,
+            ),
+            &mut v,
+        );
+        assert_eq!(ids(&v), [1, 3]);
+        assert_eq!(v[0].name(), "Su-27");
+        assert_eq!(v[0].position().0, 5.0);
+        assert_eq!(v[1].name(), "Tu-22M3");
+    }
+
+    #[test]
+    fn read_objects_into_skips_malformed_objects() {
+        let lua = Lua::new();
+        let mut v: Vec<DcsWorldObject> = Vec::new();
+        read_objects_into(
+            world_objects(
+                &lua,
+                r#"return { object("Su-27", 0), { Name = "broken" } }"#,
+            ),
+            &mut v,
+        );
+        assert_eq!(ids(&v), [1]);
+    }
 }
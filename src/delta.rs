@@ -0,0 +1,203 @@
+// Changed-only object logging. Keeps the last logged state of every object and
+// only passes on the ones that moved or turned by more than the configured
+// thresholds, with a full keyframe every `object_keyframe_interval` game
+// seconds so readers have a complete picture to start from.
+use crate::config::Config;
+use crate::dcs::{DcsWorldObject, DcsWorldUnit};
+use crate::sink::Frame;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+type Pose = ((f64, f64, f64), (f64, f64, f64));
+
+pub struct DeltaFilter {
+    position_threshold: f64,
+    angle_threshold: f64,
+    keyframe_interval: f64,
+    last_logged: HashMap<i32, Pose>,
+    last_keyframe_time: Option<f64>,
+}
+
+fn pose(object: &DcsWorldObject) -> Pose {
+    (object.position(), object.attitude())
+}
+
+fn angle_difference(a: f64, b: f64) -> f64 {
+    let d = (a - b).rem_euclid(2.0 * PI);
+    d.min(2.0 * PI - d)
+}
+
+impl DeltaFilter {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            position_threshold: config.object_delta_position,
+            angle_threshold: config.object_delta_angle.to_radians(),
+            keyframe_interval: config.object_keyframe_interval,
+            last_logged: HashMap::new(),
+            last_keyframe_time: None,
+        }
+    }
+
+    fn has_changed(&self, object: &DcsWorldObject) -> bool {
+        let Some(&(position, attitude)) = self.last_logged.get(&object.id()) else {
+            return true;
+        };
+        let (new_position, new_attitude) = pose(object);
+        let distance = ((new_position.0 - position.0).powi(2)
+            + (new_position.1 - position.1).powi(2)
+            + (new_position.2 - position.2).powi(2))
+        .sqrt();
+        distance > self.position_threshold
+            || angle_difference(new_attitude.0, attitude.0) > self.angle_threshold
+            || angle_difference(new_attitude.1, attitude.1) > self.angle_threshold
+            || angle_difference(new_attitude.2, attitude.2) > self.angle_threshold
+    }
+
+    fn select<T: Clone>(
+        &mut self,
+        objects: &[T],
+        object: impl Fn(&T) -> &DcsWorldObject,
+        is_keyframe: bool,
+    ) -> Vec<T> {
+        let mut selected = Vec::new();
+        for item in objects {
+            let obj = object(item);
+            if is_keyframe || self.has_changed(obj) {
+                self.last_logged.insert(obj.id(), pose(obj));
+                selected.push(item.clone());
+            }
+        }
+        selected
+    }
+
+    /// The units and ballistics from `frame` that should go into the object
+    /// logs.
+    pub fn apply(&mut self, frame: &Frame) -> (Arc<Vec<DcsWorldUnit>>, Arc<Vec<DcsWorldObject>>) {
+        let (units, ballistics) = self.filter(frame.game_time, frame.units, frame.ballistics);
+        (Arc::new(units), Arc::new(ballistics))
+    }
+
+    fn filter(
+        &mut self,
+        game_time: f64,
+        units: &[DcsWorldUnit],
+        ballistics: &[DcsWorldObject],
+    ) -> (Vec<DcsWorldUnit>, Vec<DcsWorldObject>) {
+        let is_keyframe = match self.last_keyframe_time {
+            Some(t) => self.keyframe_interval > 0.0 && game_time - t >= self.keyframe_interval,
+            None => true,
+        };
+        if is_keyframe {
+            // objects that have gone away drop out of the map here
+            self.last_logged.clear();
+            self.last_keyframe_time = Some(game_time);
+        }
+        let units = self.select(units, DcsWorldUnit::object, is_keyframe);
+        let ballistics = self.select(ballistics, |object| object, is_keyframe);
+        (units, ballistics)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config {
+            object_delta_position: 1.0,
+            object_delta_angle: 1.0,
+            object_keyframe_interval: 10.0,
+            ..Config::default()
+        }
+    }
+
+    fn object(id: i32, x: f64, heading: f64) -> DcsWorldObject {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "name": "F-16C_50",
+            "country": 2,
+            "coalition": "blue",
+            "coalition_id": 2,
+            "lat_lon_alt": { "lat": 0.0, "lon": 0.0, "alt": 0.0 },
+            "heading": heading,
+            "pitch": 0.0,
+            "bank": 0.0,
+            "position": { "x": x, "y": 0.0, "z": 0.0 },
+            "category": "airplane",
+        }))
+        .unwrap()
+    }
+
+    fn logged_ids(
+        filter: &mut DeltaFilter,
+        game_time: f64,
+        objects: &[DcsWorldObject],
+    ) -> Vec<i32> {
+        let (_, ballistics) = filter.filter(game_time, &[], objects);
+        ballistics.iter().map(DcsWorldObject::id).collect()
+    }
+
+    #[test]
+    fn only_objects_past_a_threshold_are_logged() {
+        let mut filter = DeltaFilter::new(&config());
+        let first = [
+            object(1, 0.0, 0.0),
+            object(2, 0.0, 0.0),
+            object(3, 0.0, 0.0),
+        ];
+        assert_eq!(logged_ids(&mut filter, 0.0, &first), [1, 2, 3]);
+
+        let moved = [
+            object(1, 0.5, 0.0),
+            object(2, 1.5, 0.0),
+            object(3, 0.0, 2f64.to_radians()),
+        ];
+        assert_eq!(logged_ids(&mut filter, 1.0, &moved), [2, 3]);
+    }
+
+    #[test]
+    fn small_changes_add_up() {
+        let mut filter = DeltaFilter::new(&config());
+        logged_ids(&mut filter, 0.0, &[object(1, 0.0, 0.0)]);
+        assert!(logged_ids(&mut filter, 1.0, &[object(1, 0.6, 0.0)]).is_empty());
+        // compared with where it was last logged, not the previous frame
+        assert_eq!(logged_ids(&mut filter, 2.0, &[object(1, 1.2, 0.0)]), [1]);
+    }
+
+    #[test]
+    fn headings_wrap_around() {
+        let mut filter = DeltaFilter::new(&config());
+        let almost_north = 2.0 * PI - 0.1f64.to_radians();
+        logged_ids(&mut filter, 0.0, &[object(1, 0.0, almost_north)]);
+        assert!(logged_ids(&mut filter, 1.0, &[object(1, 0.0, 0.1f64.to_radians())]).is_empty());
+    }
+
+    #[test]
+    fn keyframes_log_everything() {
+        let mut filter = DeltaFilter::new(&config());
+        let objects = [object(1, 0.0, 0.0), object(2, 0.0, 0.0)];
+        logged_ids(&mut filter, 0.0, &objects);
+        assert!(logged_ids(&mut filter, 9.0, &objects).is_empty());
+        assert_eq!(logged_ids(&mut filter, 10.0, &objects), [1, 2]);
+        // and start over from the keyframe
+        assert!(logged_ids(&mut filter, 11.0, &objects).is_empty());
+    }
+
+    #[test]
+    fn units_are_filtered_by_their_object() {
+        let mut filter = DeltaFilter::new(&config());
+        let unit = |x| -> DcsWorldUnit {
+            serde_json::from_value(serde_json::json!({
+                "object": serde_json::to_value(object(1, x, 0.0)).unwrap(),
+                "unit_name": "Viper 1-1",
+                "group_name": "Viper 1",
+            }))
+            .unwrap()
+        };
+        let (units, _) = filter.filter(0.0, &[unit(0.0)], &[]);
+        assert_eq!(units.len(), 1);
+        let (units, _) = filter.filter(1.0, &[unit(0.5)], &[]);
+        assert!(units.is_empty());
+    }
+}
@@ -0,0 +1,150 @@
+// Keeps tetrad from filling the drive DCS runs on. When free space on the
+// `write_dir` drive drops below `min_free_disk_mb`, the caller switches off
+// the object log (by far the biggest stream) and, if allowed, the oldest
+// tetrad logs are deleted to make room. Old logs are also pruned at session
+// start according to `max_log_age_days` and `max_log_total_gb`.
+use crate::config::Config;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use windows::core::HSTRING;
+use windows::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+const MEGABYTE: u64 = 1024 * 1024;
+const GIGABYTE: f64 = 1024.0 * 1024.0 * 1024.0;
+const DAY: f64 = 24.0 * 60.0 * 60.0;
+
+// Files in the log directory that are never deleted to free up space.
+const KEEP_FILES: [&str; 4] = [
+    "dcs_tetrad.log",
+    "status.json",
+    "summary.csv",
+    "summary.1.csv",
+];
+
+/// A file tetrad wrote under `Logs/Tetrad`.
+pub struct LogFile {
+    pub path: PathBuf,
+    pub modified: SystemTime,
+    pub size: u64,
+}
+
+/// Bytes available to this process on the drive holding `path`.
+pub fn free_space(path: &Path) -> windows::core::Result<u64> {
+    let mut available = 0u64;
+    unsafe {
+        GetDiskFreeSpaceExW(&HSTRING::from(path), Some(&mut available), None, None).ok()?;
+    }
+    Ok(available)
+}
+
+fn collect_log_files(dir: &Path, files: &mut Vec<LogFile>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        let path = entry.path();
+        if metadata.is_dir() {
+            collect_log_files(&path, files);
+        } else if !KEEP_FILES.iter().any(|name| path.ends_with(name)) {
+            files.push(LogFile {
+                path,
+                modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                size: metadata.len(),
+            });
+        }
+    }
+}
+
+/// Every output file under `dir`, oldest first, leaving out tetrad's own log.
+pub fn list_log_files(dir: &Path) -> Vec<LogFile> {
+    let mut files = Vec::new();
+    collect_log_files(dir, &mut files);
+    files.sort_by_key(|file| file.modified);
+    files
+}
+
+/// Deletes the oldest files under `dir` until `bytes` have been freed. Files
+/// written since `keep_since` belong to the running session and are left
+/// alone. Returns the number of bytes freed.
+pub fn delete_oldest(dir: &Path, bytes: u64, keep_since: SystemTime) -> u64 {
+    let mut freed = 0;
+    for file in list_log_files(dir) {
+        if freed >= bytes || file.modified >= keep_since {
+            break;
+        }
+        match std::fs::remove_file(&file.path) {
+            Ok(()) => {
+                log::info!("Deleted {:?} to free up disk space", file.path);
+                freed += file.size;
+            }
+            Err(e) => log::warn!("Couldn't delete {:?}: {}", file.path, e),
+        }
+    }
+    freed
+}
+
+/// Deletes logs older than `max_log_age_days`, then the oldest of the rest
+/// while they add up to more than `max_log_total_gb`.
+pub fn apply_retention(config: &Config) {
+    let max_age = (config.max_log_age_days > 0.0)
+        .then(|| Duration::from_secs_f64(config.max_log_age_days * DAY));
+    let max_total =
+        (config.max_log_total_gb > 0.0).then(|| (config.max_log_total_gb * GIGABYTE) as u64);
+    if max_age.is_none() && max_total.is_none() {
+        return;
+    }
+    let now = SystemTime::now();
+    let files = list_log_files(&config.log_dir());
+    let mut total: u64 = files.iter().map(|file| file.size).sum();
+    let (mut deleted, mut freed) = (0, 0);
+    for file in files {
+        let too_old = max_age.map_or(false, |max_age| {
+            now.duration_since(file.modified).unwrap_or_default() > max_age
+        });
+        let too_big = max_total.map_or(false, |max_total| total > max_total);
+        if !too_old && !too_big {
+            // the remaining files are all newer, and the total only goes down
+            break;
+        }
+        match std::fs::remove_file(&file.path) {
+            Ok(()) => {
+                log::debug!("Retention policy deleted {:?}", file.path);
+                deleted += 1;
+                freed += file.size;
+                total -= file.size;
+            }
+            Err(e) => log::warn!("Couldn't delete {:?}: {}", file.path, e),
+        }
+    }
+    if deleted > 0 {
+        log::info!(
+            "Retention policy deleted {} old log files ({} MB)",
+            deleted,
+            freed / MEGABYTE
+        );
+    }
+}
+
+/// Checks the free space on the `write_dir` drive, deleting old logs first if
+/// the config allows it. Returns the free space in megabytes if it's still
+/// below `min_free_disk_mb`.
+pub fn check(config: &Config, keep_since: SystemTime) -> Option<u64> {
+    if config.min_free_disk_mb <= 0.0 {
+        return None;
+    }
+    let min_free = (config.min_free_disk_mb * MEGABYTE as f64) as u64;
+    let mut free = match free_space(Path::new(&config.write_dir)) {
+        Ok(free) => free,
+        Err(e) => {
+            log::warn!("Couldn't check free disk space: {}", e);
+            return None;
+        }
+    };
+    if free < min_free && config.delete_old_logs_when_low {
+        free += delete_oldest(&config.log_dir(), min_free - free, keep_since);
+    }
+    (free < min_free).then_some(free / MEGABYTE)
+}
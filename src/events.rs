@@ -0,0 +1,118 @@
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Connect,
+    Disconnect,
+    SlotChange,
+    Kick,
+    Ban,
+    Pause,
+    Resume,
+    SlingLoadPickup,
+    SlingLoadDrop,
+    TroopsEmbark,
+    TroopsDisembark,
+    Kill,
+    FriendlyFire,
+    SelfKill,
+    Crash,
+    Eject,
+    PilotDeath,
+    Takeoff,
+    Landing,
+    /// Weapon shots, hits and kills from the mission's world events, see
+    /// combat.rs.
+    Shot,
+    Hit,
+    UnitKill,
+    MissionEnd,
+    /// Any other DCS GameGUI callback, see callbacks.rs.
+    Callback,
+    /// A label set by a script, see markers.rs.
+    Marker,
+    /// An `alerts` rule with the event action went off, see alerts.rs.
+    Alert,
+}
+
+/// A single record in the events stream. `details` holds whatever free-form
+/// context the event has (disconnect reason, etc.).
+#[derive(Debug, Clone, Serialize)]
+pub struct Event {
+    pub t_game: f64,
+    pub t_real: f64,
+    pub kind: EventKind,
+    pub player_id: i32,
+    pub player_name: String,
+    pub details: String,
+    /// Wall-clock time, UTC milliseconds since the Unix epoch.
+    pub epoch_ms: i64,
+}
+
+/// Names `args` after the parameters DCS documents for `onGameEvent`.
+fn describe(names: &[&str], args: &[String]) -> String {
+    names
+        .iter()
+        .zip(args)
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Classifies an `onGameEvent` callback, returning the event kind, the
+/// player it's about and its details. Returns None for events tetrad already
+/// gets through their own callbacks (connect, disconnect, change_slot).
+pub fn from_game_event(name: &str, args: &[String]) -> Option<(EventKind, i32, String)> {
+    let player_id = args.first().and_then(|id| id.parse().ok()).unwrap_or(0);
+    let rest = args.get(1..).unwrap_or_default();
+    let (kind, details) = match name {
+        "kill" => (
+            EventKind::Kill,
+            describe(
+                &[
+                    "killer_type",
+                    "killer_side",
+                    "victim_id",
+                    "victim_type",
+                    "victim_side",
+                    "weapon",
+                ],
+                rest,
+            ),
+        ),
+        "friendly_fire" => (
+            EventKind::FriendlyFire,
+            describe(&["weapon", "victim_id"], rest),
+        ),
+        "self_kill" => (EventKind::SelfKill, "".to_string()),
+        "crash" => (EventKind::Crash, describe(&["unit_id"], rest)),
+        "eject" => (EventKind::Eject, describe(&["unit_id"], rest)),
+        "pilot_death" => (EventKind::PilotDeath, describe(&["unit_id"], rest)),
+        "takeoff" => (EventKind::Takeoff, describe(&["unit_id", "airdrome"], rest)),
+        "landing" => (EventKind::Landing, describe(&["unit_id", "airdrome"], rest)),
+        // no player here, the first argument is the winning side
+        "mission_end" => {
+            return Some((
+                EventKind::MissionEnd,
+                0,
+                describe(&["winner", "message"], args),
+            ))
+        }
+        "connect" | "disconnect" | "change_slot" => return None,
+        _ => (
+            EventKind::Callback,
+            format!("onGameEvent({}, {})", name, args.join(", ")),
+        ),
+    };
+    Some((kind, player_id, details))
+}
+
+impl Event {
+    pub fn is_admin_action(&self) -> bool {
+        matches!(
+            self.kind,
+            EventKind::Kick | EventKind::Ban | EventKind::Pause | EventKind::Resume
+        )
+    }
+}
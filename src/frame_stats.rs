@@ -0,0 +1,85 @@
+// Rolling frame-time distribution for the GUI. A fixed-width histogram of the
+// last `window` frames is kept up to date as frames come and go, so the
+// percentiles don't need a sort on every repaint.
+use bounded_vec_deque::BoundedVecDeque;
+
+pub const BIN_WIDTH_MS: f64 = 1.0;
+// The last bin collects everything slower.
+const NUM_BINS: usize = 250;
+
+pub struct FrameTimeStats {
+    /// Bin index of each frame in the window, oldest first.
+    samples: BoundedVecDeque<usize>,
+    bins: Vec<u32>,
+}
+
+impl FrameTimeStats {
+    pub fn new(window: usize) -> Self {
+        Self {
+            samples: BoundedVecDeque::new(window),
+            bins: vec![0; NUM_BINS],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+        self.bins.iter_mut().for_each(|b| *b = 0);
+    }
+
+    pub fn push(&mut self, frame_time_ms: f64) {
+        let bin = ((frame_time_ms.max(0.0) / BIN_WIDTH_MS) as usize).min(NUM_BINS - 1);
+        if let Some(evicted) = self.samples.push_back(bin) {
+            self.bins[evicted] -= 1;
+        }
+        self.bins[bin] += 1;
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Frame time in ms that a fraction `p` (0 to 1) of the window is at or
+    /// below, rounded up to a bin edge.
+    pub fn percentile(&self, p: f64) -> Option<f64> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let target = ((p * self.len() as f64).ceil() as usize).max(1);
+        let mut seen = 0;
+        for (bin, count) in self.bins.iter().enumerate() {
+            seen += *count as usize;
+            if seen >= target {
+                return Some((bin + 1) as f64 * BIN_WIDTH_MS);
+            }
+        }
+        None
+    }
+
+    /// Average frame rate over the slowest 1% of frames in the window.
+    pub fn one_percent_low_fps(&self) -> Option<f64> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let worst = ((self.len() as f64 * 0.01).ceil() as usize).max(1);
+        let mut remaining = worst;
+        let mut total_ms = 0.0;
+        for (bin, count) in self.bins.iter().enumerate().rev() {
+            let taken = remaining.min(*count as usize);
+            total_ms += taken as f64 * (bin as f64 + 0.5) * BIN_WIDTH_MS;
+            remaining -= taken;
+            if remaining == 0 {
+                break;
+            }
+        }
+        Some(1000.0 * worst as f64 / total_ms)
+    }
+
+    /// Start of each non-empty bin in ms, with its frame count.
+    pub fn histogram(&self) -> impl Iterator<Item = (f64, u32)> + '_ {
+        self.bins
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(bin, count)| (bin as f64 * BIN_WIDTH_MS, *count))
+    }
+}
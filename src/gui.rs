@@ -1,53 +1,323 @@
-use crate::dcs::{DcsWorldObject, DcsWorldUnit};
+use crate::config::Config;
+use crate::dcs::{DcsWorldObject, DcsWorldUnit, ObjectCategory};
+use crate::frame_stats::{self, FrameTimeStats};
+use crate::map::{self, MapObject};
+use crate::markers::Marker;
+use crate::netstats::PingSummary;
+use crate::notification::{Notification, Severity};
+use crate::perf_monitor::MemoryUsage;
+use crate::replay::ReplayPanel;
+use crate::session_replay::SessionReplay;
 use bounded_vec_deque::BoundedVecDeque;
-use egui::plot::{Corner, Legend, Line, Plot, PlotPoints};
+use egui::plot::{
+    Bar, BarChart, Corner, Legend, Line, Plot, PlotPoint, PlotPoints, PlotUi, Text, VLine,
+};
 use egui::{self, Vec2};
+use std::path::Path;
 use std::sync::{
     atomic::AtomicBool,
     mpsc::{Receiver, Sender},
     Arc,
 };
-
-use winit::platform::windows::EventLoopBuilderExtWindows;
+use std::time::{Duration, Instant};
 
 #[derive(Default)]
 pub struct GuiInterface {}
 
 pub type ArcFlag = Arc<AtomicBool>;
+pub use egui::Context;
 
 struct Gui {
-    rx: &'static Receiver<Message>,
-    num_units: BoundedVecDeque<i32>,
-    num_ballistics: BoundedVecDeque<i32>,
+    rx: Receiver<Message>,
+    tx_to_main: Sender<ClientMessage>,
+    num_units: BoundedVecDeque<ObjectCounts>,
+    num_ballistics: BoundedVecDeque<ObjectCounts>,
     game_times: BoundedVecDeque<f64>,
     real_times: BoundedVecDeque<f64>,
+    /// Seconds tetrad spent in each frame.
+    lib_times: BoundedVecDeque<f64>,
+    memory: BoundedVecDeque<MemoryUsage>,
+    /// Markers within the plotted frames, oldest first.
+    markers: Vec<Marker>,
+    /// From the last network summary, see `net_stats_interval`.
+    pings: Option<PingSummary>,
+    update_notice: Option<String>,
+    notifications: Vec<ReceivedNotification>,
+    replay: ReplayPanel,
+    /// Set when viewing a recorded session rather than a live mission.
+    session: Option<SessionReplay>,
+    /// Frames of `session` currently in the plots.
+    session_frames_shown: usize,
+    map_objects: Vec<MapObject>,
+    filter: ObjectFilter,
+    frame_stats: FrameTimeStats,
+    plot_history: usize,
+    /// Settings of the running mission, as edited in the settings panel.
+    settings: Option<Config>,
+    stutter_threshold: f64,
+    stutters: Vec<Stutter>,
+    close_requested: bool,
+}
+
+/// A frame that took much longer than the ones before it.
+struct Stutter {
+    game_time: f64,
+    duration: f64,
+    units: usize,
+    ballistics: usize,
+}
+
+const NUM_COALITIONS: usize = 3;
+
+/// Object counts for one frame, by coalition id (neutral, red, blue) and
+/// category.
+#[derive(Clone, Copy, Default)]
+struct ObjectCounts {
+    by_class: [[i32; ObjectCategory::ALL.len()]; NUM_COALITIONS],
+    /// Objects from a replayed frame log, which only has totals. They pass
+    /// every filter.
+    unclassified: i32,
+}
+
+impl ObjectCounts {
+    fn tally<'a>(objects: impl Iterator<Item = &'a DcsWorldObject>) -> Self {
+        let mut counts = Self::default();
+        for obj in objects {
+            let coalition = obj.coalition_id().clamp(0, NUM_COALITIONS as i32 - 1) as usize;
+            counts.by_class[coalition][obj.category() as usize] += 1;
+        }
+        counts
+    }
+
+    fn unclassified(count: usize) -> Self {
+        Self {
+            unclassified: count as i32,
+            ..Default::default()
+        }
+    }
+
+    fn total(&self, filter: &ObjectFilter) -> i32 {
+        let mut total = self.unclassified;
+        for (coalition, by_category) in self.by_class.iter().enumerate() {
+            if !filter.coalitions[coalition] {
+                continue;
+            }
+            for category in ObjectCategory::ALL {
+                if filter.category.map_or(true, |c| c == category) {
+                    total += by_category[category as usize];
+                }
+            }
+        }
+        total
+    }
+}
+
+struct ObjectFilter {
+    /// Indexed by coalition id: neutral, red, blue.
+    coalitions: [bool; NUM_COALITIONS],
+    /// `None` shows every category.
+    category: Option<ObjectCategory>,
+}
+
+impl Default for ObjectFilter {
+    fn default() -> Self {
+        Self {
+            coalitions: [true; NUM_COALITIONS],
+            category: None,
+        }
+    }
+}
+
+struct ReceivedNotification {
+    received: Instant,
+    timestamp: chrono::DateTime<chrono::Local>,
+    notification: Notification,
 }
 
 const PLOT_NUM_PTS: usize = 2048;
+const TOAST_DURATION: Duration = Duration::from_secs(6);
+const MAX_NOTIFICATION_HISTORY: usize = 200;
+const FRAME_STATS_WINDOW: usize = 10_000;
+const MAX_PLOT_HISTORY: usize = 500_000;
+// Longer series are downsampled to about this many points before drawing.
+const MAX_PLOT_POINTS: usize = 2000;
+// Frames averaged to decide whether a frame is a stutter.
+const STUTTER_AVERAGE_FRAMES: usize = 120;
+const MARKER_COLOR: egui::Color32 = egui::Color32::LIGHT_YELLOW;
+const MAX_STUTTER_HISTORY: usize = 1000;
 
 pub enum Message {
-    Start(egui::Context),
+    /// A new mission started, or the window was asked to open again.
+    Start,
+    Configure(Config),
     Update {
         units: Arc<Vec<DcsWorldUnit>>,
         ballistics: Arc<Vec<DcsWorldObject>>,
         game_time: f64,
         real_time: f64,
+        /// Seconds tetrad spent in the previous frame's callback.
+        lib_time: f64,
+        memory: MemoryUsage,
     },
+    /// A marker set by a script, drawn on the plots.
+    Marker(Marker),
+    /// Server-wide ping of the last network summary.
+    Pings(PingSummary),
+    UpdateAvailable(String),
+    Notify(Notification),
+    Close,
 }
 
 pub enum ClientMessage {
     ThreadStarted(ArcFlag),
+    ConfigChanged(Config),
+    /// Open the window again if it was closed.
+    ShowWindow,
+    CloseWindow,
+    /// Close the window and stop sending it data until it's shown again.
+    QuitGui,
 }
 
 impl Gui {
-    pub fn new(rx: &'static Receiver<Message>) -> Self {
+    pub fn new(rx: Receiver<Message>, tx_to_main: Sender<ClientMessage>) -> Self {
         Self {
             rx,
+            tx_to_main,
             num_units: BoundedVecDeque::new(PLOT_NUM_PTS),
             num_ballistics: BoundedVecDeque::new(PLOT_NUM_PTS),
             game_times: BoundedVecDeque::new(PLOT_NUM_PTS),
             real_times: BoundedVecDeque::new(PLOT_NUM_PTS),
+            lib_times: BoundedVecDeque::new(PLOT_NUM_PTS),
+            memory: BoundedVecDeque::new(PLOT_NUM_PTS),
+            markers: Vec::new(),
+            pings: None,
+            update_notice: None,
+            notifications: Vec::new(),
+            replay: ReplayPanel::default(),
+            session: None,
+            session_frames_shown: 0,
+            map_objects: Vec::new(),
+            filter: ObjectFilter::default(),
+            frame_stats: FrameTimeStats::new(FRAME_STATS_WINDOW),
+            plot_history: PLOT_NUM_PTS,
+            settings: None,
+            stutter_threshold: 0.0,
+            stutters: Vec::new(),
+            close_requested: false,
+        }
+    }
+
+    fn set_plot_history(&mut self, plot_history: usize) {
+        let plot_history = plot_history.clamp(2, MAX_PLOT_HISTORY);
+        self.plot_history = plot_history;
+        // the newest samples are at the front, so this drops the oldest
+        drop(self.num_units.set_max_len(plot_history));
+        drop(self.num_ballistics.set_max_len(plot_history));
+        drop(self.game_times.set_max_len(plot_history));
+        drop(self.real_times.set_max_len(plot_history));
+        drop(self.lib_times.set_max_len(plot_history));
+        drop(self.memory.set_max_len(plot_history));
+    }
+
+    fn with_session(mut self, session: Option<SessionReplay>) -> Self {
+        if let Some(recording) = session.as_ref().and_then(|s| s.recording.as_ref()) {
+            self.replay.open(recording);
         }
+        self.session = session;
+        self.show_session_frames();
+        self
+    }
+
+    fn clear_frames(&mut self) {
+        self.num_ballistics.clear();
+        self.num_units.clear();
+        self.game_times.clear();
+        self.real_times.clear();
+        self.lib_times.clear();
+        self.memory.clear();
+        self.markers.clear();
+        self.pings = None;
+        self.map_objects.clear();
+        self.frame_stats.clear();
+        self.stutters.clear();
+    }
+
+    fn push_frame(
+        &mut self,
+        units: ObjectCounts,
+        ballistics: ObjectCounts,
+        game_time: f64,
+        real_time: f64,
+        lib_time: f64,
+        memory: MemoryUsage,
+    ) {
+        let all = ObjectFilter::default();
+        self.check_for_stutter(
+            game_time,
+            units.total(&all) as usize,
+            ballistics.total(&all) as usize,
+        );
+        self.num_units.push_front(units);
+        self.num_ballistics.push_front(ballistics);
+        if let Some(last_real_time) = self.real_times.front() {
+            self.frame_stats.push((real_time - last_real_time) * 1000.0);
+        }
+        self.game_times.push_front(game_time);
+        self.real_times.push_front(real_time);
+        self.lib_times.push_front(lib_time);
+        self.memory.push_front(memory);
+        // markers scroll off the plots with their frames
+        if let Some(oldest) = self.game_times.back() {
+            let expired = self.markers.partition_point(|m| m.t_game < *oldest);
+            self.markers.drain(..expired);
+        }
+    }
+
+    /// Brings the plots up to the replayed session's current time. Playing
+    /// forward only adds the new frames; anything else redraws the last
+    /// `plot_history` frames from scratch.
+    fn show_session_frames(&mut self) {
+        let Some(session) = self.session.take() else {
+            return;
+        };
+        let end = session.frames_shown();
+        let mut start = self.session_frames_shown;
+        if end < start {
+            self.clear_frames();
+            start = 0;
+        }
+        start = start.max(end.saturating_sub(self.plot_history));
+        if start > self.session_frames_shown {
+            self.clear_frames();
+        }
+        for frame in session.frames[start..end].iter() {
+            self.push_frame(
+                ObjectCounts::unclassified(frame.units),
+                ObjectCounts::unclassified(frame.ballistics),
+                frame.t_game,
+                frame.t_real,
+                frame.lib_time,
+                MemoryUsage::from(frame),
+            );
+            if !frame.markers.is_empty() {
+                self.markers.push(Marker::new(frame.t_game, &frame.markers));
+            }
+        }
+        self.session_frames_shown = end;
+        self.session = Some(session);
+    }
+
+    fn show_session(&mut self, ui: &mut egui::Ui) {
+        let Some(session) = self.session.as_mut() else {
+            return;
+        };
+        let t = session.time();
+        session.show(ui);
+        // the recording on the map follows along
+        if session.time() != t {
+            self.replay.seek(session.time());
+        }
+        self.show_session_frames();
     }
 
     fn handle_messages(&mut self) {
@@ -58,36 +328,313 @@ impl Gui {
 
     fn handle_message(&mut self, msg: Message) {
         match msg {
-            Message::Start(_context) => {
-                self.num_ballistics.clear();
-                self.num_units.clear();
-                self.game_times.clear();
+            Message::Start => self.clear_frames(),
+            Message::Configure(config) => {
+                self.set_plot_history(config.gui_plot_history);
+                self.stutter_threshold = config.stutter_threshold;
+                self.settings = Some(config);
             }
             Message::Update {
                 units,
                 ballistics,
                 game_time,
                 real_time,
+                lib_time,
+                memory,
             } => {
-                self.num_units.push_front(units.len() as i32);
-                self.num_ballistics.push_front(ballistics.len() as i32);
-                self.game_times.push_front(game_time);
-                self.real_times.push_front(real_time);
+                self.push_frame(
+                    ObjectCounts::tally(units.iter().map(|u| u.object())),
+                    ObjectCounts::tally(ballistics.iter()),
+                    game_time,
+                    real_time,
+                    lib_time,
+                    memory,
+                );
+                self.map_objects = units
+                    .iter()
+                    .map(|u| make_map_object(u.object(), true))
+                    .chain(ballistics.iter().map(|b| make_map_object(b, false)))
+                    .collect();
+            }
+            Message::Marker(marker) => self.markers.push(marker),
+            Message::Pings(pings) => self.pings = Some(pings),
+            Message::UpdateAvailable(version) => {
+                self.update_notice = Some(version);
+            }
+            Message::Close => {
+                self.close_requested = true;
+            }
+            Message::Notify(notification) => {
+                if self.notifications.len() >= MAX_NOTIFICATION_HISTORY {
+                    self.notifications.remove(0);
+                }
+                self.notifications.push(ReceivedNotification {
+                    received: Instant::now(),
+                    timestamp: chrono::Local::now(),
+                    notification,
+                });
             }
         };
     }
+
+    fn check_for_stutter(&mut self, game_time: f64, units: usize, ballistics: usize) {
+        if self.stutter_threshold <= 0.0 {
+            return;
+        }
+        let (Some(last_time), Some(average)) = (
+            self.game_times.front(),
+            average_frame_time(&self.game_times),
+        ) else {
+            return;
+        };
+        let duration = game_time - last_time;
+        if duration <= self.stutter_threshold * average {
+            return;
+        }
+        if self.stutters.len() >= MAX_STUTTER_HISTORY {
+            self.stutters.remove(0);
+        }
+        self.stutters.push(Stutter {
+            game_time,
+            duration,
+            units,
+            ballistics,
+        });
+    }
+
+    fn show_stutters(&self, ui: &mut egui::Ui) {
+        let title = format!("Stutters ({})", self.stutters.len());
+        egui::CollapsingHeader::new(title).show(ui, |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(200.0)
+                .show(ui, |ui| {
+                    egui::Grid::new("stutters").striped(true).show(ui, |ui| {
+                        ui.strong("Mission time (s)");
+                        ui.strong("Duration (ms)");
+                        ui.strong("Units");
+                        ui.strong("Ballistics");
+                        ui.end_row();
+                        for s in self.stutters.iter().rev() {
+                            ui.label(format!("{:.2}", s.game_time));
+                            ui.label(format!("{:.1}", s.duration * 1000.0));
+                            ui.label(s.units.to_string());
+                            ui.label(s.ballistics.to_string());
+                            ui.end_row();
+                        }
+                    });
+                });
+        });
+    }
+
+    fn show_toasts(&self, ctx: &egui::Context) {
+        let active: Vec<&ReceivedNotification> = self
+            .notifications
+            .iter()
+            .filter(|n| n.received.elapsed() < TOAST_DURATION)
+            .collect();
+        if active.is_empty() {
+            return;
+        }
+        egui::Area::new("toasts")
+            .anchor(egui::Align2::RIGHT_TOP, Vec2 { x: -16.0, y: 16.0 })
+            .show(ctx, |ui| {
+                for n in active {
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.colored_label(
+                            severity_color(n.notification.severity),
+                            &n.notification.text,
+                        );
+                    });
+                }
+            });
+        // come back to take the toasts down even if no new data arrives
+        ctx.request_repaint_after(Duration::from_millis(250));
+    }
+
+    fn show_settings(&mut self, ui: &mut egui::Ui) {
+        let Some(settings) = self.settings.as_mut() else {
+            ui.label("Waiting for a mission to start");
+            return;
+        };
+        ui.checkbox(&mut settings.enable_framerate_log, "Log frame rate");
+        ui.checkbox(&mut settings.enable_object_log, "Log objects");
+        ui.checkbox(&mut settings.debug, "Debug logging");
+        ui.horizontal(|ui| {
+            ui.add(
+                egui::DragValue::new(&mut settings.gui_update_interval)
+                    .speed(0.01)
+                    .clamp_range(-1.0..=10.0),
+            );
+            ui.label("GUI refresh interval (s), 0 or less redraws every frame");
+        });
+        if ui.button("Apply").clicked() {
+            self.tx_to_main
+                .send(ClientMessage::ConfigChanged(settings.clone()))
+                .unwrap_or(());
+        }
+    }
+
+    fn show_plot_history(&mut self, ui: &mut egui::Ui) {
+        let mut plot_history = self.plot_history;
+        let slider = egui::Slider::new(&mut plot_history, 256..=MAX_PLOT_HISTORY)
+            .logarithmic(true)
+            .text("frames of plot history");
+        if ui.add(slider).changed() {
+            self.set_plot_history(plot_history);
+            if self.session.is_some() {
+                // older frames than were kept may fit now
+                self.session_frames_shown = 0;
+                self.clear_frames();
+                self.show_session_frames();
+            }
+        }
+    }
+
+    fn show_filter(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Show:");
+            ui.checkbox(&mut self.filter.coalitions[1], "Red");
+            ui.checkbox(&mut self.filter.coalitions[2], "Blue");
+            ui.checkbox(&mut self.filter.coalitions[0], "Neutral");
+            let selected = self.filter.category.map_or("All", |c| c.label());
+            egui::ComboBox::from_label("Category")
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.filter.category, None, "All");
+                    for category in ObjectCategory::ALL {
+                        ui.selectable_value(
+                            &mut self.filter.category,
+                            Some(category),
+                            category.label(),
+                        );
+                    }
+                });
+        });
+    }
+
+    fn show_frame_time_stats(&self, ui: &mut egui::Ui) {
+        let ms = |p: f64| {
+            self.frame_stats
+                .percentile(p)
+                .map_or("-".to_string(), |t| format!("{:.0} ms", t))
+        };
+        let one_percent_low = self
+            .frame_stats
+            .one_percent_low_fps()
+            .map_or("-".to_string(), |fps| format!("{:.1} FPS", fps));
+        ui.heading(format!(
+            "Real frame times over the last {} frames: p50 {}, p95 {}, p99 {}, 1% low {}",
+            self.frame_stats.len(),
+            ms(0.50),
+            ms(0.95),
+            ms(0.99),
+            one_percent_low
+        ));
+        ui.end_row();
+
+        let bars: Vec<Bar> = self
+            .frame_stats
+            .histogram()
+            .map(|(t, count)| {
+                Bar::new(t + frame_stats::BIN_WIDTH_MS / 2.0, count as f64)
+                    .width(frame_stats::BIN_WIDTH_MS)
+            })
+            .collect();
+        Plot::new("Frame time histogram")
+            .width(1792.0)
+            .height(256.0)
+            .show(ui, |plot_ui| {
+                plot_ui.bar_chart(BarChart::new(bars).name("Frames per ms"))
+            });
+        ui.end_row();
+    }
+
+    fn show_notification_history(&self, ui: &mut egui::Ui) {
+        let title = format!("Notifications ({})", self.notifications.len());
+        egui::CollapsingHeader::new(title).show(ui, |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(150.0)
+                .show(ui, |ui| {
+                    for n in self.notifications.iter().rev() {
+                        ui.colored_label(
+                            severity_color(n.notification.severity),
+                            format!(
+                                "[{}] {}",
+                                n.timestamp.format("%H:%M:%S"),
+                                n.notification.text
+                            ),
+                        );
+                    }
+                });
+        });
+    }
+}
+
+fn severity_color(severity: Severity) -> egui::Color32 {
+    match severity {
+        Severity::Info => egui::Color32::LIGHT_GRAY,
+        Severity::Warning => egui::Color32::YELLOW,
+        Severity::Error => egui::Color32::RED,
+    }
+}
+
+fn make_map_object(object: &DcsWorldObject, is_unit: bool) -> MapObject {
+    let (north, east) = object.map_position();
+    MapObject {
+        east,
+        north,
+        coalition_id: object.coalition_id(),
+        is_unit,
+    }
 }
 
-fn make_obj_count_line(v: &BoundedVecDeque<i32>, times: &BoundedVecDeque<f64>, name: &str) -> Line {
-    let pts: PlotPoints = v
+fn make_obj_count_line(
+    v: &BoundedVecDeque<ObjectCounts>,
+    filter: &ObjectFilter,
+    times: &BoundedVecDeque<f64>,
+    name: &str,
+) -> Line {
+    let pts: Vec<[f64; 2]> = v
         .iter()
         .enumerate()
-        .map(|(idx, y)| [times[idx], *y as f64])
+        .map(|(idx, counts)| [times[idx], counts.total(filter) as f64])
         .collect();
-    let line = Line::new(pts).name(name);
+    let line = Line::new(PlotPoints::from(downsample(pts))).name(name);
     line
 }
 
+/// Reduces a long series to about `MAX_PLOT_POINTS` by keeping only the
+/// lowest and highest point of each bucket of neighbours, so spikes still show.
+fn downsample(points: Vec<[f64; 2]>) -> Vec<[f64; 2]> {
+    if points.len() <= MAX_PLOT_POINTS {
+        return points;
+    }
+    let bucket_size = (2 * points.len()).div_ceil(MAX_PLOT_POINTS);
+    let mut out = Vec::with_capacity(MAX_PLOT_POINTS + 2);
+    for bucket in points.chunks(bucket_size) {
+        let by_y = |a: &(usize, &[f64; 2]), b: &(usize, &[f64; 2])| a.1[1].total_cmp(&b.1[1]);
+        let (i_min, min) = bucket.iter().enumerate().min_by(by_y).unwrap();
+        let (i_max, max) = bucket.iter().enumerate().max_by(by_y).unwrap();
+        match i_min.cmp(&i_max) {
+            std::cmp::Ordering::Less => out.extend([*min, *max]),
+            std::cmp::Ordering::Greater => out.extend([*max, *min]),
+            std::cmp::Ordering::Equal => out.push(*min),
+        }
+    }
+    out
+}
+
+/// Mean game time step over the last `STUTTER_AVERAGE_FRAMES` frames, once
+/// there are enough of them.
+fn average_frame_time(game_times: &BoundedVecDeque<f64>) -> Option<f64> {
+    let n = STUTTER_AVERAGE_FRAMES.min(game_times.len().saturating_sub(1));
+    if n < STUTTER_AVERAGE_FRAMES / 4 {
+        return None;
+    }
+    let average = (game_times[0] - game_times[n]) / n as f64;
+    (average > 0.0).then_some(average)
+}
+
 fn get_indexed<T>(q: &BoundedVecDeque<T>, index: isize) -> Option<&T> {
     let i = if index < 0 {
         let l = q.len() as isize;
@@ -115,7 +662,7 @@ fn make_time_line(
     for idx in 1..times.len() {
         time_pairs.push([ref_times[idx], times[idx - 1] - times[idx]]);
     }
-    let fps_pts: PlotPoints = time_pairs
+    let fps_pts: Vec<[f64; 2]> = time_pairs
         .iter()
         .map(|[t, dt]| {
             let mut inv = 1.0 / *dt;
@@ -125,33 +672,100 @@ fn make_time_line(
             [*t, inv]
         })
         .collect();
-    let time_line = Line::new(time_pairs).name(name);
-    let fps_line = Line::new(fps_pts).name(name);
+    let time_line = Line::new(PlotPoints::from(downsample(time_pairs))).name(name);
+    let fps_line = Line::new(PlotPoints::from(downsample(fps_pts))).name(name);
     (time_line, fps_line)
 }
 
+fn make_value_line(
+    values: &BoundedVecDeque<f64>,
+    times: &BoundedVecDeque<f64>,
+    name: &str,
+) -> Line {
+    let pts: Vec<[f64; 2]> = values
+        .iter()
+        .enumerate()
+        .map(|(idx, v)| [times[idx], *v])
+        .collect();
+    Line::new(PlotPoints::from(downsample(pts))).name(name)
+}
+
+fn make_memory_line(
+    memory: &BoundedVecDeque<MemoryUsage>,
+    times: &BoundedVecDeque<f64>,
+    name: &str,
+    bytes: impl Fn(&MemoryUsage) -> u64,
+) -> Line {
+    let pts: Vec<[f64; 2]> = memory
+        .iter()
+        .enumerate()
+        .map(|(idx, m)| [times[idx], bytes(m) as f64 / (1024.0 * 1024.0)])
+        .collect();
+    Line::new(PlotPoints::from(downsample(pts))).name(name)
+}
+
+/// Vertical lines at the markers' mission times, labeled along the top of the
+/// plot.
+fn show_markers(plot_ui: &mut PlotUi, markers: &[Marker]) {
+    let top = plot_ui.plot_bounds().max()[1];
+    for marker in markers {
+        plot_ui.vline(VLine::new(marker.t_game).color(MARKER_COLOR));
+        plot_ui.text(
+            Text::new(PlotPoint::new(marker.t_game, top), marker.label.as_str())
+                .color(MARKER_COLOR)
+                .anchor(egui::Align2::LEFT_TOP),
+        );
+    }
+}
+
 impl eframe::App for Gui {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
         egui::CentralPanel::default().show(ctx, |ui| {
             self.handle_messages();
 
-            ui.heading("Server Monitor");
+            if self.session.is_some() {
+                ui.heading("Session Replay");
+                self.show_session(ui);
+            } else {
+                ui.heading("Server Monitor");
+            }
+            if let Some(version) = &self.update_notice {
+                ui.colored_label(
+                    egui::Color32::YELLOW,
+                    format!("Tetrad {} is available", version),
+                );
+            }
+            self.show_notification_history(ui);
+            self.show_stutters(ui);
+            egui::CollapsingHeader::new("Map").show(ui, |ui| {
+                map::show_map(ui, "live_map", &self.map_objects, 1792.0, 768.0)
+            });
+            egui::CollapsingHeader::new("Replay").show(ui, |ui| self.replay.show(ui));
+            egui::CollapsingHeader::new("Settings").show(ui, |ui| self.show_settings(ui));
+            ui.horizontal(|ui| {
+                self.show_filter(ui);
+                self.show_plot_history(ui);
+            });
 
             egui::Grid::new("main_grid").show(ui, |ui| {
                 ui.heading(format!(
                     "Active unit count: {}",
-                    self.num_units.front().unwrap_or(&0)
+                    self.num_units.front().map_or(0, |c| c.total(&self.filter))
                 ));
                 ui.end_row();
                 ui.heading(format!(
                     "Active ballistics count: {}",
-                    self.num_ballistics.front().unwrap_or(&0)
+                    self.num_ballistics
+                        .front()
+                        .map_or(0, |c| c.total(&self.filter))
                 ));
                 ui.end_row();
 
-                let u_line = make_obj_count_line(&self.num_units, &self.game_times, "Units");
+                let u_line =
+                    make_obj_count_line(&self.num_units, &self.filter, &self.game_times, "Units");
                 let b_line = make_obj_count_line(
                     &self.num_ballistics,
+                    &self.filter,
                     &self.game_times,
                     "Ballistic objects",
                 );
@@ -163,6 +777,7 @@ impl eframe::App for Gui {
                     .show(ui, |plot_ui| {
                         plot_ui.line(u_line);
                         plot_ui.line(b_line);
+                        show_markers(plot_ui, &self.markers);
                     });
                 ui.end_row();
 
@@ -173,10 +788,16 @@ impl eframe::App for Gui {
                     last_frame_game_time_ms, last_frame_real_time_ms
                 ));
                 ui.end_row();
+                ui.heading(format!(
+                    "Time spent in tetrad: {:0.03} ms",
+                    self.lib_times.front().unwrap_or(&0.0) * 1000.0
+                ));
+                ui.end_row();
                 let (game_time_line, game_time_fps_line) =
                     make_time_line(&self.game_times, &self.game_times, "Game time");
                 let (real_time_line, _real_time_fps_line) =
                     make_time_line(&self.game_times, &self.real_times, "Real time");
+                let lib_time_line = make_value_line(&self.lib_times, &self.game_times, "Tetrad");
 
                 Plot::new("Frame times")
                     .width(1792.0)
@@ -185,6 +806,8 @@ impl eframe::App for Gui {
                     .show(ui, |plot_ui| {
                         plot_ui.line(game_time_line);
                         plot_ui.line(real_time_line);
+                        plot_ui.line(lib_time_line);
+                        show_markers(plot_ui, &self.markers);
                     });
 
                 ui.end_row();
@@ -196,29 +819,81 @@ impl eframe::App for Gui {
                 Plot::new("FPS")
                     .width(1792.0)
                     .height(256.0)
-                    .show(ui, |plot_ui| plot_ui.line(game_time_fps_line));
+                    .show(ui, |plot_ui| {
+                        plot_ui.line(game_time_fps_line);
+                        show_markers(plot_ui, &self.markers);
+                    });
                 ui.end_row();
+
+                if let Some(pings) = &self.pings {
+                    ui.heading(format!(
+                        "Ping: {:.0} ms mean, p95 {:.0} ms over {} clients ({} outliers excluded, {:.0} ms mean with them)",
+                        pings.trimmed_mean,
+                        pings.p95,
+                        pings.num_clients,
+                        pings.excluded,
+                        pings.raw_mean
+                    ));
+                    ui.end_row();
+                }
+
+                let memory = self.memory.front().copied().unwrap_or_default();
+                ui.heading(format!(
+                    "Memory: {} MB working set, {} MB private, {} MB committed",
+                    memory.working_set / (1024 * 1024),
+                    memory.private_bytes / (1024 * 1024),
+                    memory.commit / (1024 * 1024)
+                ));
+                ui.end_row();
+                let working_set_line =
+                    make_memory_line(&self.memory, &self.game_times, "Working set", |m| {
+                        m.working_set
+                    });
+                let private_line =
+                    make_memory_line(&self.memory, &self.game_times, "Private bytes", |m| {
+                        m.private_bytes
+                    });
+                let commit_line =
+                    make_memory_line(&self.memory, &self.game_times, "Commit", |m| m.commit);
+
+                Plot::new("Memory (MB)")
+                    .width(1792.0)
+                    .height(256.0)
+                    .legend(Legend::default().position(Corner::RightBottom))
+                    .show(ui, |plot_ui| {
+                        plot_ui.line(working_set_line);
+                        plot_ui.line(private_line);
+                        plot_ui.line(commit_line);
+                        show_markers(plot_ui, &self.markers);
+                    });
+                ui.end_row();
+
+                self.show_frame_time_stats(ui);
             });
         });
+        self.show_toasts(ctx);
+        if self.close_requested {
+            frame.close();
+        }
     }
 }
 
-fn do_gui(rx: &Receiver<Message>, egui_context: egui::Context) {
+fn do_gui(
+    rx: Receiver<Message>,
+    tx_to_main: Sender<ClientMessage>,
+    egui_context: egui::Context,
+    session: Option<SessionReplay>,
+) {
     let mut native_options = eframe::NativeOptions::default();
-    native_options.event_loop_builder = Some(Box::new(|builder| {
-        log::debug!("Calling eframe event loop hook");
-        builder.with_any_thread(true);
-    }));
     native_options.renderer = eframe::Renderer::Wgpu;
     native_options.context = Some(egui_context);
     native_options.initial_window_size = Some(Vec2 {
         x: 1880.0,
         y: 256.0 * 4.0,
     });
-    log::info!("Spawning GUI thread");
-    let rx_forever: &'static Receiver<Message> = unsafe { std::mem::transmute(rx) };
+    log::info!("Opening the GUI window");
 
-    let gui = Gui::new(rx_forever);
+    let gui = Gui::new(rx, tx_to_main).with_session(session);
 
     eframe::run_native(
         "DCS Tetrad",
@@ -229,24 +904,27 @@ fn do_gui(rx: &Receiver<Message>, egui_context: egui::Context) {
     log::info!("Gui closed");
 }
 
-pub fn run(rx: Receiver<Message>, tx_to_main: Sender<ClientMessage>) {
-    let is_gui_shown = ArcFlag::new(AtomicBool::new(false));
-
-    let gui_thread_entry = {
-        move || loop {
-            log::debug!("Waiting for GUI start message");
-            tx_to_main
-                .send(ClientMessage::ThreadStarted(is_gui_shown.clone()))
-                .unwrap();
+/// Runs the GUI on the calling thread until its window is closed. This is
+/// `tetrad-gui.exe`, the library itself never opens a window, see `gui_ipc`.
+pub fn run_standalone(
+    rx: Receiver<Message>,
+    tx_to_main: Sender<ClientMessage>,
+    egui_context: egui::Context,
+) {
+    do_gui(rx, tx_to_main, egui_context, None);
+}
 
-            let msg = rx.recv().unwrap();
-            if let Message::Start(ctx) = msg {
-                log::debug!("Got a GUI start message");
-                is_gui_shown.store(true, std::sync::atomic::Ordering::SeqCst);
-                do_gui(&rx, ctx);
-                is_gui_shown.store(false, std::sync::atomic::Ordering::SeqCst);
-            }
-        }
-    };
-    std::thread::spawn(gui_thread_entry);
+/// Runs the GUI on the calling thread showing a recorded session instead of a
+/// live mission. `path` is a session manifest, a directory of them (the newest
+/// is used) or a CSV frame log.
+pub fn run_replay(
+    path: &Path,
+    egui_context: egui::Context,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let session = SessionReplay::open(path)?;
+    // nothing is ever sent in replay mode, but the GUI still polls for messages
+    let (_tx, rx) = std::sync::mpsc::channel();
+    let (tx_to_main, _rx_from_gui) = std::sync::mpsc::channel();
+    do_gui(rx, tx_to_main, egui_context, Some(session));
+    Ok(())
 }
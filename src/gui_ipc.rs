@@ -0,0 +1,394 @@
+// Runs the GUI in a child process (`tetrad-gui.exe`), so that wgpu doesn't
+// compete with DCS for the GPU and a crash in the wgpu/driver stack can't take
+// DCS down with it. The library never opens a window itself: `run` consumes the
+// `gui::Message`s from lib.rs and forwards them to the child, either over its
+// stdin or through a shared-memory ring buffer. Settings changed in the child
+// come back over its stdout.
+use crate::config::{Config, GuiTransport};
+use crate::dcs::{DcsWorldObject, DcsWorldUnit};
+use crate::gui::{ArcFlag, ClientMessage, Message};
+use crate::markers::Marker;
+use crate::netstats::PingSummary;
+use crate::notification::Notification;
+use crate::perf_monitor::MemoryUsage;
+use crate::shm_ring::ShmRing;
+use serde::{Deserialize, Serialize};
+use std::io::{BufWriter, Read, Write};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc::{Receiver, Sender},
+    Arc,
+};
+use std::time::{Duration, Instant};
+
+pub const GUI_EXE_NAME: &str = "tetrad-gui.exe";
+pub const SHM_ARG: &str = "--shm";
+
+const SHM_CAPACITY: usize = 64 * 1024 * 1024;
+const SHM_POLL_INTERVAL: Duration = Duration::from_millis(2);
+const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+#[derive(Serialize)]
+enum WireMessageRef<'a> {
+    Start,
+    Configure(&'a Config),
+    Update {
+        units: &'a [DcsWorldUnit],
+        ballistics: &'a [DcsWorldObject],
+        game_time: f64,
+        real_time: f64,
+        lib_time: f64,
+        memory: MemoryUsage,
+    },
+    Marker(&'a Marker),
+    Pings(&'a PingSummary),
+    UpdateAvailable(&'a str),
+    Notify(&'a Notification),
+    Close,
+}
+
+#[derive(Deserialize)]
+enum WireMessage {
+    Start,
+    Configure(Config),
+    Update {
+        units: Vec<DcsWorldUnit>,
+        ballistics: Vec<DcsWorldObject>,
+        game_time: f64,
+        real_time: f64,
+        lib_time: f64,
+        memory: MemoryUsage,
+    },
+    Marker(Marker),
+    Pings(PingSummary),
+    UpdateAvailable(String),
+    Notify(Notification),
+    Close,
+}
+
+/// Messages from the GUI process back to the library.
+#[derive(Serialize, Deserialize)]
+enum WireClientMessage {
+    ConfigChanged(Config),
+}
+
+enum Channel {
+    Pipe(BufWriter<ChildStdin>),
+    SharedMemory(ShmRing),
+}
+
+struct GuiProcess {
+    child: Child,
+    channel: Channel,
+}
+
+impl GuiProcess {
+    fn spawn(
+        exe: &PathBuf,
+        transport: GuiTransport,
+        tx_to_main: Sender<ClientMessage>,
+    ) -> std::io::Result<Self> {
+        use std::os::windows::process::CommandExt;
+
+        log::info!("Spawning GUI process {:?} using {:?}", exe, transport);
+        let mut command = Command::new(exe);
+        // no console window popping up over DCS
+        command.creation_flags(CREATE_NO_WINDOW);
+        let mut process = match transport {
+            GuiTransport::Pipe => {
+                let mut child = command
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .spawn()?;
+                let stdin = child.stdin.take().expect("Child stdin should be piped");
+                Self {
+                    child,
+                    channel: Channel::Pipe(BufWriter::new(stdin)),
+                }
+            }
+            GuiTransport::SharedMemory => {
+                let name = format!("Local\\tetrad-gui-{}", std::process::id());
+                let ring = ShmRing::create(&name, SHM_CAPACITY)
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+                let child = command
+                    .arg(SHM_ARG)
+                    .arg(&name)
+                    .stdout(Stdio::piped())
+                    .spawn()?;
+                Self {
+                    child,
+                    channel: Channel::SharedMemory(ring),
+                }
+            }
+        };
+        let stdout = process
+            .child
+            .stdout
+            .take()
+            .expect("Child stdout should be piped");
+        std::thread::spawn(move || receive_client_messages(stdout, tx_to_main));
+        Ok(process)
+    }
+
+    fn is_alive(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+
+    fn send(&mut self, msg: &Message) -> bincode::Result<()> {
+        let wire = match msg {
+            Message::Start => WireMessageRef::Start,
+            Message::Configure(config) => WireMessageRef::Configure(config),
+            Message::Update {
+                units,
+                ballistics,
+                game_time,
+                real_time,
+                lib_time,
+                memory,
+            } => WireMessageRef::Update {
+                units: units.as_slice(),
+                ballistics: ballistics.as_slice(),
+                game_time: *game_time,
+                real_time: *real_time,
+                lib_time: *lib_time,
+                memory: *memory,
+            },
+            Message::Marker(marker) => WireMessageRef::Marker(marker),
+            Message::Pings(pings) => WireMessageRef::Pings(pings),
+            Message::UpdateAvailable(version) => WireMessageRef::UpdateAvailable(version),
+            Message::Notify(notification) => WireMessageRef::Notify(notification),
+            Message::Close => WireMessageRef::Close,
+        };
+        // nothing will tell us the shared-memory reader has gone, so check explicitly
+        if matches!(self.channel, Channel::SharedMemory(_)) && !self.is_alive() {
+            return Err(Box::new(bincode::ErrorKind::Custom(
+                "GUI process exited".to_string(),
+            )));
+        }
+        match &mut self.channel {
+            Channel::Pipe(stdin) => {
+                bincode::serialize_into(&mut *stdin, &wire)?;
+                stdin.flush()?;
+            }
+            Channel::SharedMemory(ring) => {
+                // serialized straight into the mapping, no copy on the way
+                let len = bincode::serialized_size(&wire)? as usize;
+                if !ring.push_with(len, |writer| bincode::serialize_into(writer, &wire))? {
+                    log::trace!("GUI ring buffer full, dropping message");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn receive_client_messages(stdout: ChildStdout, tx_to_main: Sender<ClientMessage>) {
+    let mut reader = std::io::BufReader::new(stdout);
+    while let Ok(wire) = bincode::deserialize_from::<_, WireClientMessage>(&mut reader) {
+        let msg = match wire {
+            WireClientMessage::ConfigChanged(config) => ClientMessage::ConfigChanged(config),
+        };
+        if tx_to_main.send(msg).is_err() {
+            return;
+        }
+    }
+    log::debug!("GUI process output closed");
+}
+
+fn forward_messages(
+    rx: Receiver<Message>,
+    tx_to_main: Sender<ClientMessage>,
+    is_gui_shown: ArcFlag,
+    exe: PathBuf,
+    transport: GuiTransport,
+) {
+    let mut process: Option<GuiProcess> = None;
+    for msg in rx.iter() {
+        if let Message::Start = msg {
+            let running = process.as_mut().map_or(false, |p| p.is_alive());
+            if !running {
+                process = match GuiProcess::spawn(&exe, transport, tx_to_main.clone()) {
+                    Ok(p) => Some(p),
+                    Err(e) => {
+                        log::error!("Couldn't start GUI process {:?}: {}", exe, e);
+                        None
+                    }
+                };
+            }
+        }
+
+        let Some(p) = process.as_mut() else {
+            continue;
+        };
+        is_gui_shown.store(true, Ordering::SeqCst);
+        if let Err(e) = p.send(&msg) {
+            log::info!("GUI process went away ({}), no longer forwarding data", e);
+            is_gui_shown.store(false, Ordering::SeqCst);
+            process = None;
+        }
+    }
+    log::debug!("GUI forwarding thread RX dropped");
+}
+
+pub fn run(
+    rx: Receiver<Message>,
+    tx_to_main: Sender<ClientMessage>,
+    dll_path: &str,
+    transport: GuiTransport,
+) {
+    let is_gui_shown = ArcFlag::new(AtomicBool::new(false));
+    let exe = PathBuf::from(dll_path).join(GUI_EXE_NAME);
+    tx_to_main
+        .send(ClientMessage::ThreadStarted(is_gui_shown.clone()))
+        .unwrap();
+    std::thread::spawn(move || forward_messages(rx, tx_to_main, is_gui_shown, exe, transport));
+}
+
+fn to_message(wire: WireMessage) -> Message {
+    match wire {
+        WireMessage::Start => Message::Start,
+        WireMessage::Configure(config) => Message::Configure(config),
+        WireMessage::Update {
+            units,
+            ballistics,
+            game_time,
+            real_time,
+            lib_time,
+            memory,
+        } => Message::Update {
+            units: Arc::new(units),
+            ballistics: Arc::new(ballistics),
+            game_time,
+            real_time,
+            lib_time,
+            memory,
+        },
+        WireMessage::Marker(marker) => Message::Marker(marker),
+        WireMessage::Pings(pings) => Message::Pings(pings),
+        WireMessage::UpdateAvailable(version) => Message::UpdateAvailable(version),
+        WireMessage::Notify(notification) => Message::Notify(notification),
+        WireMessage::Close => Message::Close,
+    }
+}
+
+/// Child process side: whether the window needs repainting for a message.
+/// Frame updates repaint it at most every `gui_update_interval` seconds if
+/// that's positive, everything else straight away.
+#[derive(Default)]
+struct RepaintThrottle {
+    interval: f64,
+    last: Option<Instant>,
+}
+
+impl RepaintThrottle {
+    fn is_due(&mut self, msg: &Message) -> bool {
+        match msg {
+            Message::Configure(config) => self.interval = config.gui_update_interval,
+            Message::Update { .. } if self.interval > 0.0 => {
+                let waited = self
+                    .last
+                    .map_or(f64::INFINITY, |t| t.elapsed().as_secs_f64());
+                if waited < self.interval {
+                    return false;
+                }
+            }
+            _ => {}
+        }
+        self.last = Some(Instant::now());
+        true
+    }
+}
+
+/// Hands a message from the library to the GUI through `tx`, returning false
+/// once the GUI is gone.
+fn deliver(
+    wire: WireMessage,
+    tx: &Sender<Message>,
+    throttle: &mut RepaintThrottle,
+    on_message: &impl Fn(),
+) -> bool {
+    let msg = to_message(wire);
+    let is_due = throttle.is_due(&msg);
+    if tx.send(msg).is_err() {
+        return false;
+    }
+    if is_due {
+        on_message();
+    }
+    true
+}
+
+/// Child process side: decode messages from `reader` until it closes, handing
+/// them to the GUI through `tx`. `on_message` is called after the ones the
+/// window should be repainted for, see `RepaintThrottle`.
+pub fn receive_messages<R: Read>(reader: R, tx: Sender<Message>, on_message: impl Fn()) {
+    let mut reader = std::io::BufReader::new(reader);
+    let mut throttle = RepaintThrottle::default();
+    loop {
+        let wire: WireMessage = match bincode::deserialize_from(&mut reader) {
+            Ok(m) => m,
+            Err(e) => {
+                log::info!("GUI input closed: {}", e);
+                return;
+            }
+        };
+        if !deliver(wire, &tx, &mut throttle, &on_message) {
+            return;
+        }
+    }
+}
+
+/// Child process side: sends the GUI's messages for the library to `writer`
+/// (the process's stdout) until the GUI closes.
+pub fn send_client_messages<W: Write>(rx: Receiver<ClientMessage>, mut writer: W) {
+    for msg in rx.iter() {
+        let wire = match msg {
+            ClientMessage::ConfigChanged(config) => WireClientMessage::ConfigChanged(config),
+            ClientMessage::ThreadStarted(_)
+            | ClientMessage::ShowWindow
+            | ClientMessage::CloseWindow
+            | ClientMessage::QuitGui => continue,
+        };
+        let sent = bincode::serialize_into(&mut writer, &wire)
+            .and_then(|_| writer.flush().map_err(bincode::Error::from));
+        if let Err(e) = sent {
+            log::info!("Couldn't send to tetrad: {}", e);
+            return;
+        }
+    }
+}
+
+/// Child process side, shared-memory flavor of `receive_messages`. Polls the
+/// ring buffer named `name` until the GUI or the process feeding it goes away.
+pub fn receive_shared_memory(name: &str, tx: Sender<Message>, on_message: impl Fn()) {
+    let ring = match ShmRing::open(name) {
+        Ok(r) => r,
+        Err(e) => {
+            log::error!("Couldn't open shared memory {}: {}", name, e);
+            return;
+        }
+    };
+    let mut throttle = RepaintThrottle::default();
+    loop {
+        let Some(wire) = ring.pop_with(|reader| bincode::deserialize_from(reader)) else {
+            // nothing closes the ring when DCS exits or crashes, so watch its process
+            if !ring.wait_for_producer(SHM_POLL_INTERVAL) {
+                log::info!("Process feeding the GUI exited");
+                return;
+            }
+            continue;
+        };
+        let wire: WireMessage = match wire {
+            Ok(m) => m,
+            Err(e) => {
+                log::error!("Couldn't decode GUI message: {}", e);
+                continue;
+            }
+        };
+        if !deliver(wire, &tx, &mut throttle, &on_message) {
+            return;
+        }
+    }
+}
@@ -0,0 +1,47 @@
+// Stand-in for `gui.rs` when tetrad is built without the `gui` feature. It
+// mirrors the interface lib.rs talks to, but never spawns a window.
+use crate::config::Config;
+use crate::dcs::{DcsWorldObject, DcsWorldUnit};
+use crate::markers::Marker;
+use crate::netstats::PingSummary;
+use crate::notification::Notification;
+use crate::perf_monitor::MemoryUsage;
+use std::sync::{
+    atomic::AtomicBool,
+    mpsc::{Receiver, Sender},
+    Arc,
+};
+
+pub type ArcFlag = Arc<AtomicBool>;
+
+#[allow(dead_code)]
+pub enum Message {
+    Start,
+    Configure(Config),
+    Update {
+        units: Arc<Vec<DcsWorldUnit>>,
+        ballistics: Arc<Vec<DcsWorldObject>>,
+        game_time: f64,
+        real_time: f64,
+        lib_time: f64,
+        memory: MemoryUsage,
+    },
+    Marker(Marker),
+    Pings(PingSummary),
+    UpdateAvailable(String),
+    Notify(Notification),
+    Close,
+}
+
+#[allow(dead_code)]
+pub enum ClientMessage {
+    ThreadStarted(ArcFlag),
+    ConfigChanged(Config),
+    ShowWindow,
+    CloseWindow,
+    QuitGui,
+}
+
+pub fn run(_rx: Receiver<Message>, _tx_to_main: Sender<ClientMessage>) {
+    log::warn!("Tetrad was built without GUI support, not starting the GUI");
+}
@@ -0,0 +1,253 @@
+// Hitch detection. Frames longer than `hitch_threshold_ms` (and
+// `hitch_relative_threshold` times the recent average) are written to the
+// hitch log together with a guess at what caused them, and a warning goes to
+// the console. Unlike the min/max/avg summaries this says when a stutter
+// happened and what else was going on at that moment.
+use crate::config::Config;
+use crate::io_monitor;
+use crate::sink::{Frame, Sink, SinkResult};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+// Frames the running averages roughly span.
+const AVERAGE_FRAMES: f64 = 120.0;
+// Seconds between console warnings, so a bad patch doesn't flood the log.
+const WARNING_INTERVAL: f64 = 10.0;
+// More objects than the recent average by this many (and by
+// `OBJECT_SPIKE_FRACTION` of it) counts as a spike.
+const OBJECT_SPIKE_MIN: f64 = 20.0;
+const OBJECT_SPIKE_FRACTION: f64 = 0.25;
+// Share of all CPUs busy that counts as saturated.
+const CPU_SATURATED: f64 = 0.9;
+// Process disk traffic within a single frame that counts as heavy.
+const DISK_HEAVY_BYTES: u64 = 4 * 1024 * 1024;
+// Share of the frame spent in tetrad that makes it the likely culprit.
+const TETRAD_SHARE: f64 = 0.5;
+// Hitches kept for the session report, which only lists the worst anyway.
+const MAX_SHARED_HITCHES: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HitchCause {
+    /// The unit or ballistics count jumped.
+    ObjectSpike,
+    /// All CPUs were close to fully busy.
+    CpuSaturation,
+    /// The process read or wrote a lot in that frame.
+    DiskIo,
+    /// Tetrad itself took most of the frame.
+    Tetrad,
+    Unknown,
+}
+
+impl std::fmt::Display for HitchCause {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::ObjectSpike => "object spike",
+            Self::CpuSaturation => "CPU saturation",
+            Self::DiskIo => "disk I/O",
+            Self::Tetrad => "tetrad",
+            Self::Unknown => "unknown",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HitchRecord {
+    pub t_game: f64,
+    pub t_real: f64,
+    pub frame_ms: f64,
+    pub average_ms: f64,
+    pub units: usize,
+    pub ballistics: usize,
+    pub cpu_load: f64,
+    pub io_mb: f64,
+    pub causes: String,
+}
+
+/// Hitches seen so far, shared with the session report.
+pub type HitchList = Arc<Mutex<Vec<HitchRecord>>>;
+
+pub struct HitchSink<W: Write> {
+    writer: csv::Writer<W>,
+    threshold: f64,
+    relative_threshold: f64,
+    last_game_time: Option<f64>,
+    last_io_bytes: u64,
+    average_frame_time: Option<f64>,
+    average_objects: Option<f64>,
+    last_warning_time: Option<f64>,
+    suppressed_warnings: u32,
+    counts: BTreeMap<HitchCause, u32>,
+    shared: HitchList,
+}
+
+fn update_average(average: &mut Option<f64>, value: f64) {
+    let a = average.get_or_insert(value);
+    *a += (value - *a) / AVERAGE_FRAMES;
+}
+
+impl<W: Write> HitchSink<W> {
+    pub fn new(
+        mut writer: csv::Writer<W>,
+        config: &Config,
+        shared: HitchList,
+    ) -> csv::Result<Self> {
+        writer.write_record(&[
+            "t_game",
+            "t_real",
+            "frame_ms",
+            "average_ms",
+            "units",
+            "ballistics",
+            "cpu_load",
+            "io_mb",
+            "causes",
+        ])?;
+        Ok(Self {
+            writer,
+            threshold: config.hitch_threshold_ms / 1000.0,
+            relative_threshold: config.hitch_relative_threshold,
+            last_game_time: None,
+            last_io_bytes: io_monitor::process_io_bytes(),
+            average_frame_time: None,
+            average_objects: None,
+            last_warning_time: None,
+            suppressed_warnings: 0,
+            counts: BTreeMap::new(),
+            shared,
+        })
+    }
+
+    fn is_hitch(&self, frame_time: f64) -> bool {
+        let Some(average) = self.average_frame_time else {
+            return false;
+        };
+        frame_time > self.threshold
+            && (self.relative_threshold <= 0.0 || frame_time > self.relative_threshold * average)
+    }
+
+    fn classify(&self, frame: &Frame, frame_time: f64, io_bytes: u64) -> Vec<HitchCause> {
+        let mut causes = Vec::new();
+        let objects = (frame.num_units + frame.num_ballistics) as f64;
+        let average_objects = self.average_objects.unwrap_or(objects);
+        if objects - average_objects > OBJECT_SPIKE_MIN.max(OBJECT_SPIKE_FRACTION * average_objects)
+        {
+            causes.push(HitchCause::ObjectSpike);
+        }
+        if cpu_load(frame) >= CPU_SATURATED {
+            causes.push(HitchCause::CpuSaturation);
+        }
+        if io_bytes >= DISK_HEAVY_BYTES {
+            causes.push(HitchCause::DiskIo);
+        }
+        if frame.lib_time >= TETRAD_SHARE * frame_time {
+            causes.push(HitchCause::Tetrad);
+        }
+        if causes.is_empty() {
+            causes.push(HitchCause::Unknown);
+        }
+        causes
+    }
+
+    fn warn(&mut self, frame: &Frame, frame_time: f64, causes: &[HitchCause]) {
+        if let Some(t) = self.last_warning_time {
+            if frame.real_time - t < WARNING_INTERVAL {
+                self.suppressed_warnings += 1;
+                return;
+            }
+        }
+        self.last_warning_time = Some(frame.real_time);
+        let causes: Vec<String> = causes.iter().map(|c| c.to_string()).collect();
+        let suppressed = std::mem::take(&mut self.suppressed_warnings);
+        log::warn!(
+            "Hitch at t={:.1}: {:.0} ms frame, likely cause: {}{}",
+            frame.game_time,
+            frame_time * 1000.0,
+            causes.join(", "),
+            if suppressed > 0 {
+                format!(" ({} more since the last warning)", suppressed)
+            } else {
+                String::new()
+            }
+        );
+    }
+}
+
+/// Share of all CPUs that was busy during the frame.
+fn cpu_load(frame: &Frame) -> f64 {
+    let (busy, total) = frame.sys_time;
+    if total > 0 {
+        busy as f64 / total as f64
+    } else {
+        0.0
+    }
+}
+
+impl<W: Write> Sink for HitchSink<W> {
+    fn name(&self) -> &'static str {
+        "hitch log"
+    }
+
+    fn write_frame(&mut self, frame: &Frame) -> SinkResult {
+        let io_bytes = io_monitor::process_io_bytes();
+        let frame_io = io_bytes.saturating_sub(self.last_io_bytes);
+        self.last_io_bytes = io_bytes;
+        // game time stands still while paused, so the gap doesn't look like a hitch
+        let Some(last_game_time) = self.last_game_time.replace(frame.game_time) else {
+            return Ok(());
+        };
+        let frame_time = frame.game_time - last_game_time;
+        if frame_time <= 0.0 {
+            return Ok(());
+        }
+        let objects = (frame.num_units + frame.num_ballistics) as f64;
+        if self.is_hitch(frame_time) {
+            let causes = self.classify(frame, frame_time, frame_io);
+            for cause in causes.iter() {
+                *self.counts.entry(*cause).or_default() += 1;
+            }
+            let names: Vec<String> = causes.iter().map(|c| c.to_string()).collect();
+            let record = HitchRecord {
+                t_game: frame.game_time,
+                t_real: frame.real_time,
+                frame_ms: frame_time * 1000.0,
+                average_ms: self.average_frame_time.unwrap_or_default() * 1000.0,
+                units: frame.num_units,
+                ballistics: frame.num_ballistics,
+                cpu_load: cpu_load(frame),
+                io_mb: frame_io as f64 / (1024.0 * 1024.0),
+                causes: names.join("+"),
+            };
+            self.writer.serialize(&record)?;
+            self.writer.flush()?;
+            let mut shared = self.shared.lock().unwrap();
+            if shared.len() < MAX_SHARED_HITCHES {
+                shared.push(record);
+            }
+            drop(shared);
+            self.warn(frame, frame_time, &causes);
+        } else {
+            // hitches are left out so they don't drag the average up
+            update_average(&mut self.average_frame_time, frame_time);
+        }
+        update_average(&mut self.average_objects, objects);
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> SinkResult {
+        self.writer.flush()?;
+        if self.counts.is_empty() {
+            return Ok(());
+        }
+        let summary: Vec<String> = self
+            .counts
+            .iter()
+            .map(|(cause, n)| format!("{} {}", n, cause))
+            .collect();
+        log::info!("Hitches by likely cause: {}", summary.join(", "));
+        Ok(())
+    }
+}
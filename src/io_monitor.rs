@@ -0,0 +1,193 @@
+// Disk I/O summary for the console log. The process I/O counters cover DCS and
+// tetrad together, so tetrad's own output (counted by `io_thread`) is
+// subtracted to tell the two apart. How busy the `write_dir` volume is comes
+// from its disk performance counters, which may not be available everywhere.
+use crate::io_thread;
+use std::ffi::c_void;
+use std::path::Path;
+use windows::core::HSTRING;
+use windows::Win32::Foundation::{CloseHandle, HANDLE};
+use windows::Win32::Storage::FileSystem::{
+    CreateFileW, GetVolumePathNameW, FILE_ACCESS_FLAGS, FILE_FLAGS_AND_ATTRIBUTES, FILE_SHARE_READ,
+    FILE_SHARE_WRITE, OPEN_EXISTING,
+};
+use windows::Win32::System::Ioctl::{DISK_PERFORMANCE, IOCTL_DISK_PERFORMANCE};
+use windows::Win32::System::Threading::{GetCurrentProcess, GetProcessIoCounters, IO_COUNTERS};
+use windows::Win32::System::IO::DeviceIoControl;
+
+const MEGABYTE: f64 = 1024.0 * 1024.0;
+
+// Tetrad gets the blame for a busy disk when it wrote more than this share of
+// the process's bytes.
+const TETRAD_BLAME_SHARE: f64 = 0.5;
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Counters {
+    real_time: f64,
+    process_read: u64,
+    process_written: u64,
+    tetrad_written: u64,
+    /// Idle and total time of the disk in 100 ns units, when readable.
+    disk_times: Option<(i64, i64)>,
+}
+
+#[derive(Debug)]
+pub struct IoMonitor {
+    volume: Option<HANDLE>,
+    busy_warning: f64,
+    last: Option<Counters>,
+}
+
+/// `\\.\C:` for a path on drive C.
+fn volume_device_name(path: &Path) -> Option<String> {
+    let mut buf = [0u16; 261];
+    unsafe {
+        GetVolumePathNameW(&HSTRING::from(path), &mut buf)
+            .ok()
+            .ok()?;
+    }
+    let len = buf.iter().position(|c| *c == 0).unwrap_or(buf.len());
+    let root = String::from_utf16_lossy(&buf[..len]);
+    Some(format!(r"\\.\{}", root.trim_end_matches('\\')))
+}
+
+fn open_volume(path: &Path) -> Option<HANDLE> {
+    let name = volume_device_name(path)?;
+    let handle = unsafe {
+        CreateFileW(
+            &HSTRING::from(name.as_str()),
+            FILE_ACCESS_FLAGS(0),
+            FILE_SHARE_READ | FILE_SHARE_WRITE,
+            None,
+            OPEN_EXISTING,
+            FILE_FLAGS_AND_ATTRIBUTES(0),
+            HANDLE::default(),
+        )
+    };
+    match handle {
+        Ok(handle) => Some(handle),
+        Err(e) => {
+            log::debug!("Couldn't open {} for disk statistics: {}", name, e);
+            None
+        }
+    }
+}
+
+fn disk_performance(volume: HANDLE) -> Option<DISK_PERFORMANCE> {
+    let mut perf = DISK_PERFORMANCE::default();
+    let mut returned = 0u32;
+    let success = unsafe {
+        DeviceIoControl(
+            volume,
+            IOCTL_DISK_PERFORMANCE,
+            None,
+            0,
+            Some(&mut perf as *mut DISK_PERFORMANCE as *mut c_void),
+            std::mem::size_of::<DISK_PERFORMANCE>() as u32,
+            Some(&mut returned),
+            None,
+        )
+    };
+    success.as_bool().then_some(perf)
+}
+
+/// Bytes read and written by the DCS process, tetrad included, since it
+/// started.
+pub fn process_io_bytes() -> u64 {
+    let io = process_io();
+    io.ReadTransferCount + io.WriteTransferCount
+}
+
+fn process_io() -> IO_COUNTERS {
+    let mut counters = IO_COUNTERS::default();
+    unsafe {
+        let success = GetProcessIoCounters(GetCurrentProcess(), &mut counters);
+        assert!(success.as_bool());
+    }
+    counters
+}
+
+impl IoMonitor {
+    /// Watches the volume holding `write_dir`. `busy_warning` is the
+    /// `disk_busy_warning` percentage.
+    pub fn new(write_dir: &Path, busy_warning: f64) -> Self {
+        Self {
+            volume: open_volume(write_dir),
+            busy_warning,
+            last: None,
+        }
+    }
+
+    /// Logs throughput since the previous call, and a warning if the disk was
+    /// busy for more than `disk_busy_warning` percent of that time.
+    pub fn log_summary(&mut self, real_time: f64, lvl: log::Level) {
+        let io = process_io();
+        let perf = self.volume.and_then(disk_performance);
+        let now = Counters {
+            real_time,
+            process_read: io.ReadTransferCount,
+            process_written: io.WriteTransferCount,
+            tetrad_written: io_thread::total_bytes_written(),
+            disk_times: perf.map(|p| (p.IdleTime, p.QueryTime)),
+        };
+        let Some(last) = self.last.replace(now) else {
+            return;
+        };
+        let dt = now.real_time - last.real_time;
+        if dt <= 0.0 {
+            return;
+        }
+        let rate = |bytes: u64| bytes as f64 / MEGABYTE / dt;
+        let tetrad_written = now.tetrad_written.saturating_sub(last.tetrad_written);
+        let process_written = now.process_written.saturating_sub(last.process_written);
+        let dcs_written = process_written.saturating_sub(tetrad_written);
+        let dcs_read = now.process_read.saturating_sub(last.process_read);
+
+        let busy = match (last.disk_times, now.disk_times) {
+            (Some((idle0, query0)), Some((idle1, query1))) if query1 > query0 => {
+                let idle = (idle1 - idle0) as f64 / (query1 - query0) as f64;
+                Some((100.0 * (1.0 - idle)).clamp(0.0, 100.0))
+            }
+            _ => None,
+        };
+
+        log::log!(
+            lvl,
+            "Disk I/O: DCS read {:.2} MB/s, wrote {:.2} MB/s, tetrad wrote {:.2} MB/s",
+            rate(dcs_read),
+            rate(dcs_written),
+            rate(tetrad_written)
+        );
+        if let (Some(busy), Some(perf)) = (busy, perf) {
+            log::log!(
+                lvl,
+                "Disk busy {:.0}% of the time, queue depth {}",
+                busy,
+                perf.QueueDepth
+            );
+            if self.busy_warning > 0.0 && busy >= self.busy_warning {
+                let tetrad_share = tetrad_written as f64 / process_written.max(1) as f64;
+                if tetrad_share > TETRAD_BLAME_SHARE {
+                    log::warn!(
+                        "The disk is saturated, mostly by tetrad's logs. Consider setting \
+                         object_log_interval or writing fewer export formats."
+                    );
+                } else {
+                    log::warn!(
+                        "The disk is saturated by DCS itself (e.g. paging), not by tetrad's logs"
+                    );
+                }
+            }
+        }
+    }
+}
+
+impl Drop for IoMonitor {
+    fn drop(&mut self) {
+        if let Some(volume) = self.volume {
+            unsafe {
+                CloseHandle(volume);
+            }
+        }
+    }
+}
@@ -0,0 +1,283 @@
+use crate::sink::{Checkpoint, ZstdOptions};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, SyncSender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use tetrad_data::encrypted::{EncryptedWriter, Key};
+use zstd::stream::write::Encoder as ZstdEncoder;
+
+// Size of the chunks handed over to the I/O thread. Small enough to keep the
+// pending memory bounded, large enough that we aren't sending a message for
+// every csv record.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+// Chunks that can be queued for an I/O thread before the producer waits for
+// it, 4 MB per file. If compression or the disk can't keep up the worker
+// slows down, which shows up in its queue stats rather than as unbounded
+// memory use.
+const MAX_PENDING_CHUNKS: usize = 64;
+
+// Bytes written by every `ThreadedFile` so far, for the disk I/O summary.
+static TOTAL_WRITTEN: AtomicU64 = AtomicU64::new(0);
+
+/// Bytes tetrad has written to all of its output files since it was loaded.
+pub fn total_bytes_written() -> u64 {
+    TOTAL_WRITTEN.load(Ordering::Relaxed)
+}
+
+enum Message {
+    Write(Vec<u8>),
+    Flush,
+    Checkpoint,
+}
+
+/// A `Write` implementation that hands its data to a dedicated thread which
+/// owns the underlying file. Disk latency (e.g. an antivirus scan of the log
+/// directory) only ever stalls the I/O thread, never the producer. Files
+/// created with `Compression` are also zstd-compressed on that thread, so the
+/// worker only pays for serializing the records.
+///
+/// The data goes to `<path>.tmp` until the file is dropped, then it's renamed
+/// to `path`. A file under its real name is always complete, and one left
+/// with `.tmp` is from a session that didn't end cleanly.
+pub struct ThreadedFile {
+    buf: Vec<u8>,
+    tx: Option<SyncSender<Message>>,
+    thread_join: Option<JoinHandle<()>>,
+    written: Arc<AtomicU64>,
+}
+
+/// Where `ThreadedFile` writes to until it's done.
+fn temp_path(path: &Path) -> PathBuf {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    PathBuf::from(temp)
+}
+
+/// The file on disk, counting the bytes that actually go to it, after
+/// compression and encryption.
+struct Counted {
+    file: File,
+    written: Arc<AtomicU64>,
+}
+
+impl Write for Counted {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        let n = self.file.write(data)?;
+        self.written.fetch_add(n as u64, Ordering::Relaxed);
+        TOTAL_WRITTEN.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+enum Output {
+    Plain(Counted),
+    Encrypted(EncryptedWriter<Counted>),
+}
+
+impl Write for Output {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Output::Plain(counted) => counted.write(data),
+            Output::Encrypted(writer) => writer.write(data),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Output::Plain(counted) => counted.flush(),
+            Output::Encrypted(writer) => writer.flush(),
+        }
+    }
+}
+
+impl Output {
+    fn finish(self) -> std::io::Result<()> {
+        match self {
+            Output::Plain(mut counted) => counted.flush(),
+            Output::Encrypted(writer) => writer.finish().map(drop),
+        }
+    }
+}
+
+/// How a file's `Output` gets its data, as written or through zstd.
+enum Stage {
+    Raw(Output),
+    Compressed(ZstdEncoder<'static, Output>),
+}
+
+impl Stage {
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            Stage::Raw(output) => output,
+            Stage::Compressed(encoder) => encoder,
+        }
+    }
+
+    fn checkpoint(&mut self) -> std::io::Result<()> {
+        match self {
+            Stage::Raw(output) => output.flush(),
+            Stage::Compressed(encoder) => {
+                // ends the zstd frame, the next write starts another one and
+                // readers decompress concatenated frames as one stream
+                encoder.do_finish()?;
+                encoder.get_mut().flush()
+            }
+        }
+    }
+
+    fn finish(self) -> std::io::Result<()> {
+        let output = match self {
+            Stage::Raw(output) => output,
+            Stage::Compressed(encoder) => encoder.finish()?,
+        };
+        output.finish()
+    }
+}
+
+/// Compresses a `ThreadedFile` on its I/O thread.
+pub struct Compression<'a> {
+    pub zstd: &'a ZstdOptions,
+    pub dictionary: Option<&'a [u8]>,
+}
+
+fn io_entry(mut file: Stage, path: PathBuf, rx: Receiver<Message>) {
+    log::debug!("Starting I/O thread for {:?}", path);
+    let temp = temp_path(&path);
+    for msg in rx.iter() {
+        let result = match msg {
+            Message::Write(data) => file.writer().write_all(&data),
+            Message::Flush => file.writer().flush(),
+            Message::Checkpoint => file.checkpoint(),
+        };
+        if let Err(e) = result {
+            log::error!("Failed to write to {:?}: {}", path, e);
+        }
+    }
+    if let Err(e) = file.finish() {
+        log::error!("Failed to finish {:?}: {}", path, e);
+    }
+    if let Err(e) = std::fs::rename(&temp, &path) {
+        log::error!("Failed to rename {:?} to {:?}: {}", temp, path, e);
+    }
+    log::debug!("I/O thread for {:?} finished", path);
+}
+
+impl ThreadedFile {
+    /// Starts writing `path`, compressed if there's a `compression` and
+    /// encrypted if there's a `key`.
+    pub fn create(
+        path: &Path,
+        key: Option<&Key>,
+        compression: Option<Compression>,
+    ) -> std::io::Result<Self> {
+        let written = Arc::<AtomicU64>::default();
+        let counted = Counted {
+            file: File::create(temp_path(path))?,
+            written: written.clone(),
+        };
+        let output = match key {
+            Some(key) => Output::Encrypted(EncryptedWriter::new(counted, key)?),
+            None => Output::Plain(counted),
+        };
+        let stage = match compression {
+            Some(compression) => {
+                Stage::Compressed(compression.zstd.encoder(output, compression.dictionary)?)
+            }
+            None => Stage::Raw(output),
+        };
+        let (tx, rx) = std::sync::mpsc::sync_channel(MAX_PENDING_CHUNKS);
+        let path = path.to_path_buf();
+        let handle = std::thread::spawn(move || io_entry(stage, path, rx));
+        Ok(Self {
+            buf: Vec::with_capacity(CHUNK_SIZE),
+            tx: Some(tx),
+            thread_join: Some(handle),
+            written,
+        })
+    }
+
+    /// Running count of the bytes written to disk so far (after compression),
+    /// which stays readable after the file has been handed to a sink.
+    pub fn bytes_written(&self) -> Arc<AtomicU64> {
+        self.written.clone()
+    }
+
+    fn send(&mut self, msg: Message) -> std::io::Result<()> {
+        let tx = self.tx.as_ref().expect("I/O thread sender should exist");
+        tx.send(msg).map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::BrokenPipe, "I/O thread has exited")
+        })
+    }
+
+    fn send_pending(&mut self) -> std::io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.buf, Vec::with_capacity(CHUNK_SIZE));
+        self.send(Message::Write(chunk))
+    }
+}
+
+impl Write for ThreadedFile {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= CHUNK_SIZE {
+            self.send_pending()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.send_pending()?;
+        self.send(Message::Flush)
+    }
+}
+
+impl Checkpoint for ThreadedFile {
+    fn checkpoint(&mut self) -> std::io::Result<()> {
+        self.send_pending()?;
+        self.send(Message::Checkpoint)
+    }
+}
+
+impl Drop for ThreadedFile {
+    fn drop(&mut self) {
+        self.send_pending().unwrap_or_else(|e| {
+            log::error!("Failed to hand off final chunk to I/O thread: {}", e);
+        });
+        // dropping the sender ends the I/O thread's receive loop
+        drop(self.tx.take());
+        if let Some(handle) = self.thread_join.take() {
+            handle.join().unwrap_or_else(|_| {
+                log::error!("Failed to join I/O thread");
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_the_encrypted_bytes() {
+        let path = std::env::temp_dir().join(format!("tetrad-io-{}.enc", std::process::id()));
+        let mut file = ThreadedFile::create(&path, Some(&[7; 32]), None).unwrap();
+        let written = file.bytes_written();
+        file.write_all(&[0; 100_000]).unwrap();
+        drop(file);
+        let on_disk = std::fs::metadata(&path).unwrap().len();
+        std::fs::remove_file(&path).unwrap();
+        // the segment headers and tags make it bigger than the plaintext
+        assert!(on_disk > 100_000);
+        assert_eq!(written.load(Ordering::Relaxed), on_disk);
+    }
+}
@@ -0,0 +1,26 @@
+// `enable_json_log`: `dcs_tetrad.log` written as one JSON object per line, for
+// log collectors like Loki or ELK. Each entry carries the session it was
+// logged in, so it can be matched up with that session's files and dcs.log.
+use std::sync::{Mutex, PoisonError};
+
+static SESSION: Mutex<Option<String>> = Mutex::new(None);
+
+/// Sets the session id added to entries from now on, `None` between sessions.
+pub fn set_session(id: Option<String>) {
+    *SESSION.lock().unwrap_or_else(PoisonError::into_inner) = id;
+}
+
+pub fn format(record: &log::Record, message: &std::fmt::Arguments) -> String {
+    let session = SESSION
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    serde_json::json!({
+        "timestamp": chrono::Local::now().to_rfc3339(),
+        "level": record.level().as_str(),
+        "target": record.target(),
+        "message": message.to_string(),
+        "session": session,
+    })
+    .to_string()
+}
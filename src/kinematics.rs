@@ -0,0 +1,78 @@
+// Speeds and turn rates for the object logs. `LoGetWorldObjects` only gives
+// positions, so the worker works them out from consecutive frames, before
+// object log sampling or changed-only logging thin the frames out.
+use crate::dcs::{DcsWorldObject, DcsWorldUnit, Motion};
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+struct Sample {
+    game_time: f64,
+    position: (f64, f64, f64),
+    heading: f64,
+    motion: Motion,
+}
+
+/// `a - b` wrapped to [-pi, pi).
+fn signed_angle_difference(a: f64, b: f64) -> f64 {
+    (a - b + PI).rem_euclid(2.0 * PI) - PI
+}
+
+#[derive(Default)]
+pub struct MotionTracker {
+    samples: HashMap<i32, Sample>,
+    /// Last frame's samples, kept to reuse the allocation.
+    previous: HashMap<i32, Sample>,
+}
+
+impl MotionTracker {
+    /// Takes the positions from a frame with objects. Objects not in it are
+    /// forgotten.
+    pub fn update(
+        &mut self,
+        game_time: f64,
+        units: &[DcsWorldUnit],
+        ballistics: &[DcsWorldObject],
+    ) {
+        std::mem::swap(&mut self.samples, &mut self.previous);
+        self.samples.clear();
+        let objects = units.iter().map(DcsWorldUnit::object).chain(ballistics);
+        for object in objects {
+            let position = object.position();
+            let heading = object.attitude().0;
+            let motion = match self.previous.get(&object.id()) {
+                Some(last) if game_time > last.game_time => {
+                    let dt = game_time - last.game_time;
+                    let (dx, dy, dz) = (
+                        position.0 - last.position.0,
+                        position.1 - last.position.1,
+                        position.2 - last.position.2,
+                    );
+                    Motion {
+                        speed: (dx * dx + dy * dy + dz * dz).sqrt() / dt,
+                        vertical_speed: dy / dt,
+                        heading_rate: signed_angle_difference(heading, last.heading) / dt,
+                    }
+                }
+                // paused
+                Some(last) => last.motion,
+                None => Motion::default(),
+            };
+            self.samples.insert(
+                object.id(),
+                Sample {
+                    game_time,
+                    position,
+                    heading,
+                    motion,
+                },
+            );
+        }
+    }
+
+    /// Zero for objects seen for the first time.
+    pub fn get(&self, id: i32) -> Motion {
+        self.samples
+            .get(&id)
+            .map_or_else(Motion::default, |sample| sample.motion)
+    }
+}
@@ -1,57 +1,167 @@
+use cargo::CargoEvent;
+use combat::CombatEvent;
+use events::{Event, EventKind};
 use fern::colors::{Color, ColoredLevelConfig};
+use markers::Marker;
 use mlua::prelude::{LuaResult, LuaTable};
 use mlua::Lua;
-use monitor::Monitor;
+use monitor::{Monitor, Phase};
+use notification::{Notification, Severity};
+use std::collections::{HashMap, VecDeque};
 use std::io::Write;
-use std::path::Path;
+use std::panic::AssertUnwindSafe;
 use std::sync::{
-    mpsc::{Receiver, Sender},
-    Arc,
+    atomic::AtomicBool,
+    mpsc::{Receiver, SyncSender, TrySendError},
+    Arc, Mutex, TryLockError,
 };
 use std::thread::JoinHandle;
-use std::time::{Duration, Instant};
+use std::time::{Instant, SystemTime};
 use std::{fs::File, os::windows::io::FromRawHandle};
-use timer::Timer;
+use windows::core::HSTRING;
 use windows::Win32::System::Console;
 use windows::Win32::System::SystemInformation::GetSystemInfo;
 use windows::Win32::System::SystemInformation::SYSTEM_INFO;
 
+mod alerts;
+mod barrage;
+mod bincode_export;
+pub mod callbacks;
+mod cargo;
+mod chat;
+mod combat;
 mod config;
-mod dcs;
+mod config_reload;
+mod dashboard;
+pub mod dcs;
+mod delta;
+mod disk;
+mod events;
+#[cfg(feature = "gui")]
+mod frame_stats;
+#[cfg(feature = "gui")]
+pub mod gui;
+#[cfg(not(feature = "gui"))]
+#[path = "gui_stub.rs"]
 mod gui;
+#[cfg(feature = "gui")]
+pub mod gui_ipc;
+mod hitch;
+mod io_monitor;
+mod io_thread;
+mod json_log;
+pub mod kinematics;
+mod live_feed;
+mod load_summary;
+mod log_file;
+mod log_filter;
+mod manifest;
+#[cfg(feature = "gui")]
+mod map;
+mod markers;
 mod monitor;
+mod mqtt;
+mod net;
+mod netstats;
+mod notification;
+mod object_counts;
+mod overlay;
+mod parquet_export;
 mod perf_monitor;
+mod players;
+pub mod recorder;
+#[cfg(feature = "gui")]
+mod replay;
+mod report;
+#[cfg(feature = "gui")]
+mod session_replay;
+#[cfg(feature = "gui")]
+mod shm_ring;
+mod sink;
+mod sqlite_export;
+mod status;
+#[cfg(feature = "gui")]
+mod tray;
+mod update_check;
+mod upload;
+mod weather;
 pub mod worker;
-use perf_monitor::PerfMonitor;
+mod worker_queue;
+use perf_monitor::{PerfMonitor, Stopwatch};
 
 struct FullState {
     is_gui_enabled: bool,
-    worker_tx: Sender<worker::Message>,
+    worker_tx: worker_queue::WorkerSender,
+    worker_overflow_reported: bool,
     worker_join: JoinHandle<()>,
     monitor: Option<Monitor>,
-    gui_tx: Sender<gui::Message>,
-    gui_context: Option<egui::Context>,
+    gui_tx: SyncSender<gui::Message>,
+    /// Messages the GUI wasn't keeping up with, see `GUI_QUEUE_SIZE`.
+    gui_dropped_messages: u64,
     is_gui_shown: Option<gui::ArcFlag>,
     rx_from_gui: Receiver<gui::ClientMessage>,
+    /// Set from the tray icon to stop writing frame data for a while.
+    logging_paused: gui::ArcFlag,
     start_time: Instant,
-    gui_draw_timer: Timer,
-    gui_draw_timer_guard: Option<timer::Guard>,
-    gui_draw_interval: f64,
+    /// Seconds spent in the previous `on_frame_begin`.
     lib_last_elapsed_time: f64,
     perf_mon: PerfMonitor,
+    has_been_active: bool,
+    mission_load_duration: f64,
+    mission_load_time: Option<f64>,
+    roster_interval: f64,
+    last_roster_time: Option<f64>,
+    players: players::PlayerTracker,
+    net_stats: netstats::NetStats,
+    net_stats_interval: f64,
+    last_ping_sample_time: Option<f64>,
+    last_net_summary_time: f64,
+    last_phase: Phase,
+    worker_failure_reported: bool,
+    /// The worker stopped taking messages, see `worker_queue::STALL_TIMEOUT`.
+    is_worker_stalled: bool,
+    gui_failure_reported: bool,
+    config: config::Config,
+    /// The settings as the hook passed them, before `tetrad.toml`, for
+    /// starting the next session if the mission changes underneath us.
+    base_config: config::Config,
+    mission_name: String,
+    last_mission_check_time: f64,
+    last_broadcast_time: f64,
+    broadcast_frame_count: u32,
+    frame_times: VecDeque<f64>,
+    last_frame_real_time: Option<f64>,
+    last_load_summary_time: f64,
+    last_overlay_time: f64,
+    last_weather_time: f64,
+    last_cargo_poll_time: f64,
+    last_combat_poll_time: f64,
+    last_marker_poll_time: f64,
+    chat_scopes: HashMap<i32, bool>,
+    config_watcher: config_reload::ConfigWatcher,
+    last_config_poll_time: f64,
+    session_start_time: SystemTime,
+    last_disk_check_time: f64,
+    is_disk_low: bool,
+    object_pool: dcs::ObjectPool,
+    /// Objects from the last poll, reused on frames in between.
+    last_units: Arc<Vec<dcs::DcsWorldUnit>>,
+    last_ballistics: Arc<Vec<dcs::DcsWorldObject>>,
+    last_object_poll_time: Option<f64>,
+    frames_since_object_poll: u32,
 }
 
 enum LibState {
     GuiStarted(
-        Sender<gui::Message>,
+        SyncSender<gui::Message>,
         Receiver<gui::ClientMessage>,
         Option<gui::ArcFlag>,
-        Option<egui::Context>,
+        gui::ArcFlag,
     ),
     WorkerStarted(FullState),
 }
 
-fn setup_logging(config: &config::Config, console: File) -> Result<(), fern::InitError> {
+fn setup_logging(config: &config::Config, console: Option<File>) -> Result<(), fern::InitError> {
     let colors_line = ColoredLevelConfig::new()
         .error(Color::Red)
         .warn(Color::Yellow)
@@ -64,21 +174,17 @@ fn setup_logging(config: &config::Config, console: File) -> Result<(), fern::Ini
     let colors_level = colors_line.clone().info(Color::Green);
 
     use log::LevelFilter;
-    let level = if config.debug {
-        LevelFilter::Debug
-    } else {
-        LevelFilter::Info
-    };
-
-    let logdir = Path::new(config.write_dir.as_str())
-        .join("Logs")
-        .join("Tetrad");
+    let logdir = config.log_dir();
 
     std::fs::create_dir_all(&logdir).unwrap();
-    let p = logdir.join("dcs_tetrad.log");
+    let log_file = log_file::RotatingLog::open(
+        &logdir.join("dcs_tetrad.log"),
+        config.log_max_size_mb,
+        config.log_keep_files,
+    )?;
 
-    fern::Dispatch::new()
-        .format(move |out, message, record| {
+    let text =
+        move |out: fern::FormatCallback, message: &std::fmt::Arguments, record: &log::Record| {
             out.finish(format_args!(
                 "{color_line}[{date}][{target}][{level}{color_line}] {message}\x1B[0m",
                 color_line = format_args!(
@@ -90,18 +196,35 @@ fn setup_logging(config: &config::Config, console: File) -> Result<(), fern::Ini
                 level = colors_level.color(record.level()),
                 message = message,
             ));
+        };
+    let file = fern::Dispatch::new()
+        .level(sink_filter(config.file_log_level))
+        .chain(fern::Output::writer(Box::new(log_file), "\n"));
+    let file = if config.enable_json_log {
+        file.format(|out, message, record| {
+            out.finish(format_args!("{}", json_log::format(record, message)))
         })
-        .level(level)
+    } else {
+        file.format(text)
+    };
+
+    let mut dispatch = fern::Dispatch::new()
+        .level(LevelFilter::Trace)
         .level_for("wgpu_core", LevelFilter::Warn)
         .level_for("naga", LevelFilter::Info)
-        .chain(
-            std::fs::OpenOptions::new()
-                .write(true)
-                .create(true)
-                .open(p)?,
-        )
-        .chain(console)
-        .apply()?;
+        .chain(file);
+    if let Some(console) = console {
+        dispatch = dispatch.chain(
+            fern::Dispatch::new()
+                .level(sink_filter(config.console_log_level))
+                .format(text)
+                .chain(console),
+        );
+    }
+    dispatch.apply()?;
+    // the dispatch lets everything through, so the verbosity can be changed
+    // while running
+    log::set_max_level(log_level(config));
 
     log_panics::init();
     log::info!("Initialization of logging complete!");
@@ -109,16 +232,49 @@ fn setup_logging(config: &config::Config, console: File) -> Result<(), fern::Ini
     Ok(())
 }
 
-fn create_console() -> windows::core::Result<File> {
+/// The most verbose level any of the log outputs wants.
+fn log_level(config: &config::Config) -> log::LevelFilter {
+    let file = config.file_log_level.filter(config.debug);
+    if config.enable_console {
+        file.max(config.console_log_level.filter(config.debug))
+    } else {
+        file
+    }
+}
+
+/// The filter of one log output. `default` is left to `log_level`, so it
+/// follows `debug` while running.
+fn sink_filter(level: config::LogLevel) -> log::LevelFilter {
+    match level {
+        config::LogLevel::Default => log::LevelFilter::Trace,
+        level => level.filter(false),
+    }
+}
+
+/// Whether DCS is the dedicated server (`DCS_server.exe`) or was started
+/// with `--norender`, in which case there's nobody to look at a window.
+fn is_dedicated_server() -> bool {
+    let is_server_exe = std::env::current_exe()
+        .ok()
+        .and_then(|path| path.file_stem().map(|s| s.to_string_lossy().to_lowercase()))
+        .map_or(false, |name| name == "dcs_server");
+    is_server_exe || std::env::args().any(|arg| arg.eq_ignore_ascii_case("--norender"))
+}
+
+fn create_console(config: &config::Config) -> windows::core::Result<File> {
     unsafe {
         Console::AllocConsole();
+        let title = format!("DCS Tetrad [{}]", config.instance_name());
+        Console::SetConsoleTitleW(&HSTRING::from(title));
         let h_stdout = Console::GetStdHandle(Console::STD_OUTPUT_HANDLE)?;
         Ok(File::from_raw_handle(h_stdout.0 as *mut libc::c_void))
     }
 }
 
 fn wait_for_gui_started(rx_from_gui: &Receiver<gui::ClientMessage>) -> gui::ArcFlag {
-    let gui::ClientMessage::ThreadStarted(h) = rx_from_gui.recv().unwrap();
+    let gui::ClientMessage::ThreadStarted(h) = rx_from_gui.recv().unwrap() else {
+        panic!("GUI should report that it started before anything else");
+    };
     h
 }
 
@@ -128,19 +284,337 @@ impl FullState {
     }
 }
 
-fn get_elapsed_time() -> f64 {
-    get_lib_state().elapsed_time()
+/// Wall-clock time, UTC milliseconds since the Unix epoch.
+fn epoch_ms() -> i64 {
+    chrono::Utc::now().timestamp_millis()
 }
 
-fn is_gui_shown() -> bool {
-    match get_lib_state().is_gui_shown.as_ref() {
+fn is_gui_shown(state: &FullState) -> bool {
+    match state.is_gui_shown.as_ref() {
         Some(flag) => flag.load(std::sync::atomic::Ordering::SeqCst),
         None => false,
     }
 }
 
-fn update_lib_time(t: f64) {
-    get_lib_state().lib_last_elapsed_time = t;
+fn update_lib_time(state: &mut FullState, t: f64) {
+    state.lib_last_elapsed_time = t;
+}
+
+fn get_session_phase(state: &mut FullState, is_paused: bool, game_time: f64) -> Phase {
+    if !is_paused && game_time > 0.0 {
+        state.has_been_active = true;
+    }
+    match (is_paused, state.has_been_active) {
+        (false, false) => Phase::Loading,
+        (true, false) => Phase::Briefing,
+        (false, true) => Phase::Active,
+        (true, true) => Phase::Paused,
+    }
+}
+
+fn is_roster_due(state: &FullState, real_time: f64) -> bool {
+    if state.roster_interval <= 0.0 {
+        return false;
+    }
+    match state.last_roster_time {
+        Some(t) => real_time - t >= state.roster_interval,
+        None => true,
+    }
+}
+
+fn is_ping_sample_due(state: &FullState, real_time: f64) -> bool {
+    if state.net_stats_interval <= 0.0 {
+        return false;
+    }
+    match state.last_ping_sample_time {
+        Some(t) => real_time - t >= PING_SAMPLE_INTERVAL,
+        None => true,
+    }
+}
+
+/// Everyone connected, None if DCS couldn't list them.
+fn get_players(lua: &Lua) -> Option<Vec<dcs::PlayerInfo>> {
+    dcs::get_players(lua)
+        .map_err(|e| log::warn!("Couldn't get the player list: {}", e))
+        .ok()
+}
+
+/// Player `id`, None if they're gone or DCS couldn't look them up.
+fn get_player_info(lua: &Lua, id: i32) -> Option<dcs::PlayerInfo> {
+    dcs::get_player_info(lua, id)
+        .map_err(|e| log::warn!("Couldn't look up player {}: {}", id, e))
+        .ok()
+        .flatten()
+}
+
+/// Connected players other than the server, None if DCS couldn't list them.
+fn count_players(lua: &Lua) -> Option<usize> {
+    // player 1 is the server itself
+    get_players(lua).map(|players| players.iter().filter(|p| p.id != 1).count())
+}
+
+fn sample_net_stats(state: &mut FullState, lua: &Lua, game_time: f64, real_time: f64) {
+    state.last_ping_sample_time = Some(real_time);
+    let Some(players) = get_players(lua) else {
+        return;
+    };
+    state
+        .monitor
+        .as_mut()
+        .unwrap()
+        .set_player_count(players.len() as i32);
+    state.net_stats.add_samples(&players);
+    if real_time - state.last_net_summary_time < state.net_stats_interval {
+        return;
+    }
+    state.last_net_summary_time = real_time;
+    let records = state.net_stats.summarize(game_time, real_time);
+    if let Some(pings) = netstats::PingSummary::compute(&records) {
+        netstats::log_summary(&records, &pings);
+        send_gui_message(state, gui::Message::Pings(pings));
+    }
+    send_worker_message(state, worker::Message::NetStats(records));
+}
+
+fn broadcast_stats(state: &mut FullState, lua: &Lua, real_time: f64) {
+    let interval = state.config.chat_broadcast_interval;
+    if interval <= 0.0 {
+        return;
+    }
+    state.broadcast_frame_count += 1;
+    let elapsed = real_time - state.last_broadcast_time;
+    if elapsed < interval {
+        return;
+    }
+    let fps = state.broadcast_frame_count as f64 / elapsed;
+    state.last_broadcast_time = real_time;
+    state.broadcast_frame_count = 0;
+
+    let Some(num_players) = count_players(lua) else {
+        return;
+    };
+    let message = chat::format_broadcast(
+        &state.config.chat_broadcast_message,
+        fps,
+        num_players,
+        &state.mission_name,
+        &state.config.restart_time,
+    );
+    log::debug!("Broadcasting to chat: {}", message);
+    if let Err(e) = dcs::send_chat(lua, &message) {
+        log::warn!("Couldn't send chat message: {}", e);
+    }
+}
+
+fn record_frame_time(state: &mut FullState, real_time: f64) {
+    if let Some(last) = state.last_frame_real_time {
+        if state.frame_times.len() == FRAME_TIME_HISTORY_LEN {
+            state.frame_times.pop_front();
+        }
+        state.frame_times.push_back(real_time - last);
+    }
+    state.last_frame_real_time = Some(real_time);
+}
+
+fn push_load_summary(state: &mut FullState, lua: &Lua, real_time: f64) {
+    let interval = state.config.mission_load_summary_interval;
+    if interval <= 0.0 || real_time - state.last_load_summary_time < interval {
+        return;
+    }
+    state.last_load_summary_time = real_time;
+    if let Some(summary) = load_summary::compute(&state.frame_times) {
+        if let Err(e) = dcs::run_in_mission(lua, &summary.to_lua_assignment()) {
+            log::warn!("Couldn't push the load summary to the mission: {}", e);
+        }
+    }
+}
+
+fn push_overlay(state: &mut FullState, lua: &Lua, real_time: f64) {
+    let interval = state.config.overlay_interval;
+    if interval <= 0.0 || real_time - state.last_overlay_time < interval {
+        return;
+    }
+    state.last_overlay_time = real_time;
+    let Some(summary) = load_summary::compute(&state.frame_times) else {
+        return;
+    };
+    let Some(num_players) = count_players(lua) else {
+        return;
+    };
+    let text = overlay::format(
+        &state.config.overlay_message,
+        summary.fps,
+        state.last_units.len(),
+        state.last_ballistics.len(),
+        num_players,
+        &state.mission_name,
+    );
+    log::trace!("Showing overlay: {}", text);
+    let code = overlay::to_lua(&text, state.config.overlay_audience, interval);
+    if let Err(e) = dcs::run_in_mission(lua, &code) {
+        log::warn!("Couldn't show the overlay: {}", e);
+    }
+}
+
+fn sample_weather(state: &mut FullState, lua: &Lua, real_time: f64) {
+    let interval = state.config.weather_interval;
+    if interval <= 0.0 || real_time - state.last_weather_time < interval {
+        return;
+    }
+    state.last_weather_time = real_time;
+    if let Some(weather) = weather::read(lua) {
+        send_worker_message(state, worker::Message::Weather(weather));
+    }
+}
+
+/// Whether to read the units and ballistics from DCS this frame, see
+/// `object_poll_interval`.
+fn is_object_poll_due(state: &mut FullState, real_time: f64) -> bool {
+    state.frames_since_object_poll += 1;
+    if let Some(t) = state.last_object_poll_time {
+        if state.frames_since_object_poll < state.config.object_poll_interval_frames
+            || real_time - t < state.config.object_poll_interval
+        {
+            return false;
+        }
+    }
+    state.frames_since_object_poll = 0;
+    state.last_object_poll_time = Some(real_time);
+    true
+}
+
+fn collect_cargo_events(state: &mut FullState, lua: &Lua, real_time: f64) {
+    let interval = state.config.cargo_event_interval;
+    if interval <= 0.0 || real_time - state.last_cargo_poll_time < interval {
+        return;
+    }
+    state.last_cargo_poll_time = real_time;
+    let events = cargo::drain(lua);
+    if events.is_empty() {
+        return;
+    }
+    // the events are still worth logging without player ids
+    let players = get_players(lua).unwrap_or_default();
+    for e in events {
+        send_cargo_event(state, &players, e, real_time);
+    }
+}
+
+/// Id of the connected player called `name`, 0 if there isn't one.
+fn find_player_id(players: &[dcs::PlayerInfo], name: &str) -> i32 {
+    players
+        .iter()
+        .find(|p| !name.is_empty() && p.name == name)
+        .map_or(0, |p| p.id)
+}
+
+fn send_cargo_event(
+    state: &mut FullState,
+    players: &[dcs::PlayerInfo],
+    cargo_event: CargoEvent,
+    real_time: f64,
+) {
+    let player_id = find_player_id(players, &cargo_event.player_name);
+    let event = Event {
+        t_game: cargo_event.t_game,
+        t_real: real_time,
+        kind: cargo_event.kind,
+        player_id,
+        player_name: cargo_event.player_name.clone(),
+        details: cargo_event.details(),
+        epoch_ms: epoch_ms(),
+    };
+    log::info!("Cargo event {:?}: {}", event.kind, event.details);
+    send_worker_message(state, worker::Message::Event(event));
+}
+
+fn collect_combat_events(state: &mut FullState, lua: &Lua, real_time: f64) {
+    let interval = state.config.combat_event_interval;
+    if interval <= 0.0 || real_time - state.last_combat_poll_time < interval {
+        return;
+    }
+    state.last_combat_poll_time = real_time;
+    let events = combat::drain(lua);
+    if events.is_empty() {
+        return;
+    }
+    let players = get_players(lua).unwrap_or_default();
+    for e in events {
+        send_combat_event(state, &players, e, real_time);
+    }
+}
+
+fn send_combat_event(
+    state: &mut FullState,
+    players: &[dcs::PlayerInfo],
+    combat_event: CombatEvent,
+    real_time: f64,
+) {
+    let event = Event {
+        t_game: combat_event.t_game,
+        t_real: real_time,
+        kind: combat_event.kind,
+        player_id: find_player_id(players, &combat_event.shooter.player_name),
+        player_name: combat_event.shooter.player_name.clone(),
+        details: combat_event.details(),
+        epoch_ms: epoch_ms(),
+    };
+    log::debug!("Combat event {:?}: {}", event.kind, event.details);
+    send_worker_message(state, worker::Message::Event(event));
+}
+
+fn collect_markers(state: &mut FullState, lua: &Lua, real_time: f64) {
+    if real_time - state.last_marker_poll_time < markers::POLL_INTERVAL {
+        return;
+    }
+    state.last_marker_poll_time = real_time;
+    for marker in markers::drain(lua) {
+        send_marker(state, marker, real_time);
+    }
+}
+
+fn send_marker(state: &mut FullState, marker: Marker, real_time: f64) {
+    log::info!("Marker at t={:.1}: {}", marker.t_game, marker.label);
+    send_gui_message(state, gui::Message::Marker(marker.clone()));
+    send_worker_message(
+        state,
+        worker::Message::Event(Event {
+            t_game: marker.t_game,
+            t_real: real_time,
+            kind: EventKind::Marker,
+            player_id: 0,
+            player_name: "".to_string(),
+            details: marker.label,
+            epoch_ms: epoch_ms(),
+        }),
+    );
+}
+
+/// Takes the actions of alerts that need the DCS thread, see alerts.rs.
+fn handle_alerts(state: &mut FullState, lua: &Lua) {
+    let alerts = state.monitor.as_ref().unwrap().take_alerts();
+    for alert in alerts {
+        match alert.action {
+            config::AlertAction::Chat => {
+                if let Err(e) = dcs::send_chat(lua, &alert.message) {
+                    log::warn!("Couldn't send alert to chat: {}", e);
+                }
+            }
+            config::AlertAction::Event => send_worker_message(
+                state,
+                worker::Message::Event(Event {
+                    t_game: alert.game_time,
+                    t_real: alert.real_time,
+                    kind: EventKind::Alert,
+                    player_id: 0,
+                    player_name: "".to_string(),
+                    details: alert.message,
+                    epoch_ms: epoch_ms(),
+                }),
+            ),
+            config::AlertAction::Log | config::AlertAction::Discord => {}
+        }
+    }
 }
 
 fn get_num_cpus() -> i32 {
@@ -157,31 +631,47 @@ fn get_system_info() -> SYSTEM_INFO {
 
 impl LibState {
     fn init(config: &config::Config) -> LuaResult<Self> {
-        let mut console_out = match create_console() {
-            Err(e) => {
-                return Err(mlua::Error::RuntimeError(
-                    format!("Couldn't create console, very sad. Error was {:#?}", e).into(),
-                ));
-            }
-            Ok(f) => f,
+        let console_out = if config.enable_console {
+            let mut console_out = match create_console(config) {
+                Err(e) => {
+                    return Err(mlua::Error::RuntimeError(
+                        format!("Couldn't create console, very sad. Error was {:#?}", e).into(),
+                    ));
+                }
+                Ok(f) => f,
+            };
+            writeln!(
+                console_out,
+                "Console creation complete, setting up logging."
+            )
+            .unwrap();
+            Some(console_out)
+        } else {
+            None
         };
-        writeln!(
-            console_out,
-            "Console creation complete, setting up logging."
-        )
-        .unwrap();
         if let Err(_e) = setup_logging(&config, console_out) {
             return Err(mlua::Error::RuntimeError(
                 "Couldn't set up logging, very sad.".into(),
             ));
         }
-        log::info!("Starting library");
+        log::info!("Starting library for instance {}", config.instance_name());
         log::info!("Loading DCS tetrad version {}", env!("CARGO_PKG_VERSION"));
 
-        let (gui_tx, gui_rx) = std::sync::mpsc::channel();
+        let (gui_tx, gui_rx) = std::sync::mpsc::sync_channel(GUI_QUEUE_SIZE);
         let (tx_to_main, rx_from_gui) = std::sync::mpsc::channel();
+        let logging_paused = gui::ArcFlag::default();
         if config.enable_gui {
-            log::debug!("Calling gui::run");
+            #[cfg(feature = "gui")]
+            tray::spawn(
+                tx_to_main.clone(),
+                logging_paused.clone(),
+                config.log_dir(),
+                format!("DCS Tetrad [{}]", config.instance_name()),
+            );
+            log::debug!("Calling gui_ipc::run");
+            #[cfg(feature = "gui")]
+            gui_ipc::run(gui_rx, tx_to_main, &config.dll_path, config.gui_transport);
+            #[cfg(not(feature = "gui"))]
             gui::run(gui_rx, tx_to_main);
         }
 
@@ -192,23 +682,34 @@ impl LibState {
             None
         };
 
-        let state =
-            LibState::GuiStarted(gui_tx, rx_from_gui, handle, Some(egui::Context::default()));
+        if config.check_for_updates {
+            update_check::spawn(gui_tx.clone());
+        }
+
+        let state = LibState::GuiStarted(gui_tx, rx_from_gui, handle, logging_paused);
 
         Ok(state)
     }
 
-    fn init_session(self, config: config::Config, mission_name: String) -> Self {
-        let (worker_tx, worker_rx) = std::sync::mpsc::channel();
+    fn init_session(
+        self,
+        base_config: config::Config,
+        config: config::Config,
+        mission_name: String,
+        manifest: manifest::SessionManifest,
+    ) -> Self {
+        let (worker_tx, worker_rx) =
+            worker_queue::channel(config.worker_queue_size, config.worker_queue_policy);
         let cloned_config = config.clone();
+        let monitor_mission_name = mission_name.clone();
         log::info!("Spawning worker thread");
 
         let worker_join = std::thread::spawn(move || {
             log::info!("Inside of worker thread");
-            worker::entry(config.clone(), mission_name, worker_rx);
+            worker::entry(config.clone(), mission_name, manifest, worker_rx);
         });
 
-        let monitor = Some(Monitor::new());
+        let monitor = Some(Monitor::new(monitor_mission_name.clone(), &cloned_config));
 
         log::info!("Setting GUI context");
 
@@ -218,152 +719,552 @@ impl LibState {
         pm.update_system_time();
 
         match self {
-            Self::GuiStarted(gui_tx, rx, handle, gui_context) => Self::WorkerStarted(FullState {
+            Self::GuiStarted(gui_tx, rx, handle, paused) => Self::WorkerStarted(FullState {
                 is_gui_enabled: cloned_config.clone().enable_gui,
                 worker_tx,
+                worker_overflow_reported: false,
                 worker_join,
                 monitor,
                 gui_tx,
-                gui_context,
+                gui_dropped_messages: 0,
                 is_gui_shown: handle,
                 rx_from_gui: rx,
+                logging_paused: paused,
                 start_time: Instant::now(),
-                gui_draw_timer: Timer::new(),
-                gui_draw_timer_guard: None,
-                gui_draw_interval: cloned_config.gui_update_interval,
                 lib_last_elapsed_time: 0.0,
                 perf_mon: pm,
+                has_been_active: false,
+                mission_load_duration: cloned_config.mission_load_duration,
+                mission_load_time: None,
+                roster_interval: cloned_config.roster_interval,
+                last_roster_time: None,
+                players: players::PlayerTracker::default(),
+                net_stats: netstats::NetStats::default(),
+                net_stats_interval: cloned_config.net_stats_interval,
+                last_ping_sample_time: None,
+                last_net_summary_time: 0.0,
+                last_phase: Phase::default(),
+                worker_failure_reported: false,
+                is_worker_stalled: false,
+                gui_failure_reported: false,
+                config: cloned_config.clone(),
+                base_config,
+                mission_name: monitor_mission_name.clone(),
+                last_mission_check_time: 0.0,
+                last_broadcast_time: 0.0,
+                broadcast_frame_count: 0,
+                frame_times: VecDeque::with_capacity(FRAME_TIME_HISTORY_LEN),
+                last_frame_real_time: None,
+                last_load_summary_time: 0.0,
+                last_overlay_time: 0.0,
+                last_weather_time: 0.0,
+                last_cargo_poll_time: 0.0,
+                last_combat_poll_time: 0.0,
+                last_marker_poll_time: 0.0,
+                chat_scopes: HashMap::new(),
+                config_watcher: config_reload::ConfigWatcher::new(
+                    &cloned_config,
+                    &monitor_mission_name,
+                ),
+                last_config_poll_time: 0.0,
+                session_start_time: SystemTime::now(),
+                last_disk_check_time: 0.0,
+                is_disk_low: false,
+                object_pool: dcs::ObjectPool::default(),
+                last_units: Arc::default(),
+                last_ballistics: Arc::default(),
+                last_object_poll_time: None,
+                frames_since_object_poll: 0,
             }),
 
-            Self::WorkerStarted { .. } => panic!("Worker already started"),
+            Self::WorkerStarted { .. } => {
+                unreachable!("start_session stops the last session first")
+            }
         }
     }
 }
 
-impl<'lua> mlua::FromLua<'lua> for config::Config {
-    fn from_lua(lua_value: mlua::Value<'lua>, lua: &'lua mlua::Lua) -> mlua::Result<Self> {
-        use mlua::LuaSerdeExt;
-        let config: config::Config = lua.from_value(lua_value)?;
-        Ok(config)
-    }
-}
+const PING_SAMPLE_INTERVAL: f64 = 1.0;
+const MISSION_CHECK_INTERVAL: f64 = 1.0;
+const FRAME_TIME_HISTORY_LEN: usize = 4096;
+/// Messages that can wait for the GUI process while it's slow to read them,
+/// about two seconds of frames. Any more are dropped, so a hung GUI can't grow
+/// DCS's memory.
+const GUI_QUEUE_SIZE: usize = 120;
 
-static mut LIB_STATE: Option<LibState> = None;
+/// Everything tetrad keeps between calls from DCS. It outlives `require`
+/// reloading the module between missions, since the DLL stays loaded.
+static LIB_STATE: Mutex<Option<LibState>> = Mutex::new(None);
 
-fn get_lib_state() -> &'static mut FullState {
-    if let Some(LibState::WorkerStarted(fs)) = unsafe { LIB_STATE.as_mut() } {
-        fs
-    } else {
-        panic!("Attempted to get lib full state before it was initialized.");
+/// The running session, if there is one.
+fn session(lib: &mut Option<LibState>) -> Option<&mut FullState> {
+    match lib {
+        Some(LibState::WorkerStarted(state)) => Some(state),
+        _ => None,
     }
 }
 
-fn send_worker_message(message: worker::Message) {
+fn send_worker_message(state: &mut FullState, message: worker::Message) {
     log::trace!("sending message {:?} to worker", message);
-    get_lib_state()
-        .worker_tx
-        .send(message)
-        .expect("Should be able to send message");
+    if state.worker_tx.send(message).is_err() {
+        // reported by check_worker_health
+        log::trace!("Worker is gone, message dropped");
+    }
 }
 
-fn is_real_time_gui() -> bool {
-    get_lib_state().gui_draw_interval <= 0.0
+fn check_worker_queue(state: &mut FullState) {
+    if state.worker_overflow_reported || state.worker_tx.stats().total() == 0 {
+        return;
+    }
+    state.worker_overflow_reported = true;
+    let text = format!(
+        "Logging can't keep up, frames are being dropped ({:?})",
+        state.config.worker_queue_policy
+    );
+    notify(state, Severity::Warning, text);
 }
 
-fn send_gui_message(message: gui::Message) {
-    if !get_lib_state().is_gui_enabled {
+fn send_gui_message(state: &mut FullState, message: gui::Message) {
+    if !state.is_gui_enabled {
         return;
     }
     log::trace!("sending message to gui");
-    get_lib_state().gui_tx.send(message).unwrap_or(());
-    if let Some(ctx) = &get_lib_state().gui_context {
-        if is_real_time_gui() {
-            ctx.request_repaint();
+    match state.gui_tx.try_send(message) {
+        Ok(()) => {}
+        Err(TrySendError::Full(_)) => {
+            if state.gui_dropped_messages == 0 {
+                log::warn!("The GUI isn't keeping up, dropping its data until it catches up");
+            }
+            state.gui_dropped_messages += 1;
         }
+        Err(TrySendError::Disconnected(_)) => gui_failed(state),
     }
 }
 
-fn start_gui(config: &config::Config) {
-    if config.gui_update_interval > 0.0 {
-        let repeat =
-            chrono::Duration::from_std(Duration::from_secs_f64(config.gui_update_interval))
-                .unwrap();
-        let guard = get_lib_state()
-            .gui_draw_timer
-            .schedule_repeating(repeat, || {
-                log::trace!("Timer fired");
-                if is_gui_shown() {
-                    get_lib_state()
-                        .gui_context
-                        .as_ref()
-                        .unwrap()
-                        .request_repaint();
-                }
-            });
-        get_lib_state().gui_draw_timer_guard = Some(guard)
+/// Logs `text` and pops it up in the GUI.
+fn notify(state: &mut FullState, severity: Severity, text: String) {
+    let notification = Notification::new(severity, text);
+    log::log!(notification.log_level(), "{}", notification.text);
+    send_gui_message(state, gui::Message::Notify(notification));
+}
+
+/// The GUI thread is gone, most likely after a panic. Everything else keeps
+/// running without it.
+fn gui_failed(state: &mut FullState) {
+    state.is_gui_enabled = false;
+    state.is_gui_shown = None;
+    if !state.gui_failure_reported {
+        state.gui_failure_reported = true;
+        log::error!("GUI thread stopped unexpectedly, carrying on without the GUI");
     }
+}
 
-    if is_gui_shown() {
-        let ctx = get_lib_state().gui_context.clone();
-        log::debug!("Starting GUI");
-        send_gui_message(gui::Message::Start(ctx.unwrap()));
+fn check_worker_stall(state: &mut FullState) {
+    let is_stalled = state.worker_tx.is_stalled();
+    if is_stalled == state.is_worker_stalled {
+        return;
+    }
+    state.is_worker_stalled = is_stalled;
+    if is_stalled {
+        notify(
+            state,
+            Severity::Error,
+            format!(
+                "Worker thread hasn't taken any data for {} seconds (is the disk responding?), \
+                 log data is dropped until it catches up",
+                worker_queue::STALL_TIMEOUT.as_secs()
+            ),
+        );
     } else {
-        log::debug!("GUI already running, not starting a new GUI");
-        send_gui_message(gui::Message::Start(
-            get_lib_state().gui_context.clone().unwrap(),
-        ));
+        notify(
+            state,
+            Severity::Info,
+            "Worker thread caught up, logging again".to_string(),
+        );
     }
 }
 
-#[no_mangle]
-pub fn start(lua: &Lua, config: config::Config) -> LuaResult<i32> {
-    unsafe {
-        if LIB_STATE.is_none() {
-            LIB_STATE = Some(LibState::init(&config)?);
-        }
+fn check_worker_health(state: &mut FullState) {
+    if state.worker_failure_reported || !state.worker_join.is_finished() {
+        return;
     }
-    let mission_name = dcs::get_mission_name(lua);
+    state.worker_failure_reported = true;
+    notify(
+        state,
+        Severity::Error,
+        "Worker thread stopped unexpectedly, nothing is being logged to disk".to_string(),
+    );
+}
+
+fn start_gui(state: &mut FullState, config: &config::Config) {
+    if is_gui_shown(state) {
+        log::debug!("GUI process already running, starting it over");
+    } else {
+        log::debug!("Starting GUI process");
+    }
+    send_gui_message(state, gui::Message::Start);
+    send_gui_message(state, gui::Message::Configure(config.clone()));
+}
+
+fn start(lib: &mut Option<LibState>, lua: &Lua, config_table: mlua::Table) -> LuaResult<i32> {
+    let (mut config, unknown_keys) = config_reload::from_lua_table(lua, config_table)?;
+    let gui_requested = config.enable_gui;
+    if !cfg!(feature = "gui") {
+        config.enable_gui = false;
+    }
+    let headless = match config.headless {
+        config::Headless::Auto => is_dedicated_server(),
+        config::Headless::On => true,
+        config::Headless::Off => false,
+    };
+    if headless {
+        config.enable_gui = false;
+        config.enable_console = false;
+    }
+    if lib.is_none() {
+        *lib = Some(LibState::init(&config)?);
+    }
+    if headless {
+        log::info!("Running headless, the GUI and console are off");
+    } else if gui_requested && !config.enable_gui {
+        log::warn!("GUI was enabled in the config, but this build of tetrad has no GUI support");
+    }
+    for key in unknown_keys {
+        log::warn!("Unknown key `{}` in tetrad-config.lua, ignoring it", key);
+    }
+    start_session(lib, lua, config)
+}
+
+/// Starts logging for the mission that's loaded now. `base_config` has the
+/// settings from the hook, the mission's `tetrad.toml` overrides are applied
+/// here.
+fn start_session(
+    lib: &mut Option<LibState>,
+    lua: &Lua,
+    base_config: config::Config,
+) -> LuaResult<i32> {
+    if session(lib).is_some() {
+        log::warn!("Started again before the last session was stopped, closing it out first");
+        stop(lib, lua, ())?;
+    }
+    let mission_name = dcs::get_mission_name(lua)?;
     log::info!("Loaded in mission {}", mission_name);
     log::info!("System info: {} CPUs", get_num_cpus());
+    let mut config = match config_reload::apply_overrides(base_config.clone(), &mission_name) {
+        Ok(config) => config,
+        Err(e) => {
+            log::warn!(
+                "Couldn't apply tetrad.toml, using tetrad-config.lua only: {}",
+                e
+            );
+            base_config.clone()
+        }
+    };
+    // the GUI thread only exists if it was on when tetrad started, and it
+    // stays off when headless
+    config.enable_gui &= base_config.enable_gui;
 
-    unsafe {
-        LIB_STATE = Some(
-            LIB_STATE
-                .take()
-                .unwrap()
-                .init_session(config.clone(), mission_name),
-        );
+    disk::apply_retention(&config);
+    dcs::reset_dropped_objects();
+    let low_disk_space = disk::check(&config, SystemTime::now());
+    if low_disk_space.is_some() {
+        config.enable_object_log = false;
     }
+    let weather = if config.weather_interval > 0.0 {
+        weather::read(lua)
+    } else {
+        None
+    };
+    let manifest = manifest::SessionManifest::new(
+        mission_name.clone(),
+        manifest::SessionMetadata::read(lua, &config.write_dir),
+        weather.clone(),
+        config.clone(),
+    );
+    json_log::set_session(Some(manifest.metadata.session_id.clone()));
+
+    let Some(gui_started) = lib.take() else {
+        return Err(mlua::Error::RuntimeError(
+            "tetrad wasn't initialized".into(),
+        ));
+    };
+    let started =
+        gui_started.init_session(base_config, config.clone(), mission_name.clone(), manifest);
+    let LibState::WorkerStarted(state) = lib.insert(started) else {
+        unreachable!("init_session starts the worker");
+    };
 
     if config.enable_gui {
-        start_gui(&config);
+        start_gui(state, &config);
+    }
+    if config.cargo_event_interval > 0.0 {
+        if let Err(e) = cargo::install(lua) {
+            log::warn!("Couldn't set up the cargo events: {}", e);
+        }
+    }
+    if config.combat_event_interval > 0.0 {
+        if let Err(e) = combat::install(lua) {
+            log::warn!("Couldn't set up the combat events: {}", e);
+        }
+    }
+    if let Some(weather) = weather {
+        send_worker_message(state, worker::Message::Weather(weather));
+    }
+    if let Err(e) = markers::install(lua) {
+        log::warn!("Couldn't set up the mission markers: {}", e);
+    }
+    notify(
+        state,
+        Severity::Info,
+        format!("Logging started for mission {}", mission_name),
+    );
+    if let Some(free_mb) = low_disk_space {
+        state.is_disk_low = true;
+        notify(
+            state,
+            Severity::Warning,
+            format!(
+                "Only {} MB of disk space left, object logging is switched off",
+                free_mb
+            ),
+        );
     }
 
     Ok(0)
 }
 
-#[no_mangle]
-pub fn on_frame_begin(lua: &Lua, _: ()) -> LuaResult<()> {
-    let real_time = get_elapsed_time();
+/// Applies the settings from `new` that can change mid-mission, and passes
+/// the result on to the worker and GUI. `source` is only for the log.
+fn change_config(state: &mut FullState, new: config::Config, source: &str) {
+    log::info!("Settings changed from {}", source);
+    state.config.apply_reloadable(&new);
+    let config = state.config.clone();
+    log::set_max_level(log_level(&config));
+    state.roster_interval = config.roster_interval;
+    state.net_stats_interval = config.net_stats_interval;
+    send_gui_message(state, gui::Message::Configure(config.clone()));
+    send_worker_message(state, worker::Message::ConfigChanged(config));
+}
+
+fn reload_config_file(state: &mut FullState, lua: &Lua, real_time: f64) {
+    let interval = state.config.config_reload_interval;
+    if interval <= 0.0 || real_time - state.last_config_poll_time < interval {
+        return;
+    }
+    state.last_config_poll_time = real_time;
+    match state.config_watcher.poll(lua) {
+        Some(Ok(config)) => change_config(state, config, "the config file"),
+        Some(Err(e)) => notify(
+            state,
+            Severity::Warning,
+            format!(
+                "Couldn't reload the config, keeping the old settings: {}",
+                e
+            ),
+        ),
+        None => {}
+    }
+}
+
+/// The settings to start a new session with if the server switched missions
+/// without the hook stopping tetrad.
+fn check_mission_change(
+    state: &mut FullState,
+    lua: &Lua,
+    real_time: f64,
+) -> LuaResult<Option<config::Config>> {
+    if real_time - state.last_mission_check_time < MISSION_CHECK_INTERVAL {
+        return Ok(None);
+    }
+    state.last_mission_check_time = real_time;
+    let mission_name = dcs::get_mission_name(lua)?;
+    if mission_name == state.mission_name {
+        return Ok(None);
+    }
+    log::warn!(
+        "Mission changed from {} to {} without tetrad being stopped, starting a new session",
+        state.mission_name,
+        mission_name
+    );
+    let mut base_config = state.base_config.clone();
+    // the new mission's load time wasn't measured
+    base_config.mission_load_duration = 0.0;
+    Ok(Some(base_config))
+}
+
+fn guard_disk_space(state: &mut FullState, real_time: f64) {
+    let interval = state.config.disk_check_interval;
+    if interval <= 0.0 || real_time - state.last_disk_check_time < interval {
+        return;
+    }
+    state.last_disk_check_time = real_time;
+    let Some(free_mb) = disk::check(&state.config, state.session_start_time) else {
+        if state.is_disk_low {
+            state.is_disk_low = false;
+            notify(
+                state,
+                Severity::Info,
+                "Disk space is back above the minimum".to_string(),
+            );
+        }
+        return;
+    };
+    if !state.is_disk_low {
+        state.is_disk_low = true;
+        notify(
+            state,
+            Severity::Warning,
+            format!("Only {} MB of disk space left", free_mb),
+        );
+    }
+    // checked every time, in case a config reload switched it back on
+    if state.config.enable_object_log {
+        let mut config = state.config.clone();
+        config.enable_object_log = false;
+        change_config(state, config, "the disk space guard");
+    }
+}
+
+/// Opens the GUI window again if it was closed, returning false if the GUI
+/// is disabled.
+fn reopen_gui(state: &mut FullState) -> bool {
+    state.is_gui_enabled = state.config.enable_gui;
+    if state.is_gui_enabled && !is_gui_shown(state) {
+        log::info!("Reopening the GUI");
+        let config = state.config.clone();
+        send_gui_message(state, gui::Message::Start);
+        send_gui_message(state, gui::Message::Configure(config));
+    }
+    state.is_gui_enabled
+}
+
+fn handle_gui_messages(state: &mut FullState) {
+    while let Ok(msg) = state.rx_from_gui.try_recv() {
+        match msg {
+            gui::ClientMessage::ConfigChanged(config) => change_config(state, config, "the GUI"),
+            gui::ClientMessage::ShowWindow => {
+                reopen_gui(state);
+            }
+            gui::ClientMessage::CloseWindow => send_gui_message(state, gui::Message::Close),
+            gui::ClientMessage::QuitGui => {
+                send_gui_message(state, gui::Message::Close);
+                state.is_gui_enabled = false;
+            }
+            // only sent once, when the GUI forwarding thread starts
+            gui::ClientMessage::ThreadStarted(_) => {}
+        }
+    }
+}
+
+fn on_frame_begin(lib: &mut Option<LibState>, lua: &Lua, _: ()) -> LuaResult<()> {
+    let Some(state) = session(lib) else {
+        return Ok(());
+    };
+    let stopwatch = Stopwatch::start();
+    let real_time = state.elapsed_time();
+
+    if let Some(base_config) = check_mission_change(state, lua, real_time)? {
+        stop(lib, lua, ())?;
+        start_session(lib, lua, base_config)?;
+        // the new session's clock starts now, this frame belongs to neither
+        return Ok(());
+    }
+
+    if state.mission_load_time.is_none() {
+        // the first frame marks the end of loading
+        let load_time = state.mission_load_duration + real_time;
+        log::info!("Mission load time was {:.3} seconds", load_time);
+        state.mission_load_time = Some(load_time);
+        state.monitor.as_mut().unwrap().mission_loaded(load_time);
+    }
+
+    let proc_times = state.perf_mon.update_process_time();
+    let sys_times = state.perf_mon.update_system_time();
+    let memory = state.perf_mon.process_memory();
+
+    let is_paused = dcs::is_paused(lua)?;
+    let t = dcs::get_model_time(lua)?;
+
+    if is_ping_sample_due(state, real_time) {
+        sample_net_stats(state, lua, t, real_time);
+    }
 
-    let proc_times = get_lib_state().perf_mon.update_process_time();
-    let sys_times = get_lib_state().perf_mon.update_system_time();
+    if is_roster_due(state, real_time) {
+        state.last_roster_time = Some(real_time);
+        if let Some(players) = get_players(lua) {
+            state
+                .monitor
+                .as_mut()
+                .unwrap()
+                .set_player_count(players.len() as i32);
+            let players = state.players.update(players, real_time);
+            send_worker_message(
+                state,
+                worker::Message::Roster {
+                    players,
+                    game_time: t,
+                    real_time,
+                },
+            );
+        }
+    }
+    let phase = get_session_phase(state, is_paused, t);
+    if phase != state.last_phase {
+        state.last_phase = phase;
+        notify(
+            state,
+            Severity::Info,
+            format!("Session phase is now {}", phase),
+        );
+    }
+    check_worker_health(state);
+    check_worker_stall(state);
+    handle_gui_messages(state);
+    reload_config_file(state, lua, real_time);
+    guard_disk_space(state, real_time);
+    record_frame_time(state, real_time);
+    push_load_summary(state, lua, real_time);
+    push_overlay(state, lua, real_time);
+    sample_weather(state, lua, real_time);
+    collect_cargo_events(state, lua, real_time);
+    collect_combat_events(state, lua, real_time);
+    collect_markers(state, lua, real_time);
+    broadcast_stats(state, lua, real_time);
+    handle_alerts(state, lua);
+    let lib_time = state.lib_last_elapsed_time;
 
-    if dcs::is_paused(lua) {
+    if is_paused {
         log::trace!("DCS is paused");
+        // still tell the monitor, so paused/briefing frames get their own statistics
+        state.monitor.as_mut().unwrap().update(
+            phase,
+            &[],
+            &[],
+            real_time,
+            t,
+            lib_time,
+            sys_times.0,
+            sys_times.1,
+            proc_times.0,
+        );
+        update_lib_time(state, stopwatch.elapsed());
         return Ok(());
     }
 
     log::trace!("Frame begun");
 
-    let t = dcs::get_model_time(lua);
-    let b = dcs::get_ballistics_objects(lua);
-    let u = dcs::get_unit_objects(lua);
-    let lib_time = get_lib_state().lib_last_elapsed_time;
+    let is_object_poll = is_object_poll_due(state, real_time);
+    if is_object_poll {
+        state.last_ballistics = state.object_pool.get_ballistics_objects(lua)?;
+        state.last_units = state.object_pool.get_unit_objects(lua)?;
+    }
+    let ballistics = state.last_ballistics.clone();
+    let units = state.last_units.clone();
 
-    get_lib_state().monitor.as_mut().unwrap().update(
-        &u,
-        &b,
+    state.monitor.as_mut().unwrap().update(
+        phase,
+        &units,
+        &ballistics,
         real_time,
         t,
         lib_time,
@@ -372,70 +1273,568 @@ pub fn on_frame_begin(lua: &Lua, _: ()) -> LuaResult<()> {
         proc_times.0,
     );
 
-    let ballistics = Arc::new(b);
-    let units = Arc::new(u);
     let worker_msg = worker::Message::Update {
         units: units.clone(),
         ballistics: ballistics.clone(),
+        num_units: units.len(),
+        num_ballistics: ballistics.len(),
         game_time: t,
         real_time: real_time,
+        epoch_ms: epoch_ms(),
+        lib_time,
+        memory,
         proc_time: proc_times,
         sys_time: sys_times,
     };
+    // frames in between polls still get logged, just without the objects
+    let worker_msg = if is_object_poll {
+        worker_msg
+    } else {
+        worker_msg.without_objects()
+    };
 
     let gui_msg = gui::Message::Update {
         units: units.clone(),
         ballistics: ballistics.clone(),
         game_time: t,
         real_time: real_time,
+        lib_time,
+        memory,
+    };
+
+    if !state
+        .logging_paused
+        .load(std::sync::atomic::Ordering::SeqCst)
+    {
+        send_worker_message(state, worker_msg);
+        check_worker_queue(state);
+    }
+    if is_gui_shown(state) {
+        send_gui_message(state, gui_msg);
+    }
+    update_lib_time(state, stopwatch.elapsed());
+    Ok(())
+}
+
+fn on_frame_end(_lib: &mut Option<LibState>, _lua: &Lua, _: ()) -> LuaResult<()> {
+    Ok(())
+}
+
+fn send_event(
+    state: &mut FullState,
+    lua: &Lua,
+    kind: EventKind,
+    player_id: i32,
+    details: String,
+) -> LuaResult<()> {
+    let player_name = match get_player_info(lua, player_id) {
+        Some(info) => info.name,
+        None => "".to_string(),
+    };
+    let event = Event {
+        t_game: dcs::get_model_time(lua)?,
+        t_real: state.elapsed_time(),
+        kind,
+        player_id,
+        player_name,
+        details,
+        epoch_ms: epoch_ms(),
+    };
+    if event.is_admin_action() {
+        log::info!(
+            "Admin action {:?} affecting player {} ({}): {}",
+            event.kind,
+            event.player_id,
+            event.player_name,
+            event.details
+        );
+    }
+    send_worker_message(state, worker::Message::Event(event));
+    Ok(())
+}
+
+fn on_player_disconnect(
+    state: &mut FullState,
+    lua: &Lua,
+    (id, err_code): (i32, i32),
+) -> LuaResult<()> {
+    let kind = if Some(err_code) == dcs::get_net_constant(lua, "ERR_KICKED") {
+        EventKind::Kick
+    } else if Some(err_code) == dcs::get_net_constant(lua, "ERR_BANNED") {
+        EventKind::Ban
+    } else {
+        EventKind::Disconnect
+    };
+    let real_time = state.elapsed_time();
+    let details = match state.players.disconnect(id, real_time) {
+        Some(connected_for) => format!("err_code={} connected_for={:.0}s", err_code, connected_for),
+        None => format!("err_code={}", err_code),
+    };
+    send_event(state, lua, kind, id, details)
+}
+
+fn on_player_connect(state: &mut FullState, lua: &Lua, id: i32) -> LuaResult<()> {
+    let Some(info) = get_player_info(lua, id) else {
+        log::warn!("Connect from unknown player {}", id);
+        return Ok(());
     };
+    let details = format!("ucid={}", info.ucid);
+    let real_time = state.elapsed_time();
+    state.players.connect(info, real_time);
+    send_event(state, lua, EventKind::Connect, id, details)
+}
+
+/// Handled here rather than as a generic callback so the client's IP address
+/// never ends up in the event log.
+fn on_player_try_connect(
+    state: &mut FullState,
+    lua: &Lua,
+    (_addr, ucid, name, id): (mlua::Value, String, String, i32),
+) -> LuaResult<()> {
+    let details = format!("onPlayerTryConnect(ucid={}, name={})", ucid, name);
+    send_event(state, lua, EventKind::Callback, id, details)
+}
+
+fn on_player_change_slot(state: &mut FullState, lua: &Lua, id: i32) -> LuaResult<()> {
+    let Some(info) = get_player_info(lua, id) else {
+        log::warn!("Slot change for unknown player {}", id);
+        return Ok(());
+    };
+    let (new_slot, new_side) = (info.slot.clone(), info.side);
+    let real_time = state.elapsed_time();
+    let (old_slot, old_side) = state.players.change_slot(info, real_time);
+    let details = format!(
+        "from={} ({}) to={} ({}) side={} old_side={}",
+        old_slot,
+        dcs::get_unit_type(lua, &old_slot)?,
+        new_slot,
+        dcs::get_unit_type(lua, &new_slot)?,
+        players::side_name(new_side),
+        players::side_name(old_side)
+    );
+    send_event(state, lua, EventKind::SlotChange, id, details)
+}
+
+fn on_game_event(
+    state: &mut FullState,
+    lua: &Lua,
+    (name, args): (String, mlua::MultiValue),
+) -> LuaResult<()> {
+    let mut args: Vec<String> = args.into_iter().map(dcs::value_to_string).collect();
+    // the hook always passes all seven optional arguments
+    while args.last().map_or(false, |a| a.is_empty()) {
+        args.pop();
+    }
+    if let Some((kind, player_id, mut details)) = events::from_game_event(&name, &args) {
+        // DCS only passes the unit's mission id
+        if let (EventKind::Takeoff | EventKind::Landing, Some(unit_id)) = (kind, args.get(1)) {
+            details = format!(
+                "{} unit={} type={}",
+                details,
+                dcs::get_unit_name(lua, unit_id)?,
+                dcs::get_unit_type(lua, unit_id)?
+            );
+        }
+        send_event(state, lua, kind, player_id, details)?;
+    }
+    Ok(())
+}
 
-    send_worker_message(worker_msg);
-    if is_gui_shown() {
-        send_gui_message(gui_msg);
+/// Tells us whether a message went to everyone or just the coalition, and
+/// answers `-tetrad` commands. The message itself is logged from
+/// `on_chat_message`.
+fn on_player_try_send_chat(
+    state: &mut FullState,
+    lua: &Lua,
+    (player_id, message, all): (i32, String, mlua::Value),
+) -> LuaResult<()> {
+    // Lua truthiness, DCS versions differ in what they pass here
+    let all = !matches!(all, mlua::Value::Nil | mlua::Value::Boolean(false));
+    state.chat_scopes.insert(player_id, all);
+    if let Some(command) = chat::Command::parse(&message) {
+        answer_chat_command(state, lua, player_id, command);
     }
-    update_lib_time(get_elapsed_time() - real_time);
     Ok(())
 }
 
-#[no_mangle]
-pub fn on_frame_end(_lua: &Lua, _: ()) -> LuaResult<()> {
+/// Replies to a `-tetrad` chat command, only to the player who sent it.
+fn answer_chat_command(state: &FullState, lua: &Lua, player_id: i32, command: chat::Command) {
+    let config = &state.config;
+    if !config.enable_chat_commands {
+        return;
+    }
+    if !config.chat_command_players.is_empty() {
+        let ucid = get_player_info(lua, player_id).map_or_else(String::new, |p| p.ucid);
+        if !config.chat_command_players.contains(&ucid) {
+            log::info!(
+                "Ignoring chat command from player {} ({}), who isn't in chat_command_players",
+                player_id,
+                ucid
+            );
+            return;
+        }
+    }
+    log::info!("Chat command {:?} from player {}", command, player_id);
+    let reply = match command {
+        chat::Command::Status => format_status_reply(state, lua),
+        chat::Command::Fps => format_fps_reply(state),
+        chat::Command::Objects => format_objects_reply(state),
+        chat::Command::Help => chat::COMMAND_HELP.to_string(),
+    };
+    if let Err(e) = dcs::send_chat_to(lua, &reply, player_id) {
+        log::warn!("Couldn't send chat message to player {}: {}", player_id, e);
+    }
+}
+
+fn format_status_reply(state: &FullState, lua: &Lua) -> String {
+    let fps = load_summary::compute(&state.frame_times).map_or(0.0, |s| s.fps);
+    let num_players = count_players(lua).unwrap_or_default();
+    let uptime = state.start_time.elapsed().as_secs();
+    let memory = state.perf_mon.process_memory();
+    format!(
+        "{}: {:.0} FPS, {} players, {} units, {} ballistics, {} MB working set, up {}h {:02}m",
+        state.mission_name,
+        fps,
+        num_players,
+        state.last_units.len(),
+        state.last_ballistics.len(),
+        memory.working_set / (1024 * 1024),
+        uptime / 3600,
+        uptime / 60 % 60
+    )
+}
+
+fn format_fps_reply(state: &FullState) -> String {
+    match load_summary::compute(&state.frame_times) {
+        Some(s) => format!(
+            "{:.1} FPS over the last {} frames, frame times p50 {:.1} ms, p90 {:.1} ms, p99 {:.1} ms, max {:.1} ms ({})",
+            s.fps, s.num_frames, s.p50, s.p90, s.p99, s.max, s.trend
+        ),
+        None => "No frame times yet".to_string(),
+    }
+}
+
+fn format_objects_reply(state: &FullState) -> String {
+    // by coalition id: neutral, red, blue
+    let mut by_coalition = [0usize; 3];
+    for unit in state.last_units.iter() {
+        by_coalition[unit.object().coalition_id().clamp(0, 2) as usize] += 1;
+    }
+    format!(
+        "{} units (red {}, blue {}, neutral {}), {} ballistics",
+        state.last_units.len(),
+        by_coalition[1],
+        by_coalition[2],
+        by_coalition[0],
+        state.last_ballistics.len()
+    )
+}
+
+fn on_chat_message(
+    state: &mut FullState,
+    lua: &Lua,
+    (message, from): (String, i32),
+) -> LuaResult<()> {
+    let player_name = match get_player_info(lua, from) {
+        Some(info) => info.name,
+        None => "".to_string(),
+    };
+    let scope = match state.chat_scopes.remove(&from) {
+        Some(true) => "all",
+        Some(false) => "coalition",
+        None => "",
+    };
+    send_worker_message(
+        state,
+        worker::Message::Chat(chat::ChatRecord {
+            t_game: dcs::get_model_time(lua)?,
+            t_real: state.elapsed_time(),
+            player_id: from,
+            player_name,
+            scope: scope.to_string(),
+            message,
+        }),
+    );
+    Ok(())
+}
+
+fn on_simulation_pause(state: &mut FullState, lua: &Lua, _: ()) -> LuaResult<()> {
+    send_event(state, lua, EventKind::Pause, 0, "".to_string())
+}
+
+fn on_simulation_resume(state: &mut FullState, lua: &Lua, _: ()) -> LuaResult<()> {
+    send_event(state, lua, EventKind::Resume, 0, "".to_string())
+}
+
+fn on_callback(
+    state: &mut FullState,
+    lua: &Lua,
+    callback: &callbacks::Callback,
+    args: mlua::MultiValue,
+) -> LuaResult<()> {
+    let args: Vec<String> = args.into_iter().map(dcs::value_to_string).collect();
+    let player_id = callback
+        .player_arg
+        .and_then(|i| args.get(i))
+        .and_then(|id| id.parse().ok())
+        .unwrap_or(0);
+    let details = format!("{}({})", callback.name, args.join(", "));
+    send_event(state, lua, EventKind::Callback, player_id, details)
+}
+
+/// Returns up to the last `n` frame times (in seconds, oldest first), so that
+/// other hooks can throttle themselves when the server is struggling. Empty
+/// when no mission is running.
+fn get_frame_times(lib: &mut Option<LibState>, _lua: &Lua, n: usize) -> LuaResult<Vec<f64>> {
+    let Some(state) = session(lib) else {
+        return Ok(Vec::new());
+    };
+    let skip = state.frame_times.len().saturating_sub(n);
+    Ok(state.frame_times.iter().skip(skip).copied().collect())
+}
+
+/// Recent frame time percentiles, FPS and trend as a table, or nil when no
+/// mission is running.
+fn get_load_summary<'lua>(
+    lib: &mut Option<LibState>,
+    lua: &'lua Lua,
+    _: (),
+) -> LuaResult<mlua::Value<'lua>> {
+    use mlua::LuaSerdeExt;
+    let summary = session(lib).and_then(|state| load_summary::compute(&state.frame_times));
+    match summary {
+        Some(s) => lua.to_value(&s),
+        None => Ok(mlua::Value::Nil),
+    }
+}
+
+/// Drops a labeled marker into the frame and event logs and the GUI plots at
+/// the current mission time. Does nothing when no mission is running.
+fn mark(lib: &mut Option<LibState>, lua: &Lua, label: String) -> LuaResult<()> {
+    let Some(state) = session(lib) else {
+        return Ok(());
+    };
+    let marker = Marker::new(dcs::get_model_time(lua)?, &label);
+    let real_time = state.elapsed_time();
+    send_marker(state, marker, real_time);
     Ok(())
 }
 
-#[no_mangle]
-pub fn stop(_lua: &Lua, _: ()) -> LuaResult<()> {
+/// Opens the GUI window again after it was closed. Returns false when no
+/// mission is running or the GUI is disabled.
+fn show_gui(lib: &mut Option<LibState>, _lua: &Lua, _: ()) -> LuaResult<bool> {
+    Ok(session(lib).map_or(false, reopen_gui))
+}
+
+fn stop(lib: &mut Option<LibState>, _lua: &Lua, _: ()) -> LuaResult<()> {
+    let Some(state) = session(lib) else {
+        log::warn!("Stopped without a running session, nothing to do");
+        return Ok(());
+    };
     log::debug!("Mission stopping");
-    send_worker_message(worker::Message::Stop);
-    let monitor = std::mem::take(&mut get_lib_state().monitor);
+    let queue_stats = state.worker_tx.stats();
+    if queue_stats.total() > 0 {
+        log::warn!(
+            "The worker fell behind: {} frames dropped, {} frames logged without objects",
+            queue_stats.dropped_frames,
+            queue_stats.stripped_frames
+        );
+    }
+    if queue_stats.discarded_messages > 0 {
+        log::warn!(
+            "{} messages were thrown away while the worker was stalled",
+            queue_stats.discarded_messages
+        );
+    }
+    if state.gui_dropped_messages > 0 {
+        log::warn!(
+            "{} messages for the GUI were dropped while it wasn't keeping up",
+            state.gui_dropped_messages
+        );
+    }
+    let is_worker_stalled = state.worker_tx.is_stalled();
+    let dropped = dcs::dropped_objects();
+    if dropped > 0 {
+        log::warn!(
+            "{} malformed objects were left out of this session's logs",
+            dropped
+        );
+    }
+    notify(
+        state,
+        Severity::Info,
+        "Mission stopped, logging finished".to_string(),
+    );
+    send_worker_message(state, worker::Message::Stop);
+    let monitor = std::mem::take(&mut state.monitor);
     let handle = monitor.unwrap().stop();
     handle.join().unwrap_or_else(|_| {
         log::error!("Failed to join monitor thread");
     });
 
-    if let Some(LibState::WorkerStarted(state)) = unsafe { LIB_STATE.take() } {
-        state.worker_join.join().unwrap();
-        unsafe {
-            LIB_STATE = Some(LibState::GuiStarted(
-                state.gui_tx,
-                state.rx_from_gui,
-                state.is_gui_shown,
-                state.gui_context,
-            ))
-        };
-    } else {
-        panic!("Worker wasn't running!")
+    // checked above that a session is running
+    if let Some(LibState::WorkerStarted(state)) = lib.take() {
+        if is_worker_stalled {
+            // joining would hang DCS, the thread finishes on its own if it ever gets unstuck
+            log::error!("Worker thread is stalled, not waiting for it to finish");
+        } else if state.worker_join.join().is_err() {
+            log::error!("Worker thread panicked");
+        }
+        *lib = Some(LibState::GuiStarted(
+            state.gui_tx,
+            state.rx_from_gui,
+            state.is_gui_shown,
+            state.logging_paused,
+        ));
     }
+    json_log::set_session(None);
     log::logger().flush();
     Ok(())
 }
 
+/// Set once an entry point panicked. The state it left behind can't be
+/// trusted, so from then on every entry point does nothing until DCS is
+/// restarted.
+static IS_DISABLED: AtomicBool = AtomicBool::new(false);
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else {
+        "unknown panic"
+    }
+}
+
+/// Runs the entry point `name` with the state locked, turning a panic into a
+/// logged Lua error instead of unwinding into DCS. Returns `fallback` once
+/// tetrad is disabled, or when Lua calls back into tetrad from inside one of
+/// its own calls.
+fn guard<R>(
+    name: &str,
+    fallback: R,
+    f: impl FnOnce(&mut Option<LibState>) -> LuaResult<R>,
+) -> LuaResult<R> {
+    if IS_DISABLED.load(std::sync::atomic::Ordering::SeqCst) {
+        return Ok(fallback);
+    }
+    // only the DCS thread takes the lock, so it's held already if e.g. a chat
+    // message tetrad sent fired a callback straight away
+    let mut lib = match LIB_STATE.try_lock() {
+        Ok(lib) => lib,
+        // panics are caught before they unwind past the lock, and disable
+        // tetrad anyway
+        Err(TryLockError::Poisoned(e)) => e.into_inner(),
+        Err(TryLockError::WouldBlock) => {
+            log::trace!(
+                "{} called from inside another tetrad call, skipping it",
+                name
+            );
+            return Ok(fallback);
+        }
+    };
+    match std::panic::catch_unwind(AssertUnwindSafe(|| f(&mut *lib))) {
+        Ok(result) => result,
+        Err(payload) => {
+            IS_DISABLED.store(true, std::sync::atomic::Ordering::SeqCst);
+            let message = format!(
+                "tetrad panicked in {}: {}. It is disabled until DCS is restarted",
+                name,
+                panic_message(payload.as_ref())
+            );
+            log::error!("{}", message);
+            log::logger().flush();
+            Err(mlua::Error::RuntimeError(message))
+        }
+    }
+}
+
+/// `lua.create_function` for the entry point `$f`, run through `guard`.
+/// With `needs_session`, calls while no session is running just return
+/// `$fallback`.
+macro_rules! entry_point {
+    ($lua:expr, $f:ident, $fallback:expr) => {
+        $lua.create_function(|lua, args| {
+            guard(stringify!($f), $fallback, |lib| $f(lib, lua, args))
+        })?
+    };
+    ($lua:expr, $f:ident, $fallback:expr, needs_session) => {
+        $lua.create_function(|lua, args| {
+            guard(stringify!($f), $fallback, |lib| match session(lib) {
+                Some(state) => $f(state, lua, args),
+                None => Ok($fallback),
+            })
+        })?
+    };
+}
+
 #[mlua::lua_module]
 pub fn dcs_tetrad(lua: &Lua) -> LuaResult<LuaTable> {
     let exports = lua.create_table()?;
-    exports.set("start", lua.create_function(start)?)?;
-    exports.set("on_frame_begin", lua.create_function(on_frame_begin)?)?;
-    exports.set("on_frame_end", lua.create_function(on_frame_end)?)?;
-    exports.set("stop", lua.create_function(stop)?)?;
+    exports.set("start", entry_point!(lua, start, 0))?;
+    exports.set("on_frame_begin", entry_point!(lua, on_frame_begin, ()))?;
+    exports.set("on_frame_end", entry_point!(lua, on_frame_end, ()))?;
+    exports.set("stop", entry_point!(lua, stop, ()))?;
+    exports.set(
+        "get_frame_times",
+        entry_point!(lua, get_frame_times, Vec::new()),
+    )?;
+    exports.set(
+        "get_load_summary",
+        entry_point!(lua, get_load_summary, mlua::Value::Nil),
+    )?;
+    exports.set("show_gui", entry_point!(lua, show_gui, false))?;
+    exports.set("mark", entry_point!(lua, mark, ()))?;
+    exports.set(
+        "on_player_disconnect",
+        entry_point!(lua, on_player_disconnect, (), needs_session),
+    )?;
+    exports.set(
+        "on_chat_message",
+        entry_point!(lua, on_chat_message, (), needs_session),
+    )?;
+    exports.set(
+        "on_player_try_send_chat",
+        entry_point!(lua, on_player_try_send_chat, (), needs_session),
+    )?;
+    exports.set(
+        "on_game_event",
+        entry_point!(lua, on_game_event, (), needs_session),
+    )?;
+    exports.set(
+        "on_player_connect",
+        entry_point!(lua, on_player_connect, (), needs_session),
+    )?;
+    exports.set(
+        "on_player_try_connect",
+        entry_point!(lua, on_player_try_connect, (), needs_session),
+    )?;
+    exports.set(
+        "on_player_change_slot",
+        entry_point!(lua, on_player_change_slot, (), needs_session),
+    )?;
+    exports.set(
+        "on_simulation_pause",
+        entry_point!(lua, on_simulation_pause, (), needs_session),
+    )?;
+    exports.set(
+        "on_simulation_resume",
+        entry_point!(lua, on_simulation_resume, (), needs_session),
+    )?;
+    let generic = callbacks::CALLBACKS
+        .iter()
+        .filter(|cb| cb.handler == callbacks::Handler::Generic);
+    for cb in generic {
+        exports.set(
+            cb.export_name(),
+            lua.create_function(move |lua, args| {
+                guard(cb.name, (), |lib| match session(lib) {
+                    Some(state) => on_callback(state, lua, cb, args),
+                    None => Ok(()),
+                })
+            })?,
+        )?;
+    }
     Ok(exports)
 }
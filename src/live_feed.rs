@@ -0,0 +1,192 @@
+// The latest frame stats and objects in a named shared memory region
+// (`Local\tetrad-live-<instance>`), for overlays and other tools on the same
+// machine that want live data without a file or socket. Readers only ever
+// see the newest snapshot; the layout is documented in the Readme.
+//
+// The region starts with a 32 byte little-endian header:
+//   0  magic    b"TTRL"
+//   4  version  u32, currently 1
+//   8  sequence u64, odd while a snapshot is being written
+//   16 capacity u64, bytes available for the snapshot
+//   24 length   u64, bytes of the current snapshot
+// followed by the snapshot, UTF-8 JSON. A reader copies the snapshot and
+// checks the sequence was the same even number before and after.
+use crate::dcs::{DcsWorldObject, DcsWorldUnit};
+use crate::shm_ring::to_wide;
+use crate::sink::{Frame, Sink, SinkResult};
+use serde::Serialize;
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+use std::sync::Arc;
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{CloseHandle, HANDLE, INVALID_HANDLE_VALUE};
+use windows::Win32::System::Memory::{
+    CreateFileMappingW, MapViewOfFile, UnmapViewOfFile, FILE_MAP_ALL_ACCESS, PAGE_READWRITE,
+};
+
+const MAGIC: [u8; 4] = *b"TTRL";
+const VERSION: u32 = 1;
+const CAPACITY: usize = 16 * 1024 * 1024;
+
+#[repr(C)]
+struct Header {
+    magic: [u8; 4],
+    version: u32,
+    sequence: AtomicU64,
+    capacity: u64,
+    length: AtomicU64,
+}
+
+const HEADER_SIZE: usize = std::mem::size_of::<Header>();
+
+#[derive(Serialize)]
+struct Snapshot<'a> {
+    mission: &'a str,
+    frame: i32,
+    game_time: f64,
+    real_time: f64,
+    lib_time: f64,
+    num_units: usize,
+    num_ballistics: usize,
+    /// Game time the objects are from, they're only read every
+    /// `object_poll_interval`.
+    objects_time: f64,
+    /// Set when the objects didn't fit and were left out.
+    objects_truncated: bool,
+    units: &'a [DcsWorldUnit],
+    ballistics: &'a [DcsWorldObject],
+}
+
+pub struct LiveFeed {
+    handle: HANDLE,
+    view: *mut u8,
+    mission_name: String,
+    /// Real seconds between snapshots.
+    interval: f64,
+    last_publish_time: Option<f64>,
+    units: Arc<Vec<DcsWorldUnit>>,
+    ballistics: Arc<Vec<DcsWorldObject>>,
+    objects_time: f64,
+}
+
+// Only the worker thread writes the mapping, other processes only read it.
+unsafe impl Send for LiveFeed {}
+
+impl LiveFeed {
+    pub fn create(name: &str, mission_name: &str, interval: f64) -> windows::core::Result<Self> {
+        let wide = to_wide(name);
+        let total = (HEADER_SIZE + CAPACITY) as u64;
+        unsafe {
+            let handle = CreateFileMappingW(
+                INVALID_HANDLE_VALUE,
+                None,
+                PAGE_READWRITE,
+                (total >> 32) as u32,
+                total as u32,
+                PCWSTR(wide.as_ptr()),
+            )?;
+            let view = MapViewOfFile(handle, FILE_MAP_ALL_ACCESS, 0, 0, total as usize);
+            if view.is_null() {
+                let err = windows::core::Error::from_win32();
+                CloseHandle(handle);
+                return Err(err);
+            }
+            std::ptr::write_volatile(
+                view as *mut Header,
+                Header {
+                    magic: MAGIC,
+                    version: VERSION,
+                    sequence: AtomicU64::new(0),
+                    capacity: CAPACITY as u64,
+                    length: AtomicU64::new(0),
+                },
+            );
+            Ok(Self {
+                handle,
+                view: view as *mut u8,
+                mission_name: mission_name.to_string(),
+                interval,
+                last_publish_time: None,
+                units: Arc::default(),
+                ballistics: Arc::default(),
+                objects_time: 0.0,
+            })
+        }
+    }
+
+    fn header(&self) -> &Header {
+        unsafe { &*(self.view as *const Header) }
+    }
+
+    fn publish(&self, snapshot: &[u8]) {
+        let header = self.header();
+        let sequence = header.sequence.load(Ordering::Relaxed);
+        header.sequence.store(sequence + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                snapshot.as_ptr(),
+                self.view.add(HEADER_SIZE),
+                snapshot.len(),
+            );
+        }
+        header
+            .length
+            .store(snapshot.len() as u64, Ordering::Relaxed);
+        header.sequence.store(sequence + 2, Ordering::Release);
+    }
+}
+
+impl Sink for LiveFeed {
+    fn name(&self) -> &'static str {
+        "live feed"
+    }
+
+    fn write_frame(&mut self, frame: &Frame) -> SinkResult {
+        if frame.has_objects() {
+            self.units = frame.units.clone();
+            self.ballistics = frame.ballistics.clone();
+            self.objects_time = frame.game_time;
+        }
+        if let Some(last) = self.last_publish_time {
+            if frame.real_time - last < self.interval {
+                return Ok(());
+            }
+        }
+        self.last_publish_time = Some(frame.real_time);
+        let mut snapshot = Snapshot {
+            mission: &self.mission_name,
+            frame: frame.frame_count,
+            game_time: frame.game_time,
+            real_time: frame.real_time,
+            lib_time: frame.lib_time,
+            num_units: frame.num_units,
+            num_ballistics: frame.num_ballistics,
+            objects_time: self.objects_time,
+            objects_truncated: false,
+            units: &self.units,
+            ballistics: &self.ballistics,
+        };
+        let mut json = serde_json::to_vec(&snapshot)?;
+        if json.len() > CAPACITY {
+            snapshot.objects_truncated = true;
+            snapshot.units = &[];
+            snapshot.ballistics = &[];
+            json = serde_json::to_vec(&snapshot)?;
+        }
+        self.publish(&json);
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> SinkResult {
+        Ok(())
+    }
+}
+
+impl Drop for LiveFeed {
+    fn drop(&mut self) {
+        unsafe {
+            UnmapViewOfFile(self.view as *const libc::c_void);
+            CloseHandle(self.handle);
+        }
+    }
+}
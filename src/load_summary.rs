@@ -0,0 +1,98 @@
+// Compact view of recent server load, aimed at missions that scale their AI up
+// or down depending on how the server is coping.
+use serde::Serialize;
+use std::collections::VecDeque;
+
+// number of most recent frames summarized, and compared against the same
+// number of frames before them to get the trend
+const WINDOW_LEN: usize = 512;
+const TREND_THRESHOLD: f64 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Trend {
+    Improving,
+    Stable,
+    Degrading,
+}
+
+impl std::fmt::Display for Trend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Improving => "improving",
+            Self::Stable => "stable",
+            Self::Degrading => "degrading",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Frame time percentiles are in milliseconds.
+#[derive(Debug, Clone, Serialize)]
+pub struct LoadSummary {
+    pub num_frames: usize,
+    pub fps: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+    pub trend: Trend,
+}
+
+fn mean(v: &[f64]) -> f64 {
+    v.iter().sum::<f64>() / v.len() as f64
+}
+
+/// Nearest-rank percentile of already sorted values, `p` in 0-100.
+pub fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+pub fn compute(frame_times: &VecDeque<f64>) -> Option<LoadSummary> {
+    let all: Vec<f64> = frame_times.iter().copied().collect();
+    if all.is_empty() {
+        return None;
+    }
+    let split = all.len().saturating_sub(WINDOW_LEN);
+    let recent = &all[split..];
+    let previous = &all[split.saturating_sub(WINDOW_LEN)..split];
+
+    let trend = if previous.is_empty() {
+        Trend::Stable
+    } else {
+        let ratio = mean(recent) / mean(previous);
+        if ratio > 1.0 + TREND_THRESHOLD {
+            Trend::Degrading
+        } else if ratio < 1.0 - TREND_THRESHOLD {
+            Trend::Improving
+        } else {
+            Trend::Stable
+        }
+    };
+
+    let mut sorted = recent.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    Some(LoadSummary {
+        num_frames: recent.len(),
+        fps: 1.0 / mean(recent),
+        p50: percentile(&sorted, 50.0) * 1000.0,
+        p90: percentile(&sorted, 90.0) * 1000.0,
+        p99: percentile(&sorted, 99.0) * 1000.0,
+        max: sorted[sorted.len() - 1] * 1000.0,
+        trend,
+    })
+}
+
+impl LoadSummary {
+    /// Lua statement assigning the summary to the global `TETRAD_LOAD`.
+    pub fn to_lua_assignment(&self) -> String {
+        format!(
+            "TETRAD_LOAD = {{ num_frames = {}, fps = {:.3}, p50 = {:.3}, p90 = {:.3}, p99 = {:.3}, max = {:.3}, trend = \"{}\" }}",
+            self.num_frames, self.fps, self.p50, self.p90, self.p99, self.max, self.trend
+        )
+    }
+}
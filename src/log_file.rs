@@ -0,0 +1,88 @@
+// `dcs_tetrad.log`, rotated by size. Each DCS start begins a new file, and
+// once one grows past `log_max_size_mb` it's moved to `dcs_tetrad.1.log` (the
+// one before that to `dcs_tetrad.2.log`, and so on up to `log_keep_files`).
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const MEGABYTE: f64 = 1024.0 * 1024.0;
+
+pub struct RotatingLog {
+    path: PathBuf,
+    /// Only `None` while rotating, Windows can't rename an open file.
+    file: Option<File>,
+    size: u64,
+    /// 0 to never rotate while running.
+    max_size: u64,
+    keep: u32,
+}
+
+fn backup_path(path: &Path, n: u32) -> PathBuf {
+    path.with_extension(format!("{}.log", n))
+}
+
+/// Shifts the old logs along, dropping the oldest, and moves `path` to the
+/// first backup (or deletes it when none are kept).
+fn rotate(path: &Path, keep: u32) -> io::Result<()> {
+    if keep == 0 {
+        return match std::fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    }
+    // the oldest one is allowed to be missing, as is any of them
+    let _ = std::fs::remove_file(backup_path(path, keep));
+    for n in (1..keep).rev() {
+        let _ = std::fs::rename(backup_path(path, n), backup_path(path, n + 1));
+    }
+    match std::fs::rename(path, backup_path(path, 1)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+impl RotatingLog {
+    /// Starts a new log at `path`, keeping the last one as a backup.
+    pub fn open(path: &Path, max_size_mb: f64, keep: u32) -> io::Result<Self> {
+        rotate(path, keep)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: Some(File::create(path)?),
+            size: 0,
+            max_size: (max_size_mb.max(0.0) * MEGABYTE) as u64,
+            keep,
+        })
+    }
+}
+
+impl Write for RotatingLog {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => self.file.insert(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)?,
+            ),
+        };
+        let n = file.write(buf)?;
+        self.size += n as u64;
+        // only at the end of a line, so entries aren't split across files
+        if self.max_size > 0 && self.size >= self.max_size && buf[..n].ends_with(b"\n") {
+            drop(self.file.take());
+            self.size = 0;
+            // there's nowhere to report this, it just keeps appending to the
+            // same file
+            let _ = rotate(&self.path, self.keep);
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
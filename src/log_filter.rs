@@ -0,0 +1,111 @@
+// Leaves objects the mission doesn't care about out of the object logs, e.g.
+// everything but the blue side on a training server, or everything outside
+// one range. The GUI, telemetry and flight recordings still get every object.
+use crate::config::{Config, Region};
+use crate::dcs::{DcsWorldObject, DcsWorldUnit};
+use crate::sink::Frame;
+use std::sync::Arc;
+
+pub struct LogFilter {
+    /// Coalition ids to keep, all of them if empty.
+    coalitions: Vec<i32>,
+    region: Option<Region>,
+}
+
+impl LogFilter {
+    /// None if `log_coalitions` and `log_region` let every object through.
+    pub fn new(config: &Config) -> Option<Self> {
+        let region = config.log_region.is_set().then_some(config.log_region);
+        if config.log_coalitions.is_empty() && region.is_none() {
+            return None;
+        }
+        Some(Self {
+            coalitions: config.log_coalitions.iter().map(|c| c.id()).collect(),
+            region,
+        })
+    }
+
+    fn keeps(&self, object: &DcsWorldObject) -> bool {
+        if !self.coalitions.is_empty() && !self.coalitions.contains(&object.coalition_id()) {
+            return false;
+        }
+        let (lat, lon, _) = object.lat_lon_alt();
+        self.region.map_or(true, |r| r.contains(lat, lon))
+    }
+
+    /// The units and ballistics from `frame` that should go into the object
+    /// logs.
+    pub fn apply(&self, frame: &Frame) -> (Arc<Vec<DcsWorldUnit>>, Arc<Vec<DcsWorldObject>>) {
+        let units = frame
+            .units
+            .iter()
+            .filter(|unit| self.keeps(unit.object()))
+            .cloned()
+            .collect();
+        let ballistics = frame
+            .ballistics
+            .iter()
+            .filter(|object| self.keeps(object))
+            .cloned()
+            .collect();
+        (Arc::new(units), Arc::new(ballistics))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Coalition;
+
+    fn object(coalition_id: i32, lat: f64, lon: f64) -> DcsWorldObject {
+        serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "name": "T-72B",
+            "country": 0,
+            "coalition": "",
+            "coalition_id": coalition_id,
+            "lat_lon_alt": { "lat": lat, "lon": lon, "alt": 0.0 },
+            "heading": 0.0,
+            "pitch": 0.0,
+            "bank": 0.0,
+            "position": { "x": 0.0, "y": 0.0, "z": 0.0 },
+            "category": "ground",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn no_filter_without_settings() {
+        assert!(LogFilter::new(&Config::default()).is_none());
+    }
+
+    #[test]
+    fn keeps_only_the_picked_coalitions() {
+        let config = Config {
+            log_coalitions: vec![Coalition::Blue, Coalition::Neutral],
+            ..Config::default()
+        };
+        let filter = LogFilter::new(&config).unwrap();
+        assert!(filter.keeps(&object(2, 0.0, 0.0)));
+        assert!(filter.keeps(&object(0, 0.0, 0.0)));
+        assert!(!filter.keeps(&object(1, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn keeps_only_objects_in_the_region() {
+        let config = Config {
+            log_coalitions: vec![Coalition::Red],
+            log_region: Region {
+                south: 41.0,
+                west: 41.0,
+                north: 43.0,
+                east: 45.0,
+            },
+            ..Config::default()
+        };
+        let filter = LogFilter::new(&config).unwrap();
+        assert!(filter.keeps(&object(1, 42.0, 43.0)));
+        assert!(!filter.keeps(&object(1, 44.0, 43.0)));
+        assert!(!filter.keeps(&object(2, 42.0, 43.0)));
+    }
+}
@@ -0,0 +1,139 @@
+// `Logs/Tetrad/sessions/<mission> - <time>.session.json`, describing one
+// session for analysis scripts: what ran, with which settings, and which files
+// it wrote. It's written when the session starts and rewritten with the end
+// time, frame count and output files when it stops.
+use crate::config::Config;
+use crate::dcs;
+use crate::weather::Weather;
+use mlua::{Lua, LuaSerdeExt};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tetrad_data::schema;
+
+/// What a session ran on, for grouping sessions in analysis.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SessionMetadata {
+    /// Random UUID of this session, also in every row of the frame, object
+    /// and event logs, to join them with other logs.
+    pub session_id: String,
+    pub theatre: Option<String>,
+    pub dcs_version: Option<String>,
+    /// Last part of `dcs_version`, e.g. 37556.
+    pub dcs_build: Option<u32>,
+    pub multiplayer: bool,
+    /// Whether this DCS instance hosts the mission, as a server or as the
+    /// player hosting it.
+    pub server: bool,
+    /// The server's settings when hosting a multiplayer mission, as DCS
+    /// stores them, without passwords.
+    pub server_settings: Option<serde_json::Value>,
+}
+
+/// Drops every key with "password" in its name, at any depth.
+fn remove_passwords(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            map.retain(|key, _| !key.to_lowercase().contains("password"));
+            map.values_mut().for_each(remove_passwords);
+        }
+        serde_json::Value::Array(values) => values.iter_mut().for_each(remove_passwords),
+        _ => {}
+    }
+}
+
+fn read_server_settings(lua: &Lua, write_dir: &str) -> Option<serde_json::Value> {
+    let table = dcs::get_server_settings(lua, write_dir)?;
+    match lua.from_value::<serde_json::Value>(mlua::Value::Table(table)) {
+        Ok(mut settings) => {
+            remove_passwords(&mut settings);
+            Some(settings)
+        }
+        Err(e) => {
+            log::warn!("Couldn't read the server settings: {}", e);
+            None
+        }
+    }
+}
+
+impl SessionMetadata {
+    pub fn read(lua: &Lua, write_dir: &str) -> Self {
+        let dcs_version = dcs::get_dcs_version(lua);
+        let dcs_build = dcs_version
+            .as_deref()
+            .and_then(|v| v.rsplit('.').next())
+            .and_then(|build| build.parse().ok());
+        let multiplayer = dcs::is_multiplayer(lua);
+        let server = dcs::is_server(lua);
+        Self {
+            session_id: uuid::Uuid::new_v4().to_string(),
+            theatre: dcs::get_theatre(lua),
+            dcs_version,
+            dcs_build,
+            multiplayer,
+            server,
+            server_settings: (multiplayer && server)
+                .then(|| read_server_settings(lua, write_dir))
+                .flatten(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionManifest {
+    pub mission: String,
+    #[serde(flatten)]
+    pub metadata: SessionMetadata,
+    /// Weather and clock when the session started.
+    pub weather: Option<Weather>,
+    pub tetrad_version: &'static str,
+    /// Versions of the layouts of the outputs, see `tetrad_data::schema`.
+    pub schemas: BTreeMap<&'static str, u32>,
+    pub started_at: String,
+    pub ended_at: Option<String>,
+    pub frames: Option<i32>,
+    pub output_files: Vec<PathBuf>,
+    pub config: Config,
+}
+
+impl SessionManifest {
+    pub fn new(
+        mission: String,
+        metadata: SessionMetadata,
+        weather: Option<Weather>,
+        config: Config,
+    ) -> Self {
+        Self {
+            mission,
+            metadata,
+            weather,
+            tetrad_version: env!("CARGO_PKG_VERSION"),
+            schemas: schema::ALL
+                .iter()
+                .map(|schema| (schema.name, schema.version))
+                .collect(),
+            started_at: chrono::Local::now().to_rfc3339(),
+            ended_at: None,
+            frames: None,
+            output_files: Vec::new(),
+            // manifests get shared, passwords don't belong in them
+            config: config.redacted(),
+        }
+    }
+
+    /// Fills in what's only known once the session is over.
+    pub fn finish(&mut self, frames: i32, output_files: Vec<PathBuf>) {
+        self.ended_at = Some(chrono::Local::now().to_rfc3339());
+        self.frames = Some(frames);
+        self.output_files = output_files;
+    }
+
+    /// Writes to a temporary file and renames it over the old one, like the
+    /// status file.
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        let tmp = path.with_extension("tmp");
+        let contents = serde_json::to_vec_pretty(self)?;
+        std::fs::write(&tmp, contents)?;
+        std::fs::rename(&tmp, path)
+    }
+}
@@ -0,0 +1,62 @@
+// Top-down map plot of object positions, shared by the live GUI and replays.
+use egui::plot::{Corner, Legend, Plot, PlotPoints, Points};
+use egui::Color32;
+
+pub struct MapObject {
+    /// DCS z coordinate, meters
+    pub east: f64,
+    /// DCS x coordinate, meters
+    pub north: f64,
+    pub coalition_id: i32,
+    pub is_unit: bool,
+}
+
+fn coalition_color(coalition_id: i32) -> Color32 {
+    match coalition_id {
+        1 => Color32::from_rgb(230, 60, 60),
+        2 => Color32::from_rgb(70, 130, 240),
+        _ => Color32::GRAY,
+    }
+}
+
+fn coalition_name(coalition_id: i32) -> &'static str {
+    match coalition_id {
+        1 => "Red",
+        2 => "Blue",
+        _ => "Neutral",
+    }
+}
+
+pub fn show_map(ui: &mut egui::Ui, id: &str, objects: &[MapObject], width: f32, height: f32) {
+    let mut layers = Vec::new();
+    for coalition_id in 0..=2 {
+        for is_unit in [true, false] {
+            let pts: PlotPoints = objects
+                .iter()
+                .filter(|o| o.coalition_id == coalition_id && o.is_unit == is_unit)
+                .map(|o| [o.east, o.north])
+                .collect();
+            let name = if is_unit {
+                coalition_name(coalition_id).to_string()
+            } else {
+                format!("{} weapons", coalition_name(coalition_id))
+            };
+            let points = Points::new(pts)
+                .name(name)
+                .color(coalition_color(coalition_id))
+                .radius(if is_unit { 3.0 } else { 1.5 });
+            layers.push(points);
+        }
+    }
+
+    Plot::new(id)
+        .width(width)
+        .height(height)
+        .data_aspect(1.0)
+        .legend(Legend::default().position(Corner::RightBottom))
+        .show(ui, |plot_ui| {
+            for layer in layers {
+                plot_ui.points(layer);
+            }
+        });
+}
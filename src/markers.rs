@@ -0,0 +1,84 @@
+// Labeled markers ("wave 3 spawned", "CAP package launched") that scripts drop
+// into the logs, to line up what the mission was doing with the frame times.
+// Hooks call `TETRAD.lib.mark(label)` directly. Mission scripts can't reach the
+// hook environment, so a small script in the mission queues their
+// `tetrad_mark(label)` calls and tetrad collects them from the hook, like the
+// cargo events.
+use crate::dcs;
+use mlua::Lua;
+use serde::{Deserialize, Serialize};
+
+/// Seconds between collecting the markers queued in the mission. Each marker
+/// keeps the mission time it was set at.
+pub const POLL_INTERVAL: f64 = 1.0;
+
+// Runs inside a_do_script([[...]]), so it must not contain a closing long
+// bracket.
+const MISSION_SCRIPT: &str = r#"
+if not tetrad_markers then
+    tetrad_markers = { queue = {} }
+    local tm = tetrad_markers
+
+    function tetrad_mark(label)
+        tm.queue[#tm.queue + 1] = timer.getTime() .. "\t"
+            .. (tostring(label):gsub("%c", " "))
+    end
+
+    function tm.drain()
+        local s = table.concat(tm.queue, "\n")
+        tm.queue = {}
+        return s
+    end
+end
+"#;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Marker {
+    pub t_game: f64,
+    pub label: String,
+}
+
+impl Marker {
+    /// Control characters in `label` become spaces so it stays on one line in
+    /// the logs.
+    pub fn new(t_game: f64, label: &str) -> Self {
+        Self {
+            t_game,
+            label: label.trim().replace(char::is_control, " "),
+        }
+    }
+
+    fn parse(queued: &str) -> Option<Self> {
+        let (t_game, label) = queued.split_once('\t')?;
+        Some(Self::new(t_game.parse().ok()?, label))
+    }
+}
+
+/// Defines `tetrad_mark` in the mission scripting environment. Safe to call
+/// more than once per mission.
+pub fn install(lua: &Lua) -> mlua::Result<()> {
+    dcs::run_in_mission(lua, MISSION_SCRIPT)
+}
+
+/// Takes all markers queued in the mission since the last call.
+pub fn drain(lua: &Lua) -> Vec<Marker> {
+    let code = "return tetrad_markers and tetrad_markers.drain() or ''";
+    let queued = match dcs::eval_in_mission(lua, code) {
+        Ok(Some(queued)) => queued,
+        Ok(None) => return Vec::new(),
+        Err(e) => {
+            log::warn!("Couldn't read the markers from the mission: {}", e);
+            return Vec::new();
+        }
+    };
+    queued
+        .lines()
+        .filter_map(|line| {
+            let marker = Marker::parse(line);
+            if marker.is_none() {
+                log::warn!("Couldn't parse marker {:?}", line);
+            }
+            marker
+        })
+        .collect()
+}
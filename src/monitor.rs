@@ -1,16 +1,56 @@
+use crate::alerts::{Alert, AlertEngine, AlertSample};
+use crate::config::Config;
 use crate::dcs::{DcsWorldObject, DcsWorldUnit};
+use crate::io_monitor::IoMonitor;
+use crate::mqtt::{MqttOptions, MqttPublisher};
+use crate::status::{self, ServerStatus, StatusWindow};
 use num::traits::AsPrimitive;
 use ordered_float::OrderedFloat;
-use std::collections::VecDeque;
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::File;
 use std::iter::Sum;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::{Receiver, Sender};
 use std::thread::JoinHandle;
 
 enum Message {
     FrameUpdate(FrameState),
+    MissionLoaded(f64),
+    PlayerCount(i32),
+}
+
+/// What the server was doing when a frame ran. Statistics are kept separately
+/// per phase so that e.g. the briefing screen's framerate doesn't get mixed in
+/// with the mission's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Phase {
+    Loading,
+    Briefing,
+    Active,
+    Paused,
+}
+
+impl Default for Phase {
+    fn default() -> Self {
+        Self::Loading
+    }
+}
+
+impl std::fmt::Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Loading => "loading",
+            Self::Briefing => "briefing",
+            Self::Active => "active",
+            Self::Paused => "paused",
+        };
+        write!(f, "{}", name)
+    }
 }
 
 struct FrameState {
+    phase: Phase,
     num_units: i32,
     num_ballistics: i32,
     real_time: f64,
@@ -24,11 +64,59 @@ struct FrameState {
 pub struct Monitor {
     thread_join: Option<JoinHandle<()>>,
     tx_to_thread: Sender<Message>,
+    rx_alerts: Receiver<Alert>,
+}
+
+#[derive(Debug, Default)]
+struct PhaseStats {
+    num_frames: u64,
+    total_real_time: f64,
+    max_real_frame_time: f64,
+}
+
+impl PhaseStats {
+    fn update(&mut self, real_frame_time: f64) {
+        self.num_frames += 1;
+        self.total_real_time += real_frame_time;
+        self.max_real_frame_time = self.max_real_frame_time.max(real_frame_time);
+    }
+
+    fn log_to_console(&self, phase: Phase) {
+        if self.num_frames == 0 {
+            return;
+        }
+        log::info!(
+            "Phase {}: {} frames over {:.1} seconds, average FPS {:.2}, worst frame {:.3} milliseconds",
+            phase,
+            self.num_frames,
+            self.total_real_time,
+            self.num_frames as f64 / self.total_real_time,
+            self.max_real_frame_time * 1000.0
+        );
+    }
 }
 
 #[derive(Debug, Default)]
 struct MonitorImpl {
     frame_log: FrameLog,
+    io_monitor: Option<IoMonitor>,
+    alerts: Option<AlertEngine>,
+    summary_interval: f64,
+    summary_log: Option<SummaryLog>,
+    mqtt: Option<MqttPublisher>,
+    /// Prefix of the MQTT topics, see `mqtt_topic`.
+    mqtt_topic: String,
+    phase: Phase,
+    phase_stats: BTreeMap<Phase, PhaseStats>,
+    mission_load_time: Option<f64>,
+    mission_name: String,
+    status_path: Option<PathBuf>,
+    status_interval: f64,
+    status_window: StatusWindow,
+    last_status_time: f64,
+    num_players: i32,
+    num_units: i32,
+    num_ballistics: i32,
     last_game_time: f64,
     last_real_time: f64,
     last_logged_time: f64,
@@ -75,8 +163,58 @@ where
     Some(time_stats_to_float::<T>(result))
 }
 
-fn log_times(series: &VecDeque<i32>, totals: &VecDeque<i32>, name: &str, lvl: log::Level) {
-    let result: f64 = series
+// Share of samples dropped from each end for the trimmed mean.
+const TRIM_FRACTION: f64 = 0.02;
+
+/// Percentiles and trimmed mean, which single outliers like an autosave pause
+/// can't skew the way they do the max and mean.
+struct RobustStats {
+    p50: f64,
+    p95: f64,
+    p99: f64,
+    trimmed_mean: f64,
+}
+
+/// Nearest-rank percentile of sorted samples.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn robust_stats(v: &VecDeque<OrderedFloat<f64>>) -> Option<RobustStats> {
+    if v.is_empty() {
+        return None;
+    }
+    let mut sorted: Vec<f64> = v.iter().map(|x| x.0).collect();
+    sorted.sort_by(f64::total_cmp);
+    let trim = (sorted.len() as f64 * TRIM_FRACTION) as usize;
+    let kept = &sorted[trim..sorted.len() - trim];
+    Some(RobustStats {
+        p50: percentile(&sorted, 50.0),
+        p95: percentile(&sorted, 95.0),
+        p99: percentile(&sorted, 99.0),
+        trimmed_mean: kept.iter().sum::<f64>() / kept.len() as f64,
+    })
+}
+
+fn log_robust_stats(v: &VecDeque<OrderedFloat<f64>>, name: &str, lvl: log::Level) {
+    let Some(stats) = robust_stats(v) else {
+        return;
+    };
+    log::log!(
+        lvl,
+        "{} (p50/p95/p99): {:.3}, {:.3}, {:.3} milliseconds, trimmed mean {:.3} milliseconds",
+        name,
+        stats.p50 * 1000.0,
+        stats.p95 * 1000.0,
+        stats.p99 * 1000.0,
+        stats.trimmed_mean * 1000.0,
+    );
+}
+
+/// Average share of `totals` taken up by `series`.
+fn cpu_load(series: &VecDeque<i32>, totals: &VecDeque<i32>) -> f64 {
+    series
         .iter()
         .zip(totals.iter())
         .filter(|(_, y)| **y > 0)
@@ -86,10 +224,98 @@ fn log_times(series: &VecDeque<i32>, totals: &VecDeque<i32>, name: &str, lvl: lo
             xf / yf
         })
         .sum::<f64>()
-        / series.len() as f64;
+        / series.len() as f64
+}
+
+fn log_times(series: &VecDeque<i32>, totals: &VecDeque<i32>, name: &str, lvl: log::Level) {
+    let result = cpu_load(series, totals);
     log::log!(lvl, "{} {:.3}%", name, result * 100.0);
 }
 
+// `summary.csv` is moved to `summary.1.csv` once it grows past this, so it
+// can't grow without bound over months of uptime.
+const SUMMARY_MAX_SIZE: u64 = 16 * 1024 * 1024;
+
+/// One row of `summary.csv`, covering one summary interval.
+#[derive(Serialize)]
+struct SummaryRecord<'a> {
+    timestamp: String,
+    mission: &'a str,
+    phase: String,
+    frames: usize,
+    t_game: f64,
+    t_real: f64,
+    max_units: i32,
+    max_ballistics: i32,
+    fps: f64,
+    frame_min_ms: f64,
+    frame_max_ms: f64,
+    frame_mean_ms: f64,
+    frame_p50_ms: f64,
+    frame_p95_ms: f64,
+    frame_p99_ms: f64,
+    frame_trimmed_mean_ms: f64,
+    real_mean_ms: f64,
+    dcs_cpu: f64,
+    total_cpu: f64,
+    lib_time_mean_ms: f64,
+}
+
+/// The whole summary as JSON to `<topic>/summary`, and the main numbers
+/// each to a retained topic of their own for dashboards that show one value.
+fn publish_summary(mqtt: &MqttPublisher, topic: &str, record: &SummaryRecord) {
+    match serde_json::to_vec(record) {
+        Ok(json) => mqtt.publish(format!("{}/summary", topic), json, false),
+        Err(e) => log::warn!("Couldn't serialize the summary for MQTT: {}", e),
+    }
+    for (name, value) in [
+        ("fps", record.fps),
+        ("frame_max_ms", record.frame_max_ms),
+        ("units", record.max_units as f64),
+        ("ballistics", record.max_ballistics as f64),
+        ("dcs_cpu", record.dcs_cpu),
+        ("total_cpu", record.total_cpu),
+    ] {
+        mqtt.publish(format!("{}/{}", topic, name), value.to_string(), true);
+    }
+    mqtt.publish(format!("{}/phase", topic), record.phase.clone(), true);
+}
+
+/// The console summaries, appended to a CSV file that carries on across
+/// sessions so long-term trends can be graphed.
+#[derive(Debug)]
+struct SummaryLog {
+    path: PathBuf,
+    writer: csv::Writer<File>,
+}
+
+impl SummaryLog {
+    fn open(path: &Path) -> std::io::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        let is_new = file.metadata()?.len() == 0;
+        let writer = csv::WriterBuilder::new()
+            .has_headers(is_new)
+            .from_writer(file);
+        Ok(Self {
+            path: path.to_path_buf(),
+            writer,
+        })
+    }
+
+    fn write(&mut self, record: &SummaryRecord) -> Result<(), Box<dyn std::error::Error>> {
+        self.writer.serialize(record)?;
+        self.writer.flush()?;
+        if self.writer.get_ref().metadata()?.len() >= SUMMARY_MAX_SIZE {
+            std::fs::rename(&self.path, self.path.with_extension("1.csv"))?;
+            *self = Self::open(&self.path)?;
+        }
+        Ok(())
+    }
+}
+
 impl FrameLog {
     fn update(&mut self, state: &FrameState, last_game_time: f64, last_real_time: f64) {
         self.num_units.push_back(state.num_units);
@@ -115,21 +341,58 @@ impl FrameLog {
         self.proc_cpu_times.clear();
     }
 
+    fn summary_record<'a>(
+        &self,
+        phase: Phase,
+        mission: &'a str,
+        state: &FrameState,
+    ) -> Option<SummaryRecord<'a>> {
+        let (_, max_units, _) = get_stats(&self.num_units)?;
+        let (_, max_ballistics, _) = get_stats(&self.num_ballistics)?;
+        let (g_min, g_max, g_mean) = float_stats(&self.game_times)?;
+        let (_, _, r_mean) = float_stats(&self.real_times)?;
+        let (_, _, l_mean) = float_stats(&self.lib_times)?;
+        let robust = robust_stats(&self.game_times)?;
+        Some(SummaryRecord {
+            timestamp: chrono::Local::now().to_rfc3339(),
+            mission,
+            phase: phase.to_string(),
+            frames: self.game_times.len(),
+            t_game: state.game_time,
+            t_real: state.real_time,
+            max_units,
+            max_ballistics,
+            fps: 1.0 / g_mean,
+            frame_min_ms: g_min * 1000.0,
+            frame_max_ms: g_max * 1000.0,
+            frame_mean_ms: g_mean * 1000.0,
+            frame_p50_ms: robust.p50 * 1000.0,
+            frame_p95_ms: robust.p95 * 1000.0,
+            frame_p99_ms: robust.p99 * 1000.0,
+            frame_trimmed_mean_ms: robust.trimmed_mean * 1000.0,
+            real_mean_ms: r_mean * 1000.0,
+            dcs_cpu: cpu_load(&self.proc_cpu_times, &self.sys_wall_times),
+            total_cpu: cpu_load(&self.sys_cpu_times, &self.sys_wall_times),
+            lib_time_mean_ms: l_mean * 1000.0,
+        })
+    }
+
     fn is_empty(&self) -> bool {
         self.game_times.len() == 0
     }
 
-    #[allow(dead_code)]
     fn has_data(&self) -> bool {
         !self.is_empty()
     }
 
-    fn log_to_console(&self) {
+    fn log_to_console(&self, phase: Phase, io_monitor: Option<&mut IoMonitor>, real_time: f64) {
         if self.is_empty() {
-            log::warn!("No new frame in the last five seconds.");
+            log::warn!("No new frame since the last summary.");
             return;
         }
 
+        log::info!("Session phase: {}", phase);
+
         let Some((_, max_units, _)) = get_stats(&self.num_units) else {
             log::error!("Units vector was unexpectedly empty");
             return;
@@ -158,6 +421,7 @@ impl FrameLog {
             g_max * 1000.0,
             g_mean * 1000.0,
         );
+        log_robust_stats(&self.game_times, "Frame times", lvl);
 
         let Some((r_min, r_max, r_mean)) = float_stats(&self.real_times) else {
             log::error!("Real times vector was unexpectedly empty");
@@ -171,6 +435,7 @@ impl FrameLog {
             r_max * 1000.0,
             r_mean * 1000.0,
         );
+        log_robust_stats(&self.real_times, "Real times", lvl);
 
         log::log!(lvl, "Average FPS: {:.03}", 1.0 / g_mean);
         log::log!(
@@ -206,6 +471,10 @@ impl FrameLog {
             l_mean
         );
 
+        if let Some(io_monitor) = io_monitor {
+            io_monitor.log_summary(real_time, lvl);
+        }
+
         log::log!(
             lvl,
             "----------------------------------------------------------------"
@@ -214,12 +483,64 @@ impl FrameLog {
 }
 impl MonitorImpl {
     fn update_log(&mut self, state: &FrameState) {
-        self.frame_log
-            .update(state, self.last_game_time, self.last_real_time);
+        if state.phase != self.phase {
+            if self.frame_log.has_data() {
+                self.log_summary(state);
+            }
+            log::info!(
+                "Session phase changed from {} to {}",
+                self.phase,
+                state.phase
+            );
+            self.phase = state.phase;
+            self.last_logged_time = state.game_time;
+        }
+        if self.frame_count > 0 {
+            let real_frame_time = state.real_time - self.last_real_time;
+            self.phase_stats
+                .entry(state.phase)
+                .or_default()
+                .update(real_frame_time);
+            self.status_window.update(state.real_time, real_frame_time);
+            if let Some(alerts) = self.alerts.as_mut() {
+                if state.phase == Phase::Active {
+                    alerts.update(&AlertSample {
+                        real_time: state.real_time,
+                        game_time: state.game_time,
+                        real_frame_time,
+                        lib_time: state.lib_time,
+                        units: state.num_units,
+                        ballistics: state.num_ballistics,
+                        players: self.num_players,
+                    });
+                } else {
+                    alerts.interrupt();
+                }
+            }
+        }
 
-        if state.game_time - self.last_logged_time >= 5.0 {
-            self.frame_log.log_to_console();
-            self.frame_log.reset();
+        // game time stands still while paused, so only the per-phase stats apply
+        let is_paused = matches!(state.phase, Phase::Briefing | Phase::Paused);
+        if !is_paused {
+            if self.summary_interval > 0.0 {
+                self.frame_log
+                    .update(state, self.last_game_time, self.last_real_time);
+            }
+            self.num_units = state.num_units;
+            self.num_ballistics = state.num_ballistics;
+        }
+
+        if self.status_interval > 0.0
+            && state.real_time - self.last_status_time >= self.status_interval
+        {
+            self.write_status(state);
+            self.last_status_time = state.real_time;
+        }
+
+        if self.summary_interval > 0.0
+            && state.game_time - self.last_logged_time >= self.summary_interval
+        {
+            self.log_summary(state);
             self.last_logged_frame = self.frame_count;
             self.last_logged_time = state.game_time;
         }
@@ -229,30 +550,126 @@ impl MonitorImpl {
         self.frame_count += 1;
     }
 
+    /// Logs the statistics since the last summary to the console,
+    /// `summary.csv` and MQTT, and starts collecting the next ones.
+    fn log_summary(&mut self, state: &FrameState) {
+        self.frame_log
+            .log_to_console(self.phase, self.io_monitor.as_mut(), state.real_time);
+        if self.summary_log.is_some() || self.mqtt.is_some() {
+            let record = self
+                .frame_log
+                .summary_record(self.phase, &self.mission_name, state);
+            if let Some(record) = record {
+                if let Some(summary_log) = self.summary_log.as_mut() {
+                    if let Err(e) = summary_log.write(&record) {
+                        log::warn!("Couldn't write to {:?}: {}", summary_log.path, e);
+                    }
+                }
+                if let Some(mqtt) = self.mqtt.as_ref() {
+                    publish_summary(mqtt, &self.mqtt_topic, &record);
+                }
+            }
+        }
+        self.frame_log.reset();
+    }
+
+    fn write_status(&mut self, state: &FrameState) {
+        let Some(path) = self.status_path.as_ref() else {
+            return;
+        };
+        let (fps, max_frame_time) = self.status_window.take(state.real_time);
+        let status = ServerStatus {
+            version: env!("CARGO_PKG_VERSION"),
+            mission: self.mission_name.clone(),
+            phase: self.phase.to_string(),
+            fps,
+            max_frame_time_ms: max_frame_time * 1000.0,
+            players: self.num_players,
+            units: self.num_units,
+            ballistics: self.num_ballistics,
+            uptime: state.real_time,
+            mission_time: state.game_time,
+            health: status::health_score(fps, max_frame_time),
+            updated: chrono::Local::now().to_rfc3339(),
+        };
+        if let Err(e) = status::write_status(path, &status) {
+            log::warn!("Couldn't write status file {:?}: {}", path, e);
+        }
+    }
+
     fn entry(&mut self, rx: Receiver<Message>) {
         log::debug!("Starting monitor thread");
         log::info!("----------------------------------------------------------------");
         loop {
-            let Ok(Message::FrameUpdate(state)) = rx.recv() else {
-                log::debug!("Monitor thread RX dropped");
-                break;
-            };
-            self.update_log(&state);
+            match rx.recv() {
+                Ok(Message::FrameUpdate(state)) => self.update_log(&state),
+                Ok(Message::MissionLoaded(t)) => self.mission_load_time = Some(t),
+                Ok(Message::PlayerCount(n)) => self.num_players = n,
+                Err(_) => {
+                    log::debug!("Monitor thread RX dropped");
+                    break;
+                }
+            }
+        }
+        if let Some(t) = self.mission_load_time {
+            log::info!("Mission load time: {:.3} seconds", t);
+        }
+        for (phase, stats) in self.phase_stats.iter() {
+            stats.log_to_console(*phase);
         }
     }
 }
 
 impl Monitor {
-    pub fn new() -> Self {
+    pub fn new(mission_name: String, config: &Config) -> Self {
         log::debug!("Starting monitor");
         let (tx, rx) = std::sync::mpsc::channel();
+        let (tx_alerts, rx_alerts) = std::sync::mpsc::channel();
 
         let mut me = Self {
             thread_join: None,
             tx_to_thread: tx,
+            rx_alerts,
         };
 
-        let mut imp = MonitorImpl::default();
+        let log_dir = config.log_dir();
+        let summary_log = if config.enable_summary_log && config.summary_interval > 0.0 {
+            let path = log_dir.join("summary.csv");
+            match SummaryLog::open(&path) {
+                Ok(summary_log) => Some(summary_log),
+                Err(e) => {
+                    log::warn!("Couldn't open {:?}: {}", path, e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let mut imp = MonitorImpl {
+            mission_name,
+            status_path: (config.status_interval > 0.0).then(|| log_dir.join("status.json")),
+            status_interval: config.status_interval,
+            io_monitor: Some(IoMonitor::new(
+                Path::new(&config.write_dir),
+                config.disk_busy_warning,
+            )),
+            alerts: (!config.alerts.is_empty())
+                .then(|| AlertEngine::new(&config.alerts, tx_alerts)),
+            summary_interval: config.summary_interval,
+            summary_log,
+            mqtt: (!config.mqtt_broker.is_empty() && config.summary_interval > 0.0).then(|| {
+                MqttPublisher::start(MqttOptions {
+                    broker: config.mqtt_broker.clone(),
+                    client_id: format!("tetrad-{}", config.instance_name()),
+                    username: config.mqtt_username.clone(),
+                    password: config.mqtt_password.clone(),
+                })
+            }),
+            mqtt_topic: config
+                .mqtt_topic
+                .replace("{instance}", &config.instance_name()),
+            ..Default::default()
+        };
 
         let handle = std::thread::spawn(move || {
             imp.entry(rx);
@@ -264,6 +681,7 @@ impl Monitor {
 
     pub fn update(
         &mut self,
+        phase: Phase,
         units: &[DcsWorldUnit],
         ballistics: &[DcsWorldObject],
         real_time: f64,
@@ -274,6 +692,7 @@ impl Monitor {
         proc_cpu: i32,
     ) {
         let fs = FrameState {
+            phase,
             num_units: units.len() as i32,
             num_ballistics: ballistics.len() as i32,
             real_time,
@@ -286,8 +705,70 @@ impl Monitor {
         self.tx_to_thread.send(Message::FrameUpdate(fs)).unwrap();
     }
 
+    pub fn mission_loaded(&mut self, load_time: f64) {
+        self.tx_to_thread
+            .send(Message::MissionLoaded(load_time))
+            .unwrap();
+    }
+
+    pub fn set_player_count(&mut self, num_players: i32) {
+        self.tx_to_thread
+            .send(Message::PlayerCount(num_players))
+            .unwrap();
+    }
+
+    /// Alerts that went off since the last call and need the DCS thread for
+    /// their action.
+    pub fn take_alerts(&self) -> Vec<Alert> {
+        self.rx_alerts.try_iter().collect()
+    }
+
     pub fn stop(&mut self) -> JoinHandle<()> {
         let join = std::mem::take(&mut self.thread_join).unwrap();
         join
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples(values: impl IntoIterator<Item = f64>) -> VecDeque<OrderedFloat<f64>> {
+        values.into_iter().map(OrderedFloat).collect()
+    }
+
+    #[test]
+    fn percentile_uses_nearest_rank() {
+        let sorted: Vec<f64> = (1..=100).map(f64::from).collect();
+        assert_eq!(percentile(&sorted, 50.0), 50.0);
+        assert_eq!(percentile(&sorted, 95.0), 95.0);
+        assert_eq!(percentile(&sorted, 99.0), 99.0);
+        assert_eq!(percentile(&sorted, 0.0), 1.0);
+        assert_eq!(percentile(&sorted, 100.0), 100.0);
+        assert_eq!(percentile(&[7.0], 99.0), 7.0);
+    }
+
+    #[test]
+    fn robust_stats_of_nothing() {
+        assert!(robust_stats(&VecDeque::new()).is_none());
+    }
+
+    #[test]
+    fn trimmed_mean_ignores_outliers() {
+        // 100 samples trim 2 from each end, so the two spikes drop out
+        let mut values = vec![0.010; 96];
+        values.extend([0.0, 0.0, 5.0, 5.0]);
+        let stats = robust_stats(&samples(values)).unwrap();
+        assert!((stats.trimmed_mean - 0.010).abs() < 1e-12);
+        assert_eq!(stats.p50, 0.010);
+        assert_eq!(stats.p99, 5.0);
+    }
+
+    #[test]
+    fn few_samples_are_not_trimmed() {
+        let stats = robust_stats(&samples([1.0, 2.0, 6.0])).unwrap();
+        assert_eq!(stats.trimmed_mean, 3.0);
+        assert_eq!(stats.p50, 2.0);
+        assert_eq!(stats.p95, 6.0);
+    }
+}
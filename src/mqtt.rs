@@ -0,0 +1,175 @@
+// Publishes the monitor summaries to an MQTT broker (`mqtt_broker`), for
+// server dashboards built on MQTT. Only what that needs of MQTT 3.1.1 is
+// spoken: QoS 0 publishes, optionally retained, on a connection of its own
+// thread so a slow or missing broker never holds up the monitor.
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::time::Duration;
+
+const KEEP_ALIVE: u16 = 60;
+const TIMEOUT: Duration = Duration::from_secs(5);
+// Between connection attempts while the broker can't be reached.
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+pub struct MqttOptions {
+    /// `host:port`.
+    pub broker: String,
+    pub client_id: String,
+    pub username: String,
+    pub password: String,
+}
+
+struct Publish {
+    topic: String,
+    payload: Vec<u8>,
+    retain: bool,
+}
+
+#[derive(Debug)]
+pub struct MqttPublisher {
+    tx: Sender<Publish>,
+}
+
+fn put_string(packet: &mut Vec<u8>, s: &str) {
+    packet.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    packet.extend_from_slice(s.as_bytes());
+}
+
+/// `body` with the fixed header in front.
+fn packet(kind: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![kind];
+    let mut len = body.len();
+    loop {
+        let byte = (len % 128) as u8;
+        len /= 128;
+        if len == 0 {
+            packet.push(byte);
+            break;
+        }
+        packet.push(byte | 0x80);
+    }
+    packet.extend_from_slice(body);
+    packet
+}
+
+fn connect(options: &MqttOptions) -> io::Result<TcpStream> {
+    let address = options
+        .broker
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "broker address not found"))?;
+    let mut stream = TcpStream::connect_timeout(&address, TIMEOUT)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    let mut body = Vec::new();
+    put_string(&mut body, "MQTT");
+    body.push(4); // protocol level 3.1.1
+    let mut flags = 0x02; // clean session
+    if !options.username.is_empty() {
+        flags |= 0x80;
+        if !options.password.is_empty() {
+            flags |= 0x40;
+        }
+    }
+    body.push(flags);
+    body.extend_from_slice(&KEEP_ALIVE.to_be_bytes());
+    put_string(&mut body, &options.client_id);
+    if flags & 0x80 != 0 {
+        put_string(&mut body, &options.username);
+    }
+    if flags & 0x40 != 0 {
+        put_string(&mut body, &options.password);
+    }
+    stream.write_all(&packet(0x10, &body))?;
+
+    let mut connack = [0u8; 4];
+    stream.read_exact(&mut connack)?;
+    if connack[0] != 0x20 || connack[3] != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!("broker refused the connection (code {})", connack[3]),
+        ));
+    }
+    Ok(stream)
+}
+
+fn publish(stream: &mut TcpStream, message: &Publish) -> io::Result<()> {
+    let mut body = Vec::with_capacity(2 + message.topic.len() + message.payload.len());
+    put_string(&mut body, &message.topic);
+    body.extend_from_slice(&message.payload);
+    let kind = if message.retain { 0x31 } else { 0x30 };
+    stream.write_all(&packet(kind, &body))
+}
+
+fn entry(options: MqttOptions, rx: Receiver<Publish>) {
+    let mut stream: Option<TcpStream> = None;
+    let mut is_broker_down = false;
+    loop {
+        let message = match rx.recv_timeout(Duration::from_secs(KEEP_ALIVE as u64 / 2)) {
+            Ok(message) => Some(message),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        if stream.is_none() {
+            // nothing to connect for yet
+            if message.is_none() {
+                continue;
+            }
+            match connect(&options) {
+                Ok(s) => {
+                    log::info!("Connected to MQTT broker {}", options.broker);
+                    is_broker_down = false;
+                    stream = Some(s);
+                }
+                Err(e) => {
+                    if !is_broker_down {
+                        log::warn!("Couldn't connect to MQTT broker {}: {}", options.broker, e);
+                        is_broker_down = true;
+                    }
+                    // summaries that come in while it's down are just skipped
+                    std::thread::sleep(RETRY_INTERVAL);
+                    while rx.try_recv().is_ok() {}
+                    continue;
+                }
+            }
+        }
+        let Some(s) = stream.as_mut() else {
+            continue;
+        };
+        let result = match message.as_ref() {
+            Some(message) => publish(s, message),
+            // PINGREQ, so the broker doesn't drop a quiet connection
+            None => s.write_all(&[0xC0, 0x00]),
+        };
+        if let Err(e) = result {
+            log::warn!("Lost connection to MQTT broker {}: {}", options.broker, e);
+            stream = None;
+        }
+    }
+    if let Some(mut s) = stream {
+        // DISCONNECT
+        let _ = s.write_all(&[0xE0, 0x00]);
+    }
+    log::debug!("MQTT publisher stopped");
+}
+
+impl MqttPublisher {
+    pub fn start(options: MqttOptions) -> Self {
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || entry(options, rx));
+        Self { tx }
+    }
+
+    pub fn publish(&self, topic: String, payload: impl Into<Vec<u8>>, retain: bool) {
+        let message = Publish {
+            topic,
+            payload: payload.into(),
+            retain,
+        };
+        // the thread only ends once this is dropped
+        let _ = self.tx.send(message);
+    }
+}
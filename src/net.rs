@@ -0,0 +1,168 @@
+// Live telemetry over WebSocket. Every client connected to `websocket_port`
+// gets each frame's units and ballistics as a JSON text message, the same data
+// the worker and GUI see.
+use crate::dcs::{DcsWorldObject, DcsWorldUnit};
+use crate::sink::{Frame, Sink, SinkResult};
+use serde::Serialize;
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tungstenite::WebSocket;
+
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+// A client that can't take a frame within this long gets dropped rather than
+// holding up everyone else.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+type Clients = Arc<Mutex<Vec<WebSocket<TcpStream>>>>;
+
+struct Update {
+    units: Arc<Vec<DcsWorldUnit>>,
+    ballistics: Arc<Vec<DcsWorldObject>>,
+    game_time: f64,
+    real_time: f64,
+}
+
+#[derive(Serialize)]
+struct UpdatePayload<'a> {
+    game_time: f64,
+    real_time: f64,
+    units: &'a [DcsWorldUnit],
+    ballistics: &'a [DcsWorldObject],
+}
+
+pub struct TelemetryServer {
+    tx: Sender<Update>,
+    is_running: Arc<AtomicBool>,
+}
+
+fn add_client(stream: TcpStream, clients: &Clients) {
+    let addr = stream.peer_addr().ok();
+    let setup = stream
+        .set_nonblocking(false)
+        .and_then(|_| stream.set_write_timeout(Some(WRITE_TIMEOUT)))
+        .and_then(|_| stream.set_nodelay(true));
+    if let Err(e) = setup {
+        log::warn!(
+            "Couldn't set up telemetry connection from {:?}: {}",
+            addr,
+            e
+        );
+        return;
+    }
+    match tungstenite::accept(stream) {
+        Ok(ws) => {
+            log::info!("Telemetry client {:?} connected", addr);
+            clients.lock().unwrap().push(ws);
+        }
+        Err(e) => log::warn!("Telemetry handshake with {:?} failed: {}", addr, e),
+    }
+}
+
+fn accept_clients(listener: TcpListener, clients: Clients, is_running: Arc<AtomicBool>) {
+    while is_running.load(Ordering::SeqCst) {
+        match listener.accept() {
+            Ok((stream, _)) => add_client(stream, &clients),
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                std::thread::sleep(ACCEPT_POLL_INTERVAL)
+            }
+            Err(e) => {
+                log::error!("Telemetry listener failed: {}", e);
+                break;
+            }
+        }
+    }
+    log::debug!("Telemetry listener stopped");
+}
+
+fn broadcast(rx: Receiver<Update>, clients: Clients) {
+    while let Ok(mut update) = rx.recv() {
+        // a live view only cares about the newest frame
+        loop {
+            match rx.try_recv() {
+                Ok(newer) => update = newer,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        }
+        let mut clients = clients.lock().unwrap();
+        if clients.is_empty() {
+            continue;
+        }
+        let payload = UpdatePayload {
+            game_time: update.game_time,
+            real_time: update.real_time,
+            units: &update.units,
+            ballistics: &update.ballistics,
+        };
+        let json = match serde_json::to_string(&payload) {
+            Ok(json) => json,
+            Err(e) => {
+                log::error!("Couldn't serialize telemetry: {}", e);
+                continue;
+            }
+        };
+        clients.retain_mut(
+            |ws| match ws.write_message(tungstenite::Message::Text(json.clone())) {
+                Ok(()) => true,
+                Err(e) => {
+                    log::info!("Dropping telemetry client: {}", e);
+                    false
+                }
+            },
+        );
+    }
+}
+
+impl TelemetryServer {
+    pub fn start(address: &str, port: u16) -> std::io::Result<Self> {
+        let listener = TcpListener::bind((address, port))?;
+        listener.set_nonblocking(true)?;
+        log::info!("Streaming telemetry on ws://{}", listener.local_addr()?);
+
+        let clients = Clients::default();
+        let is_running = Arc::new(AtomicBool::new(true));
+        let accept_clients_list = clients.clone();
+        let accept_is_running = is_running.clone();
+        std::thread::spawn(move || {
+            accept_clients(listener, accept_clients_list, accept_is_running)
+        });
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || broadcast(rx, clients));
+        Ok(Self { tx, is_running })
+    }
+}
+
+impl Sink for TelemetryServer {
+    fn name(&self) -> &'static str {
+        "telemetry"
+    }
+
+    fn write_frame(&mut self, frame: &Frame) -> SinkResult {
+        if !frame.has_objects() {
+            return Ok(());
+        }
+        let update = Update {
+            units: frame.units.clone(),
+            ballistics: frame.ballistics.clone(),
+            game_time: frame.game_time,
+            real_time: frame.real_time,
+        };
+        // a closed channel just means nobody is listening any more
+        self.tx.send(update).unwrap_or(());
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> SinkResult {
+        Ok(())
+    }
+}
+
+impl Drop for TelemetryServer {
+    fn drop(&mut self) {
+        // dropping `tx` ends the broadcast thread, this ends the listener
+        self.is_running.store(false, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,157 @@
+use crate::dcs::PlayerInfo;
+use crate::load_summary::percentile;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// DCS only exposes ping through the net API, so jitter is derived here from
+// consecutive ping samples. There is no packet loss figure to record.
+
+/// Share of clients with the highest pings left out of the server-wide ping
+/// figures, so one player on a satellite link doesn't skew them.
+const PING_OUTLIER_FRACTION: f64 = 0.1;
+
+#[derive(Debug, Default)]
+struct ClientStats {
+    name: String,
+    last_ping: Option<i32>,
+    num_samples: i32,
+    ping_total: i64,
+    max_ping: i32,
+    jitter_total: i64,
+    num_jitter_samples: i32,
+}
+
+/// Per-client connection quality over one summary interval.
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientNetRecord {
+    pub t_game: f64,
+    pub t_real: f64,
+    pub player_id: i32,
+    pub player_name: String,
+    pub mean_ping: f64,
+    pub max_ping: i32,
+    pub jitter: f64,
+}
+
+/// Server-wide ping over one summary interval, from the clients' mean pings
+/// with the highest `PING_OUTLIER_FRACTION` of them left out.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PingSummary {
+    pub num_clients: usize,
+    /// Clients left out as outliers.
+    pub excluded: usize,
+    /// Milliseconds.
+    pub trimmed_mean: f64,
+    pub p95: f64,
+    /// Mean over all clients, outliers included.
+    pub raw_mean: f64,
+}
+
+impl PingSummary {
+    pub fn compute(records: &[ClientNetRecord]) -> Option<Self> {
+        if records.is_empty() {
+            return None;
+        }
+        let mut pings: Vec<f64> = records.iter().map(|r| r.mean_ping).collect();
+        pings.sort_by(f64::total_cmp);
+        let raw_mean = pings.iter().sum::<f64>() / pings.len() as f64;
+        // always keep at least half the clients
+        let excluded =
+            ((pings.len() as f64 * PING_OUTLIER_FRACTION).ceil() as usize).min(pings.len() / 2);
+        let kept = &pings[..pings.len() - excluded];
+        Some(Self {
+            num_clients: pings.len(),
+            excluded,
+            trimmed_mean: kept.iter().sum::<f64>() / kept.len() as f64,
+            p95: percentile(kept, 95.0),
+            raw_mean,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct NetStats {
+    clients: HashMap<i32, ClientStats>,
+}
+
+impl ClientStats {
+    fn add_sample(&mut self, ping: i32) {
+        if let Some(last) = self.last_ping {
+            self.jitter_total += (ping - last).abs() as i64;
+            self.num_jitter_samples += 1;
+        }
+        self.last_ping = Some(ping);
+        self.num_samples += 1;
+        self.ping_total += ping as i64;
+        self.max_ping = std::cmp::max(self.max_ping, ping);
+    }
+
+    fn reset(&mut self) {
+        self.num_samples = 0;
+        self.ping_total = 0;
+        self.max_ping = 0;
+        self.jitter_total = 0;
+        self.num_jitter_samples = 0;
+    }
+}
+
+impl NetStats {
+    pub fn add_samples(&mut self, players: &[PlayerInfo]) {
+        // the server itself is player 1 and always has zero ping
+        let players = players.iter().filter(|p| p.id != 1);
+        let mut seen = Vec::new();
+        for p in players {
+            let client = self.clients.entry(p.id).or_default();
+            client.name = p.name.clone();
+            client.add_sample(p.ping);
+            seen.push(p.id);
+        }
+        self.clients.retain(|id, _| seen.contains(id));
+    }
+
+    /// Produces a record per client for the interval that just ended, and
+    /// starts a new one.
+    pub fn summarize(&mut self, t_game: f64, t_real: f64) -> Vec<ClientNetRecord> {
+        let mut records = Vec::new();
+        for (id, client) in self.clients.iter_mut() {
+            if client.num_samples == 0 {
+                continue;
+            }
+            let jitter = if client.num_jitter_samples > 0 {
+                client.jitter_total as f64 / client.num_jitter_samples as f64
+            } else {
+                0.0
+            };
+            records.push(ClientNetRecord {
+                t_game,
+                t_real,
+                player_id: *id,
+                player_name: client.name.clone(),
+                mean_ping: client.ping_total as f64 / client.num_samples as f64,
+                max_ping: client.max_ping,
+                jitter,
+            });
+            client.reset();
+        }
+        records
+    }
+}
+
+pub fn log_summary(records: &[ClientNetRecord], pings: &PingSummary) {
+    let mean_jitter = records.iter().map(|r| r.jitter).sum::<f64>() / records.len() as f64;
+    let worst = records
+        .iter()
+        .max_by(|a, b| a.jitter.total_cmp(&b.jitter))
+        .unwrap();
+    log::info!(
+        "Network: {} clients, ping {:.0} ms (p95 {:.0} ms, {} outliers excluded, {:.0} ms with them), mean jitter {:.1} ms, worst jitter {:.1} ms ({})",
+        pings.num_clients,
+        pings.trimmed_mean,
+        pings.p95,
+        pings.excluded,
+        pings.raw_mean,
+        mean_jitter,
+        worst.jitter,
+        worst.player_name
+    );
+}
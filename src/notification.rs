@@ -0,0 +1,33 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// Something worth popping up in the GUI rather than leaving to scroll away
+/// in the console.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Notification {
+    pub severity: Severity,
+    pub text: String,
+}
+
+impl Notification {
+    pub fn new(severity: Severity, text: impl Into<String>) -> Self {
+        Self {
+            severity,
+            text: text.into(),
+        }
+    }
+
+    pub fn log_level(&self) -> log::Level {
+        match self.severity {
+            Severity::Info => log::Level::Info,
+            Severity::Warning => log::Level::Warn,
+            Severity::Error => log::Level::Error,
+        }
+    }
+}
@@ -0,0 +1,43 @@
+// Object counts by coalition and category ("blue_airplane", "red_ground",
+// "red_weapon", ...) for the CSV frame log, so the usual curves can be plotted
+// straight from it without going through the object log.
+use crate::dcs::{DcsWorldObject, DcsWorldUnit, ObjectCategory};
+
+/// Indexed by coalition id.
+const COALITIONS: [&str; 3] = ["neutral", "red", "blue"];
+
+pub const NUM_COLUMNS: usize = COALITIONS.len() * ObjectCategory::ALL.len();
+
+pub struct ObjectCounts {
+    by_class: [[usize; ObjectCategory::ALL.len()]; COALITIONS.len()],
+}
+
+impl ObjectCounts {
+    pub fn tally(units: &[DcsWorldUnit], ballistics: &[DcsWorldObject]) -> Self {
+        let mut counts = Self {
+            by_class: Default::default(),
+        };
+        for object in units.iter().map(DcsWorldUnit::object).chain(ballistics) {
+            let coalition = object.coalition_id().clamp(0, COALITIONS.len() as i32 - 1) as usize;
+            counts.by_class[coalition][object.category() as usize] += 1;
+        }
+        counts
+    }
+
+    /// `<coalition>_<category>` for every coalition and category, in the
+    /// order of `values`.
+    pub fn column_names() -> Vec<String> {
+        COALITIONS
+            .iter()
+            .flat_map(|coalition| {
+                ObjectCategory::ALL
+                    .iter()
+                    .map(move |category| format!("{}_{}", coalition, category.as_str()))
+            })
+            .collect()
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = usize> + '_ {
+        self.by_class.iter().flatten().copied()
+    }
+}
@@ -0,0 +1,63 @@
+// A compact stats line shown on players' screens, so people on public servers
+// can see that a dip in performance is the server and not their own machine.
+// It goes out through `trigger.action.outText` in the mission environment.
+use crate::config::OverlayAudience;
+
+/// Seconds each overlay line stays on screen, at most. Shorter intervals
+/// replace it sooner.
+const MAX_DISPLAY_TIME: f64 = 10.0;
+
+/// Fills in the `{fps}`, `{units}`, `{ballistics}`, `{players}` and
+/// `{mission}` placeholders of the configured overlay template.
+pub fn format(
+    template: &str,
+    fps: f64,
+    num_units: usize,
+    num_ballistics: usize,
+    num_players: usize,
+    mission_name: &str,
+) -> String {
+    template
+        .replace("{fps}", &format!("{:.0}", fps))
+        .replace("{units}", &num_units.to_string())
+        .replace("{ballistics}", &num_ballistics.to_string())
+        .replace("{players}", &num_players.to_string())
+        .replace("{mission}", mission_name)
+}
+
+/// `text` as a Lua string literal. The code ends up inside
+/// `a_do_script([[...]])`, so it must not contain a closing long bracket
+/// either.
+fn lua_quote(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.replace("]]", "] ]").chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if c.is_control() => quoted.push(' '),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Mission scripting code that shows `text` to `audience` until the next line
+/// is due in `interval` seconds.
+pub fn to_lua(text: &str, audience: OverlayAudience, interval: f64) -> String {
+    let text = lua_quote(text);
+    let display_time = interval.min(MAX_DISPLAY_TIME).ceil();
+    match audience {
+        OverlayAudience::All => format!("trigger.action.outText({}, {})", text, display_time),
+        OverlayAudience::Red => format!(
+            "trigger.action.outTextForCoalition(coalition.side.RED, {}, {})",
+            text, display_time
+        ),
+        OverlayAudience::Blue => format!(
+            "trigger.action.outTextForCoalition(coalition.side.BLUE, {}, {})",
+            text, display_time
+        ),
+    }
+}
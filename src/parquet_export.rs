@@ -0,0 +1,408 @@
+// Columnar Parquet output for the frame and object logs, enabled by listing
+// "parquet" in `export_formats`. Rows are buffered per column and written out a
+// row group at a time.
+use crate::dcs::{DcsWorldObject, DcsWorldUnit, Motion};
+use crate::io_thread::ThreadedFile;
+use crate::kinematics::MotionTracker;
+use crate::sink::{Frame, Sink, SinkResult};
+use arrow::array::{ArrayRef, Float64Builder, Int32Builder, StringBuilder, UInt64Builder};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::errors::Result;
+use parquet::file::metadata::KeyValue;
+use parquet::file::properties::WriterProperties;
+use std::sync::Arc;
+use tetrad_data::schema::{self, Schema as TetradSchema};
+
+const ROW_GROUP_SIZE: usize = 64 * 1024;
+
+fn create_writer(
+    file: ThreadedFile,
+    schema: SchemaRef,
+    tetrad_schema: TetradSchema,
+) -> Result<ArrowWriter<ThreadedFile>> {
+    let stamp = KeyValue::new(schema::METADATA_KEY.to_string(), tetrad_schema.id());
+    let props = WriterProperties::builder()
+        .set_compression(Compression::ZSTD)
+        .set_max_row_group_size(ROW_GROUP_SIZE)
+        .set_key_value_metadata(Some(vec![stamp]))
+        .build();
+    ArrowWriter::try_new(file, schema, Some(props))
+}
+
+fn write_batch(
+    writer: &mut ArrowWriter<ThreadedFile>,
+    schema: &SchemaRef,
+    columns: Vec<ArrayRef>,
+) -> Result<()> {
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+    writer.write(&batch)
+}
+
+pub struct ParquetSink {
+    frame_table: Option<FrameTable>,
+    object_table: Option<ObjectTable>,
+}
+
+impl ParquetSink {
+    pub fn new(
+        frame_file: Option<ThreadedFile>,
+        object_file: Option<ThreadedFile>,
+    ) -> Result<Self> {
+        Ok(Self {
+            frame_table: frame_file.map(FrameTable::new).transpose()?,
+            object_table: object_file.map(ObjectTable::new).transpose()?,
+        })
+    }
+}
+
+impl Sink for ParquetSink {
+    fn name(&self) -> &'static str {
+        "parquet"
+    }
+
+    fn rotates(&self) -> bool {
+        true
+    }
+
+    fn write_frame(&mut self, frame: &Frame) -> SinkResult {
+        if let Some(table) = self.frame_table.as_mut() {
+            table.push(frame)?;
+        }
+        if let Some(table) = self.object_table.as_mut().filter(|_| frame.log_objects) {
+            table.push_frame(
+                frame.frame_count,
+                frame.game_time,
+                frame.real_time,
+                frame.units,
+                frame.ballistics,
+                frame.motion,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> SinkResult {
+        if let Some(table) = self.frame_table {
+            table.close()?;
+        }
+        if let Some(table) = self.object_table {
+            table.close()?;
+        }
+        Ok(())
+    }
+}
+
+struct FrameTable {
+    writer: ArrowWriter<ThreadedFile>,
+    schema: SchemaRef,
+    rows: usize,
+    frame_count: Int32Builder,
+    t_game: Float64Builder,
+    t_real: Float64Builder,
+    units: Int32Builder,
+    ballistics: Int32Builder,
+    sys_cpu: Int32Builder,
+    sys_wall: Int32Builder,
+    proc_cpu: Int32Builder,
+    lib_time: Float64Builder,
+    working_set: UInt64Builder,
+    private_bytes: UInt64Builder,
+    commit: UInt64Builder,
+    dcs_cpu: Float64Builder,
+    total_cpu: Float64Builder,
+}
+
+impl FrameTable {
+    fn new(file: ThreadedFile) -> Result<Self> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("frame_count", DataType::Int32, false),
+            Field::new("t_game", DataType::Float64, false),
+            Field::new("t_real", DataType::Float64, false),
+            Field::new("units", DataType::Int32, false),
+            Field::new("ballistics", DataType::Int32, false),
+            Field::new("sys_cpu", DataType::Int32, false),
+            Field::new("sys_wall", DataType::Int32, false),
+            Field::new("proc_cpu", DataType::Int32, false),
+            Field::new("lib_time", DataType::Float64, false),
+            Field::new("working_set", DataType::UInt64, false),
+            Field::new("private_bytes", DataType::UInt64, false),
+            Field::new("commit", DataType::UInt64, false),
+            Field::new("dcs_cpu", DataType::Float64, false),
+            Field::new("total_cpu", DataType::Float64, false),
+        ]));
+        Ok(Self {
+            writer: create_writer(file, schema.clone(), schema::PARQUET_FRAMES)?,
+            schema,
+            rows: 0,
+            frame_count: Int32Builder::new(),
+            t_game: Float64Builder::new(),
+            t_real: Float64Builder::new(),
+            units: Int32Builder::new(),
+            ballistics: Int32Builder::new(),
+            sys_cpu: Int32Builder::new(),
+            sys_wall: Int32Builder::new(),
+            proc_cpu: Int32Builder::new(),
+            lib_time: Float64Builder::new(),
+            working_set: UInt64Builder::new(),
+            private_bytes: UInt64Builder::new(),
+            commit: UInt64Builder::new(),
+            dcs_cpu: Float64Builder::new(),
+            total_cpu: Float64Builder::new(),
+        })
+    }
+
+    fn push(&mut self, frame: &Frame) -> Result<()> {
+        self.frame_count.append_value(frame.frame_count);
+        self.t_game.append_value(frame.game_time);
+        self.t_real.append_value(frame.real_time);
+        self.units.append_value(frame.num_units as i32);
+        self.ballistics.append_value(frame.num_ballistics as i32);
+        self.sys_cpu.append_value(frame.sys_time.0);
+        self.sys_wall.append_value(frame.sys_time.1);
+        self.proc_cpu.append_value(frame.proc_time.0);
+        self.lib_time.append_value(frame.lib_time);
+        self.working_set.append_value(frame.memory.working_set);
+        self.private_bytes.append_value(frame.memory.private_bytes);
+        self.commit.append_value(frame.memory.commit);
+        self.dcs_cpu.append_value(frame.cpu_load.dcs);
+        self.total_cpu.append_value(frame.cpu_load.total);
+        self.rows += 1;
+        if self.rows >= ROW_GROUP_SIZE {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if self.rows == 0 {
+            return Ok(());
+        }
+        self.rows = 0;
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.frame_count.finish()),
+            Arc::new(self.t_game.finish()),
+            Arc::new(self.t_real.finish()),
+            Arc::new(self.units.finish()),
+            Arc::new(self.ballistics.finish()),
+            Arc::new(self.sys_cpu.finish()),
+            Arc::new(self.sys_wall.finish()),
+            Arc::new(self.proc_cpu.finish()),
+            Arc::new(self.lib_time.finish()),
+            Arc::new(self.working_set.finish()),
+            Arc::new(self.private_bytes.finish()),
+            Arc::new(self.commit.finish()),
+            Arc::new(self.dcs_cpu.finish()),
+            Arc::new(self.total_cpu.finish()),
+        ];
+        write_batch(&mut self.writer, &self.schema, columns)
+    }
+
+    /// Writes any buffered rows and the Parquet footer. The file isn't
+    /// readable until this has been called.
+    fn close(mut self) -> Result<()> {
+        self.flush()?;
+        self.writer.close()?;
+        Ok(())
+    }
+}
+
+struct ObjectTable {
+    writer: ArrowWriter<ThreadedFile>,
+    schema: SchemaRef,
+    rows: usize,
+    frame_count: Int32Builder,
+    t_game: Float64Builder,
+    t_real: Float64Builder,
+    unit_name: StringBuilder,
+    group_name: StringBuilder,
+    id: Int32Builder,
+    name: StringBuilder,
+    category: StringBuilder,
+    country: Int32Builder,
+    coalition: StringBuilder,
+    coalition_id: Int32Builder,
+    lat: Float64Builder,
+    lon: Float64Builder,
+    alt: Float64Builder,
+    heading: Float64Builder,
+    pitch: Float64Builder,
+    bank: Float64Builder,
+    x: Float64Builder,
+    y: Float64Builder,
+    z: Float64Builder,
+    speed: Float64Builder,
+    vertical_speed: Float64Builder,
+    heading_rate: Float64Builder,
+}
+
+impl ObjectTable {
+    fn new(file: ThreadedFile) -> Result<Self> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("frame_count", DataType::Int32, false),
+            Field::new("t_game", DataType::Float64, false),
+            Field::new("t_real", DataType::Float64, false),
+            Field::new("unit_name", DataType::Utf8, false),
+            Field::new("group_name", DataType::Utf8, false),
+            Field::new("id", DataType::Int32, false),
+            Field::new("name", DataType::Utf8, false),
+            Field::new("category", DataType::Utf8, false),
+            Field::new("country", DataType::Int32, false),
+            Field::new("coalition", DataType::Utf8, false),
+            Field::new("coalition_id", DataType::Int32, false),
+            Field::new("lat", DataType::Float64, false),
+            Field::new("lon", DataType::Float64, false),
+            Field::new("alt", DataType::Float64, false),
+            Field::new("heading", DataType::Float64, false),
+            Field::new("pitch", DataType::Float64, false),
+            Field::new("bank", DataType::Float64, false),
+            Field::new("x", DataType::Float64, false),
+            Field::new("y", DataType::Float64, false),
+            Field::new("z", DataType::Float64, false),
+            Field::new("speed", DataType::Float64, false),
+            Field::new("vertical_speed", DataType::Float64, false),
+            Field::new("heading_rate", DataType::Float64, false),
+        ]));
+        Ok(Self {
+            writer: create_writer(file, schema.clone(), schema::PARQUET_OBJECTS)?,
+            schema,
+            rows: 0,
+            frame_count: Int32Builder::new(),
+            t_game: Float64Builder::new(),
+            t_real: Float64Builder::new(),
+            unit_name: StringBuilder::new(),
+            group_name: StringBuilder::new(),
+            id: Int32Builder::new(),
+            name: StringBuilder::new(),
+            category: StringBuilder::new(),
+            country: Int32Builder::new(),
+            coalition: StringBuilder::new(),
+            coalition_id: Int32Builder::new(),
+            lat: Float64Builder::new(),
+            lon: Float64Builder::new(),
+            alt: Float64Builder::new(),
+            heading: Float64Builder::new(),
+            pitch: Float64Builder::new(),
+            bank: Float64Builder::new(),
+            x: Float64Builder::new(),
+            y: Float64Builder::new(),
+            z: Float64Builder::new(),
+            speed: Float64Builder::new(),
+            vertical_speed: Float64Builder::new(),
+            heading_rate: Float64Builder::new(),
+        })
+    }
+
+    fn push(
+        &mut self,
+        frame_count: i32,
+        t_game: f64,
+        t_real: f64,
+        unit_name: &str,
+        group_name: &str,
+        object: &DcsWorldObject,
+        motion: Motion,
+    ) -> Result<()> {
+        let (lat, lon, alt) = object.lat_lon_alt();
+        let (heading, pitch, bank) = object.attitude();
+        let (x, y, z) = object.position();
+        self.frame_count.append_value(frame_count);
+        self.t_game.append_value(t_game);
+        self.t_real.append_value(t_real);
+        self.unit_name.append_value(unit_name);
+        self.group_name.append_value(group_name);
+        self.id.append_value(object.id());
+        self.name.append_value(object.name());
+        self.category.append_value(object.category().as_str());
+        self.country.append_value(object.country());
+        self.coalition.append_value(object.coalition());
+        self.coalition_id.append_value(object.coalition_id());
+        self.lat.append_value(lat);
+        self.lon.append_value(lon);
+        self.alt.append_value(alt);
+        self.heading.append_value(heading);
+        self.pitch.append_value(pitch);
+        self.bank.append_value(bank);
+        self.x.append_value(x);
+        self.y.append_value(y);
+        self.z.append_value(z);
+        self.speed.append_value(motion.speed);
+        self.vertical_speed.append_value(motion.vertical_speed);
+        self.heading_rate.append_value(motion.heading_rate);
+        self.rows += 1;
+        if self.rows >= ROW_GROUP_SIZE {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn push_frame(
+        &mut self,
+        frame_count: i32,
+        t_game: f64,
+        t_real: f64,
+        units: &[DcsWorldUnit],
+        ballistics: &[DcsWorldObject],
+        motion: &MotionTracker,
+    ) -> Result<()> {
+        for unit in units {
+            self.push(
+                frame_count,
+                t_game,
+                t_real,
+                unit.unit_name(),
+                unit.group_name(),
+                unit.object(),
+                motion.get(unit.object().id()),
+            )?;
+        }
+        for object in ballistics {
+            let object_motion = motion.get(object.id());
+            self.push(frame_count, t_game, t_real, "", "", object, object_motion)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if self.rows == 0 {
+            return Ok(());
+        }
+        self.rows = 0;
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(self.frame_count.finish()),
+            Arc::new(self.t_game.finish()),
+            Arc::new(self.t_real.finish()),
+            Arc::new(self.unit_name.finish()),
+            Arc::new(self.group_name.finish()),
+            Arc::new(self.id.finish()),
+            Arc::new(self.name.finish()),
+            Arc::new(self.category.finish()),
+            Arc::new(self.country.finish()),
+            Arc::new(self.coalition.finish()),
+            Arc::new(self.coalition_id.finish()),
+            Arc::new(self.lat.finish()),
+            Arc::new(self.lon.finish()),
+            Arc::new(self.alt.finish()),
+            Arc::new(self.heading.finish()),
+            Arc::new(self.pitch.finish()),
+            Arc::new(self.bank.finish()),
+            Arc::new(self.x.finish()),
+            Arc::new(self.y.finish()),
+            Arc::new(self.z.finish()),
+            Arc::new(self.speed.finish()),
+            Arc::new(self.vertical_speed.finish()),
+            Arc::new(self.heading_rate.finish()),
+        ];
+        write_batch(&mut self.writer, &self.schema, columns)
+    }
+
+    /// Writes any buffered rows and the Parquet footer. The file isn't
+    /// readable until this has been called.
+    fn close(mut self) -> Result<()> {
+        self.flush()?;
+        self.writer.close()?;
+        Ok(())
+    }
+}
@@ -1,4 +1,10 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
 use windows::Win32::Foundation::FILETIME;
+use windows::Win32::System::Performance::{QueryPerformanceCounter, QueryPerformanceFrequency};
+use windows::Win32::System::ProcessStatus::{
+    GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS, PROCESS_MEMORY_COUNTERS_EX,
+};
 use windows::Win32::System::Threading::{GetCurrentProcess, GetProcessTimes, GetSystemTimes};
 
 fn to_i64(ft: FILETIME) -> i64 {
@@ -36,6 +42,122 @@ impl PerfMonitor {
         }
         self.process.update(0, to_i64(kernel), to_i64(user))
     }
+
+    pub fn process_memory(&self) -> MemoryUsage {
+        let mut counters = PROCESS_MEMORY_COUNTERS_EX::default();
+        unsafe {
+            let success = GetProcessMemoryInfo(
+                GetCurrentProcess(),
+                &mut counters as *mut PROCESS_MEMORY_COUNTERS_EX as *mut PROCESS_MEMORY_COUNTERS,
+                std::mem::size_of::<PROCESS_MEMORY_COUNTERS_EX>() as u32,
+            );
+            assert!(success.as_bool());
+        }
+        MemoryUsage {
+            working_set: counters.WorkingSetSize as u64,
+            private_bytes: counters.PrivateUsage as u64,
+            commit: counters.PagefileUsage as u64,
+        }
+    }
+}
+
+/// Memory use of the DCS process, in bytes.
+#[derive(Debug, Default, Clone, Copy, Deserialize, Serialize)]
+pub struct MemoryUsage {
+    pub working_set: u64,
+    pub private_bytes: u64,
+    pub commit: u64,
+}
+
+// Seconds of frames the frame log's CPU load is averaged over. Windows counts
+// CPU time in 15.6 ms ticks, so over a single frame the load jumps between 0
+// and 100%.
+const CPU_LOAD_WINDOW: f64 = 1.0;
+
+/// DCS's and the whole system's CPU load, as fractions of all cores.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CpuLoad {
+    pub dcs: f64,
+    pub total: f64,
+}
+
+/// CPU load over the frames of the last `CPU_LOAD_WINDOW` seconds, from the
+/// per-frame times `PerfMonitor` returns.
+#[derive(Default)]
+pub struct CpuWindow {
+    /// Real time, system busy and total time and process time of each frame.
+    samples: VecDeque<(f64, i64, i64, i64)>,
+    sys_cpu: i64,
+    sys_wall: i64,
+    proc_cpu: i64,
+}
+
+impl CpuWindow {
+    pub fn update(
+        &mut self,
+        real_time: f64,
+        sys_time: (i32, i32),
+        proc_time: (i32, i32),
+    ) -> CpuLoad {
+        let sample = (
+            real_time,
+            sys_time.0 as i64,
+            sys_time.1 as i64,
+            proc_time.0 as i64,
+        );
+        self.samples.push_back(sample);
+        self.sys_cpu += sample.1;
+        self.sys_wall += sample.2;
+        self.proc_cpu += sample.3;
+        while let Some(&(t, sys_cpu, sys_wall, proc_cpu)) = self.samples.front() {
+            if real_time - t <= CPU_LOAD_WINDOW {
+                break;
+            }
+            self.samples.pop_front();
+            self.sys_cpu -= sys_cpu;
+            self.sys_wall -= sys_wall;
+            self.proc_cpu -= proc_cpu;
+        }
+        if self.sys_wall <= 0 {
+            return CpuLoad::default();
+        }
+        CpuLoad {
+            dcs: self.proc_cpu as f64 / self.sys_wall as f64,
+            total: self.sys_cpu as f64 / self.sys_wall as f64,
+        }
+    }
+}
+
+/// Times tetrad's own work with the high resolution performance counter.
+pub struct Stopwatch {
+    start: i64,
+}
+
+fn performance_counter() -> i64 {
+    let mut count = 0;
+    unsafe {
+        let success = QueryPerformanceCounter(&mut count);
+        assert!(success.as_bool());
+    }
+    count
+}
+
+impl Stopwatch {
+    pub fn start() -> Self {
+        Self {
+            start: performance_counter(),
+        }
+    }
+
+    /// Seconds since `start`.
+    pub fn elapsed(&self) -> f64 {
+        let mut frequency = 0;
+        unsafe {
+            let success = QueryPerformanceFrequency(&mut frequency);
+            assert!(success.as_bool());
+        }
+        (performance_counter() - self.start) as f64 / frequency as f64
+    }
 }
 
 #[derive(Default)]
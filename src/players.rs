@@ -0,0 +1,88 @@
+// Who is connected, in which slot, and since when. Players are picked up from
+// `onPlayerConnect`, and from the periodic roster polls for anyone who was
+// already connected when the mission loaded.
+use crate::dcs::PlayerInfo;
+use std::collections::HashMap;
+
+struct ConnectedPlayer {
+    info: PlayerInfo,
+    connected_at: f64,
+}
+
+/// One row of the roster log.
+#[derive(Debug, Clone)]
+pub struct RosterEntry {
+    pub player: PlayerInfo,
+    /// Real seconds since tetrad first saw the player connected.
+    pub connected_for: f64,
+}
+
+/// Name of a multiplayer side as `net.get_player_info` reports it.
+pub fn side_name(side: i32) -> &'static str {
+    match side {
+        1 => "red",
+        2 => "blue",
+        _ => "spectators",
+    }
+}
+
+#[derive(Default)]
+pub struct PlayerTracker {
+    players: HashMap<i32, ConnectedPlayer>,
+}
+
+impl PlayerTracker {
+    pub fn connect(&mut self, info: PlayerInfo, real_time: f64) {
+        self.players.insert(
+            info.id,
+            ConnectedPlayer {
+                info,
+                connected_at: real_time,
+            },
+        );
+    }
+
+    /// Forgets the player, returning how long they were connected.
+    pub fn disconnect(&mut self, id: i32, real_time: f64) -> Option<f64> {
+        self.players.remove(&id).map(|p| real_time - p.connected_at)
+    }
+
+    /// Records the player's new slot, returning the slot and side they left.
+    pub fn change_slot(&mut self, info: PlayerInfo, real_time: f64) -> (String, i32) {
+        match self.players.get_mut(&info.id) {
+            Some(p) => {
+                let old = std::mem::replace(&mut p.info, info);
+                (old.slot, old.side)
+            }
+            None => {
+                self.connect(info, real_time);
+                ("".to_string(), 0)
+            }
+        }
+    }
+
+    /// Brings the tracker in line with a fresh `net.get_player_list()` poll.
+    pub fn update(&mut self, players: Vec<PlayerInfo>, real_time: f64) -> Vec<RosterEntry> {
+        self.players
+            .retain(|id, _| players.iter().any(|p| p.id == *id));
+        players
+            .into_iter()
+            .map(|info| {
+                let connected_at = match self.players.get_mut(&info.id) {
+                    Some(p) => {
+                        p.info = info.clone();
+                        p.connected_at
+                    }
+                    None => {
+                        self.connect(info.clone(), real_time);
+                        real_time
+                    }
+                };
+                RosterEntry {
+                    player: info,
+                    connected_for: real_time - connected_at,
+                }
+            })
+            .collect()
+    }
+}
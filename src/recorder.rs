@@ -0,0 +1,63 @@
+// Compact binary flight recorder. The format, and the reader for it, are in
+// `tetrad_data::recording`.
+use crate::dcs::{DcsWorldObject, DcsWorldUnit};
+use std::collections::HashSet;
+use std::io::Write;
+
+pub use tetrad_data::recording::*;
+
+pub struct Recorder<W: Write> {
+    writer: W,
+    known_ids: HashSet<i32>,
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(mut writer: W, mission_name: &str) -> bincode::Result<Self> {
+        let header = RecordingHeader {
+            magic: MAGIC,
+            version: FORMAT_VERSION,
+            mission_name: mission_name.to_string(),
+        };
+        bincode::serialize_into(&mut writer, &header)?;
+        Ok(Self {
+            writer,
+            known_ids: HashSet::new(),
+        })
+    }
+
+    pub fn record(
+        &mut self,
+        t_game: f64,
+        units: &[DcsWorldUnit],
+        ballistics: &[DcsWorldObject],
+    ) -> bincode::Result<()> {
+        let mut frame = RecordedFrame {
+            t_game,
+            new_names: Vec::new(),
+            objects: Vec::with_capacity(units.len() + ballistics.len()),
+        };
+        let all = units
+            .iter()
+            .map(|u| (u.object(), ObjectKind::Unit))
+            .chain(ballistics.iter().map(|b| (b, ObjectKind::Ballistic)));
+        for (obj, kind) in all {
+            if self.known_ids.insert(obj.id()) {
+                frame.new_names.push((obj.id(), obj.name().to_string()));
+            }
+            frame.objects.push(RecordedObject::new(obj, kind));
+        }
+        bincode::serialize_into(&mut self.writer, &frame)
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
@@ -0,0 +1,140 @@
+// GUI replay of flight recordings (see recorder.rs) on the map view.
+use crate::map::{self, MapObject};
+use crate::recorder::{ObjectKind, Recording};
+use std::time::Instant;
+
+pub const PLAYBACK_SPEEDS: [f64; 6] = [0.5, 1.0, 2.0, 4.0, 8.0, 16.0];
+
+pub struct ReplayPanel {
+    path: String,
+    recording: Option<Recording>,
+    error: Option<String>,
+    t: f64,
+    is_playing: bool,
+    speed: f64,
+    last_tick: Option<Instant>,
+}
+
+impl Default for ReplayPanel {
+    fn default() -> Self {
+        Self {
+            path: String::new(),
+            recording: None,
+            error: None,
+            t: 0.0,
+            is_playing: false,
+            speed: 1.0,
+            last_tick: None,
+        }
+    }
+}
+
+impl ReplayPanel {
+    fn load(&mut self) {
+        log::info!("Loading recording {}", self.path);
+        match Recording::open(std::path::Path::new(&self.path)) {
+            Ok(r) => {
+                self.t = r.start_time();
+                self.recording = Some(r);
+                self.error = None;
+            }
+            Err(e) => {
+                self.recording = None;
+                self.error = Some(format!("Couldn't load recording: {}", e));
+            }
+        }
+        self.is_playing = false;
+    }
+
+    /// Loads the recording at `path`, as if it had been typed in and loaded.
+    pub fn open(&mut self, path: &std::path::Path) {
+        self.path = path.to_string_lossy().into_owned();
+        self.load();
+    }
+
+    /// Pauses and moves to mission time `t`.
+    pub fn seek(&mut self, t: f64) {
+        if let Some(recording) = &self.recording {
+            self.t = t.clamp(recording.start_time(), recording.end_time());
+            self.is_playing = false;
+        }
+    }
+
+    fn advance(&mut self, ctx: &egui::Context) {
+        let now = Instant::now();
+        let last = self.last_tick.replace(now);
+        if !self.is_playing {
+            return;
+        }
+        let Some(recording) = &self.recording else {
+            return;
+        };
+        if let Some(last) = last {
+            self.t += (now - last).as_secs_f64() * self.speed;
+        }
+        if self.t >= recording.end_time() {
+            self.t = recording.end_time();
+            self.is_playing = false;
+        }
+        ctx.request_repaint();
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Recording:");
+            ui.text_edit_singleline(&mut self.path);
+            if ui.button("Load").clicked() {
+                self.load();
+            }
+        });
+        if let Some(e) = &self.error {
+            ui.colored_label(egui::Color32::RED, e);
+        }
+
+        self.advance(ui.ctx());
+        let Some(recording) = &self.recording else {
+            return;
+        };
+
+        ui.horizontal(|ui| {
+            let label = if self.is_playing { "Pause" } else { "Play" };
+            if ui.button(label).clicked() {
+                if !self.is_playing && self.t >= recording.end_time() {
+                    self.t = recording.start_time();
+                }
+                self.is_playing = !self.is_playing;
+            }
+            egui::ComboBox::from_id_source("replay_speed")
+                .selected_text(format!("{}x", self.speed))
+                .show_ui(ui, |ui| {
+                    for speed in PLAYBACK_SPEEDS {
+                        ui.selectable_value(&mut self.speed, speed, format!("{}x", speed));
+                    }
+                });
+            ui.add(
+                egui::Slider::new(&mut self.t, recording.start_time()..=recording.end_time())
+                    .text("mission time (s)"),
+            );
+        });
+
+        let objects: Vec<MapObject> = match recording.frame_at(self.t) {
+            Some(frame) => frame
+                .objects
+                .iter()
+                .map(|o| MapObject {
+                    east: o.z as f64,
+                    north: o.x as f64,
+                    coalition_id: o.coalition_id as i32,
+                    is_unit: o.kind == ObjectKind::Unit,
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        ui.label(format!(
+            "{}: {} objects",
+            recording.mission_name,
+            objects.len()
+        ));
+        map::show_map(ui, "replay_map", &objects, 1792.0, 768.0);
+    }
+}
@@ -0,0 +1,615 @@
+// End-of-session report, `Logs/Tetrad/sessions/<mission> - <time>.report.md`.
+// Collects the top-line numbers while the session runs and writes them out as
+// Markdown when it stops, for admins who never open the CSVs.
+use crate::events::{Event, EventKind};
+use crate::hitch::HitchList;
+use crate::sink::{Frame, Sink, SinkResult};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+
+// Frame time histogram resolution and range. Longer frames go in the last
+// bucket.
+const BUCKET_SECONDS: f64 = 0.0001;
+const NUM_BUCKETS: usize = 50_000;
+// Game seconds per row of the load table, before merging rows down to at
+// most `MAX_LOAD_ROWS`.
+const LOAD_BIN_SECONDS: f64 = 60.0;
+const MAX_LOAD_ROWS: usize = 60;
+const BAR_WIDTH: usize = 20;
+const MAX_LISTED_HITCHES: usize = 20;
+// Frames at least this long count as spikes when the hitch log, which has its
+// own threshold, is off.
+const DEFAULT_SPIKE_SECONDS: f64 = 0.1;
+
+/// What frame times are compared against.
+const FACTORS: [&str; 3] = ["units", "ballistics", "CPU load"];
+
+/// Running sums for the correlation between frame time and one factor.
+#[derive(Debug, Default, Clone, Copy)]
+struct Correlation {
+    n: f64,
+    sum_x: f64,
+    sum_y: f64,
+    sum_xx: f64,
+    sum_yy: f64,
+    sum_xy: f64,
+    /// The factor summed over spike frames only.
+    spike_sum_y: f64,
+}
+
+impl Correlation {
+    fn push(&mut self, frame_time: f64, y: f64, is_spike: bool) {
+        self.n += 1.0;
+        self.sum_x += frame_time;
+        self.sum_y += y;
+        self.sum_xx += frame_time * frame_time;
+        self.sum_yy += y * y;
+        self.sum_xy += frame_time * y;
+        if is_spike {
+            self.spike_sum_y += y;
+        }
+    }
+
+    /// Pearson's r, if there's any variation to go on.
+    fn r(&self) -> Option<f64> {
+        let cov = self.n * self.sum_xy - self.sum_x * self.sum_y;
+        let var_x = self.n * self.sum_xx - self.sum_x * self.sum_x;
+        let var_y = self.n * self.sum_yy - self.sum_y * self.sum_y;
+        (var_x > 0.0 && var_y > 0.0).then(|| cov / (var_x * var_y).sqrt())
+    }
+
+    fn mean(&self) -> f64 {
+        self.sum_y / self.n.max(1.0)
+    }
+}
+
+fn strength(r: f64) -> &'static str {
+    match r.abs() {
+        r if r < 0.1 => "no",
+        r if r < 0.3 => "a weak",
+        r if r < 0.5 => "a moderate",
+        _ => "a strong",
+    }
+}
+
+/// Totals over one row of the load table.
+#[derive(Debug, Default, Clone)]
+struct LoadBin {
+    t_game: f64,
+    frames: u64,
+    frame_time: f64,
+    max_units: usize,
+    max_ballistics: usize,
+    proc_cpu: i64,
+    sys_cpu: i64,
+    sys_wall: i64,
+}
+
+impl LoadBin {
+    fn merge(&mut self, other: &LoadBin) {
+        self.frames += other.frames;
+        self.frame_time += other.frame_time;
+        self.max_units = self.max_units.max(other.max_units);
+        self.max_ballistics = self.max_ballistics.max(other.max_ballistics);
+        self.proc_cpu += other.proc_cpu;
+        self.sys_cpu += other.sys_cpu;
+        self.sys_wall += other.sys_wall;
+    }
+
+    fn fps(&self) -> f64 {
+        if self.frame_time > 0.0 {
+            self.frames as f64 / self.frame_time
+        } else {
+            0.0
+        }
+    }
+
+    /// DCS's and the whole system's CPU load, as fractions.
+    fn cpu_loads(&self) -> (f64, f64) {
+        if self.sys_wall > 0 {
+            (
+                self.proc_cpu as f64 / self.sys_wall as f64,
+                self.sys_cpu as f64 / self.sys_wall as f64,
+            )
+        } else {
+            (0.0, 0.0)
+        }
+    }
+}
+
+/// Takeoffs and landings of one player, from the events log.
+#[derive(Debug, Default)]
+struct Pilot {
+    sorties: u32,
+    landings: u32,
+    airborne: f64,
+    /// Game time of the takeoff, while in the air.
+    took_off_at: Option<f64>,
+}
+
+impl Pilot {
+    fn end_sortie(&mut self, t_game: f64) {
+        if let Some(t) = self.took_off_at.take() {
+            self.airborne += t_game - t;
+        }
+    }
+}
+
+pub struct ReportSink {
+    path: PathBuf,
+    mission_name: String,
+    started_at: chrono::DateTime<chrono::Local>,
+    hitches: Option<HitchList>,
+    histogram: Vec<u64>,
+    frames: u64,
+    total_frame_time: f64,
+    max_frame_time: f64,
+    first_game_time: Option<f64>,
+    last_game_time: Option<f64>,
+    peak_units: (usize, f64),
+    peak_ballistics: (usize, f64),
+    load: Vec<LoadBin>,
+    spike_threshold: f64,
+    spike_frames: u64,
+    correlations: [Correlation; FACTORS.len()],
+    pilots: BTreeMap<String, Pilot>,
+}
+
+fn bar(fraction: f64) -> String {
+    let filled = (fraction.clamp(0.0, 1.0) * BAR_WIDTH as f64).round() as usize;
+    format!("{}{}", "█".repeat(filled), "░".repeat(BAR_WIDTH - filled))
+}
+
+fn format_duration(seconds: f64) -> String {
+    let seconds = seconds.max(0.0) as u64;
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+impl ReportSink {
+    /// `hitches` is the hitch log's list, if it's enabled. Frames of at least
+    /// `spike_threshold` seconds count as spikes.
+    pub fn new(
+        path: PathBuf,
+        mission_name: &str,
+        hitches: Option<HitchList>,
+        spike_threshold: Option<f64>,
+    ) -> Self {
+        Self {
+            path,
+            mission_name: mission_name.to_string(),
+            started_at: chrono::Local::now(),
+            hitches,
+            histogram: vec![0; NUM_BUCKETS],
+            frames: 0,
+            total_frame_time: 0.0,
+            max_frame_time: 0.0,
+            first_game_time: None,
+            last_game_time: None,
+            peak_units: (0, 0.0),
+            peak_ballistics: (0, 0.0),
+            load: Vec::new(),
+            spike_threshold: spike_threshold.unwrap_or(DEFAULT_SPIKE_SECONDS),
+            spike_frames: 0,
+            correlations: [Correlation::default(); FACTORS.len()],
+            pilots: BTreeMap::new(),
+        }
+    }
+
+    /// Sorties per player, most active first. Flights still in the air count
+    /// up to the end of the session.
+    fn render_sorties(&self, out: &mut String) {
+        let end = self.last_game_time.unwrap_or_default();
+        let mut pilots: Vec<(&String, &Pilot)> = self.pilots.iter().collect();
+        pilots.sort_by(|a, b| b.1.sorties.cmp(&a.1.sorties));
+        let total: u32 = pilots.iter().map(|(_, p)| p.sorties).sum();
+        writeln!(out).unwrap();
+        writeln!(out, "## Sorties").unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "{} sorties by {} players.", total, pilots.len()).unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "| player | sorties | landings | time airborne |").unwrap();
+        writeln!(out, "|---|---|---|---|").unwrap();
+        for (name, pilot) in pilots {
+            let in_flight = pilot.took_off_at.map_or(0.0, |t| end - t);
+            writeln!(
+                out,
+                "| {} | {} | {} | {} |",
+                name,
+                pilot.sorties,
+                pilot.landings,
+                format_duration(pilot.airborne + in_flight)
+            )
+            .unwrap();
+        }
+    }
+
+    /// One line per factor on how it goes along with frame time.
+    fn correlation_findings(&self) -> Vec<String> {
+        let mut findings = Vec::new();
+        for (name, c) in FACTORS.iter().zip(self.correlations.iter()) {
+            let Some(r) = c.r() else {
+                continue;
+            };
+            let direction = if r >= 0.0 { "longer" } else { "shorter" };
+            let mut finding = if r.abs() < 0.1 {
+                format!("Frame time shows no relation to {} (r = {:.2})", name, r)
+            } else {
+                format!(
+                    "Frame time has {} correlation with {} (r = {:.2}): frames get {} as it rises",
+                    strength(r),
+                    name,
+                    r,
+                    direction
+                )
+            };
+            if self.spike_frames > 0 {
+                let spike_mean = c.spike_sum_y / self.spike_frames as f64;
+                let format_value = |v: f64| {
+                    if *name == "CPU load" {
+                        format!("{:.0}%", v * 100.0)
+                    } else {
+                        format!("{:.0}", v)
+                    }
+                };
+                finding.push_str(&format!(
+                    ". During spikes it averaged {}, against {} overall",
+                    format_value(spike_mean),
+                    format_value(c.mean())
+                ));
+            }
+            finding.push('.');
+            findings.push(finding);
+        }
+        // the strongest relation first
+        let mut ranked: Vec<(usize, f64)> = self
+            .correlations
+            .iter()
+            .enumerate()
+            .filter_map(|(i, c)| c.r().map(|r| (i, r)))
+            .filter(|(_, r)| *r >= 0.3)
+            .collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+        if let Some((i, r)) = ranked.first() {
+            findings.insert(
+                0,
+                format!(
+                    "Slow frames are most closely tied to {} (r = {:.2}).",
+                    FACTORS[*i], r
+                ),
+            );
+        }
+        findings
+    }
+
+    /// Frame time below which `p` percent of frames fall, in seconds.
+    fn percentile(&self, p: f64) -> f64 {
+        let rank = ((p / 100.0) * self.frames as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (i, n) in self.histogram.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                return (i + 1) as f64 * BUCKET_SECONDS;
+            }
+        }
+        self.max_frame_time
+    }
+
+    /// The load table, with neighbouring rows merged until it's short enough.
+    fn load_rows(&self) -> Vec<LoadBin> {
+        let per_row = ((self.load.len() + MAX_LOAD_ROWS - 1) / MAX_LOAD_ROWS).max(1);
+        self.load
+            .chunks(per_row)
+            .map(|chunk| {
+                let mut row = chunk[0].clone();
+                for bin in &chunk[1..] {
+                    row.merge(bin);
+                }
+                row
+            })
+            .collect()
+    }
+
+    fn render(&self) -> String {
+        let mut out = String::new();
+        let ended_at = chrono::Local::now();
+        let game_duration = match (self.first_game_time, self.last_game_time) {
+            (Some(first), Some(last)) => last - first,
+            _ => 0.0,
+        };
+        let fps = if self.total_frame_time > 0.0 {
+            self.frames as f64 / self.total_frame_time
+        } else {
+            0.0
+        };
+
+        writeln!(out, "# Tetrad session report: {}", self.mission_name).unwrap();
+        writeln!(out).unwrap();
+        writeln!(
+            out,
+            "- Started {}, ended {}",
+            self.started_at.format("%Y-%m-%d %H:%M:%S"),
+            ended_at.format("%Y-%m-%d %H:%M:%S")
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "- Mission time covered: {}",
+            format_duration(game_duration)
+        )
+        .unwrap();
+        writeln!(out, "- Frames: {}", self.frames).unwrap();
+        writeln!(out, "- Average FPS: {:.1}", fps).unwrap();
+        writeln!(
+            out,
+            "- Peak units: {} (at t={:.0})",
+            self.peak_units.0, self.peak_units.1
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "- Peak ballistics: {} (at t={:.0})",
+            self.peak_ballistics.0, self.peak_ballistics.1
+        )
+        .unwrap();
+
+        writeln!(out).unwrap();
+        writeln!(out, "## Frame times").unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "| p50 | p90 | p95 | p99 | p99.9 | max |").unwrap();
+        writeln!(out, "|---|---|---|---|---|---|").unwrap();
+        if self.frames > 0 {
+            writeln!(
+                out,
+                "| {:.1} ms | {:.1} ms | {:.1} ms | {:.1} ms | {:.1} ms | {:.1} ms |",
+                self.percentile(50.0) * 1000.0,
+                self.percentile(90.0) * 1000.0,
+                self.percentile(95.0) * 1000.0,
+                self.percentile(99.0) * 1000.0,
+                self.percentile(99.9) * 1000.0,
+                self.max_frame_time * 1000.0
+            )
+            .unwrap();
+        }
+
+        writeln!(out).unwrap();
+        writeln!(out, "## Load over time").unwrap();
+        writeln!(out).unwrap();
+        writeln!(
+            out,
+            "| t | FPS | units | ballistics | DCS CPU | total CPU | |"
+        )
+        .unwrap();
+        writeln!(out, "|---|---|---|---|---|---|---|").unwrap();
+        for row in self.load_rows() {
+            let (dcs_cpu, total_cpu) = row.cpu_loads();
+            writeln!(
+                out,
+                "| {} | {:.1} | {} | {} | {:.0}% | {:.0}% | `{}` |",
+                format_duration(row.t_game),
+                row.fps(),
+                row.max_units,
+                row.max_ballistics,
+                dcs_cpu * 100.0,
+                total_cpu * 100.0,
+                bar(total_cpu)
+            )
+            .unwrap();
+        }
+
+        writeln!(out).unwrap();
+        writeln!(out, "## What goes with slow frames").unwrap();
+        writeln!(out).unwrap();
+        writeln!(
+            out,
+            "{} frames were spikes of at least {:.0} ms.",
+            self.spike_frames,
+            self.spike_threshold * 1000.0
+        )
+        .unwrap();
+        writeln!(out).unwrap();
+        for finding in self.correlation_findings() {
+            writeln!(out, "- {}", finding).unwrap();
+        }
+
+        if !self.pilots.is_empty() {
+            self.render_sorties(&mut out);
+        }
+
+        if let Some(hitches) = self.hitches.as_ref() {
+            let mut hitches = hitches.lock().unwrap().clone();
+            writeln!(out).unwrap();
+            writeln!(out, "## Hitches").unwrap();
+            writeln!(out).unwrap();
+            writeln!(out, "{} frames were hitches.", hitches.len()).unwrap();
+            if !hitches.is_empty() {
+                hitches.sort_by(|a, b| b.frame_ms.total_cmp(&a.frame_ms));
+                writeln!(out).unwrap();
+                writeln!(out, "| t | frame | units | ballistics | likely cause |").unwrap();
+                writeln!(out, "|---|---|---|---|---|").unwrap();
+                for hitch in hitches.iter().take(MAX_LISTED_HITCHES) {
+                    writeln!(
+                        out,
+                        "| {} | {:.0} ms | {} | {} | {} |",
+                        format_duration(hitch.t_game),
+                        hitch.frame_ms,
+                        hitch.units,
+                        hitch.ballistics,
+                        hitch.causes
+                    )
+                    .unwrap();
+                }
+            }
+        }
+        out
+    }
+}
+
+impl Sink for ReportSink {
+    fn name(&self) -> &'static str {
+        "session report"
+    }
+
+    fn write_frame(&mut self, frame: &Frame) -> SinkResult {
+        if frame.num_units > self.peak_units.0 {
+            self.peak_units = (frame.num_units, frame.game_time);
+        }
+        if frame.num_ballistics > self.peak_ballistics.0 {
+            self.peak_ballistics = (frame.num_ballistics, frame.game_time);
+        }
+        self.first_game_time.get_or_insert(frame.game_time);
+        // paused frames don't advance game time and don't count
+        let Some(last_game_time) = self.last_game_time.replace(frame.game_time) else {
+            return Ok(());
+        };
+        let frame_time = frame.game_time - last_game_time;
+        if frame_time <= 0.0 {
+            return Ok(());
+        }
+        let bucket = ((frame_time / BUCKET_SECONDS) as usize).min(NUM_BUCKETS - 1);
+        self.histogram[bucket] += 1;
+        self.frames += 1;
+        self.total_frame_time += frame_time;
+        self.max_frame_time = self.max_frame_time.max(frame_time);
+
+        let is_spike = frame_time >= self.spike_threshold;
+        if is_spike {
+            self.spike_frames += 1;
+        }
+        let (busy, total) = frame.sys_time;
+        let cpu_load = if total > 0 {
+            busy as f64 / total as f64
+        } else {
+            0.0
+        };
+        let factors = [
+            frame.num_units as f64,
+            frame.num_ballistics as f64,
+            cpu_load,
+        ];
+        for (c, y) in self.correlations.iter_mut().zip(factors) {
+            c.push(frame_time, y, is_spike);
+        }
+
+        let first_game_time = self.first_game_time.unwrap_or_default();
+        let index = ((frame.game_time - first_game_time) / LOAD_BIN_SECONDS) as usize;
+        while self.load.len() <= index {
+            let t_game = self.load.len() as f64 * LOAD_BIN_SECONDS;
+            self.load.push(LoadBin {
+                t_game,
+                ..Default::default()
+            });
+        }
+        let bin = &mut self.load[index];
+        bin.frames += 1;
+        bin.frame_time += frame_time;
+        bin.max_units = bin.max_units.max(frame.num_units);
+        bin.max_ballistics = bin.max_ballistics.max(frame.num_ballistics);
+        bin.proc_cpu += frame.proc_time.0 as i64;
+        bin.sys_cpu += frame.sys_time.0 as i64;
+        bin.sys_wall += frame.sys_time.1 as i64;
+        Ok(())
+    }
+
+    fn write_event(&mut self, event: &Event) -> SinkResult {
+        // AI flights don't get takeoff and landing events
+        if event.player_name.is_empty() {
+            return Ok(());
+        }
+        match event.kind {
+            EventKind::Takeoff => {
+                let pilot = self.pilots.entry(event.player_name.clone()).or_default();
+                pilot.end_sortie(event.t_game);
+                pilot.sorties += 1;
+                pilot.took_off_at = Some(event.t_game);
+            }
+            EventKind::Landing => {
+                let pilot = self.pilots.entry(event.player_name.clone()).or_default();
+                pilot.landings += 1;
+                pilot.end_sortie(event.t_game);
+            }
+            EventKind::Crash | EventKind::Eject | EventKind::PilotDeath => {
+                if let Some(pilot) = self.pilots.get_mut(&event.player_name) {
+                    pilot.end_sortie(event.t_game);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> SinkResult {
+        for finding in self.correlation_findings() {
+            log::info!("{}", finding);
+        }
+        std::fs::write(&self.path, self.render())?;
+        log::info!("Session report written to {:?}", self.path);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn correlation(samples: &[(f64, f64)]) -> Correlation {
+        let mut c = Correlation::default();
+        for &(frame_time, y) in samples {
+            c.push(frame_time, y, false);
+        }
+        c
+    }
+
+    #[test]
+    fn perfect_correlations() {
+        let rising = correlation(&[(0.01, 10.0), (0.02, 20.0), (0.03, 30.0)]);
+        assert!((rising.r().unwrap() - 1.0).abs() < 1e-9);
+        let falling = correlation(&[(0.01, 30.0), (0.02, 20.0), (0.03, 10.0)]);
+        assert!((falling.r().unwrap() + 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn no_correlation_without_variation() {
+        assert!(correlation(&[]).r().is_none());
+        assert!(correlation(&[(0.01, 5.0), (0.02, 5.0)]).r().is_none());
+        assert!(correlation(&[(1.0, 5.0), (1.0, 6.0)]).r().is_none());
+    }
+
+    #[test]
+    fn unrelated_factor() {
+        let c = correlation(&[(0.01, 1.0), (0.02, 2.0), (0.03, 2.0), (0.04, 1.0)]);
+        assert!(c.r().unwrap().abs() < 0.1);
+        assert_eq!(strength(0.05), "no");
+        assert_eq!(strength(-0.2), "a weak");
+        assert_eq!(strength(0.4), "a moderate");
+        assert_eq!(strength(-0.9), "a strong");
+    }
+
+    #[test]
+    fn findings_lead_with_the_strongest_factor() {
+        let mut sink = ReportSink::new(PathBuf::new(), "test", None, Some(0.1));
+        for frame_time in [0.01, 0.02, 0.2] {
+            let is_spike = frame_time >= sink.spike_threshold;
+            if is_spike {
+                sink.spike_frames += 1;
+            }
+            let factors = [frame_time * 1000.0, 4.0, 1.0 - frame_time];
+            for (c, y) in sink.correlations.iter_mut().zip(factors) {
+                c.push(frame_time, y, is_spike);
+            }
+        }
+        let findings = sink.correlation_findings();
+        // ballistics never changed, so there's nothing to say about them
+        assert_eq!(findings.len(), 3);
+        assert!(findings[0].starts_with("Slow frames are most closely tied to units"));
+        assert!(findings[1].contains("a strong correlation with units"));
+        assert!(findings[1].contains("During spikes it averaged 200, against 77 overall"));
+        assert!(findings[2].contains("CPU load"));
+        assert!(findings[2].contains("frames get shorter"));
+    }
+}
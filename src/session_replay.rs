@@ -0,0 +1,190 @@
+// Offline viewing of a recorded session in the GUI. The session's frame log is
+// read back and fed to the same plots the live data goes to, up to a point in
+// the mission picked with a slider or played back like a flight recording.
+use crate::perf_monitor::MemoryUsage;
+use crate::replay::PLAYBACK_SPEEDS;
+use serde::Deserialize;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use tetrad_data::csv_log::{self, FrameRow};
+
+const MANIFEST_SUFFIX: &str = ".session.json";
+const FRAME_LOG_SUFFIX: &str = ".csv.zstd";
+
+impl From<&FrameRow> for MemoryUsage {
+    fn from(frame: &FrameRow) -> Self {
+        Self {
+            working_set: frame.working_set,
+            private_bytes: frame.private_bytes,
+            commit: frame.commit,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    mission: String,
+    output_files: Vec<PathBuf>,
+}
+
+pub struct SessionReplay {
+    pub mission_name: String,
+    pub frames: Vec<FrameRow>,
+    /// The session's flight recording, for the map.
+    pub recording: Option<PathBuf>,
+    t: f64,
+    is_playing: bool,
+    speed: f64,
+    last_tick: Option<Instant>,
+}
+
+/// The most recently written session manifest in `dir`.
+fn newest_manifest(dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    std::fs::read_dir(dir)?
+        .flatten()
+        .filter(|e| e.file_name().to_string_lossy().ends_with(MANIFEST_SUFFIX))
+        .max_by_key(|e| e.metadata().and_then(|m| m.modified()).ok())
+        .map(|e| e.path())
+        .ok_or_else(|| format!("no session manifests in {:?}", dir).into())
+}
+
+/// Frames from a CSV frame log. A log that was still being written when it was
+/// copied ends in a partial row; everything before it is kept.
+fn read_frame_log(path: &Path, frames: &mut Vec<FrameRow>) -> Result<(), Box<dyn Error>> {
+    for row in csv_log::read_frames(path)? {
+        match row {
+            Ok(frame) => frames.push(frame),
+            Err(e) => {
+                log::warn!("Stopped reading {:?} at {}", path, e);
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn has_suffix(path: &Path, suffix: &str) -> bool {
+    path.to_string_lossy().ends_with(suffix)
+}
+
+impl SessionReplay {
+    /// Opens a session from its manifest, from the newest manifest in a
+    /// directory, or straight from a `.csv.zstd` frame log.
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let path = if path.is_dir() {
+            newest_manifest(path)?
+        } else {
+            path.to_path_buf()
+        };
+        let (mission_name, frame_logs, recording) = if has_suffix(&path, MANIFEST_SUFFIX) {
+            let manifest: Manifest = serde_json::from_slice(&std::fs::read(&path)?)?;
+            let frame_logs: Vec<PathBuf> = manifest
+                .output_files
+                .iter()
+                .filter(|f| {
+                    has_suffix(f, FRAME_LOG_SUFFIX)
+                        && f.parent().map_or(false, |p| p.ends_with("frames"))
+                })
+                .cloned()
+                .collect();
+            let recording = manifest
+                .output_files
+                .iter()
+                .find(|f| f.extension().map_or(false, |e| e == "ttrd"))
+                .cloned();
+            (manifest.mission, frame_logs, recording)
+        } else {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let name = name.trim_end_matches(FRAME_LOG_SUFFIX).to_string();
+            (name, vec![path.clone()], None)
+        };
+        if frame_logs.is_empty() {
+            return Err(format!(
+                "{:?} has no CSV frame log; it needs enable_framerate_log and the csv export format",
+                path
+            )
+            .into());
+        }
+
+        let mut frames = Vec::new();
+        for log in frame_logs.iter() {
+            log::info!("Loading frame log {:?}", log);
+            read_frame_log(log, &mut frames)?;
+        }
+        let Some(first) = frames.first() else {
+            return Err(format!("{:?} has no frames", path).into());
+        };
+        Ok(Self {
+            mission_name,
+            t: first.t_game,
+            frames,
+            recording,
+            is_playing: false,
+            speed: 1.0,
+            last_tick: None,
+        })
+    }
+
+    fn start_time(&self) -> f64 {
+        self.frames.first().map_or(0.0, |f| f.t_game)
+    }
+
+    fn end_time(&self) -> f64 {
+        self.frames.last().map_or(0.0, |f| f.t_game)
+    }
+
+    /// Mission time the replay is at.
+    pub fn time(&self) -> f64 {
+        self.t
+    }
+
+    /// Number of frames up to and including the current time.
+    pub fn frames_shown(&self) -> usize {
+        self.frames.partition_point(|f| f.t_game <= self.t)
+    }
+
+    fn advance(&mut self, ctx: &egui::Context) {
+        let now = Instant::now();
+        let last = self.last_tick.replace(now);
+        if !self.is_playing {
+            return;
+        }
+        if let Some(last) = last {
+            self.t += (now - last).as_secs_f64() * self.speed;
+        }
+        if self.t >= self.end_time() {
+            self.t = self.end_time();
+            self.is_playing = false;
+        }
+        ctx.request_repaint();
+    }
+
+    /// Draws the playback controls.
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        self.advance(ui.ctx());
+        let (start, end) = (self.start_time(), self.end_time());
+        ui.horizontal(|ui| {
+            ui.label(format!(
+                "{}: {} frames",
+                self.mission_name,
+                self.frames.len()
+            ));
+            let label = if self.is_playing { "Pause" } else { "Play" };
+            if ui.button(label).clicked() {
+                if !self.is_playing && self.t >= end {
+                    self.t = start;
+                }
+                self.is_playing = !self.is_playing;
+            }
+            egui::ComboBox::from_id_source("session_replay_speed")
+                .selected_text(format!("{}x", self.speed))
+                .show_ui(ui, |ui| {
+                    for speed in PLAYBACK_SPEEDS {
+                        ui.selectable_value(&mut self.speed, speed, format!("{}x", speed));
+                    }
+                });
+            ui.add(egui::Slider::new(&mut self.t, start..=end).text("mission time (s)"));
+        });
+    }
+}
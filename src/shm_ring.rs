@@ -0,0 +1,367 @@
+// Single-producer/single-consumer ring buffer living in a named Windows file
+// mapping. Records are length-prefixed byte blobs written and read in place;
+// the producer never blocks, it drops records when the consumer has fallen too
+// far behind.
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{CloseHandle, BOOL, HANDLE, INVALID_HANDLE_VALUE, WAIT_OBJECT_0};
+use windows::Win32::System::Memory::{
+    CreateFileMappingW, MapViewOfFile, OpenFileMappingW, UnmapViewOfFile, FILE_MAP_ALL_ACCESS,
+    PAGE_READWRITE,
+};
+use windows::Win32::System::Threading::{OpenProcess, WaitForSingleObject, PROCESS_SYNCHRONIZE};
+
+#[repr(C)]
+struct Header {
+    write_pos: AtomicU64,
+    read_pos: AtomicU64,
+    capacity: u64,
+    /// So the reader can tell when the process writing the records is gone.
+    producer_pid: u64,
+}
+
+const HEADER_SIZE: usize = std::mem::size_of::<Header>();
+const LEN_SIZE: usize = std::mem::size_of::<u32>();
+
+pub struct ShmRing {
+    handle: HANDLE,
+    view: *mut u8,
+    capacity: usize,
+    /// The producer's process, only opened on the reading side.
+    producer: Option<HANDLE>,
+}
+
+// The mapping is only touched through the atomics in the header and the
+// region between them, which the SPSC protocol keeps disjoint.
+unsafe impl Send for ShmRing {}
+
+pub fn to_wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(std::iter::once(0)).collect()
+}
+
+impl ShmRing {
+    /// Creates a new mapping named `name` with room for `capacity` bytes of
+    /// records.
+    pub fn create(name: &str, capacity: usize) -> windows::core::Result<Self> {
+        let wide = to_wide(name);
+        let total = (HEADER_SIZE + capacity) as u64;
+        unsafe {
+            let handle = CreateFileMappingW(
+                INVALID_HANDLE_VALUE,
+                None,
+                PAGE_READWRITE,
+                (total >> 32) as u32,
+                total as u32,
+                PCWSTR(wide.as_ptr()),
+            )?;
+            let ring = Self::map(handle, capacity)?;
+            let header = ring.header();
+            header.write_pos.store(0, Ordering::SeqCst);
+            header.read_pos.store(0, Ordering::SeqCst);
+            std::ptr::write_volatile(
+                std::ptr::addr_of!(header.capacity) as *mut u64,
+                capacity as u64,
+            );
+            std::ptr::write_volatile(
+                std::ptr::addr_of!(header.producer_pid) as *mut u64,
+                std::process::id() as u64,
+            );
+            Ok(ring)
+        }
+    }
+
+    /// Opens a mapping previously made by `create`, possibly in another
+    /// process.
+    pub fn open(name: &str) -> windows::core::Result<Self> {
+        let wide = to_wide(name);
+        unsafe {
+            let handle = OpenFileMappingW(FILE_MAP_ALL_ACCESS.0, BOOL(0), PCWSTR(wide.as_ptr()))?;
+            // map just the header first to learn the capacity
+            let header_only = Self::map(handle, 0)?;
+            let capacity = header_only.header().capacity as usize;
+            let producer_pid = header_only.header().producer_pid as u32;
+            UnmapViewOfFile(header_only.view as *const libc::c_void);
+            std::mem::forget(header_only);
+            let mut ring = Self::map(handle, capacity)?;
+            ring.producer = Some(OpenProcess(PROCESS_SYNCHRONIZE, BOOL(0), producer_pid)?);
+            Ok(ring)
+        }
+    }
+
+    unsafe fn map(handle: HANDLE, capacity: usize) -> windows::core::Result<Self> {
+        let view = MapViewOfFile(handle, FILE_MAP_ALL_ACCESS, 0, 0, HEADER_SIZE + capacity);
+        if view.is_null() {
+            let err = windows::core::Error::from_win32();
+            CloseHandle(handle);
+            return Err(err);
+        }
+        Ok(Self {
+            handle,
+            view: view as *mut u8,
+            capacity,
+            producer: None,
+        })
+    }
+
+    fn header(&self) -> &Header {
+        unsafe { &*(self.view as *const Header) }
+    }
+
+    fn data(&self) -> *mut u8 {
+        unsafe { self.view.add(HEADER_SIZE) }
+    }
+
+    fn copy_in(&self, pos: u64, bytes: &[u8]) {
+        let start = (pos % self.capacity as u64) as usize;
+        let first = std::cmp::min(bytes.len(), self.capacity - start);
+        unsafe {
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), self.data().add(start), first);
+            std::ptr::copy_nonoverlapping(
+                bytes[first..].as_ptr(),
+                self.data(),
+                bytes.len() - first,
+            );
+        }
+    }
+
+    fn copy_out(&self, pos: u64, bytes: &mut [u8]) {
+        let start = (pos % self.capacity as u64) as usize;
+        let first = std::cmp::min(bytes.len(), self.capacity - start);
+        unsafe {
+            std::ptr::copy_nonoverlapping(self.data().add(start), bytes.as_mut_ptr(), first);
+            let rest = bytes.len() - first;
+            std::ptr::copy_nonoverlapping(self.data(), bytes[first..].as_mut_ptr(), rest);
+        }
+    }
+
+    /// Appends a `len` byte record, letting `write` fill it in straight into
+    /// the mapping. Returns Ok(false) (and drops the record) if the reader
+    /// hasn't made enough room for it. If `write` fails or comes up short the
+    /// record is dropped too.
+    pub fn push_with<E: From<io::Error>>(
+        &self,
+        len: usize,
+        write: impl FnOnce(&mut RecordWriter) -> Result<(), E>,
+    ) -> Result<bool, E> {
+        let header = self.header();
+        let write_pos = header.write_pos.load(Ordering::Relaxed);
+        let read_pos = header.read_pos.load(Ordering::Acquire);
+        let needed = (LEN_SIZE + len) as u64;
+        if len > u32::MAX as usize || needed > self.capacity as u64 - (write_pos - read_pos) {
+            return Ok(false);
+        }
+        self.copy_in(write_pos, &(len as u32).to_le_bytes());
+        let mut writer = RecordWriter {
+            ring: self,
+            pos: write_pos + LEN_SIZE as u64,
+            end: write_pos + needed,
+        };
+        write(&mut writer)?;
+        if writer.pos != writer.end {
+            return Err(io::Error::new(io::ErrorKind::WriteZero, "record came up short").into());
+        }
+        header
+            .write_pos
+            .store(write_pos + needed, Ordering::Release);
+        Ok(true)
+    }
+
+    /// Appends a record. Returns false (and drops the record) if the reader
+    /// hasn't made enough room for it.
+    pub fn push(&self, record: &[u8]) -> bool {
+        self.push_with(record.len(), |writer| writer.write_all(record))
+            .unwrap_or(false)
+    }
+
+    /// Hands the oldest record, if any, to `read` straight from the mapping.
+    /// The record is taken whether or not `read` gets through all of it.
+    pub fn pop_with<T>(&self, read: impl FnOnce(&mut RecordReader) -> T) -> Option<T> {
+        let header = self.header();
+        let read_pos = header.read_pos.load(Ordering::Relaxed);
+        let write_pos = header.write_pos.load(Ordering::Acquire);
+        if read_pos == write_pos {
+            return None;
+        }
+        let mut len = [0u8; LEN_SIZE];
+        self.copy_out(read_pos, &mut len);
+        let end = read_pos + (LEN_SIZE + u32::from_le_bytes(len) as usize) as u64;
+        let mut reader = RecordReader {
+            ring: self,
+            pos: read_pos + LEN_SIZE as u64,
+            end,
+        };
+        let value = read(&mut reader);
+        header.read_pos.store(end, Ordering::Release);
+        Some(value)
+    }
+
+    /// Takes the oldest record, if any.
+    pub fn pop(&self) -> Option<Vec<u8>> {
+        self.pop_with(|reader| {
+            let mut record = Vec::new();
+            reader
+                .read_to_end(&mut record)
+                .expect("Reading from the mapping shouldn't fail");
+            record
+        })
+    }
+
+    /// Waits up to `timeout` for the producer to exit, returning false once it
+    /// has. Always true on the producer's side.
+    pub fn wait_for_producer(&self, timeout: Duration) -> bool {
+        let Some(producer) = self.producer else {
+            std::thread::sleep(timeout);
+            return true;
+        };
+        let millis = timeout.as_millis().min(u32::MAX as u128) as u32;
+        unsafe { WaitForSingleObject(producer, millis) != WAIT_OBJECT_0 }
+    }
+}
+
+impl Drop for ShmRing {
+    fn drop(&mut self) {
+        unsafe {
+            UnmapViewOfFile(self.view as *const libc::c_void);
+            CloseHandle(self.handle);
+            if let Some(producer) = self.producer {
+                CloseHandle(producer);
+            }
+        }
+    }
+}
+
+/// Writes one record in place, see `ShmRing::push_with`.
+pub struct RecordWriter<'a> {
+    ring: &'a ShmRing,
+    pos: u64,
+    end: u64,
+}
+
+impl Write for RecordWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = std::cmp::min(buf.len() as u64, self.end - self.pos) as usize;
+        self.ring.copy_in(self.pos, &buf[..n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Reads one record in place, see `ShmRing::pop_with`.
+pub struct RecordReader<'a> {
+    ring: &'a ShmRing,
+    pos: u64,
+    end: u64,
+}
+
+impl Read for RecordReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = std::cmp::min(buf.len() as u64, self.end - self.pos) as usize;
+        self.ring.copy_out(self.pos, &mut buf[..n]);
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    /// A fresh ring, tests run in parallel so every one needs its own name.
+    fn ring(capacity: usize) -> ShmRing {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let name = format!(
+            "Local\\tetrad-test-ring-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        );
+        ShmRing::create(&name, capacity).unwrap()
+    }
+
+    #[test]
+    fn pops_records_in_order() {
+        let ring = ring(64);
+        assert!(ring.pop().is_none());
+        assert!(ring.push(b"one"));
+        assert!(ring.push(b""));
+        assert!(ring.push(b"three"));
+        assert_eq!(ring.pop().unwrap(), b"one");
+        assert_eq!(ring.pop().unwrap(), b"");
+        assert_eq!(ring.pop().unwrap(), b"three");
+        assert!(ring.pop().is_none());
+    }
+
+    #[test]
+    fn records_wrap_around_the_end() {
+        let ring = ring(32);
+        // 4 + 10 bytes a record, so the third one straddles the end
+        for i in 0..10u8 {
+            let record = [i; 10];
+            assert!(ring.push(&record));
+            assert!(ring.push(&record));
+            assert_eq!(ring.pop().unwrap(), record);
+            assert_eq!(ring.pop().unwrap(), record);
+        }
+    }
+
+    #[test]
+    fn drops_records_when_full() {
+        let ring = ring(32);
+        assert!(ring.push(&[1; 12]));
+        assert!(ring.push(&[2; 12]));
+        assert!(!ring.push(&[3; 1]));
+        assert_eq!(ring.pop().unwrap(), [1; 12]);
+        // there's room again once the reader catches up
+        assert!(ring.push(&[3; 1]));
+        assert_eq!(ring.pop().unwrap(), [2; 12]);
+        assert_eq!(ring.pop().unwrap(), [3; 1]);
+        assert!(ring.pop().is_none());
+    }
+
+    #[test]
+    fn drops_records_bigger_than_the_ring() {
+        let ring = ring(32);
+        assert!(!ring.push(&[0; 29]));
+        assert!(!ring.push(&[0; 1000]));
+        assert!(ring.pop().is_none());
+        assert!(ring.push(&[0; 28]));
+        assert_eq!(ring.pop().unwrap(), [0; 28]);
+    }
+
+    #[test]
+    fn short_writes_are_dropped() {
+        let ring = ring(48);
+        let pushed = ring.push_with(8, |writer| writer.write_all(b"abc"));
+        assert!(pushed.is_err());
+        assert!(ring.pop().is_none());
+        let pushed = ring.push_with(3, |writer| writer.write_all(b"abcdef"));
+        assert!(pushed.is_err());
+        assert!(ring.pop().is_none());
+    }
+
+    #[test]
+    fn writes_and_reads_in_place() {
+        let ring = ring(64);
+        let value = (42u32, String::from("tetrad"));
+        let len = bincode::serialized_size(&value).unwrap() as usize;
+        let pushed = ring.push_with(len, |writer| bincode::serialize_into(writer, &value));
+        assert!(pushed.unwrap());
+        let popped: (u32, String) = ring
+            .pop_with(|reader| bincode::deserialize_from(reader))
+            .unwrap()
+            .unwrap();
+        assert_eq!(popped, value);
+    }
+
+    #[test]
+    fn producer_side_never_sees_itself_exit() {
+        let ring = ring(16);
+        assert!(ring.wait_for_producer(Duration::from_millis(1)));
+    }
+}
@@ -0,0 +1,451 @@
+// Outputs for the per-frame data (frame stats and object states). The worker
+// hands every frame to each configured sink, so several formats can be written
+// at once. The low-volume streams (roster, events, ...) are always CSV.
+use crate::barrage::BallisticsAggregate;
+use crate::config::Config;
+use crate::dcs::{DcsWorldObject, DcsWorldUnit, Loggable, RowStamp};
+use crate::events::Event;
+use crate::io_thread::ThreadedFile;
+use crate::kinematics::MotionTracker;
+use crate::manifest::SessionMetadata;
+use crate::object_counts::{self, ObjectCounts};
+use crate::perf_monitor::{CpuLoad, MemoryUsage};
+use crate::recorder::Recorder;
+use crate::weather::Weather;
+use std::io::Write;
+use std::sync::Arc;
+use tetrad_data::csv_log::OBJECT_COLUMNS;
+use zstd::stream::write::Encoder as ZstdEncoder;
+
+pub type SinkResult = Result<(), Box<dyn std::error::Error>>;
+
+/// Output that can be made readable up to what was written so far, so a DCS
+/// crash only loses the data since the last checkpoint.
+pub trait Checkpoint: Write {
+    fn checkpoint(&mut self) -> std::io::Result<()>;
+}
+
+/// zstd's match window with `zstd_long_window`, 128 MB. Any decoder takes
+/// windows up to this size without being told to.
+const LONG_WINDOW_LOG: u32 = 27;
+
+/// How the zstd-compressed outputs are compressed, see `zstd_level`,
+/// `zstd_long_window`, `zstd_threads` and `object_log_dictionary`.
+#[derive(Debug, Clone)]
+pub struct ZstdOptions {
+    pub level: i32,
+    pub long_window: bool,
+    pub threads: u32,
+    pub object_dictionary: Option<Arc<Vec<u8>>>,
+}
+
+impl Default for ZstdOptions {
+    fn default() -> Self {
+        Self {
+            level: 10,
+            long_window: false,
+            threads: 0,
+            object_dictionary: None,
+        }
+    }
+}
+
+impl ZstdOptions {
+    pub fn from_config(config: &Config) -> Self {
+        let object_dictionary =
+            config
+                .object_dictionary_file()
+                .and_then(|path| match std::fs::read(&path) {
+                    Ok(dictionary) => Some(Arc::new(dictionary)),
+                    Err(e) => {
+                        log::error!(
+                            "Couldn't read object log dictionary {:?}, not using one: {}",
+                            path,
+                            e
+                        );
+                        None
+                    }
+                });
+        Self {
+            level: config.zstd_level,
+            long_window: config.zstd_long_window,
+            threads: config.zstd_threads,
+            object_dictionary,
+        }
+    }
+
+    pub fn encoder<W: Write>(
+        &self,
+        file: W,
+        dictionary: Option<&[u8]>,
+    ) -> std::io::Result<ZstdEncoder<'static, W>> {
+        let mut encoder = match dictionary {
+            Some(dictionary) => ZstdEncoder::with_dictionary(file, self.level, dictionary)?,
+            None => ZstdEncoder::new(file, self.level)?,
+        };
+        if self.long_window {
+            encoder.long_distance_matching(true)?;
+            encoder.window_log(LONG_WINDOW_LOG)?;
+        }
+        if self.threads > 0 {
+            encoder.multithread(self.threads)?;
+        }
+        Ok(encoder)
+    }
+}
+
+pub fn checkpoint_csv<W: Checkpoint>(writer: &mut csv::Writer<W>) -> std::io::Result<()> {
+    writer.flush()?;
+    writer.get_mut().checkpoint()
+}
+
+pub struct Frame<'a> {
+    pub frame_count: i32,
+    pub game_time: f64,
+    pub real_time: f64,
+    /// Wall-clock time of the frame, UTC milliseconds since the epoch.
+    pub epoch_ms: i64,
+    /// See `SessionMetadata::session_id`.
+    pub session_id: &'a str,
+    pub units: &'a Arc<Vec<DcsWorldUnit>>,
+    pub ballistics: &'a Arc<Vec<DcsWorldObject>>,
+    pub num_units: usize,
+    pub num_ballistics: usize,
+    /// Whether the object logs take this frame, see `object_log_interval`.
+    pub log_objects: bool,
+    /// Seconds tetrad spent in the previous frame's callback.
+    pub lib_time: f64,
+    pub memory: MemoryUsage,
+    pub sys_time: (i32, i32),
+    pub proc_time: (i32, i32),
+    /// CPU load over the last second of frames.
+    pub cpu_load: CpuLoad,
+    /// Labels of the markers set since the previous frame, `; `-separated.
+    pub markers: &'a str,
+    /// Set on the frames that take a weather sample, see `weather_interval`.
+    pub weather: Option<&'a Weather>,
+    /// Set on the object log frames with more than `ballistics_log_cap`
+    /// ballistics, which then leave them out.
+    pub ballistics_aggregate: Option<&'a BallisticsAggregate>,
+    /// Set on the frames with objects when `enable_object_count_columns` is.
+    pub object_counts: Option<&'a ObjectCounts>,
+    /// Speeds and turn rates of the objects, for the object logs.
+    pub motion: &'a MotionTracker,
+}
+
+impl Frame<'_> {
+    /// False if the worker queue dropped the objects to catch up, leaving only
+    /// the counts.
+    pub fn has_objects(&self) -> bool {
+        self.units.len() == self.num_units && self.ballistics.len() == self.num_ballistics
+    }
+}
+
+pub trait Sink {
+    /// Short description for log messages.
+    fn name(&self) -> &'static str;
+
+    fn write_frame(&mut self, frame: &Frame) -> SinkResult;
+
+    /// Entries of the events log, for sinks that summarise them.
+    fn write_event(&mut self, _event: &Event) -> SinkResult {
+        Ok(())
+    }
+
+    /// Whether the output is split into numbered files by
+    /// `export_rotate_size_mb` and `export_rotate_interval`.
+    fn rotates(&self) -> bool {
+        false
+    }
+
+    /// Makes everything written so far readable, see `flush_interval`.
+    fn checkpoint(&mut self) -> SinkResult {
+        Ok(())
+    }
+
+    /// Flushes and closes the output at the end of the session.
+    fn finish(self: Box<Self>) -> SinkResult;
+}
+
+/// The original zstd-compressed CSV frame and object logs.
+pub struct CsvSink<W: Checkpoint> {
+    frame_writer: Option<csv::Writer<W>>,
+    object_writer: Option<csv::Writer<W>>,
+    /// Whether the frame log has the `ObjectCounts` columns.
+    count_columns: bool,
+    /// Values repeated at the end of every frame log row, see
+    /// `enable_metadata_columns`.
+    metadata_fields: Vec<String>,
+    /// The object log columns, all of them if `None`, see
+    /// `object_log_columns`.
+    object_columns: Option<Vec<&'static str>>,
+}
+
+impl<W: Checkpoint> CsvSink<W> {
+    pub fn new(
+        mut frame_writer: Option<csv::Writer<W>>,
+        mut object_writer: Option<csv::Writer<W>>,
+        count_columns: bool,
+        metadata: Option<&SessionMetadata>,
+        object_columns: Option<Vec<&'static str>>,
+        object_header: bool,
+    ) -> csv::Result<Self> {
+        let metadata_fields = match metadata {
+            Some(m) => vec![
+                m.theatre.clone().unwrap_or_default(),
+                m.multiplayer.to_string(),
+                m.dcs_build.map_or_else(String::new, |b| b.to_string()),
+            ],
+            None => Vec::new(),
+        };
+        if let Some(writer) = frame_writer.as_mut() {
+            let mut header = vec![
+                "frame_count",
+                "t_game",
+                "t_real",
+                "units",
+                "ballistics",
+                "sys_cpu",
+                "sys_wall",
+                "proc_cpu",
+                "lib_time",
+                "working_set",
+                "private_bytes",
+                "commit",
+                "markers",
+                "time_of_day",
+                "wind_speed",
+                "wind_dir",
+                "temperature",
+                "qnh",
+                "cloud_preset",
+                "cloud_density",
+                "precipitation",
+                "fog_visibility",
+                "aggregated_ballistics",
+                "ballistics_lat",
+                "ballistics_lon",
+                "ballistics_alt",
+                "ballistics_south",
+                "ballistics_west",
+                "ballistics_north",
+                "ballistics_east",
+                "epoch_ms",
+                "session_id",
+                "dcs_cpu",
+                "total_cpu",
+            ]
+            .into_iter()
+            .map(String::from)
+            .collect::<Vec<_>>();
+            if count_columns {
+                header.extend(ObjectCounts::column_names());
+            }
+            if metadata.is_some() {
+                header.extend(["theatre", "multiplayer", "dcs_build"].map(String::from));
+            }
+            writer.write_record(&header)?;
+        }
+        if let Some(writer) = object_writer.as_mut().filter(|_| object_header) {
+            match object_columns.as_deref() {
+                Some(columns) => writer.write_record(columns)?,
+                None => writer.write_record(OBJECT_COLUMNS)?,
+            }
+        }
+        Ok(Self {
+            frame_writer,
+            object_writer,
+            count_columns,
+            metadata_fields,
+            object_columns,
+        })
+    }
+}
+
+fn log_frame<W: Write>(
+    writer: &mut csv::Writer<W>,
+    frame: &Frame,
+    count_columns: bool,
+    metadata_fields: &[String],
+) -> csv::Result<()> {
+    writer.write_field(frame.frame_count.to_string())?;
+    writer.write_field(format!("{:.8}", frame.game_time))?;
+    writer.write_field(format!("{:.8}", frame.real_time))?;
+    writer.write_field(frame.num_units.to_string())?;
+    writer.write_field(frame.num_ballistics.to_string())?;
+    writer.write_field(frame.sys_time.0.to_string())?;
+    writer.write_field(frame.sys_time.1.to_string())?;
+    writer.write_field(frame.proc_time.0.to_string())?;
+    writer.write_field(format!("{:.8}", frame.lib_time))?;
+    writer.write_field(frame.memory.working_set.to_string())?;
+    writer.write_field(frame.memory.private_bytes.to_string())?;
+    writer.write_field(frame.memory.commit.to_string())?;
+    writer.write_field(frame.markers)?;
+    match frame.weather {
+        Some(w) => {
+            writer.write_field(format!("{:.0}", w.time_of_day))?;
+            writer.write_field(format!("{:.1}", w.wind_speed))?;
+            writer.write_field(format!("{:.0}", w.wind_dir))?;
+            writer.write_field(format!("{:.1}", w.temperature))?;
+            writer.write_field(format!("{:.0}", w.qnh))?;
+            writer.write_field(&w.cloud_preset)?;
+            writer.write_field(w.cloud_density.to_string())?;
+            writer.write_field(w.precipitation.to_string())?;
+            writer.write_field(format!("{:.0}", w.fog_visibility))?;
+        }
+        None => {
+            for _ in 0..9 {
+                writer.write_field("")?;
+            }
+        }
+    }
+    match frame.ballistics_aggregate {
+        Some(a) => {
+            writer.write_field(a.count.to_string())?;
+            for value in [a.lat, a.lon] {
+                writer.write_field(format!("{:.6}", value))?;
+            }
+            writer.write_field(format!("{:.0}", a.alt))?;
+            for value in [a.south, a.west, a.north, a.east] {
+                writer.write_field(format!("{:.6}", value))?;
+            }
+        }
+        None => {
+            for _ in 0..8 {
+                writer.write_field("")?;
+            }
+        }
+    }
+    writer.write_field(frame.epoch_ms.to_string())?;
+    writer.write_field(frame.session_id)?;
+    writer.write_field(format!("{:.4}", frame.cpu_load.dcs))?;
+    writer.write_field(format!("{:.4}", frame.cpu_load.total))?;
+    if count_columns {
+        match frame.object_counts {
+            Some(counts) => {
+                for count in counts.values() {
+                    writer.write_field(count.to_string())?;
+                }
+            }
+            None => {
+                for _ in 0..object_counts::NUM_COLUMNS {
+                    writer.write_field("")?;
+                }
+            }
+        }
+    }
+    for field in metadata_fields {
+        writer.write_field(field)?;
+    }
+    writer.write_record(None::<&[u8]>)
+}
+
+fn log_dcs_objects<W: Write, T: Loggable>(
+    writer: &mut csv::Writer<W>,
+    frame: &Frame,
+    objects: &[T],
+    columns: Option<&[&str]>,
+) -> csv::Result<()> {
+    let stamp = RowStamp {
+        frame_count: frame.frame_count,
+        frame_time: frame.game_time,
+        real_time: frame.real_time,
+        epoch_ms: frame.epoch_ms,
+        session_id: frame.session_id,
+    };
+    for obj in objects {
+        obj.log_as_csv(&stamp, frame.motion, columns, writer)?;
+    }
+    Ok(())
+}
+
+impl<W: Checkpoint> Sink for CsvSink<W> {
+    fn name(&self) -> &'static str {
+        "csv"
+    }
+
+    fn rotates(&self) -> bool {
+        true
+    }
+
+    fn write_frame(&mut self, frame: &Frame) -> SinkResult {
+        if let Some(writer) = self.frame_writer.as_mut() {
+            log_frame(writer, frame, self.count_columns, &self.metadata_fields)?;
+        }
+        if let Some(writer) = self.object_writer.as_mut().filter(|_| frame.log_objects) {
+            log::trace!("Logging {} units", frame.units.len());
+            let columns = self.object_columns.as_deref();
+            log_dcs_objects(writer, frame, frame.units.as_slice(), columns)?;
+            log::trace!("Logging {} ballistics objects", frame.ballistics.len());
+            log_dcs_objects(writer, frame, frame.ballistics.as_slice(), columns)?;
+        }
+        Ok(())
+    }
+
+    fn checkpoint(&mut self) -> SinkResult {
+        for writer in [self.frame_writer.as_mut(), self.object_writer.as_mut()]
+            .into_iter()
+            .flatten()
+        {
+            checkpoint_csv(writer)?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> SinkResult {
+        self.checkpoint()
+    }
+}
+
+/// Binary flight recording (see recorder.rs), sampled every `interval` game
+/// seconds.
+pub struct RecorderSink {
+    recorder: Recorder<ThreadedFile>,
+    interval: f64,
+    last_recorded_time: Option<f64>,
+}
+
+impl RecorderSink {
+    pub fn new(recorder: Recorder<ThreadedFile>, interval: f64) -> Self {
+        Self {
+            recorder,
+            interval,
+            last_recorded_time: None,
+        }
+    }
+}
+
+impl Sink for RecorderSink {
+    fn name(&self) -> &'static str {
+        "flight recording"
+    }
+
+    fn write_frame(&mut self, frame: &Frame) -> SinkResult {
+        if !frame.has_objects() {
+            return Ok(());
+        }
+        if let Some(t) = self.last_recorded_time {
+            if frame.game_time - t < self.interval {
+                return Ok(());
+            }
+        }
+        self.last_recorded_time = Some(frame.game_time);
+        self.recorder.record(
+            frame.game_time,
+            frame.units.as_slice(),
+            frame.ballistics.as_slice(),
+        )?;
+        Ok(())
+    }
+
+    fn checkpoint(&mut self) -> SinkResult {
+        self.recorder.get_mut().checkpoint()?;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> SinkResult {
+        // dropping the file waits for its I/O thread to finish it
+        self.recorder.into_inner().flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,239 @@
+// SQLite output for the frame and object logs, enabled by listing "sqlite" in
+// `export_formats`. Each session gets its own database with
+// `sessions`, `frames` and `objects` tables, so it can be queried directly.
+use crate::dcs::{DcsWorldObject, Motion};
+use crate::sink::{Frame, Sink, SinkResult};
+use rusqlite::{params, Connection, Result};
+use std::path::Path;
+use tetrad_data::schema;
+
+// Frames per transaction. Committing every frame would make SQLite sync the
+// journal far too often.
+const FRAMES_PER_COMMIT: i32 = 100;
+
+const SCHEMA: &str = "
+PRAGMA journal_mode = WAL;
+PRAGMA synchronous = NORMAL;
+CREATE TABLE IF NOT EXISTS sessions (
+    id INTEGER PRIMARY KEY,
+    mission TEXT NOT NULL,
+    tetrad_version TEXT NOT NULL,
+    started_at TEXT NOT NULL,
+    ended_at TEXT,
+    frames INTEGER
+);
+CREATE TABLE IF NOT EXISTS frames (
+    session_id INTEGER NOT NULL REFERENCES sessions(id),
+    frame_count INTEGER NOT NULL,
+    t_game REAL NOT NULL,
+    t_real REAL NOT NULL,
+    units INTEGER NOT NULL,
+    ballistics INTEGER NOT NULL,
+    sys_cpu INTEGER NOT NULL,
+    sys_wall INTEGER NOT NULL,
+    proc_cpu INTEGER NOT NULL,
+    lib_time REAL NOT NULL,
+    working_set INTEGER NOT NULL,
+    private_bytes INTEGER NOT NULL,
+    commit_bytes INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS objects (
+    session_id INTEGER NOT NULL REFERENCES sessions(id),
+    frame_count INTEGER NOT NULL,
+    t_game REAL NOT NULL,
+    t_real REAL NOT NULL,
+    kind TEXT NOT NULL,
+    unit_name TEXT NOT NULL,
+    group_name TEXT NOT NULL,
+    id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    category TEXT NOT NULL,
+    country INTEGER NOT NULL,
+    coalition TEXT NOT NULL,
+    coalition_id INTEGER NOT NULL,
+    lat REAL NOT NULL,
+    lon REAL NOT NULL,
+    alt REAL NOT NULL,
+    heading REAL NOT NULL,
+    pitch REAL NOT NULL,
+    bank REAL NOT NULL,
+    x REAL NOT NULL,
+    y REAL NOT NULL,
+    z REAL NOT NULL,
+    speed REAL NOT NULL,
+    vertical_speed REAL NOT NULL,
+    heading_rate REAL NOT NULL
+);
+CREATE INDEX IF NOT EXISTS objects_t_game ON objects(t_game);
+";
+
+pub struct SessionDb {
+    conn: Connection,
+    session_id: i64,
+    log_frames: bool,
+    log_objects: bool,
+    frames: i32,
+}
+
+impl SessionDb {
+    pub fn create(
+        path: &Path,
+        mission_name: &str,
+        log_frames: bool,
+        log_objects: bool,
+    ) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+        conn.pragma_update(None, "user_version", schema::SQLITE.version)?;
+        conn.execute(
+            "INSERT INTO sessions (mission, tetrad_version, started_at) VALUES (?1, ?2, ?3)",
+            params![
+                mission_name,
+                env!("CARGO_PKG_VERSION"),
+                chrono::Local::now().to_rfc3339()
+            ],
+        )?;
+        let session_id = conn.last_insert_rowid();
+        conn.execute_batch("BEGIN")?;
+        Ok(Self {
+            conn,
+            session_id,
+            log_frames,
+            log_objects,
+            frames: 0,
+        })
+    }
+
+    fn insert_object(
+        &self,
+        frame_count: i32,
+        t_game: f64,
+        t_real: f64,
+        kind: &str,
+        unit_name: &str,
+        group_name: &str,
+        object: &DcsWorldObject,
+        motion: Motion,
+    ) -> Result<()> {
+        let (lat, lon, alt) = object.lat_lon_alt();
+        let (heading, pitch, bank) = object.attitude();
+        let (x, y, z) = object.position();
+        let mut stmt = self.conn.prepare_cached(
+            "INSERT INTO objects VALUES \
+             (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25)",
+        )?;
+        stmt.execute(params![
+            self.session_id,
+            frame_count,
+            t_game,
+            t_real,
+            kind,
+            unit_name,
+            group_name,
+            object.id(),
+            object.name(),
+            object.category().as_str(),
+            object.country(),
+            object.coalition(),
+            object.coalition_id(),
+            lat,
+            lon,
+            alt,
+            heading,
+            pitch,
+            bank,
+            x,
+            y,
+            z,
+            motion.speed,
+            motion.vertical_speed,
+            motion.heading_rate
+        ])?;
+        Ok(())
+    }
+
+    fn push_frame(&mut self, frame: &Frame) -> Result<()> {
+        let (frame_count, t_game, t_real) = (frame.frame_count, frame.game_time, frame.real_time);
+        if self.log_frames {
+            let mut stmt = self.conn.prepare_cached(
+                "INSERT INTO frames VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            )?;
+            stmt.execute(params![
+                self.session_id,
+                frame_count,
+                t_game,
+                t_real,
+                frame.num_units as i32,
+                frame.num_ballistics as i32,
+                frame.sys_time.0,
+                frame.sys_time.1,
+                frame.proc_time.0,
+                frame.lib_time,
+                frame.memory.working_set as i64,
+                frame.memory.private_bytes as i64,
+                frame.memory.commit as i64
+            ])?;
+        }
+        if self.log_objects && frame.log_objects {
+            for unit in frame.units.iter() {
+                self.insert_object(
+                    frame_count,
+                    t_game,
+                    t_real,
+                    "unit",
+                    unit.unit_name(),
+                    unit.group_name(),
+                    unit.object(),
+                    frame.motion.get(unit.object().id()),
+                )?;
+            }
+            for object in frame.ballistics.iter() {
+                self.insert_object(
+                    frame_count,
+                    t_game,
+                    t_real,
+                    "ballistic",
+                    "",
+                    "",
+                    object,
+                    frame.motion.get(object.id()),
+                )?;
+            }
+        }
+        self.frames += 1;
+        if self.frames % FRAMES_PER_COMMIT == 0 {
+            self.conn.execute_batch("COMMIT; BEGIN")?;
+        }
+        Ok(())
+    }
+
+    /// Commits outstanding rows and marks the session as ended.
+    fn close(self) -> Result<()> {
+        self.conn.execute(
+            "UPDATE sessions SET ended_at = ?1, frames = ?2 WHERE id = ?3",
+            params![
+                chrono::Local::now().to_rfc3339(),
+                self.frames,
+                self.session_id
+            ],
+        )?;
+        self.conn.execute_batch("COMMIT")?;
+        Ok(())
+    }
+}
+
+impl Sink for SessionDb {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    fn write_frame(&mut self, frame: &Frame) -> SinkResult {
+        self.push_frame(frame)?;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> SinkResult {
+        self.close()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,77 @@
+// Small `status.json` file for hosting panels and server-status bots, which
+// poll it rather than parse tetrad's logs.
+use serde::Serialize;
+use std::path::Path;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerStatus {
+    pub version: &'static str,
+    pub mission: String,
+    pub phase: String,
+    pub fps: f64,
+    pub max_frame_time_ms: f64,
+    pub players: i32,
+    pub units: i32,
+    pub ballistics: i32,
+    pub uptime: f64,
+    pub mission_time: f64,
+    pub health: i32,
+    pub updated: String,
+}
+
+#[derive(Debug, Default)]
+pub struct StatusWindow {
+    start_real_time: Option<f64>,
+    num_frames: i32,
+    max_frame_time: f64,
+}
+
+impl StatusWindow {
+    pub fn update(&mut self, real_time: f64, real_frame_time: f64) {
+        if self.start_real_time.is_none() {
+            self.start_real_time = Some(real_time);
+        }
+        self.num_frames += 1;
+        self.max_frame_time = self.max_frame_time.max(real_frame_time);
+    }
+
+    pub fn elapsed(&self, real_time: f64) -> f64 {
+        self.start_real_time.map_or(0.0, |t| real_time - t)
+    }
+
+    /// Returns (fps, worst frame time) over the window and starts a new one.
+    pub fn take(&mut self, real_time: f64) -> (f64, f64) {
+        let elapsed = self.elapsed(real_time);
+        let fps = if elapsed > 0.0 {
+            self.num_frames as f64 / elapsed
+        } else {
+            0.0
+        };
+        let result = (fps, self.max_frame_time);
+        *self = Self::default();
+        result
+    }
+}
+
+/// 0-100 rating of how smoothly the server is running. Anything at or above
+/// 30 FPS without long hitches is considered fully healthy.
+pub fn health_score(fps: f64, max_frame_time: f64) -> i32 {
+    let fps_score = (fps / 30.0).clamp(0.0, 1.0);
+    let hitch_penalty = if max_frame_time > 0.5 {
+        0.5
+    } else if max_frame_time > 0.1 {
+        0.8
+    } else {
+        1.0
+    };
+    (fps_score * hitch_penalty * 100.0).round() as i32
+}
+
+/// Writes to a temporary file and renames it over the old one, so pollers
+/// never see a half-written file.
+pub fn write_status(path: &Path, status: &ServerStatus) -> std::io::Result<()> {
+    let tmp = path.with_extension("json.tmp");
+    let contents = serde_json::to_vec_pretty(status)?;
+    std::fs::write(&tmp, contents)?;
+    std::fs::rename(&tmp, path)
+}
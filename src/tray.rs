@@ -0,0 +1,242 @@
+// Notification area icon for controlling tetrad while the GUI window is
+// minimized or closed. It runs its own hidden window and message loop, and
+// passes requests to lib.rs as `ClientMessage`s like the GUI does. The same
+// window receives the global hotkey that reopens the GUI.
+use crate::gui::{ArcFlag, ClientMessage};
+use once_cell::sync::OnceCell;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::Sender;
+use std::sync::Mutex;
+use windows::core::{HSTRING, PCWSTR};
+use windows::Win32::Foundation::{HINSTANCE, HWND, LPARAM, LRESULT, POINT, WPARAM};
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::UI::Input::KeyboardAndMouse::{
+    RegisterHotKey, MOD_ALT, MOD_CONTROL, MOD_NOREPEAT, MOD_SHIFT,
+};
+use windows::Win32::UI::Shell::{
+    ShellExecuteW, Shell_NotifyIconW, NIF_ICON, NIF_MESSAGE, NIF_TIP, NIM_ADD, NIM_DELETE,
+    NOTIFYICONDATAW,
+};
+use windows::Win32::UI::WindowsAndMessaging::{
+    AppendMenuW, CreatePopupMenu, CreateWindowExW, DefWindowProcW, DestroyMenu, DispatchMessageW,
+    GetCursorPos, GetMessageW, LoadIconW, PostQuitMessage, RegisterClassW, SetForegroundWindow,
+    TrackPopupMenu, TranslateMessage, HMENU, HWND_MESSAGE, IDI_APPLICATION, MF_CHECKED,
+    MF_SEPARATOR, MF_STRING, MSG, SW_SHOWNORMAL, TPM_RIGHTBUTTON, WINDOW_EX_STYLE, WINDOW_STYLE,
+    WM_APP, WM_COMMAND, WM_CONTEXTMENU, WM_HOTKEY, WM_LBUTTONDBLCLK, WM_RBUTTONUP, WNDCLASSW,
+};
+
+const WM_TRAY: u32 = WM_APP + 1;
+
+const ID_SHOW: usize = 1;
+const ID_CLOSE: usize = 2;
+const ID_PAUSE: usize = 3;
+const ID_OPEN_LOGS: usize = 4;
+const ID_QUIT: usize = 5;
+
+// Ctrl+Alt+Shift+T shows the GUI
+const HOTKEY_ID: i32 = 1;
+const HOTKEY_KEY: u32 = b'T' as u32;
+
+struct Tray {
+    tx_to_main: Sender<ClientMessage>,
+    logging_paused: ArcFlag,
+    log_dir: PathBuf,
+}
+
+// The window procedure has no other way to get at this.
+static TRAY: OnceCell<Mutex<Tray>> = OnceCell::new();
+
+fn with_tray(f: impl FnOnce(&Tray)) {
+    if let Some(tray) = TRAY.get() {
+        f(&tray.lock().unwrap());
+    }
+}
+
+fn send(msg: ClientMessage) {
+    with_tray(|tray| tray.tx_to_main.send(msg).unwrap_or(()));
+}
+
+fn open_log_dir(log_dir: &Path) {
+    let result = unsafe {
+        ShellExecuteW(
+            HWND::default(),
+            &HSTRING::from("open"),
+            &HSTRING::from(log_dir.to_string_lossy().as_ref()),
+            PCWSTR::null(),
+            PCWSTR::null(),
+            SW_SHOWNORMAL,
+        )
+    };
+    // anything above 32 is success
+    if result.0 <= 32 {
+        log::warn!("Couldn't open {:?}, error {}", log_dir, result.0);
+    }
+}
+
+fn toggle_logging_paused(tray: &Tray) {
+    let paused = !tray.logging_paused.load(Ordering::SeqCst);
+    tray.logging_paused.store(paused, Ordering::SeqCst);
+    if paused {
+        log::info!("Frame logging paused from the tray icon");
+    } else {
+        log::info!("Frame logging resumed from the tray icon");
+    }
+}
+
+fn on_command(id: usize) {
+    match id {
+        ID_SHOW => send(ClientMessage::ShowWindow),
+        ID_CLOSE => send(ClientMessage::CloseWindow),
+        ID_PAUSE => with_tray(toggle_logging_paused),
+        ID_OPEN_LOGS => with_tray(|tray| open_log_dir(&tray.log_dir)),
+        ID_QUIT => {
+            send(ClientMessage::QuitGui);
+            unsafe { PostQuitMessage(0) };
+        }
+        _ => {}
+    }
+}
+
+fn show_menu(hwnd: HWND) {
+    let mut paused = false;
+    with_tray(|tray| paused = tray.logging_paused.load(Ordering::SeqCst));
+    unsafe {
+        let Ok(menu) = CreatePopupMenu() else {
+            return;
+        };
+        let pause_flags = if paused {
+            MF_STRING | MF_CHECKED
+        } else {
+            MF_STRING
+        };
+        AppendMenuW(menu, MF_STRING, ID_SHOW, &HSTRING::from("Show window"));
+        AppendMenuW(menu, MF_STRING, ID_CLOSE, &HSTRING::from("Close window"));
+        AppendMenuW(menu, pause_flags, ID_PAUSE, &HSTRING::from("Pause logging"));
+        AppendMenuW(
+            menu,
+            MF_STRING,
+            ID_OPEN_LOGS,
+            &HSTRING::from("Open log folder"),
+        );
+        AppendMenuW(menu, MF_SEPARATOR, 0, PCWSTR::null());
+        AppendMenuW(menu, MF_STRING, ID_QUIT, &HSTRING::from("Quit GUI"));
+        let mut pt = POINT::default();
+        GetCursorPos(&mut pt);
+        // otherwise the menu stays up when clicking somewhere else
+        SetForegroundWindow(hwnd);
+        TrackPopupMenu(menu, TPM_RIGHTBUTTON, pt.x, pt.y, 0, hwnd, None);
+        DestroyMenu(menu);
+    }
+}
+
+extern "system" fn window_proc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    match msg {
+        WM_TRAY => {
+            match lparam.0 as u32 {
+                WM_LBUTTONDBLCLK => send(ClientMessage::ShowWindow),
+                WM_RBUTTONUP | WM_CONTEXTMENU => show_menu(hwnd),
+                _ => {}
+            }
+            LRESULT(0)
+        }
+        WM_HOTKEY => {
+            send(ClientMessage::ShowWindow);
+            LRESULT(0)
+        }
+        WM_COMMAND => {
+            on_command(wparam.0 & 0xffff);
+            LRESULT(0)
+        }
+        _ => unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) },
+    }
+}
+
+fn run(tooltip: &str) -> windows::core::Result<()> {
+    unsafe {
+        let instance = GetModuleHandleW(PCWSTR::null())?;
+        let class_name = HSTRING::from("TetradTray");
+        let class = WNDCLASSW {
+            lpfnWndProc: Some(window_proc),
+            hInstance: instance,
+            lpszClassName: PCWSTR(class_name.as_ptr()),
+            ..Default::default()
+        };
+        if RegisterClassW(&class) == 0 {
+            return Err(windows::core::Error::from_win32());
+        }
+        let hwnd = CreateWindowExW(
+            WINDOW_EX_STYLE::default(),
+            &class_name,
+            &HSTRING::from(tooltip),
+            WINDOW_STYLE::default(),
+            0,
+            0,
+            0,
+            0,
+            HWND_MESSAGE,
+            HMENU::default(),
+            instance,
+            None,
+        );
+        if hwnd.0 == 0 {
+            return Err(windows::core::Error::from_win32());
+        }
+
+        let mut data = NOTIFYICONDATAW {
+            cbSize: std::mem::size_of::<NOTIFYICONDATAW>() as u32,
+            hWnd: hwnd,
+            uID: 1,
+            uFlags: NIF_ICON | NIF_MESSAGE | NIF_TIP,
+            uCallbackMessage: WM_TRAY,
+            hIcon: LoadIconW(HINSTANCE::default(), IDI_APPLICATION)?,
+            ..Default::default()
+        };
+        let tip: Vec<u16> = tooltip.encode_utf16().take(data.szTip.len() - 1).collect();
+        data.szTip[..tip.len()].copy_from_slice(&tip);
+        if !Shell_NotifyIconW(NIM_ADD, &data).as_bool() {
+            return Err(windows::core::Error::from_win32());
+        }
+
+        let modifiers = MOD_CONTROL | MOD_ALT | MOD_SHIFT | MOD_NOREPEAT;
+        if !RegisterHotKey(hwnd, HOTKEY_ID, modifiers, HOTKEY_KEY).as_bool() {
+            log::warn!(
+                "Couldn't register the GUI hotkey: {}",
+                windows::core::Error::from_win32()
+            );
+        }
+
+        let mut msg = MSG::default();
+        while GetMessageW(&mut msg, HWND::default(), 0, 0).as_bool() {
+            TranslateMessage(&msg);
+            DispatchMessageW(&msg);
+        }
+        Shell_NotifyIconW(NIM_DELETE, &data);
+    }
+    log::debug!("Tray icon removed");
+    Ok(())
+}
+
+/// Puts tetrad's icon in the notification area. `logging_paused` is set and
+/// cleared from its menu.
+pub fn spawn(
+    tx_to_main: Sender<ClientMessage>,
+    logging_paused: ArcFlag,
+    log_dir: PathBuf,
+    tooltip: String,
+) {
+    let tray = Tray {
+        tx_to_main,
+        logging_paused,
+        log_dir,
+    };
+    if TRAY.set(Mutex::new(tray)).is_err() {
+        log::warn!("Tray icon is already running");
+        return;
+    }
+    std::thread::spawn(move || {
+        if let Err(e) = run(&tooltip) {
+            log::error!("Couldn't create the tray icon: {}", e);
+        }
+    });
+}
@@ -0,0 +1,71 @@
+// Startup check for a newer tetrad release on GitHub. This only ever tells the
+// user about it, nothing gets downloaded or installed.
+use crate::gui;
+use serde::Deserialize;
+use std::sync::mpsc::SyncSender;
+
+const RELEASES_URL: &str = "https://api.github.com/repos/bobmoretti/dcs-tetrad/releases/latest";
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    html_url: String,
+}
+
+fn parse_version(s: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = s.trim_start_matches('v').split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    let patch = parts
+        .next()?
+        .trim_end_matches(|c: char| !c.is_ascii_digit());
+    Some((major, minor, patch.parse().ok()?))
+}
+
+fn fetch_latest_release() -> Result<Release, Box<dyn std::error::Error>> {
+    let release = ureq::get(RELEASES_URL)
+        .set(
+            "User-Agent",
+            concat!("dcs-tetrad/", env!("CARGO_PKG_VERSION")),
+        )
+        .timeout(std::time::Duration::from_secs(10))
+        .call()?
+        .into_json()?;
+    Ok(release)
+}
+
+fn check(gui_tx: SyncSender<gui::Message>) {
+    let release = match fetch_latest_release() {
+        Ok(r) => r,
+        Err(e) => {
+            log::debug!("Couldn't check for tetrad updates: {}", e);
+            return;
+        }
+    };
+    let current = env!("CARGO_PKG_VERSION");
+    let (Some(latest_version), Some(current_version)) =
+        (parse_version(&release.tag_name), parse_version(current))
+    else {
+        log::debug!("Couldn't parse release version {}", release.tag_name);
+        return;
+    };
+    if latest_version > current_version {
+        log::warn!(
+            "A new tetrad version {} is available (running {}): {}",
+            release.tag_name,
+            current,
+            release.html_url
+        );
+        gui_tx
+            .send(gui::Message::UpdateAvailable(release.tag_name))
+            .unwrap_or(());
+    } else {
+        log::info!("Tetrad {} is up to date", current);
+    }
+}
+
+/// Checks for a new release in the background, so a slow network never holds
+/// up mission loading.
+pub fn spawn(gui_tx: SyncSender<gui::Message>) {
+    std::thread::spawn(move || check(gui_tx));
+}
@@ -0,0 +1,261 @@
+// Uploads each session's files once it stops (`upload_url`), so they don't
+// have to be fetched off the server by hand. The files are packed into one
+// `.tar.zst` archive next to the session manifest, then sent on a background
+// thread with retries and an optional bandwidth cap:
+// - `http(s)://...`: an HTTP PUT, signed for S3 (AWS Signature Version 4,
+//   path-style) when `upload_access_key` is set.
+// - `sftp://user@host[:port]/dir/`: `scp` from Windows' OpenSSH, which needs
+//   key authentication to be set up for the user DCS runs as.
+// A URL ending in `/` gets the archive name appended.
+use crate::config::Config;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+type UploadResult = Result<(), Box<dyn std::error::Error>>;
+
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub struct UploadOptions {
+    url: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+    /// Kilobits per second, 0 for no limit.
+    max_kbps: f64,
+    retries: u32,
+}
+
+impl UploadOptions {
+    pub fn from_config(config: &Config) -> Option<Self> {
+        (!config.upload_url.is_empty()).then(|| Self {
+            url: config.upload_url.clone(),
+            region: config.upload_s3_region.clone(),
+            access_key: config.upload_access_key.clone(),
+            secret_key: config.upload_secret_key.clone(),
+            max_kbps: config.upload_max_kbps.max(0.0),
+            retries: config.upload_retries,
+        })
+    }
+}
+
+/// Reads no faster than `bytes_per_second` on average.
+struct Throttled<R> {
+    inner: R,
+    bytes_per_second: f64,
+    start: Instant,
+    bytes: u64,
+}
+
+impl<R: Read> Read for Throttled<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(16 * 1024);
+        let n = self.inner.read(&mut buf[..len])?;
+        self.bytes += n as u64;
+        let due = Duration::from_secs_f64(self.bytes as f64 / self.bytes_per_second);
+        if let Some(wait) = due.checked_sub(self.start.elapsed()) {
+            std::thread::sleep(wait);
+        }
+        Ok(n)
+    }
+}
+
+/// Packs `files` into `archive`, under their paths relative to `log_dir`.
+fn write_archive(archive: &Path, log_dir: &Path, files: &[PathBuf]) -> io::Result<()> {
+    let encoder = zstd::stream::write::Encoder::new(File::create(archive)?, 3)?;
+    let mut builder = tar::Builder::new(encoder);
+    for path in files {
+        if !path.exists() {
+            // e.g. a file that was never written to and got cleaned up
+            continue;
+        }
+        let name = path.strip_prefix(log_dir).unwrap_or(path);
+        builder.append_path_with_name(path, name)?;
+    }
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes any key length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Percent-encodes everything but the unreserved characters and `/`, as both
+/// URLs and S3 signatures want it.
+fn encode_path(path: &str) -> String {
+    path.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// The `Authorization` header and the headers it covers for an S3 PUT of an
+/// unsigned payload to `host` and `path` (already encoded).
+fn sign_s3(
+    options: &UploadOptions,
+    host: &str,
+    path: &str,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Vec<(&'static str, String)> {
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+    let payload = "UNSIGNED-PAYLOAD";
+    let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+    let canonical_request = format!(
+        "PUT\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+        path, host, payload, amz_date, signed_headers, payload
+    );
+    let scope = format!("{}/{}/s3/aws4_request", date, options.region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex(&Sha256::digest(canonical_request.as_bytes()))
+    );
+    let mut key = format!("AWS4{}", options.secret_key).into_bytes();
+    for part in [date.as_str(), options.region.as_str(), "s3", "aws4_request"] {
+        key = hmac_sha256(&key, part);
+    }
+    let signature = hex(&hmac_sha256(&key, &string_to_sign));
+    vec![
+        (
+            "Authorization",
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                options.access_key, scope, signed_headers, signature
+            ),
+        ),
+        ("x-amz-content-sha256", payload.to_string()),
+        ("x-amz-date", amz_date),
+    ]
+}
+
+fn put_http(options: &UploadOptions, archive: &Path, name: &str) -> UploadResult {
+    let (scheme, rest) = options
+        .url
+        .split_once("://")
+        .ok_or("no scheme in upload_url")?;
+    let (host, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, "/"),
+    };
+    let mut path = path.to_string();
+    if path.ends_with('/') {
+        path.push_str(&encode_path(name));
+    }
+    let file = File::open(archive)?;
+    let len = file.metadata()?.len();
+    let mut request = ureq::put(&format!("{}://{}{}", scheme, host, path))
+        .timeout_connect(TIMEOUT)
+        .set("Content-Length", &len.to_string())
+        .set(
+            "User-Agent",
+            concat!("dcs-tetrad/", env!("CARGO_PKG_VERSION")),
+        );
+    if !options.access_key.is_empty() {
+        for (header, value) in sign_s3(options, host, &path, chrono::Utc::now()) {
+            request = request.set(header, &value);
+        }
+    }
+    if options.max_kbps > 0.0 {
+        request.send(Throttled {
+            inner: file,
+            bytes_per_second: options.max_kbps * 1000.0 / 8.0,
+            start: Instant::now(),
+            bytes: 0,
+        })?;
+    } else {
+        request.send(file)?;
+    }
+    Ok(())
+}
+
+fn copy_scp(options: &UploadOptions, archive: &Path) -> UploadResult {
+    use std::os::windows::process::CommandExt;
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+    let rest = options.url.trim_start_matches("sftp://");
+    let (authority, path) = rest.split_once('/').ok_or("no path in upload_url")?;
+    let mut command = std::process::Command::new("scp");
+    command.creation_flags(CREATE_NO_WINDOW).args(["-B", "-q"]);
+    let host = match authority.rsplit_once(':') {
+        Some((host, port)) => {
+            command.args(["-P", port]);
+            host
+        }
+        None => authority,
+    };
+    if options.max_kbps > 0.0 {
+        command.args(["-l", &(options.max_kbps as u64).max(1).to_string()]);
+    }
+    let output = command
+        .arg(archive)
+        .arg(format!("{}:/{}", host, path))
+        .output()?;
+    if !output.status.success() {
+        return Err(format!(
+            "scp failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+fn upload(options: &UploadOptions, archive: &Path) -> UploadResult {
+    let name = archive
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    if options.url.starts_with("sftp://") {
+        copy_scp(options, archive)
+    } else {
+        put_http(options, archive, &name)
+    }
+}
+
+fn entry(options: UploadOptions, archive: PathBuf, log_dir: PathBuf, files: Vec<PathBuf>) {
+    if let Err(e) = write_archive(&archive, &log_dir, &files) {
+        log::error!("Couldn't pack the session into {:?}: {}", archive, e);
+        return;
+    }
+    for attempt in 0..=options.retries {
+        match upload(&options, &archive) {
+            Ok(()) => {
+                log::info!("Uploaded {:?} to {}", archive, options.url);
+                // the files it was made from are still there
+                std::fs::remove_file(&archive).unwrap_or(());
+                return;
+            }
+            Err(e) if attempt < options.retries => {
+                log::warn!("Uploading {:?} failed, trying again: {}", archive, e);
+                std::thread::sleep(RETRY_DELAY * (attempt + 1));
+            }
+            Err(e) => log::error!("Couldn't upload {:?}, giving up: {}", archive, e),
+        }
+    }
+}
+
+/// Packs and uploads a finished session's `files` in the background. The
+/// archive is named after `manifest_path`, and kept if the upload fails.
+pub fn spawn(options: UploadOptions, log_dir: PathBuf, manifest_path: &Path, files: Vec<PathBuf>) {
+    let archive = manifest_path.with_extension("tar.zst");
+    std::thread::spawn(move || entry(options, archive, log_dir, files));
+}
@@ -0,0 +1,88 @@
+// Mission weather and clock, so sessions can be compared knowing whether one
+// was a clear afternoon and the other a night thunderstorm. DCS weather is set
+// in the mission and doesn't change while it runs, but the clock does, so it's
+// sampled periodically into the frame log as well as into the session manifest.
+use crate::dcs;
+use mlua::Lua;
+use serde::Serialize;
+
+const SECONDS_PER_DAY: f64 = 86400.0;
+
+// Evaluated in the mission scripting environment, returns one tab-separated
+// line.
+const READ_WEATHER: &str = r#"
+local w = env.mission.weather or {}
+local ground = w.wind and w.wind.atGround or {}
+local clouds = w.clouds or {}
+local d = env.mission.date or {}
+return table.concat({
+    timer.getAbsTime(),
+    string.format("%04d-%02d-%02d", d.Year or 0, d.Month or 0, d.Day or 0),
+    ground.speed or 0, ground.dir or 0,
+    w.season and w.season.temperature or 0,
+    w.qnh or 0,
+    clouds.preset or "", clouds.density or 0, clouds.iprecptns or 0,
+    w.enable_fog and w.fog and w.fog.visibility or 0,
+}, "\t")
+"#;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Weather {
+    /// Mission clock, seconds since midnight.
+    pub time_of_day: f64,
+    /// Date the mission started on, `YYYY-MM-DD`.
+    pub date: String,
+    /// Wind at ground level, m/s, and the direction in degrees as set in the
+    /// mission editor.
+    pub wind_speed: f64,
+    pub wind_dir: f64,
+    /// Degrees Celsius at sea level.
+    pub temperature: f64,
+    /// mmHg, like the mission editor shows it.
+    pub qnh: f64,
+    /// Cloud preset name, empty for clouds set by hand (see `cloud_density`).
+    pub cloud_preset: String,
+    /// 0-10, only used without a preset.
+    pub cloud_density: i32,
+    /// 0 none, 1 rain, 2 thunderstorm, 3 snow, 4 snowstorm.
+    pub precipitation: i32,
+    /// Fog visibility in meters, 0 without fog.
+    pub fog_visibility: f64,
+}
+
+impl Weather {
+    fn parse(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [abs_time, date, wind_speed, wind_dir, temperature, qnh, cloud_preset, cloud_density, precipitation, fog_visibility] =
+            fields[..]
+        else {
+            return None;
+        };
+        Some(Self {
+            time_of_day: abs_time.parse::<f64>().ok()? % SECONDS_PER_DAY,
+            date: date.to_string(),
+            wind_speed: wind_speed.parse().ok()?,
+            wind_dir: wind_dir.parse().ok()?,
+            temperature: temperature.parse().ok()?,
+            qnh: qnh.parse().ok()?,
+            cloud_preset: cloud_preset.to_string(),
+            cloud_density: cloud_density.parse().ok()?,
+            precipitation: precipitation.parse().ok()?,
+            fog_visibility: fog_visibility.parse().ok()?,
+        })
+    }
+}
+
+/// The mission's weather and current clock, None if the mission environment
+/// couldn't be read.
+pub fn read(lua: &Lua) -> Option<Weather> {
+    let line = dcs::eval_in_mission(lua, READ_WEATHER)
+        .map_err(|e| log::warn!("Couldn't read the mission weather: {}", e))
+        .ok()
+        .flatten()?;
+    let weather = Weather::parse(&line);
+    if weather.is_none() {
+        log::warn!("Couldn't parse mission weather {:?}", line);
+    }
+    weather
+}
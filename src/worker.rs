@@ -1,21 +1,69 @@
-use crate::config::Config;
-use crate::dcs;
+use crate::barrage::BallisticsAggregate;
+use crate::bincode_export::BincodeSink;
+use crate::chat::ChatRecord;
+use crate::config::{Config, ExportFormat};
+use crate::dashboard::DashboardServer;
 use crate::dcs::DcsWorldObject;
 use crate::dcs::DcsWorldUnit;
-use std::fs::File;
-use std::path::Path;
-use std::sync::{mpsc::Receiver, Arc};
-use zstd::stream::write::Encoder as ZstdEncoder;
+use crate::delta::DeltaFilter;
+use crate::events::{Event, EventKind};
+use crate::hitch::{HitchList, HitchSink};
+use crate::io_thread::{Compression, ThreadedFile};
+use crate::kinematics::MotionTracker;
+use crate::live_feed::LiveFeed;
+use crate::log_filter::LogFilter;
+use crate::manifest::{SessionManifest, SessionMetadata};
+use crate::net::TelemetryServer;
+use crate::netstats::ClientNetRecord;
+use crate::object_counts::ObjectCounts;
+use crate::parquet_export::ParquetSink;
+use crate::perf_monitor::{CpuWindow, MemoryUsage};
+use crate::players::RosterEntry;
+use crate::recorder::Recorder;
+use crate::report::ReportSink;
+use crate::sink::{self, CsvSink, Frame, RecorderSink, Sink, ZstdOptions};
+use crate::sqlite_export::SessionDb;
+use crate::upload::{self, UploadOptions};
+use crate::weather::Weather;
+use crate::worker_queue::WorkerReceiver;
+use std::cell::{Cell, RefCell};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tetrad_data::encrypted::Key;
+use tetrad_data::schema::{self, Schema};
 
 pub enum Message {
     Update {
         units: Arc<Vec<DcsWorldUnit>>,
         ballistics: Arc<Vec<DcsWorldObject>>,
+        /// Object counts for the frame log, kept even when the objects
+        /// themselves are dropped by the worker queue.
+        num_units: usize,
+        num_ballistics: usize,
         game_time: f64,
         real_time: f64,
+        /// Wall-clock time of the frame, UTC milliseconds since the epoch.
+        epoch_ms: i64,
+        /// Seconds tetrad spent in the previous frame's callback.
+        lib_time: f64,
+        memory: MemoryUsage,
         sys_time: (i32, i32),
         proc_time: (i32, i32),
     },
+    Roster {
+        players: Vec<RosterEntry>,
+        game_time: f64,
+        real_time: f64,
+    },
+    Event(Event),
+    NetStats(Vec<ClientNetRecord>),
+    Chat(ChatRecord),
+    /// A sample of the mission weather and clock for the frame log.
+    Weather(Weather),
+    /// Settings changed from the GUI while the mission is running.
+    ConfigChanged(Config),
     Stop,
 }
 
@@ -23,200 +71,564 @@ impl std::fmt::Debug for Message {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Update {
-                units,
-                ballistics,
+                num_units,
+                num_ballistics,
                 game_time,
-                real_time: _,
-                sys_time: _,
-                proc_time: _,
+                ..
             } => f.write_fmt(format_args!(
                 "Update at t={} with {} units and {} ballistics objects",
+                game_time, num_units, num_ballistics
+            )),
+            Self::Roster {
+                players,
+                game_time,
+                real_time: _,
+            } => f.write_fmt(format_args!(
+                "Roster at t={} with {} players",
                 game_time,
-                units.len(),
-                ballistics.len()
+                players.len()
+            )),
+            Self::Event(event) => {
+                f.write_fmt(format_args!("Event {:?} at t={}", event.kind, event.t_game))
+            }
+            Self::NetStats(records) => {
+                f.write_fmt(format_args!("Network stats for {} clients", records.len()))
+            }
+            Self::Chat(record) => f.write_fmt(format_args!(
+                "Chat from player {} at t={}",
+                record.player_id, record.t_game
             )),
+            Self::Weather(weather) => write!(f, "Weather at {:.0}", weather.time_of_day),
+            Self::ConfigChanged(_) => write!(f, "ConfigChanged"),
             Self::Stop => write!(f, "Stop"),
         }
     }
 }
 
+impl Message {
+    pub fn is_update(&self) -> bool {
+        matches!(self, Self::Update { .. })
+    }
+
+    /// The same update with the units and ballistics left out, so only the
+    /// frame statistics get logged.
+    pub fn without_objects(self) -> Self {
+        match self {
+            Self::Update {
+                num_units,
+                num_ballistics,
+                game_time,
+                real_time,
+                epoch_ms,
+                lib_time,
+                memory,
+                sys_time,
+                proc_time,
+                ..
+            } => Self::Update {
+                units: Arc::default(),
+                ballistics: Arc::default(),
+                num_units,
+                num_ballistics,
+                game_time,
+                real_time,
+                epoch_ms,
+                lib_time,
+                memory,
+                sys_time,
+                proc_time,
+            },
+            other => other,
+        }
+    }
+}
+
 fn format_now() -> String {
     let date = chrono::Local::now();
     date.format("%Y-%m-%d %H-%M-%S").to_string()
 }
 
-fn create_csv_file(mission_name: &str, dir_name: &Path) -> csv::Writer<ZstdEncoder<'static, File>> {
-    std::fs::create_dir_all(&dir_name).unwrap();
+fn create_file(
+    mission_name: &str,
+    dir_name: &Path,
+    extension: &str,
+    compression: Option<Compression>,
+) -> io::Result<ThreadedFile> {
+    let file_name = format!("{} - {}.{}", mission_name, format_now(), extension);
+    create_named_file(dir_name, &file_name, compression)
+}
+
+thread_local! {
+    // Every file this session's worker has created, for the manifest.
+    static OUTPUT_FILES: RefCell<Vec<PathBuf>> = RefCell::new(Vec::new());
+}
+
+thread_local! {
+    // Set with `encryption_key`, for this session's files.
+    static ENCRYPTION_KEY: Cell<Option<Key>> = Cell::new(None);
+    // Likewise with the zstd settings.
+    static ZSTD_OPTIONS: RefCell<ZstdOptions> = RefCell::new(ZstdOptions::default());
+}
 
-    let fname = dir_name.join(format!("{} - {}.csv.zstd", mission_name, format_now()));
-    log::debug!("Trying to open csv file: {:?}", fname);
+fn zstd_options() -> ZstdOptions {
+    ZSTD_OPTIONS.with(|options| options.borrow().clone())
+}
 
-    let csv_file = match File::create(&fname) {
-        Err(why) => {
-            log::error!("Couldn't open file {:?} because {}", fname, why);
-            panic!("failed")
-        }
-        Ok(file) => file,
+fn record_output_file(path: &Path) {
+    OUTPUT_FILES.with(|files| files.borrow_mut().push(path.to_path_buf()));
+}
+
+fn take_output_files() -> Vec<PathBuf> {
+    OUTPUT_FILES.with(|files| std::mem::take(&mut *files.borrow_mut()))
+}
+
+fn create_named_file(
+    dir_name: &Path,
+    file_name: &str,
+    compression: Option<Compression>,
+) -> io::Result<ThreadedFile> {
+    std::fs::create_dir_all(dir_name)?;
+
+    let key = ENCRYPTION_KEY.with(Cell::get);
+    let fname = match key {
+        Some(_) => dir_name.join(format!("{}.enc", file_name)),
+        None => dir_name.join(file_name),
     };
-    let encoder = ZstdEncoder::new(csv_file, 10).unwrap();
-    let csv_writer = csv::WriterBuilder::new()
+    log::debug!("Trying to open output file: {:?}", fname);
+    let file = ThreadedFile::create(&fname, key.as_ref(), compression)
+        .map_err(|e| io::Error::new(e.kind(), format!("{:?}: {}", fname, e)))?;
+    record_output_file(&fname);
+    Ok(file)
+}
+
+fn create_output_file(
+    mission_name: &str,
+    dir_name: &Path,
+    extension: &str,
+) -> io::Result<ThreadedFile> {
+    let zstd = zstd_options();
+    let compression = Compression {
+        zstd: &zstd,
+        dictionary: None,
+    };
+    create_file(
+        mission_name,
+        dir_name,
+        &format!("{}.zstd", extension),
+        Some(compression),
+    )
+}
+
+/// Starts a CSV log with its schema comment.
+fn csv_writer(mut file: ThreadedFile, schema: Schema) -> io::Result<OutputWriter> {
+    writeln!(file, "{}", schema.comment())?;
+    Ok(csv::WriterBuilder::new()
         .has_headers(false)
-        .from_writer(encoder);
-    csv_writer
+        .from_writer(file))
 }
 
-fn log_dcs_objects<W: std::io::Write, T: dcs::Loggable>(
-    frame_count: i32,
-    t: f64,
-    real_time: f64,
-    writer: &mut csv::Writer<W>,
-    objects: &[T],
+fn create_csv_file(
+    mission_name: &str,
+    dir_name: &Path,
+    schema: Schema,
+) -> io::Result<OutputWriter> {
+    csv_writer(create_output_file(mission_name, dir_name, "csv")?, schema)
+}
+
+/// Logs why an output couldn't be opened, the session carries on without it.
+fn opened<T, E: std::fmt::Display>(what: &str, result: Result<T, E>) -> Option<T> {
+    result
+        .map_err(|e| log::error!("Couldn't create the {}, not writing it: {}", what, e))
+        .ok()
+}
+
+/// Writes to a CSV log, closing it for the rest of the session the first
+/// time a write fails so a full disk doesn't flood the log with errors.
+fn write_or_close(
+    writer: &mut Option<OutputWriter>,
+    what: &str,
+    write: impl FnOnce(&mut OutputWriter) -> csv::Result<()>,
 ) {
-    for obj in objects.into_iter() {
-        obj.log_as_csv(frame_count, t, real_time, writer);
+    let Some(w) = writer.as_mut() else {
+        return;
+    };
+    if let Err(e) = write(w) {
+        log::error!("Couldn't write the {}, closing it: {}", what, e);
+        *writer = None;
     }
 }
 
-fn finish<W: std::io::Write>(obj: &mut Option<csv::Writer<W>>) {
+fn checkpoint_writer(obj: &mut Option<OutputWriter>) {
     if let Some(ref mut writer) = obj {
-        writer.flush().unwrap();
+        if let Err(e) = sink::checkpoint_csv(writer) {
+            log::error!("Couldn't flush output file: {}", e);
+        }
     }
 }
 
-fn log_frame(
-    writer: &mut csv::Writer<zstd::Encoder<'_, File>>,
-    game_time: f64,
-    real_time: f64,
-    n: i32,
-    num_units: i32,
-    num_ballistics: i32,
-    sys_cpu_time: i32,
-    sys_wall_time: i32,
-    proc_cpu_time: i32,
-) {
-    writer.write_field((n).to_string()).unwrap();
-    writer.write_field(format!("{:.8}", game_time)).unwrap();
-    writer.write_field(format!("{:.8}", real_time)).unwrap();
-    writer.write_field(num_units.to_string()).unwrap();
-    writer.write_field(num_ballistics.to_string()).unwrap();
-    writer.write_field(sys_cpu_time.to_string()).unwrap();
-    writer.write_field(sys_wall_time.to_string()).unwrap();
-    writer.write_field(proc_cpu_time.to_string()).unwrap();
-    writer.write_record(None::<&[u8]>).unwrap();
+type OutputWriter = csv::Writer<ThreadedFile>;
+
+/// Where the frame and object logs are when they're split into parts by size
+/// or duration.
+#[derive(Default)]
+struct Rotation {
+    part: u32,
+    started_at: Option<f64>,
+    file_sizes: Vec<Arc<AtomicU64>>,
 }
 
-type OutputWriter = csv::Writer<ZstdEncoder<'static, File>>;
+impl Rotation {
+    /// Opens a frame or object log file, numbered if rotation is enabled.
+    fn create_file(
+        &mut self,
+        config: &Config,
+        mission_name: &str,
+        dir_name: &Path,
+        extension: &str,
+        compression: Option<Compression>,
+    ) -> io::Result<ThreadedFile> {
+        let file = if config.rotates_exports() {
+            let file_name = format!(
+                "{} - {} - {:03}.{}",
+                mission_name,
+                format_now(),
+                self.part,
+                extension
+            );
+            create_named_file(dir_name, &file_name, compression)?
+        } else {
+            create_file(mission_name, dir_name, extension, compression)?
+        };
+        self.file_sizes.push(file.bytes_written());
+        Ok(file)
+    }
+
+    fn is_due(&mut self, config: &Config, real_time: f64) -> bool {
+        let started_at = *self.started_at.get_or_insert(real_time);
+        let max_size = config.export_rotate_size_mb * 1024.0 * 1024.0;
+        let too_big = config.export_rotate_size_mb > 0.0
+            && self
+                .file_sizes
+                .iter()
+                .any(|size| size.load(Ordering::Relaxed) as f64 >= max_size);
+        let too_long = config.export_rotate_interval > 0.0
+            && real_time - started_at >= config.export_rotate_interval;
+        too_big || too_long
+    }
+
+    fn next_part(&mut self) {
+        self.part += 1;
+        self.started_at = None;
+        self.file_sizes.clear();
+    }
+}
 
 struct Logger {
-    prev_game_time: f64,
-    most_recent_game_time: f64,
-    current_real_time: f64,
+    config: Config,
+    mission_name: String,
+    metadata: SessionMetadata,
     frame_count: i32,
-    frame_writer: Option<OutputWriter>,
-    object_writer: Option<OutputWriter>,
+    /// Frame count and game time of the last object log snapshot.
+    last_object_sample: Option<(i32, f64)>,
+    /// Set when only some coalitions or a region are logged.
+    log_filter: Option<LogFilter>,
+    /// Set when only changed objects are logged.
+    delta_filter: Option<DeltaFilter>,
+    /// Object speeds, updated on every frame with objects.
+    motion: MotionTracker,
+    /// CPU load for the frame logs, updated on every frame.
+    cpu_window: CpuWindow,
+    /// Marker labels waiting for the next frame log row.
+    pending_markers: Vec<String>,
+    /// Weather sample waiting for the next frame log row.
+    pending_weather: Option<Weather>,
+    /// Frame and object logs in the configured `export_formats`.
+    export_sinks: Vec<Box<dyn Sink>>,
+    rotation: Rotation,
+    /// Everything else that wants each frame (telemetry, flight recorder).
+    sinks: Vec<Box<dyn Sink>>,
+    roster_writer: Option<OutputWriter>,
+    event_writer: Option<OutputWriter>,
+    network_writer: Option<OutputWriter>,
+    chat_writer: Option<OutputWriter>,
+    last_checkpoint_time: f64,
 }
 
 impl Logger {
-    fn new(frame_writer: Option<OutputWriter>, object_writer: Option<OutputWriter>) -> Self {
+    fn new(
+        config: Config,
+        mission_name: String,
+        metadata: SessionMetadata,
+        roster_writer: Option<OutputWriter>,
+        event_writer: Option<OutputWriter>,
+        network_writer: Option<OutputWriter>,
+        chat_writer: Option<OutputWriter>,
+    ) -> Self {
+        let mut rotation = Rotation::default();
+        let log_filter = LogFilter::new(&config);
+        let delta_filter = config.object_log_deltas.then(|| DeltaFilter::new(&config));
         let mut me = Self {
-            prev_game_time: 0.0,
-            current_real_time: 0.0,
-            most_recent_game_time: 0.0,
+            export_sinks: create_export_sinks(
+                &config,
+                &mission_name,
+                &metadata,
+                &config.export_formats,
+                &mut rotation,
+            ),
+            rotation,
+            sinks: create_sinks(&config, &mission_name),
+            config,
+            mission_name,
+            metadata,
             frame_count: 0,
-            frame_writer,
-            object_writer,
+            last_object_sample: None,
+            log_filter,
+            delta_filter,
+            motion: MotionTracker::default(),
+            cpu_window: CpuWindow::default(),
+            pending_markers: Vec::new(),
+            pending_weather: None,
+            roster_writer,
+            event_writer,
+            network_writer,
+            chat_writer,
+            last_checkpoint_time: 0.0,
         };
-        me.frame_writer
-            .as_mut()
-            .unwrap()
-            .write_record(&[
-                "frame_count",
+        write_or_close(&mut me.roster_writer, "roster log", |writer| {
+            writer.write_record(&[
+                "t_game",
+                "t_real",
+                "id",
+                "name",
+                "ucid",
+                "side",
+                "slot",
+                "ping",
+                "connected_for",
+            ])
+        });
+        write_or_close(&mut me.event_writer, "event log", |writer| {
+            writer.write_record(&[
+                "t_game",
+                "t_real",
+                "kind",
+                "player_id",
+                "player_name",
+                "details",
+                "epoch_ms",
+                "session_id",
+            ])
+        });
+        write_or_close(&mut me.network_writer, "network log", |writer| {
+            writer.write_record(&[
                 "t_game",
                 "t_real",
-                "units",
-                "ballistics",
-                "sys_cpu",
-                "sys_wall",
-                "proc_cpu",
+                "player_id",
+                "player_name",
+                "mean_ping",
+                "max_ping",
+                "jitter",
             ])
-            .unwrap();
+        });
+        write_or_close(&mut me.chat_writer, "chat log", |writer| {
+            writer.write_record(&[
+                "t_game",
+                "t_real",
+                "player_id",
+                "player_name",
+                "scope",
+                "message",
+            ])
+        });
         me
     }
 
-    fn log_frame(
-        &mut self,
-        t: f64,
-        units: &[DcsWorldUnit],
-        ballistics: &[DcsWorldObject],
-        sys_time: (i32, i32),
-        proc_time: (i32, i32),
-    ) {
-        log_frame(
-            self.frame_writer.as_mut().unwrap(),
-            t,
-            self.current_real_time,
+    /// Whether the object logs are due another snapshot, given
+    /// `object_log_interval` and `object_log_interval_frames`.
+    fn is_object_sample_due(&mut self, game_time: f64) -> bool {
+        if let Some((frame_count, t)) = self.last_object_sample {
+            let frames = self.config.object_log_interval_frames as i32;
+            if self.frame_count - frame_count < frames
+                || game_time - t < self.config.object_log_interval
+            {
+                return false;
+            }
+        }
+        self.last_object_sample = Some((self.frame_count, game_time));
+        true
+    }
+
+    fn handle_update(&mut self, frame: &Frame) {
+        log::trace!(
+            "New frame message, n = {}, t = {}",
             self.frame_count,
-            units.len() as i32,
-            ballistics.len() as i32,
-            sys_time.0,
-            sys_time.1,
-            proc_time.0,
+            frame.game_time
         );
+        let kept = match self.log_filter.as_ref() {
+            Some(filter) if frame.log_objects => Some(filter.apply(frame)),
+            _ => None,
+        };
+        let kept_frame = match &kept {
+            Some((units, ballistics)) => Frame {
+                units,
+                ballistics,
+                ..*frame
+            },
+            None => Frame { ..*frame },
+        };
+        let cap = self.config.ballistics_log_cap;
+        let aggregate = if cap > 0 && frame.log_objects && kept_frame.ballistics.len() > cap {
+            BallisticsAggregate::compute(kept_frame.ballistics)
+        } else {
+            None
+        };
+        let no_ballistics = Arc::default();
+        let capped_frame = match &aggregate {
+            Some(aggregate) => Frame {
+                ballistics: &no_ballistics,
+                ballistics_aggregate: Some(aggregate),
+                ..kept_frame
+            },
+            None => kept_frame,
+        };
+        let changed = match self.delta_filter.as_mut() {
+            Some(filter) if frame.log_objects => Some(filter.apply(&capped_frame)),
+            _ => None,
+        };
+        // the object logs only get the kept objects that changed, everything
+        // else the full frame
+        let export_frame = match &changed {
+            Some((units, ballistics)) => Frame {
+                units,
+                ballistics,
+                ..capped_frame
+            },
+            None => capped_frame,
+        };
+        for sink in self.export_sinks.iter_mut() {
+            if let Err(e) = sink.write_frame(&export_frame) {
+                log::error!("Couldn't write frame to {}: {}", sink.name(), e);
+            }
+        }
+        for sink in self.sinks.iter_mut() {
+            if let Err(e) = sink.write_frame(frame) {
+                log::error!("Couldn't write frame to {}: {}", sink.name(), e);
+            }
+        }
+        self.frame_count += 1;
+        if self.config.rotates_exports() && self.rotation.is_due(&self.config, frame.real_time) {
+            self.rotate_exports();
+        }
+        self.checkpoint(frame.real_time);
     }
 
-    fn log_objects(&mut self, units: &[DcsWorldUnit], ballistics: &[DcsWorldObject]) {
-        log::trace!("Logging Units message with {} elements", units.len());
-        let n = self.frame_count;
-        let t = self.most_recent_game_time;
-        log_dcs_objects(
-            n,
-            t,
-            self.current_real_time,
-            self.object_writer.as_mut().unwrap(),
-            units,
-        );
+    /// Makes every output file readable up to now every `flush_interval`
+    /// seconds, so a DCS crash doesn't cost the whole session.
+    fn checkpoint(&mut self, real_time: f64) {
+        let interval = self.config.flush_interval;
+        if interval <= 0.0 || real_time - self.last_checkpoint_time < interval {
+            return;
+        }
+        self.last_checkpoint_time = real_time;
+        checkpoint_writer(&mut self.roster_writer);
+        checkpoint_writer(&mut self.event_writer);
+        checkpoint_writer(&mut self.network_writer);
+        checkpoint_writer(&mut self.chat_writer);
+        for sink in self.export_sinks.iter_mut().chain(self.sinks.iter_mut()) {
+            if let Err(e) = sink.checkpoint() {
+                log::error!("Couldn't flush {}: {}", sink.name(), e);
+            }
+        }
+    }
 
-        log::trace!(
-            "Logging Ballistics message with {} elements",
-            ballistics.len()
-        );
-        log_dcs_objects(
-            n,
-            t,
-            self.current_real_time,
-            self.object_writer.as_mut().unwrap(),
-            ballistics,
+    /// Closes the current CSV/Parquet files and carries on in the next part.
+    fn rotate_exports(&mut self) {
+        self.rotation.next_part();
+        log::info!(
+            "Starting part {} of the frame/object logs",
+            self.rotation.part
         );
+        let (mut rotated, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut self.export_sinks)
+            .into_iter()
+            .partition(|sink| sink.rotates());
+        finish_sinks(&mut rotated);
+        let formats: Vec<ExportFormat> = self
+            .config
+            .export_formats
+            .iter()
+            .copied()
+            .filter(|format| {
+                matches!(
+                    format,
+                    ExportFormat::Csv | ExportFormat::Parquet | ExportFormat::Bincode
+                )
+            })
+            .collect();
+        self.export_sinks = kept;
+        self.export_sinks.extend(create_export_sinks(
+            &self.config,
+            &self.mission_name,
+            &self.metadata,
+            &formats,
+            &mut self.rotation,
+        ));
     }
 
-    fn handle_update(
-        &mut self,
-        units: &Vec<DcsWorldUnit>,
-        ballistics: &Vec<DcsWorldObject>,
-        game_time: f64,
-        real_time: f64,
-        sys_time: (i32, i32),
-        proc_time: (i32, i32),
-    ) {
-        let n = self.frame_count;
-        log::trace!("New frame message, n = {}, t = {}", n, game_time);
-
-        self.prev_game_time = self.most_recent_game_time;
-        self.most_recent_game_time = game_time;
-        self.current_real_time = real_time;
-        if self.frame_writer.is_some() {
-            self.log_frame(
-                game_time,
-                units.as_slice(),
-                &ballistics.as_slice(),
-                sys_time,
-                proc_time,
+    fn log_roster(&mut self, players: &[RosterEntry], game_time: f64, real_time: f64) {
+        log::trace!("Logging roster with {} players", players.len());
+        write_or_close(&mut self.roster_writer, "roster log", |writer| {
+            for entry in players {
+                writer.serialize((game_time, real_time, &entry.player, entry.connected_for))?;
+            }
+            Ok(())
+        });
+    }
+
+    fn log_event(&mut self, event: &Event) {
+        let session_id = &self.metadata.session_id;
+        write_or_close(&mut self.event_writer, "event log", |writer| {
+            writer.serialize((event, session_id))?;
+            // events are rare and valuable, don't leave them sitting in a buffer
+            writer.flush()?;
+            Ok(())
+        });
+    }
+
+    fn log_net_stats(&mut self, records: &[ClientNetRecord]) {
+        write_or_close(&mut self.network_writer, "network log", |writer| {
+            records
+                .iter()
+                .try_for_each(|record| writer.serialize(record))
+        });
+    }
+
+    fn log_chat(&mut self, record: &ChatRecord) {
+        write_or_close(&mut self.chat_writer, "chat log", |writer| {
+            writer.serialize(record)
+        });
+    }
+
+    fn change_config(&mut self, config: Config) {
+        let logs_changed = config.enable_framerate_log != self.config.enable_framerate_log
+            || config.enable_object_log != self.config.enable_object_log;
+        self.config = config;
+        if logs_changed {
+            // the new logs start in fresh files, the old ones are closed out
+            log::info!(
+                "Frame log {}, object log {}",
+                enabled_str(self.config.enable_framerate_log),
+                enabled_str(self.config.enable_object_log)
+            );
+            finish_sinks(&mut self.export_sinks);
+            self.rotation.next_part();
+            self.export_sinks = create_export_sinks(
+                &self.config,
+                &self.mission_name,
+                &self.metadata,
+                &self.config.export_formats,
+                &mut self.rotation,
             );
         }
-        if self.object_writer.is_some() {
-            self.log_objects(units.as_slice(), ballistics.as_slice());
-        }
-        self.frame_count += 1;
     }
 
     fn handle_message(&mut self, msg: Message) -> bool {
@@ -224,19 +636,82 @@ impl Logger {
             Message::Update {
                 units,
                 ballistics,
+                num_units,
+                num_ballistics,
                 game_time,
                 real_time,
+                epoch_ms,
+                lib_time,
+                memory,
                 sys_time,
                 proc_time,
             } => {
-                self.handle_update(
-                    &units,
-                    &ballistics,
+                // frames between object polls (or stripped by the queue) only
+                // carry the counts
+                let has_objects = units.len() == num_units && ballistics.len() == num_ballistics;
+                let log_objects = has_objects && self.is_object_sample_due(game_time);
+                let markers = std::mem::take(&mut self.pending_markers).join("; ");
+                let weather = self.pending_weather.take();
+                let counts = (has_objects && self.config.enable_object_count_columns)
+                    .then(|| ObjectCounts::tally(&units, &ballistics));
+                let cpu_load = self.cpu_window.update(real_time, sys_time, proc_time);
+                let mut motion = std::mem::take(&mut self.motion);
+                if has_objects {
+                    motion.update(game_time, &units, &ballistics);
+                }
+                self.handle_update(&Frame {
+                    frame_count: self.frame_count,
                     game_time,
                     real_time,
+                    epoch_ms,
+                    session_id: &self.metadata.session_id,
+                    units: &units,
+                    ballistics: &ballistics,
+                    num_units,
+                    num_ballistics,
+                    log_objects,
+                    lib_time,
+                    memory,
                     sys_time,
                     proc_time,
-                );
+                    cpu_load,
+                    markers: &markers,
+                    weather: weather.as_ref(),
+                    ballistics_aggregate: None,
+                    object_counts: counts.as_ref(),
+                    motion: &motion,
+                });
+                self.motion = motion;
+            }
+            Message::Roster {
+                players,
+                game_time,
+                real_time,
+            } => {
+                self.log_roster(&players, game_time, real_time);
+            }
+            Message::Event(event) => {
+                if event.kind == EventKind::Marker {
+                    self.pending_markers.push(event.details.clone());
+                }
+                for sink in self.sinks.iter_mut() {
+                    if let Err(e) = sink.write_event(&event) {
+                        log::error!("Couldn't write event to {}: {}", sink.name(), e);
+                    }
+                }
+                self.log_event(&event);
+            }
+            Message::NetStats(records) => {
+                self.log_net_stats(&records);
+            }
+            Message::Chat(record) => {
+                self.log_chat(&record);
+            }
+            Message::Weather(weather) => {
+                self.pending_weather = Some(weather);
+            }
+            Message::ConfigChanged(config) => {
+                self.change_config(config);
             }
             Message::Stop => {
                 log::debug!("Stopping!");
@@ -247,41 +722,334 @@ impl Logger {
     }
 
     fn finish(&mut self) {
-        finish(&mut self.object_writer);
-        finish(&mut self.frame_writer);
+        checkpoint_writer(&mut self.roster_writer);
+        checkpoint_writer(&mut self.event_writer);
+        checkpoint_writer(&mut self.network_writer);
+        checkpoint_writer(&mut self.chat_writer);
+        finish_sinks(&mut self.export_sinks);
+        finish_sinks(&mut self.sinks);
     }
 }
 
-pub fn entry(config: Config, mission_name: String, rx: Receiver<Message>) {
-    let log_dir = Path::new(config.write_dir.as_str())
-        .join("Logs")
-        .join("Tetrad");
+fn enabled_str(enabled: bool) -> &'static str {
+    if enabled {
+        "enabled"
+    } else {
+        "disabled"
+    }
+}
 
-    let frame_writer = if config.enable_framerate_log {
-        let writer = create_csv_file(&mission_name, &log_dir.join("frames"));
-        Some(writer)
+fn finish_sinks(sinks: &mut Vec<Box<dyn Sink>>) {
+    for sink in sinks.drain(..) {
+        let name = sink.name();
+        if let Err(e) = sink.finish() {
+            log::error!("Couldn't finish {} output: {}", name, e);
+        }
+    }
+}
+
+fn create_export_sinks(
+    config: &Config,
+    mission_name: &str,
+    metadata: &SessionMetadata,
+    formats: &[ExportFormat],
+    rotation: &mut Rotation,
+) -> Vec<Box<dyn Sink>> {
+    let log_dir = config.log_dir();
+    let mut sinks: Vec<Box<dyn Sink>> = Vec::new();
+    let has_frame_data = config.enable_framerate_log || config.enable_object_log;
+    for format in formats {
+        if !has_frame_data {
+            break;
+        }
+        match format {
+            ExportFormat::Csv => {
+                let zstd = zstd_options();
+                let mut create_csv = |schema: Schema, dictionary: Option<&[u8]>| {
+                    let compression = Compression {
+                        zstd: &zstd,
+                        dictionary,
+                    };
+                    let file = rotation.create_file(
+                        config,
+                        mission_name,
+                        &log_dir.join(schema.name),
+                        "csv.zstd",
+                        Some(compression),
+                    )?;
+                    csv_writer(file, schema)
+                };
+                let frame_writer = config
+                    .enable_framerate_log
+                    .then(|| opened("CSV frame log", create_csv(schema::FRAMES, None)))
+                    .flatten();
+                let object_writer = config
+                    .enable_object_log
+                    .then(|| {
+                        let dictionary = zstd.object_dictionary.as_deref().map(Vec::as_slice);
+                        opened("CSV object log", create_csv(schema::OBJECTS, dictionary))
+                    })
+                    .flatten();
+                if frame_writer.is_none() && object_writer.is_none() {
+                    continue;
+                }
+                let metadata = config.enable_metadata_columns.then_some(metadata);
+                let sink = CsvSink::new(
+                    frame_writer,
+                    object_writer,
+                    config.enable_object_count_columns,
+                    metadata,
+                    config.object_columns(),
+                    config.enable_object_log_header,
+                );
+                if let Some(sink) = opened("CSV logs", sink) {
+                    sinks.push(Box::new(sink));
+                }
+            }
+            ExportFormat::Parquet => {
+                let mut create_parquet = |dir_name: &str| {
+                    rotation.create_file(
+                        config,
+                        mission_name,
+                        &log_dir.join(dir_name),
+                        "parquet",
+                        None,
+                    )
+                };
+                let frame_file = config
+                    .enable_framerate_log
+                    .then(|| opened("Parquet frame log", create_parquet("frames")))
+                    .flatten();
+                let object_file = config
+                    .enable_object_log
+                    .then(|| opened("Parquet object log", create_parquet("objects")))
+                    .flatten();
+                if frame_file.is_none() && object_file.is_none() {
+                    continue;
+                }
+                if let Some(sink) =
+                    opened("Parquet logs", ParquetSink::new(frame_file, object_file))
+                {
+                    sinks.push(Box::new(sink));
+                }
+            }
+            ExportFormat::Bincode => {
+                let zstd = zstd_options();
+                let mut create_bincode = |dir_name: &str| {
+                    let compression = Compression {
+                        zstd: &zstd,
+                        dictionary: None,
+                    };
+                    rotation.create_file(
+                        config,
+                        mission_name,
+                        &log_dir.join(dir_name),
+                        "bin.zstd",
+                        Some(compression),
+                    )
+                };
+                let frame_file = config
+                    .enable_framerate_log
+                    .then(|| opened("bincode frame log", create_bincode("frames")))
+                    .flatten();
+                let object_file = config
+                    .enable_object_log
+                    .then(|| opened("bincode object log", create_bincode("objects")))
+                    .flatten();
+                if frame_file.is_none() && object_file.is_none() {
+                    continue;
+                }
+                let sink = BincodeSink::new(frame_file, object_file, mission_name);
+                if let Some(sink) = opened("bincode logs", sink) {
+                    sinks.push(Box::new(sink));
+                }
+            }
+            ExportFormat::Sqlite => {
+                let dir = log_dir.join("sessions");
+                if opened("sessions directory", std::fs::create_dir_all(&dir)).is_none() {
+                    continue;
+                }
+                let path = dir.join(format!("{} - {}.sqlite", mission_name, format_now()));
+                match SessionDb::create(
+                    &path,
+                    mission_name,
+                    config.enable_framerate_log,
+                    config.enable_object_log,
+                ) {
+                    Ok(db) => {
+                        record_output_file(&path);
+                        sinks.push(Box::new(db));
+                    }
+                    Err(e) => log::error!("Couldn't create session database {:?}: {}", path, e),
+                }
+            }
+        }
+    }
+    sinks
+}
+
+fn create_sinks(config: &Config, mission_name: &str) -> Vec<Box<dyn Sink>> {
+    let log_dir = config.log_dir();
+    let mut sinks: Vec<Box<dyn Sink>> = Vec::new();
+    if config.websocket_port != 0 {
+        match TelemetryServer::start(&config.websocket_address, config.websocket_port) {
+            Ok(server) => sinks.push(Box::new(server)),
+            Err(e) => log::error!("Couldn't start telemetry server: {}", e),
+        }
+    }
+    if config.live_feed_interval > 0.0 {
+        let name = format!("Local\\tetrad-live-{}", config.instance_name());
+        match LiveFeed::create(&name, mission_name, config.live_feed_interval) {
+            Ok(feed) => {
+                log::info!("Publishing live data to shared memory {}", name);
+                sinks.push(Box::new(feed));
+            }
+            Err(e) => log::error!("Couldn't create the live feed {}: {}", name, e),
+        }
+    }
+    if config.dashboard_port != 0 {
+        match DashboardServer::start(
+            &config.dashboard_address,
+            config.dashboard_port,
+            mission_name,
+        ) {
+            Ok(server) => sinks.push(Box::new(server)),
+            Err(e) => log::error!("Couldn't start dashboard: {}", e),
+        }
+    }
+    let hitches = (config.hitch_threshold_ms > 0.0).then(HitchList::default);
+    if let Some(hitches) = hitches.as_ref() {
+        let sink = create_csv_file(mission_name, &log_dir.join("hitches"), schema::HITCHES)
+            .map_err(csv::Error::from)
+            .and_then(|writer| HitchSink::new(writer, config, hitches.clone()));
+        if let Some(sink) = opened("hitch log", sink) {
+            sinks.push(Box::new(sink));
+        }
+    }
+    let report_dir = log_dir.join("sessions");
+    if config.enable_session_report
+        && opened("session report", std::fs::create_dir_all(&report_dir)).is_some()
+    {
+        let path = report_dir.join(format!("{} - {}.report.md", mission_name, format_now()));
+        record_output_file(&path);
+        let spike_threshold =
+            (config.hitch_threshold_ms > 0.0).then(|| config.hitch_threshold_ms / 1000.0);
+        sinks.push(Box::new(ReportSink::new(
+            path,
+            mission_name,
+            hitches,
+            spike_threshold,
+        )));
+    }
+    if config.flight_recorder_interval > 0.0 {
+        let recorder = create_output_file(mission_name, &log_dir.join("recordings"), "ttrd")
+            .map_err(bincode::Error::from)
+            .and_then(|file| Recorder::new(file, mission_name));
+        if let Some(recorder) = opened("flight recording", recorder) {
+            sinks.push(Box::new(RecorderSink::new(
+                recorder,
+                config.flight_recorder_interval,
+            )));
+        }
+    }
+    sinks
+}
+
+fn write_manifest(manifest: &SessionManifest, path: &Path) {
+    if let Err(e) = manifest.write(path) {
+        log::error!("Couldn't write session manifest {:?}: {}", path, e);
+    }
+}
+
+pub fn entry(
+    config: Config,
+    mission_name: String,
+    mut manifest: SessionManifest,
+    rx: WorkerReceiver,
+) {
+    let log_dir = config.log_dir();
+    // checked when the config was loaded, but never fall back to writing the
+    // logs unencrypted if it's bad anyway
+    let key = match config.encryption_key() {
+        Ok(key) => key,
+        Err(e) => {
+            log::error!("Bad encryption key, not writing any logs: {}", e);
+            return;
+        }
+    };
+    ENCRYPTION_KEY.with(|cell| cell.set(key));
+    ZSTD_OPTIONS.with(|options| *options.borrow_mut() = ZstdOptions::from_config(&config));
+
+    let manifest_dir = log_dir.join("sessions");
+    if let Err(e) = std::fs::create_dir_all(&manifest_dir) {
+        log::error!("Couldn't create {:?}: {}", manifest_dir, e);
+    }
+    let manifest_path =
+        manifest_dir.join(format!("{} - {}.session.json", mission_name, format_now()));
+    write_manifest(&manifest, &manifest_path);
+
+    let roster_writer = if config.roster_interval > 0.0 {
+        let writer = create_csv_file(&mission_name, &log_dir.join("roster"), schema::ROSTER);
+        opened("roster log", writer)
     } else {
         None
     };
 
-    let object_writer = if config.enable_object_log {
-        let writer = create_csv_file(&mission_name, &log_dir.join("objects"));
-        Some(writer)
+    let event_writer = if config.enable_event_log {
+        let writer = create_csv_file(&mission_name, &log_dir.join("events"), schema::EVENTS);
+        opened("event log", writer)
+    } else {
+        None
+    };
+
+    let network_writer = if config.net_stats_interval > 0.0 {
+        let writer = create_csv_file(&mission_name, &log_dir.join("network"), schema::NETWORK);
+        opened("network log", writer)
+    } else {
+        None
+    };
+
+    let chat_writer = if config.enable_chat_log {
+        let writer = create_csv_file(&mission_name, &log_dir.join("chat"), schema::CHAT);
+        opened("chat log", writer)
     } else {
         None
     };
 
-    let mut logger = Logger::new(frame_writer, object_writer);
     log::debug!("Starting with config {:?}", config);
+    let upload_options = UploadOptions::from_config(&config);
+    let mut logger = Logger::new(
+        config,
+        mission_name,
+        manifest.metadata.clone(),
+        roster_writer,
+        event_writer,
+        network_writer,
+        chat_writer,
+    );
 
     loop {
         log::trace!("Waiting for message");
-        let msg = rx.recv().expect("Should be able to receive a message");
+        let Ok(msg) = rx.recv() else {
+            // tetrad went away without stopping the session, e.g. DCS
+            // unloaded it, so close the files off all the same
+            log::warn!("Worker queue closed without a stop message");
+            break;
+        };
         let done = logger.handle_message(msg);
         if done {
             break;
         }
     }
-    log::debug!("finishing csv files!");
+    log::debug!("finishing output files!");
     logger.finish();
+    manifest.finish(logger.frame_count, take_output_files());
+    write_manifest(&manifest, &manifest_path);
+    // closes the remaining files
+    drop(logger);
+    if let Some(options) = upload_options {
+        let mut files = manifest.output_files.clone();
+        files.push(manifest_path.clone());
+        upload::spawn(options, log_dir, &manifest_path, files);
+    }
 }
@@ -0,0 +1,371 @@
+// Bounded queue between the DCS thread and the worker. Only frame updates
+// count against the capacity; everything else (events, roster, chat, ...) is
+// rare and always queued. When the worker falls behind, e.g. because the disk
+// stalls, the `WorkerQueuePolicy` decides what happens to new updates instead
+// of letting memory climb until DCS dies. Updates stripped by `drop_objects`
+// are small and get a capacity of their own, once that's used up the oldest
+// of them is thrown away.
+//
+// If the worker stops taking messages altogether, e.g. blocked on a dead disk,
+// the queue is considered stalled after `STALL_TIMEOUT` and everything sent
+// from then on is thrown away, until the worker takes a message again.
+use crate::config::WorkerQueuePolicy;
+use crate::worker::Message;
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long the worker can leave queued messages untouched before it counts
+/// as stalled. Also the longest the `block` policy makes DCS wait.
+pub const STALL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Frame updates that didn't make it to the worker intact.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct QueueStats {
+    /// Updates thrown away entirely (`drop_oldest`, or `drop_objects` once
+    /// the stripped updates fill the queue too).
+    pub dropped_frames: u64,
+    /// Updates whose units and ballistics were thrown away, keeping the frame
+    /// statistics (`drop_objects`).
+    pub stripped_frames: u64,
+    /// Messages of any kind thrown away while the worker was stalled.
+    pub discarded_messages: u64,
+}
+
+impl QueueStats {
+    pub fn total(&self) -> u64 {
+        self.dropped_frames + self.stripped_frames
+    }
+}
+
+#[derive(Debug)]
+pub struct Disconnected;
+
+/// A queued message, and whether it's an update `drop_objects` stripped.
+struct Queued {
+    message: Message,
+    is_stripped: bool,
+}
+
+#[derive(Default)]
+struct State {
+    messages: VecDeque<Queued>,
+    /// Queued updates with their objects.
+    updates: usize,
+    /// Queued updates stripped of their objects.
+    stripped: usize,
+    stats: QueueStats,
+    /// Since when the worker has had messages waiting without taking one.
+    waiting_since: Option<Instant>,
+    is_stalled: bool,
+    is_sender_closed: bool,
+    is_receiver_closed: bool,
+}
+
+impl State {
+    fn forget(&mut self, queued: &Queued) {
+        if queued.is_stripped {
+            self.stripped -= 1;
+        } else if queued.message.is_update() {
+            self.updates -= 1;
+        }
+    }
+
+    /// Throws away the oldest queued update matching `is_match`.
+    fn drop_oldest(&mut self, is_match: impl Fn(&Queued) -> bool) {
+        let Some(i) = self.messages.iter().position(is_match) else {
+            return;
+        };
+        if let Some(queued) = self.messages.remove(i) {
+            self.forget(&queued);
+            self.stats.dropped_frames += 1;
+        }
+    }
+}
+
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+    capacity: usize,
+    policy: WorkerQueuePolicy,
+}
+
+pub struct WorkerSender {
+    shared: Arc<Shared>,
+}
+
+pub struct WorkerReceiver {
+    shared: Arc<Shared>,
+}
+
+pub fn channel(capacity: usize, policy: WorkerQueuePolicy) -> (WorkerSender, WorkerReceiver) {
+    let shared = Arc::new(Shared {
+        state: Mutex::default(),
+        changed: Condvar::new(),
+        capacity: capacity.max(1),
+        policy,
+    });
+    (
+        WorkerSender {
+            shared: shared.clone(),
+        },
+        WorkerReceiver { shared },
+    )
+}
+
+impl WorkerSender {
+    pub fn send(&self, mut message: Message) -> Result<(), Disconnected> {
+        let shared = &self.shared;
+        let mut state = shared.state.lock().unwrap();
+        // the worker still needs the stop message to ever finish
+        if state.is_stalled && !matches!(message, Message::Stop) {
+            state.stats.discarded_messages += 1;
+            return Ok(());
+        }
+        let mut is_stripped = false;
+        if message.is_update() && state.updates >= shared.capacity {
+            match shared.policy {
+                WorkerQueuePolicy::Block => {
+                    let (new_state, wait) = shared
+                        .changed
+                        .wait_timeout_while(state, STALL_TIMEOUT, |s| {
+                            !s.is_receiver_closed && s.updates >= shared.capacity
+                        })
+                        .unwrap();
+                    state = new_state;
+                    if wait.timed_out() {
+                        state.is_stalled = true;
+                        state.stats.discarded_messages += 1;
+                        return Ok(());
+                    }
+                }
+                WorkerQueuePolicy::DropOldest => {
+                    state.drop_oldest(|queued| queued.message.is_update());
+                }
+                WorkerQueuePolicy::DropObjects => {
+                    message = message.without_objects();
+                    is_stripped = true;
+                    state.stats.stripped_frames += 1;
+                    if state.stripped >= shared.capacity {
+                        state.drop_oldest(|queued| queued.is_stripped);
+                    }
+                }
+            }
+        }
+        if state.is_receiver_closed {
+            return Err(Disconnected);
+        }
+        if is_stripped {
+            state.stripped += 1;
+        } else if message.is_update() {
+            state.updates += 1;
+        }
+        if state.messages.is_empty() {
+            state.waiting_since = Some(Instant::now());
+        }
+        state.messages.push_back(Queued {
+            message,
+            is_stripped,
+        });
+        shared.changed.notify_all();
+        Ok(())
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        self.shared.state.lock().unwrap().stats
+    }
+
+    /// Whether the worker has left its messages untouched for
+    /// `STALL_TIMEOUT`. While it has, everything sent is thrown away.
+    pub fn is_stalled(&self) -> bool {
+        let mut state = self.shared.state.lock().unwrap();
+        let is_stalled = state
+            .waiting_since
+            .map_or(false, |t| t.elapsed() >= STALL_TIMEOUT);
+        state.is_stalled |= is_stalled;
+        state.is_stalled
+    }
+}
+
+impl Drop for WorkerSender {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().is_sender_closed = true;
+        self.shared.changed.notify_all();
+    }
+}
+
+impl WorkerReceiver {
+    /// Waits for the next message, failing once the queue is empty and the
+    /// sender has gone away.
+    pub fn recv(&self) -> Result<Message, Disconnected> {
+        let shared = &self.shared;
+        let mut state = shared
+            .changed
+            .wait_while(shared.state.lock().unwrap(), |s| {
+                s.messages.is_empty() && !s.is_sender_closed
+            })
+            .unwrap();
+        let queued = state.messages.pop_front().ok_or(Disconnected)?;
+        state.forget(&queued);
+        state.waiting_since = (!state.messages.is_empty()).then(Instant::now);
+        state.is_stalled = false;
+        shared.changed.notify_all();
+        Ok(queued.message)
+    }
+}
+
+impl Drop for WorkerReceiver {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().is_receiver_closed = true;
+        self.shared.changed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dcs::DcsWorldObject;
+
+    fn update(game_time: f64) -> Message {
+        let object: DcsWorldObject = serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "name": "AIM_120C",
+            "country": 2,
+            "coalition": "blue",
+            "coalition_id": 2,
+            "lat_lon_alt": { "lat": 0.0, "lon": 0.0, "alt": 0.0 },
+            "heading": 0.0,
+            "pitch": 0.0,
+            "bank": 0.0,
+            "position": { "x": 0.0, "y": 0.0, "z": 0.0 },
+            "category": "missile",
+        }))
+        .unwrap();
+        Message::Update {
+            units: Arc::default(),
+            ballistics: Arc::new(vec![object]),
+            num_units: 0,
+            num_ballistics: 1,
+            game_time,
+            real_time: game_time,
+            epoch_ms: 0,
+            lib_time: 0.0,
+            memory: Default::default(),
+            sys_time: (0, 0),
+            proc_time: (0, 0),
+        }
+    }
+
+    /// Game time of each queued message, and whether it still has its objects.
+    fn drain(rx: &WorkerReceiver) -> Vec<(f64, bool)> {
+        let mut received = Vec::new();
+        while !rx.shared.state.lock().unwrap().messages.is_empty() {
+            match rx.recv().unwrap() {
+                Message::Update {
+                    game_time,
+                    ballistics,
+                    ..
+                } => received.push((game_time, !ballistics.is_empty())),
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+        received
+    }
+
+    #[test]
+    fn drop_oldest_keeps_the_newest_updates() {
+        let (tx, rx) = channel(2, WorkerQueuePolicy::DropOldest);
+        for t in 0..4 {
+            tx.send(update(t as f64)).unwrap();
+        }
+        assert_eq!(drain(&rx), [(2.0, true), (3.0, true)]);
+        assert_eq!(tx.stats().dropped_frames, 2);
+    }
+
+    #[test]
+    fn only_updates_count_against_the_capacity() {
+        let (tx, rx) = channel(1, WorkerQueuePolicy::DropOldest);
+        tx.send(update(0.0)).unwrap();
+        tx.send(Message::Stop).unwrap();
+        tx.send(Message::Stop).unwrap();
+        assert_eq!(rx.shared.state.lock().unwrap().messages.len(), 3);
+        assert_eq!(tx.stats().total(), 0);
+    }
+
+    #[test]
+    fn drop_objects_strips_updates_past_the_capacity() {
+        let (tx, rx) = channel(2, WorkerQueuePolicy::DropObjects);
+        for t in 0..4 {
+            tx.send(update(t as f64)).unwrap();
+        }
+        assert_eq!(
+            drain(&rx),
+            [(0.0, true), (1.0, true), (2.0, false), (3.0, false)]
+        );
+        assert_eq!(tx.stats().stripped_frames, 2);
+        assert_eq!(tx.stats().dropped_frames, 0);
+    }
+
+    #[test]
+    fn drop_objects_drops_the_oldest_stripped_update_when_those_fill_up() {
+        let (tx, rx) = channel(2, WorkerQueuePolicy::DropObjects);
+        for t in 0..6 {
+            tx.send(update(t as f64)).unwrap();
+        }
+        assert_eq!(
+            drain(&rx),
+            [(0.0, true), (1.0, true), (4.0, false), (5.0, false)]
+        );
+        assert_eq!(tx.stats().stripped_frames, 4);
+        assert_eq!(tx.stats().dropped_frames, 2);
+    }
+
+    #[test]
+    fn block_waits_for_the_worker() {
+        let (tx, rx) = channel(1, WorkerQueuePolicy::Block);
+        tx.send(update(0.0)).unwrap();
+        let worker = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            let first = rx.recv().unwrap();
+            (first, rx)
+        });
+        // full, so this waits until the worker took the first one
+        tx.send(update(1.0)).unwrap();
+        let (first, rx) = worker.join().unwrap();
+        assert!(matches!(first, Message::Update { game_time, .. } if game_time == 0.0));
+        assert_eq!(drain(&rx), [(1.0, true)]);
+        assert_eq!(tx.stats().total(), 0);
+        assert!(!tx.is_stalled());
+    }
+
+    #[test]
+    fn stalled_queue_discards_everything_but_stop() {
+        let (tx, rx) = channel(1, WorkerQueuePolicy::DropOldest);
+        tx.send(update(0.0)).unwrap();
+        rx.shared.state.lock().unwrap().waiting_since = Some(Instant::now() - STALL_TIMEOUT);
+        assert!(tx.is_stalled());
+        tx.send(update(1.0)).unwrap();
+        tx.send(Message::Stop).unwrap();
+        assert_eq!(tx.stats().discarded_messages, 1);
+        assert!(rx.recv().unwrap().is_update());
+        assert!(matches!(rx.recv().unwrap(), Message::Stop));
+        // the worker taking messages again ends the stall
+        assert!(!tx.is_stalled());
+    }
+
+    #[test]
+    fn receiver_drains_the_queue_after_the_sender_closes() {
+        let (tx, rx) = channel(2, WorkerQueuePolicy::DropOldest);
+        tx.send(update(0.0)).unwrap();
+        drop(tx);
+        assert!(rx.recv().unwrap().is_update());
+        assert!(rx.recv().is_err());
+    }
+
+    #[test]
+    fn sender_fails_once_the_receiver_closes() {
+        let (tx, rx) = channel(2, WorkerQueuePolicy::Block);
+        drop(rx);
+        assert!(tx.send(update(0.0)).is_err());
+    }
+}
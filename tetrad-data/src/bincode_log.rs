@@ -0,0 +1,158 @@
+// The frame and object logs of the "bincode" export format.
+//
+// Each file is a zstd-compressed stream of length-prefixed records: a
+// little-endian u32 byte count followed by that many bytes of bincode. The
+// first record is a `LogHeader`, then frame logs hold one `FrameRecord` per
+// frame and object logs one `ObjectFrame` per frame. Readers should check
+// `LogHeader::version` against `FORMAT_VERSION`.
+use crate::object::{DcsWorldObject, Motion};
+use crate::recording::ObjectKind;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::marker::PhantomData;
+use std::path::Path;
+
+pub const MAGIC: [u8; 4] = *b"TTLG";
+pub const FORMAT_VERSION: u32 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum LogKind {
+    Frames,
+    Objects,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LogHeader {
+    pub magic: [u8; 4],
+    pub version: u32,
+    pub kind: LogKind,
+    pub mission_name: String,
+    pub tetrad_version: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FrameRecord {
+    pub frame_count: i32,
+    pub t_game: f64,
+    pub t_real: f64,
+    pub units: u32,
+    pub ballistics: u32,
+    pub sys_cpu: i32,
+    pub sys_wall: i32,
+    pub proc_cpu: i32,
+    /// Seconds tetrad spent in the previous frame's callback.
+    pub lib_time: f64,
+    /// Memory use of the DCS process, in bytes.
+    pub working_set: u64,
+    pub private_bytes: u64,
+    pub commit: u64,
+}
+
+/// Borrowed when tetrad writes it, owned when read back.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ObjectRecord<'a> {
+    pub kind: ObjectKind,
+    pub unit_name: Cow<'a, str>,
+    pub group_name: Cow<'a, str>,
+    pub object: Cow<'a, DcsWorldObject>,
+    pub motion: Motion,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ObjectFrame<'a> {
+    pub frame_count: i32,
+    pub t_game: f64,
+    pub t_real: f64,
+    pub objects: Vec<ObjectRecord<'a>>,
+}
+
+fn invalid(message: String) -> bincode::Error {
+    Box::new(bincode::ErrorKind::Custom(message))
+}
+
+pub struct LogReader<R: Read> {
+    reader: R,
+    header: LogHeader,
+    buf: Vec<u8>,
+}
+
+impl LogReader<BufReader<zstd::stream::read::Decoder<'static, BufReader<File>>>> {
+    pub fn open(path: &Path) -> bincode::Result<Self> {
+        let decoder = zstd::stream::read::Decoder::new(File::open(path)?)?;
+        Self::new(BufReader::new(decoder))
+    }
+}
+
+/// The next record, or `None` at the end of the stream. A truncated final
+/// record, e.g. from a crash, counts as the end.
+fn read_record<R: Read, T: DeserializeOwned>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+) -> Option<bincode::Result<T>> {
+    let mut len = [0u8; 4];
+    reader.read_exact(&mut len).ok()?;
+    buf.resize(u32::from_le_bytes(len) as usize, 0);
+    reader.read_exact(buf).ok()?;
+    Some(bincode::deserialize(buf))
+}
+
+impl<R: Read> LogReader<R> {
+    /// Reads the header from a (decompressed) stream.
+    pub fn new(mut reader: R) -> bincode::Result<Self> {
+        let mut buf = Vec::new();
+        let header: LogHeader = read_record(&mut reader, &mut buf)
+            .unwrap_or_else(|| Err(invalid("empty log".to_string())))?;
+        if header.magic != MAGIC || header.version != FORMAT_VERSION {
+            return Err(invalid(format!(
+                "not a tetrad log of version {} (version {})",
+                FORMAT_VERSION, header.version
+            )));
+        }
+        Ok(Self {
+            reader,
+            header,
+            buf,
+        })
+    }
+
+    pub fn header(&self) -> &LogHeader {
+        &self.header
+    }
+
+    fn records<T: DeserializeOwned>(self, kind: LogKind) -> bincode::Result<Records<R, T>> {
+        if self.header.kind != kind {
+            return Err(invalid(format!(
+                "expected a {:?} log, this is a {:?} log",
+                kind, self.header.kind
+            )));
+        }
+        Ok(Records {
+            log: self,
+            record: PhantomData,
+        })
+    }
+
+    pub fn frames(self) -> bincode::Result<Records<R, FrameRecord>> {
+        self.records(LogKind::Frames)
+    }
+
+    pub fn objects(self) -> bincode::Result<Records<R, ObjectFrame<'static>>> {
+        self.records(LogKind::Objects)
+    }
+}
+
+pub struct Records<R: Read, T> {
+    log: LogReader<R>,
+    record: PhantomData<T>,
+}
+
+impl<R: Read, T: DeserializeOwned> Iterator for Records<R, T> {
+    type Item = bincode::Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        read_record(&mut self.log.reader, &mut self.log.buf)
+    }
+}
@@ -0,0 +1,273 @@
+// The CSV frame and object logs, `Logs/Tetrad/frames` and `Logs/Tetrad/objects`.
+// Both are usually zstd-compressed (`.csv.zstd`) and start with a schema
+// comment row (see `schema`). The frame log has a header row. The object log
+// only has one with `enable_object_log_header`; without it, its rows are read
+// by position.
+use crate::object::ObjectCategory;
+use crate::schema::{self, Schema};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+/// One row of the frame log. Logs from older versions lack the later columns,
+/// which then read as 0.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct FrameRow {
+    pub frame_count: i32,
+    pub t_game: f64,
+    pub t_real: f64,
+    pub units: usize,
+    pub ballistics: usize,
+    pub sys_cpu: i32,
+    pub sys_wall: i32,
+    pub proc_cpu: i32,
+    /// Seconds tetrad spent in the previous frame's callback.
+    #[serde(default)]
+    pub lib_time: f64,
+    /// Memory use of the DCS process, in bytes.
+    #[serde(default)]
+    pub working_set: u64,
+    #[serde(default)]
+    pub private_bytes: u64,
+    #[serde(default)]
+    pub commit: u64,
+    /// Labels of the markers set since the previous frame, `; `-separated.
+    #[serde(default)]
+    pub markers: String,
+    /// Mission weather and clock, only on the frames that took a sample:
+    /// seconds since midnight, wind in m/s and degrees, degrees Celsius, QNH
+    /// in mmHg, precipitation 0-4 (none to snowstorm), fog visibility in
+    /// meters.
+    #[serde(default)]
+    pub time_of_day: Option<f64>,
+    #[serde(default)]
+    pub wind_speed: Option<f64>,
+    #[serde(default)]
+    pub wind_dir: Option<f64>,
+    #[serde(default)]
+    pub temperature: Option<f64>,
+    #[serde(default)]
+    pub qnh: Option<f64>,
+    #[serde(default)]
+    pub cloud_preset: Option<String>,
+    #[serde(default)]
+    pub cloud_density: Option<i32>,
+    #[serde(default)]
+    pub precipitation: Option<i32>,
+    #[serde(default)]
+    pub fog_visibility: Option<f64>,
+    /// Only on the frames with more ballistics than `ballistics_log_cap`,
+    /// which the object log leaves out: their count, centroid (degrees and
+    /// meters) and bounding box.
+    #[serde(default)]
+    pub aggregated_ballistics: Option<usize>,
+    #[serde(default)]
+    pub ballistics_lat: Option<f64>,
+    #[serde(default)]
+    pub ballistics_lon: Option<f64>,
+    #[serde(default)]
+    pub ballistics_alt: Option<f64>,
+    #[serde(default)]
+    pub ballistics_south: Option<f64>,
+    #[serde(default)]
+    pub ballistics_west: Option<f64>,
+    #[serde(default)]
+    pub ballistics_north: Option<f64>,
+    #[serde(default)]
+    pub ballistics_east: Option<f64>,
+    /// Wall-clock time of the frame, UTC milliseconds since the Unix epoch,
+    /// and the session's UUID, to join the log with others. Empty in logs from
+    /// older versions.
+    #[serde(default)]
+    pub epoch_ms: Option<i64>,
+    #[serde(default)]
+    pub session_id: Option<String>,
+    /// DCS's and the whole system's CPU load over the last second, as
+    /// fractions of all cores. Empty in logs from older versions.
+    #[serde(default)]
+    pub dcs_cpu: Option<f64>,
+    #[serde(default)]
+    pub total_cpu: Option<f64>,
+    /// Only in logs written with `enable_metadata_columns`.
+    #[serde(default)]
+    pub theatre: Option<String>,
+    #[serde(default)]
+    pub multiplayer: Option<bool>,
+    #[serde(default)]
+    pub dcs_build: Option<u32>,
+}
+
+/// The object log's columns, in the order they're written when
+/// `object_log_columns` doesn't pick others.
+pub const OBJECT_COLUMNS: [&str; 25] = [
+    "frame_count",
+    "t_game",
+    "t_real",
+    "unit_name",
+    "group_name",
+    "id",
+    "name",
+    "country",
+    "coalition",
+    "coalition_id",
+    "lat",
+    "lon",
+    "alt",
+    "heading",
+    "pitch",
+    "bank",
+    "x",
+    "y",
+    "z",
+    "category",
+    "speed",
+    "vertical_speed",
+    "heading_rate",
+    "epoch_ms",
+    "session_id",
+];
+
+/// One object in one frame of the object log. `unit_name` and `group_name`
+/// are empty for ballistics. Columns left out of a log with a header read as
+/// 0 or empty.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ObjectRow {
+    pub frame_count: i32,
+    pub t_game: f64,
+    pub t_real: f64,
+    pub unit_name: String,
+    pub group_name: String,
+    pub id: i32,
+    pub name: String,
+    pub country: i32,
+    pub coalition: String,
+    pub coalition_id: i32,
+    pub lat: f64,
+    pub lon: f64,
+    pub alt: f64,
+    /// Radians.
+    pub heading: f64,
+    pub pitch: f64,
+    pub bank: f64,
+    /// DCS world position (x north, y up, z east), meters.
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub category: ObjectCategory,
+    /// Meters per second, 0 in logs from older versions.
+    pub speed: f64,
+    pub vertical_speed: f64,
+    /// Radians per second.
+    pub heading_rate: f64,
+    /// Wall-clock time of the frame, UTC milliseconds since the Unix epoch,
+    /// and the session's UUID. Empty in logs from older versions.
+    pub epoch_ms: Option<i64>,
+    pub session_id: Option<String>,
+}
+
+pub type Rows<T> = csv::DeserializeRecordsIntoIter<Box<dyn Read>, T>;
+
+/// Opens a log, decompressing it if it ends in `.zstd`.
+pub fn open(path: &Path) -> std::io::Result<Box<dyn Read>> {
+    open_with_dictionary(path, None)
+}
+
+/// Like `open`, for object logs compressed with `object_log_dictionary`,
+/// which need the same dictionary to be read.
+pub fn open_with_dictionary(
+    path: &Path,
+    dictionary: Option<&[u8]>,
+) -> std::io::Result<Box<dyn Read>> {
+    let file = BufReader::new(File::open(path)?);
+    if path.extension().map_or(false, |e| e == "zstd") {
+        let decoder = match dictionary {
+            Some(dictionary) => zstd::stream::read::Decoder::with_dictionary(file, dictionary)?,
+            None => zstd::stream::read::Decoder::with_buffer(file)?,
+        };
+        Ok(Box::new(decoder))
+    } else {
+        Ok(Box::new(file))
+    }
+}
+
+/// Fails if the log's schema comment is from a newer version of `expected`
+/// than this crate knows, which would be read into the wrong fields.
+fn check_schema(data: &[u8], expected: Schema) -> std::io::Result<()> {
+    let line = data.split(|&b| b == b'\n').next().unwrap_or_default();
+    let Some((name, version)) = std::str::from_utf8(line)
+        .ok()
+        .and_then(schema::parse_comment)
+    else {
+        return Ok(());
+    };
+    if name != expected.name || version > expected.version {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "log has schema {}/{}, expected {} up to version {}",
+                name, version, expected.name, expected.version
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Skips the comment rows at the start of `data`.
+fn skip_comments(mut data: &[u8]) -> &[u8] {
+    while data.first() == Some(&b'#') {
+        data = match data.iter().position(|&b| b == b'\n') {
+            Some(end) => &data[end + 1..],
+            None => &[],
+        };
+    }
+    data
+}
+
+/// The rows of a frame log. A log cut off mid-write (tetrad still running, or
+/// DCS crashed) ends in an error, after all the complete rows.
+pub fn read_frames(path: &Path) -> csv::Result<Rows<FrameRow>> {
+    let mut reader = BufReader::new(open(path)?);
+    check_schema(reader.fill_buf()?, schema::FRAMES)?;
+    let reader: Box<dyn Read> = Box::new(reader);
+    Ok(csv::ReaderBuilder::new()
+        .comment(Some(b'#'))
+        .from_reader(reader)
+        .into_deserialize())
+}
+
+/// The rows of an object log, like `read_frames`.
+pub fn read_objects(path: &Path) -> csv::Result<Rows<ObjectRow>> {
+    read_objects_with_dictionary(path, None)
+}
+
+/// `read_objects` for a log compressed with a dictionary, see
+/// `open_with_dictionary`.
+pub fn read_objects_with_dictionary(
+    path: &Path,
+    dictionary: Option<&[u8]>,
+) -> csv::Result<Rows<ObjectRow>> {
+    let mut reader = BufReader::new(open_with_dictionary(path, dictionary)?);
+    let data = reader.fill_buf()?;
+    check_schema(data, schema::OBJECTS)?;
+    let has_header = starts_with_header(skip_comments(data));
+    let reader: Box<dyn Read> = Box::new(reader);
+    Ok(csv::ReaderBuilder::new()
+        .comment(Some(b'#'))
+        .has_headers(has_header)
+        .from_reader(reader)
+        .into_deserialize())
+}
+
+/// Whether an object log starts with a header row, which is told apart from
+/// a data row by its first field being a column name.
+fn starts_with_header(data: &[u8]) -> bool {
+    let end = data
+        .iter()
+        .position(|&b| b == b',' || b == b'\n' || b == b'\r')
+        .unwrap_or(data.len());
+    OBJECT_COLUMNS
+        .iter()
+        .any(|column| data[..end] == *column.as_bytes())
+}
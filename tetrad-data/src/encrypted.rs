@@ -0,0 +1,244 @@
+// Logs encrypted with `encryption_key`, in AES-256-GCM segments (the STREAM
+// construction, so segments can't be reordered, dropped or cut off unnoticed).
+//
+// A file starts with `TTRE`, a version byte (1) and the 7 byte nonce prefix,
+// followed by the segments. Each segment is a flag byte (1 on the last one,
+// 0 otherwise), the u32 little-endian length of the ciphertext and the
+// ciphertext, which has a 16 byte tag at the end. Segments hold up to 64 KiB
+// of plaintext, less where the writer was flushed. A file without a last
+// segment was cut off, e.g. by a crash.
+use aes_gcm::aead::generic_array::GenericArray;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::stream::{DecryptorBE32, EncryptorBE32};
+use aes_gcm::aead::OsRng;
+use aes_gcm::{Aes256Gcm, KeyInit};
+use std::io::{self, Read, Write};
+
+const MAGIC: &[u8; 4] = b"TTRE";
+const VERSION: u8 = 1;
+const NONCE_PREFIX_SIZE: usize = 7;
+const SEGMENT_SIZE: usize = 64 * 1024;
+
+pub type Key = [u8; 32];
+
+/// Environment variable read for the key when the config doesn't have one.
+pub const KEY_VARIABLE: &str = "TETRAD_ENCRYPTION_KEY";
+
+/// Parses a key written as 64 hex digits.
+pub fn parse_key(hex: &str) -> Result<Key, String> {
+    let hex = hex.trim();
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Err("should be 64 hex digits".to_string());
+    }
+    let mut key = [0; 32];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16)
+            .map_err(|_| "should be 64 hex digits".to_string())?;
+    }
+    Ok(key)
+}
+
+fn crypto_error(_: aes_gcm::aead::Error) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "decryption failed, wrong key or damaged file",
+    )
+}
+
+/// Encrypts everything written to it. Writes are gathered into full segments,
+/// `flush` seals what's gathered so far so it can be decrypted.
+pub struct EncryptedWriter<W: Write> {
+    inner: W,
+    encryptor: EncryptorBE32<Aes256Gcm>,
+    /// Plaintext of the next segment.
+    segment: Vec<u8>,
+}
+
+impl<W: Write> EncryptedWriter<W> {
+    pub fn new(mut inner: W, key: &Key) -> io::Result<Self> {
+        let mut nonce = [0; NONCE_PREFIX_SIZE];
+        OsRng.fill_bytes(&mut nonce);
+        inner.write_all(MAGIC)?;
+        inner.write_all(&[VERSION])?;
+        inner.write_all(&nonce)?;
+        let cipher = Aes256Gcm::new(GenericArray::from_slice(key));
+        Ok(Self {
+            inner,
+            encryptor: EncryptorBE32::from_aead(cipher, GenericArray::from_slice(&nonce)),
+            segment: Vec::with_capacity(SEGMENT_SIZE),
+        })
+    }
+
+    fn seal_segment(&mut self) -> io::Result<()> {
+        if self.segment.is_empty() {
+            return Ok(());
+        }
+        let ciphertext = self
+            .encryptor
+            .encrypt_next(self.segment.as_slice())
+            .map_err(crypto_error)?;
+        Self::write_segment(&mut self.inner, false, &ciphertext)?;
+        self.segment.clear();
+        Ok(())
+    }
+
+    fn write_segment(inner: &mut W, last: bool, ciphertext: &[u8]) -> io::Result<()> {
+        inner.write_all(&[last as u8])?;
+        inner.write_all(&(ciphertext.len() as u32).to_le_bytes())?;
+        inner.write_all(ciphertext)
+    }
+
+    /// Writes the last segment, marking the file as complete.
+    pub fn finish(self) -> io::Result<W> {
+        let Self {
+            mut inner,
+            encryptor,
+            segment,
+        } = self;
+        let ciphertext = encryptor
+            .encrypt_last(segment.as_slice())
+            .map_err(crypto_error)?;
+        Self::write_segment(&mut inner, true, &ciphertext)?;
+        inner.flush()?;
+        Ok(inner)
+    }
+}
+
+impl<W: Write> Write for EncryptedWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let n = data.len().min(SEGMENT_SIZE - self.segment.len());
+        self.segment.extend_from_slice(&data[..n]);
+        if self.segment.len() == SEGMENT_SIZE {
+            self.seal_segment()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.seal_segment()?;
+        self.inner.flush()
+    }
+}
+
+/// Decrypts `input` to `output`. Returns false if the file was cut off, in
+/// which case everything up to there was written.
+pub fn decrypt<R: Read, W: Write>(mut input: R, mut output: W, key: &Key) -> io::Result<bool> {
+    let mut header = [0; 5 + NONCE_PREFIX_SIZE];
+    input.read_exact(&mut header)?;
+    if &header[..4] != MAGIC || header[4] != VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a file encrypted by tetrad",
+        ));
+    }
+    let cipher = Aes256Gcm::new(GenericArray::from_slice(key));
+    let mut decryptor = DecryptorBE32::from_aead(cipher, GenericArray::from_slice(&header[5..]));
+    loop {
+        let mut segment_header = [0; 5];
+        match input.read_exact(&mut segment_header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(false),
+            Err(e) => return Err(e),
+        }
+        let len = u32::from_le_bytes(segment_header[1..].try_into().unwrap()) as usize;
+        let mut ciphertext = vec![0; len];
+        match input.read_exact(&mut ciphertext) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(false),
+            Err(e) => return Err(e),
+        }
+        if segment_header[0] == 1 {
+            let plaintext = decryptor
+                .decrypt_last(ciphertext.as_slice())
+                .map_err(crypto_error)?;
+            output.write_all(&plaintext)?;
+            return Ok(true);
+        }
+        let plaintext = decryptor
+            .decrypt_next(ciphertext.as_slice())
+            .map_err(crypto_error)?;
+        output.write_all(&plaintext)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: Key = [7; 32];
+
+    fn encrypt(chunks: &[&[u8]], flush: bool) -> Vec<u8> {
+        let mut writer = EncryptedWriter::new(Vec::new(), &KEY).unwrap();
+        for chunk in chunks {
+            writer.write_all(chunk).unwrap();
+            if flush {
+                writer.flush().unwrap();
+            }
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn parses_hex_keys() {
+        let hex = "00".repeat(31) + "fF";
+        let key = parse_key(&format!(" {}\n", hex)).unwrap();
+        assert_eq!(key[31], 0xff);
+        assert!(parse_key(&hex[2..]).is_err());
+        assert!(parse_key(&("zz".to_string() + &hex[2..])).is_err());
+        assert!(parse_key(&("é".to_string() + &hex[2..])).is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        for flush in [false, true] {
+            let encrypted = encrypt(&[&data[..10], &data[10..150_000], &data[150_000..]], flush);
+            let mut decrypted = Vec::new();
+            assert!(decrypt(encrypted.as_slice(), &mut decrypted, &KEY).unwrap());
+            assert_eq!(decrypted, data);
+        }
+    }
+
+    #[test]
+    fn empty_file_round_trip() {
+        let encrypted = encrypt(&[], false);
+        let mut decrypted = Vec::new();
+        assert!(decrypt(encrypted.as_slice(), &mut decrypted, &KEY).unwrap());
+        assert!(decrypted.is_empty());
+    }
+
+    #[test]
+    fn plaintext_is_gathered_into_segments() {
+        let small_writes = vec![b"row\n".as_slice(); 1000];
+        let encrypted = encrypt(&small_writes, false);
+        // header, then the last segment with everything in it
+        assert_eq!(encrypted.len(), 5 + NONCE_PREFIX_SIZE + 5 + 4000 + 16);
+    }
+
+    #[test]
+    fn wrong_key_fails() {
+        let encrypted = encrypt(&[b"secret"], false);
+        let e = decrypt(encrypted.as_slice(), io::sink(), &[8; 32]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn cut_off_file_keeps_the_sealed_segments() {
+        let encrypted = encrypt(&[b"first", b"second"], true);
+        // drop the last segment, which only has the tag
+        let cut = &encrypted[..encrypted.len() - 5 - 16];
+        let mut decrypted = Vec::new();
+        assert!(!decrypt(cut, &mut decrypted, &KEY).unwrap());
+        assert_eq!(decrypted, b"firstsecond");
+    }
+
+    #[test]
+    fn dropped_segments_are_noticed() {
+        let encrypted = encrypt(&[b"first", b"second"], true);
+        // leave out "first", the segments are bound to their position
+        let header = 5 + NONCE_PREFIX_SIZE;
+        let mut tampered = encrypted[..header].to_vec();
+        tampered.extend_from_slice(&encrypted[header + 5 + 5 + 16..]);
+        assert!(decrypt(tampered.as_slice(), io::sink(), &KEY).is_err());
+    }
+}
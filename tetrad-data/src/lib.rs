@@ -0,0 +1,20 @@
+// Record types for the logs dcs-tetrad writes, and readers for them, so tools
+// outside tetrad can read a session without copying its struct definitions.
+//
+// - `csv_log`: the `.csv.zstd` frame and object logs
+// - `bincode_log`: the `.bin.zstd` logs of the "bincode" export format
+// - `encrypted`: logs written with `encryption_key` (`.enc`)
+// - `recording`: flight recordings (`.ttrd`)
+// - `recover`: salvaging logs that were cut off by a crash
+// - `schema`: the versions of every output's layout
+pub mod bincode_log;
+pub mod csv_log;
+pub mod encrypted;
+#[cfg(feature = "lua")]
+mod lua;
+mod object;
+pub mod recording;
+pub mod recover;
+pub mod schema;
+
+pub use object::{DcsWorldObject, DcsWorldUnit, Motion, ObjectCategory};
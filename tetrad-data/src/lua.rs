@@ -0,0 +1,105 @@
+// Reading objects out of the tables DCS's `LoGetWorldObjects` returns. Only
+// built with the `lua` feature.
+use crate::object::{DcsPosition, DcsWorldObject, DcsWorldUnit, LatLonAlt, ObjectCategory};
+use mlua::prelude::LuaTable;
+
+/// Reads a string field into an existing buffer, reusing its allocation.
+fn read_string_into(table: &LuaTable, key: &str, s: &mut String) -> mlua::Result<()> {
+    let value: mlua::String = get_field(table, key)?;
+    s.clear();
+    s.push_str(value.to_str()?);
+    Ok(())
+}
+
+/// Reads one field of a DCS table, naming it in the error.
+fn get_field<'lua, T: mlua::FromLua<'lua>>(table: &LuaTable<'lua>, key: &str) -> mlua::Result<T> {
+    table
+        .get(key)
+        .map_err(|e| mlua::Error::RuntimeError(format!("field `{}`: {}", key, e)))
+}
+
+impl<'lua> DcsWorldObject {
+    pub fn from_lua_with_id(id: i32, table: &LuaTable<'lua>) -> mlua::Result<Self> {
+        let mut object = Self::default();
+        object.update_from_lua(id, table)?;
+        Ok(object)
+    }
+
+    /// Overwrites this object with the one in `table`, reusing the string
+    /// buffers. Left half-updated on error.
+    pub fn update_from_lua(&mut self, id: i32, table: &LuaTable<'lua>) -> mlua::Result<()> {
+        self.update_from_table(id, table)
+            .map_err(|e| mlua::Error::RuntimeError(format!("object {}: {}", id, e)))
+    }
+
+    /// Updates only the fields that change from frame to frame (position and
+    /// attitude), for an object already read in full.
+    pub fn update_motion_from_lua(&mut self, table: &LuaTable<'lua>) -> mlua::Result<()> {
+        self.update_motion(table)
+            .map_err(|e| mlua::Error::RuntimeError(format!("object {}: {}", self.id, e)))
+    }
+
+    fn update_from_table(&mut self, id: i32, table: &LuaTable<'lua>) -> mlua::Result<()> {
+        self.update_motion(table)?;
+
+        let category = match table.get::<_, LuaTable>("Type") {
+            Ok(t) => ObjectCategory::from_type_levels(
+                t.get("level1").unwrap_or(0),
+                t.get("level2").unwrap_or(0),
+            ),
+            Err(_e) => ObjectCategory::Other,
+        };
+
+        self.id = id;
+        read_string_into(table, "Name", &mut self.name)?;
+        self.country = get_field(table, "Country")?;
+        read_string_into(table, "Coalition", &mut self.coalition)?;
+        self.coalition_id = get_field(table, "CoalitionID")?;
+        self.category = category;
+        Ok(())
+    }
+
+    fn update_motion(&mut self, table: &LuaTable<'lua>) -> mlua::Result<()> {
+        let lat_lon_alt: LuaTable = get_field(table, "LatLongAlt")?;
+        let position: LuaTable = get_field(table, "Position")?;
+
+        self.lat_lon_alt = LatLonAlt {
+            lat: get_field(&lat_lon_alt, "Lat")?,
+            lon: get_field(&lat_lon_alt, "Long")?,
+            alt: get_field(&lat_lon_alt, "Alt")?,
+        };
+
+        self.position = DcsPosition {
+            x: get_field(&position, "x")?,
+            y: get_field(&position, "y")?,
+            z: get_field(&position, "z")?,
+        };
+
+        self.heading = get_field(table, "Heading")?;
+        self.pitch = get_field(table, "Pitch")?;
+        self.bank = get_field(table, "Bank")?;
+        Ok(())
+    }
+}
+
+impl<'lua> DcsWorldUnit {
+    pub fn from_lua_with_id(id: i32, table: LuaTable<'lua>) -> mlua::Result<Self> {
+        let mut unit = Self::default();
+        unit.update_from_lua(id, &table)?;
+        Ok(unit)
+    }
+
+    /// Like `DcsWorldObject::update_from_lua`.
+    pub fn update_from_lua(&mut self, id: i32, table: &LuaTable<'lua>) -> mlua::Result<()> {
+        self.object.update_from_lua(id, table)?;
+        if read_string_into(table, "UnitName", &mut self.unit_name).is_err() {
+            self.unit_name.clear();
+            self.unit_name.push_str("NoName");
+        }
+        if read_string_into(table, "GroupName", &mut self.group_name).is_err() {
+            self.group_name.clear();
+            self.group_name.push_str("NoName");
+        }
+        Ok(())
+    }
+}
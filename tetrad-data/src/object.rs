@@ -0,0 +1,194 @@
+// The objects DCS reports each frame, as tetrad logs them.
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub(crate) struct LatLonAlt {
+    pub(crate) lat: f64,
+    pub(crate) lon: f64,
+    pub(crate) alt: f64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub(crate) struct DcsPosition {
+    pub(crate) x: f64,
+    pub(crate) y: f64,
+    pub(crate) z: f64,
+}
+
+/// How fast an object is moving. DCS only reports where objects are, so this
+/// is worked out from their positions in consecutive frames.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub struct Motion {
+    /// Meters per second, in three dimensions.
+    pub speed: f64,
+    /// Meters per second, positive when climbing.
+    pub vertical_speed: f64,
+    /// Radians per second, positive when turning right.
+    pub heading_rate: f64,
+}
+
+/// Broad object class, from the first two levels of the DCS `Type` table.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ObjectCategory {
+    Airplane,
+    Helicopter,
+    /// Aircraft DCS doesn't say more about. Logs from before airplanes and
+    /// helicopters were told apart have all aircraft as this.
+    Air,
+    Ground,
+    Naval,
+    Weapon,
+    Static,
+    #[default]
+    Other,
+}
+
+impl ObjectCategory {
+    pub const ALL: [ObjectCategory; 8] = [
+        ObjectCategory::Airplane,
+        ObjectCategory::Helicopter,
+        ObjectCategory::Air,
+        ObjectCategory::Ground,
+        ObjectCategory::Naval,
+        ObjectCategory::Weapon,
+        ObjectCategory::Static,
+        ObjectCategory::Other,
+    ];
+
+    #[cfg(feature = "lua")]
+    pub(crate) fn from_type_levels(level1: i32, level2: i32) -> Self {
+        match (level1, level2) {
+            (1, 1) => ObjectCategory::Airplane,
+            (1, 2) => ObjectCategory::Helicopter,
+            (1, _) => ObjectCategory::Air,
+            (2, _) => ObjectCategory::Ground,
+            (3, _) => ObjectCategory::Naval,
+            (4, _) => ObjectCategory::Weapon,
+            (5, _) => ObjectCategory::Static,
+            _ => ObjectCategory::Other,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ObjectCategory::Airplane => "Airplane",
+            ObjectCategory::Helicopter => "Helicopter",
+            ObjectCategory::Air => "Air",
+            ObjectCategory::Ground => "Ground",
+            ObjectCategory::Naval => "Naval",
+            ObjectCategory::Weapon => "Weapon",
+            ObjectCategory::Static => "Static",
+            ObjectCategory::Other => "Other",
+        }
+    }
+
+    /// The name used in the logs, e.g. "helicopter".
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ObjectCategory::Airplane => "airplane",
+            ObjectCategory::Helicopter => "helicopter",
+            ObjectCategory::Air => "air",
+            ObjectCategory::Ground => "ground",
+            ObjectCategory::Naval => "naval",
+            ObjectCategory::Weapon => "weapon",
+            ObjectCategory::Static => "static",
+            ObjectCategory::Other => "other",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct DcsWorldObject {
+    pub(crate) id: i32,
+    /// DCS type name, e.g. "Su-27" or "AIM_120C".
+    pub(crate) name: String,
+    pub(crate) country: i32,
+    pub(crate) coalition: String,
+    pub(crate) coalition_id: i32,
+    pub(crate) lat_lon_alt: LatLonAlt,
+    pub(crate) heading: f64,
+    pub(crate) pitch: f64,
+    pub(crate) bank: f64,
+    pub(crate) position: DcsPosition,
+    pub(crate) category: ObjectCategory,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct DcsWorldUnit {
+    pub(crate) object: DcsWorldObject,
+    pub(crate) unit_name: String,
+    pub(crate) group_name: String,
+}
+
+impl DcsWorldObject {
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// DCS type name, e.g. "Su-27" or "AIM_120C".
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn country(&self) -> i32 {
+        self.country
+    }
+
+    pub fn coalition(&self) -> &str {
+        &self.coalition
+    }
+
+    pub fn coalition_id(&self) -> i32 {
+        self.coalition_id
+    }
+
+    pub fn lat_lon_alt(&self) -> (f64, f64, f64) {
+        (
+            self.lat_lon_alt.lat,
+            self.lat_lon_alt.lon,
+            self.lat_lon_alt.alt,
+        )
+    }
+
+    /// Heading, pitch and bank, radians.
+    pub fn attitude(&self) -> (f64, f64, f64) {
+        (self.heading, self.pitch, self.bank)
+    }
+
+    /// DCS world position (x north, y up, z east), meters.
+    pub fn position(&self) -> (f64, f64, f64) {
+        (self.position.x, self.position.y, self.position.z)
+    }
+
+    pub fn heading(&self) -> f64 {
+        self.heading
+    }
+
+    pub fn altitude(&self) -> f64 {
+        self.lat_lon_alt.alt
+    }
+
+    /// Position on the map plane, DCS x (north) and z (east) in meters.
+    pub fn map_position(&self) -> (f64, f64) {
+        (self.position.x, self.position.z)
+    }
+
+    pub fn category(&self) -> ObjectCategory {
+        self.category
+    }
+}
+
+impl DcsWorldUnit {
+    pub fn object(&self) -> &DcsWorldObject {
+        &self.object
+    }
+
+    pub fn unit_name(&self) -> &str {
+        &self.unit_name
+    }
+
+    pub fn group_name(&self) -> &str {
+        &self.group_name
+    }
+}
@@ -0,0 +1,107 @@
+// Flight recordings (`.ttrd`): a zstd-compressed stream of bincode records, a
+// `RecordingHeader` and then one `RecordedFrame` per sample. Object names are
+// only stored the first time an object shows up.
+use crate::object::DcsWorldObject;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Read;
+
+pub const MAGIC: [u8; 4] = *b"TTRD";
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ObjectKind {
+    Unit,
+    Ballistic,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RecordingHeader {
+    pub magic: [u8; 4],
+    pub version: u32,
+    pub mission_name: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RecordedObject {
+    pub id: i32,
+    pub kind: ObjectKind,
+    pub coalition_id: i8,
+    pub x: f32,
+    pub z: f32,
+    pub altitude: f32,
+    pub heading: f32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RecordedFrame {
+    pub t_game: f64,
+    pub new_names: Vec<(i32, String)>,
+    pub objects: Vec<RecordedObject>,
+}
+
+impl RecordedObject {
+    pub fn new(obj: &DcsWorldObject, kind: ObjectKind) -> Self {
+        let (x, z) = obj.map_position();
+        Self {
+            id: obj.id(),
+            kind,
+            coalition_id: obj.coalition_id() as i8,
+            x: x as f32,
+            z: z as f32,
+            altitude: obj.altitude() as f32,
+            heading: obj.heading() as f32,
+        }
+    }
+}
+
+/// A recording read back into memory.
+#[derive(Debug, Default)]
+pub struct Recording {
+    pub mission_name: String,
+    pub names: HashMap<i32, String>,
+    pub frames: Vec<RecordedFrame>,
+}
+
+impl Recording {
+    /// Reads a recording from a (decompressed) stream. A truncated final frame,
+    /// e.g. from a crash, is ignored.
+    pub fn read<R: Read>(mut reader: R) -> bincode::Result<Self> {
+        let header: RecordingHeader = bincode::deserialize_from(&mut reader)?;
+        if header.magic != MAGIC || header.version != FORMAT_VERSION {
+            return Err(Box::new(bincode::ErrorKind::Custom(format!(
+                "not a tetrad recording (version {})",
+                header.version
+            ))));
+        }
+        let mut recording = Self {
+            mission_name: header.mission_name,
+            ..Default::default()
+        };
+        while let Ok(frame) = bincode::deserialize_from::<_, RecordedFrame>(&mut reader) {
+            recording.names.extend(frame.new_names.iter().cloned());
+            recording.frames.push(frame);
+        }
+        Ok(recording)
+    }
+
+    pub fn open(path: &std::path::Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = std::fs::File::open(path)?;
+        let decoder = zstd::stream::read::Decoder::new(file)?;
+        Ok(Self::read(std::io::BufReader::new(decoder))?)
+    }
+
+    pub fn start_time(&self) -> f64 {
+        self.frames.first().map_or(0.0, |f| f.t_game)
+    }
+
+    pub fn end_time(&self) -> f64 {
+        self.frames.last().map_or(0.0, |f| f.t_game)
+    }
+
+    /// The last frame at or before `t`.
+    pub fn frame_at(&self, t: f64) -> Option<&RecordedFrame> {
+        let idx = self.frames.partition_point(|f| f.t_game <= t);
+        self.frames.get(idx.saturating_sub(1))
+    }
+}
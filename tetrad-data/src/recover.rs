@@ -0,0 +1,165 @@
+// Salvages what can still be read from a zstd-compressed log that was cut off,
+// e.g. by DCS crashing before tetrad finished the file. The data is decoded up
+// to where the stream breaks, trimmed to the last complete row or record and
+// compressed again into a file every reader accepts.
+use std::io::{self, Read, Write};
+
+/// How a log is split into entries, so it can be cut without leaving half of
+/// one behind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Boundary {
+    /// CSV logs, one row per line.
+    Lines,
+    /// Bincode logs, length-prefixed records (see `bincode_log`).
+    Records,
+}
+
+impl Boundary {
+    /// `.bin.zstd` files hold records, everything else is taken as CSV.
+    pub fn for_file_name(name: &str) -> Self {
+        if name.contains(".bin.") {
+            Self::Records
+        } else {
+            Self::Lines
+        }
+    }
+
+    /// Length of the part of `data` made of complete entries.
+    fn complete_len(&self, data: &[u8]) -> usize {
+        match self {
+            Self::Lines => data.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1),
+            Self::Records => {
+                let mut len = 0;
+                while let Some(prefix) = data.get(len..len + 4) {
+                    let size = u32::from_le_bytes(prefix.try_into().unwrap()) as usize;
+                    if data.len() - len - 4 < size {
+                        break;
+                    }
+                    len += 4 + size;
+                }
+                len
+            }
+        }
+    }
+}
+
+/// What `recover` got out of a file.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Recovery {
+    /// Decompressed bytes written out.
+    pub bytes: u64,
+    /// Decompressed bytes of the incomplete entry at the end, left out.
+    pub dropped_bytes: u64,
+    /// Whether decoding stopped at an error rather than a clean end of the
+    /// stream.
+    pub truncated: bool,
+}
+
+/// Decompresses `input` as far as it goes and writes every complete entry to
+/// `output`, compressed again.
+pub fn recover<R: Read, W: Write>(input: R, output: W, boundary: Boundary) -> io::Result<Recovery> {
+    let mut decoder = zstd::stream::read::Decoder::new(input)?;
+    let mut encoder = zstd::stream::write::Encoder::new(output, 10)?;
+    let mut recovery = Recovery::default();
+    let mut pending = Vec::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = match decoder.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) => {
+                recovery.truncated = true;
+                break;
+            }
+        };
+        pending.extend_from_slice(&buf[..n]);
+        let len = boundary.complete_len(&pending);
+        encoder.write_all(&pending[..len])?;
+        pending.drain(..len);
+        recovery.bytes += len as u64;
+    }
+    recovery.dropped_bytes = pending.len() as u64;
+    encoder.finish()?;
+    Ok(recovery)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(payload: &[u8]) -> Vec<u8> {
+        let mut record = (payload.len() as u32).to_le_bytes().to_vec();
+        record.extend_from_slice(payload);
+        record
+    }
+
+    #[test]
+    fn boundary_from_file_name() {
+        assert_eq!(
+            Boundary::for_file_name("objects.bin.zstd"),
+            Boundary::Records
+        );
+        assert_eq!(Boundary::for_file_name("objects.csv.zstd"), Boundary::Lines);
+    }
+
+    #[test]
+    fn complete_lines() {
+        assert_eq!(Boundary::Lines.complete_len(b"a,b\nc,d\ne,"), 8);
+        assert_eq!(Boundary::Lines.complete_len(b"a,b\n"), 4);
+        assert_eq!(Boundary::Lines.complete_len(b"a,b"), 0);
+    }
+
+    #[test]
+    fn complete_records() {
+        let mut data = record(b"first");
+        data.extend(record(b"second"));
+        let complete = data.len();
+        assert_eq!(Boundary::Records.complete_len(&data), complete);
+        data.extend(&record(b"third")[..6]);
+        assert_eq!(Boundary::Records.complete_len(&data), complete);
+        data.truncate(complete + 2);
+        assert_eq!(Boundary::Records.complete_len(&data), complete);
+    }
+
+    fn recover_to_vec(compressed: &[u8], boundary: Boundary) -> (Vec<u8>, Recovery) {
+        let mut output = Vec::new();
+        let recovery = recover(compressed, &mut output, boundary).unwrap();
+        (zstd::decode_all(output.as_slice()).unwrap(), recovery)
+    }
+
+    #[test]
+    fn complete_file_comes_back_unchanged() {
+        let data: Vec<u8> = (0..1000)
+            .flat_map(|i| format!("{},{}\n", i, i * 2).into_bytes())
+            .collect();
+        let compressed = zstd::encode_all(data.as_slice(), 10).unwrap();
+        let (recovered, recovery) = recover_to_vec(&compressed, Boundary::Lines);
+        assert_eq!(recovered, data);
+        assert!(!recovery.truncated);
+        assert_eq!(recovery.dropped_bytes, 0);
+    }
+
+    #[test]
+    fn cut_off_file_is_trimmed_to_the_last_row() {
+        let data: Vec<u8> = (0..100_000)
+            .flat_map(|i| format!("{},{}\n", i, i * 7 % 13).into_bytes())
+            .collect();
+        // flushed in blocks like the writers do, so the start can be decoded
+        // without the rest
+        let mut encoder = zstd::stream::write::Encoder::new(Vec::new(), 10).unwrap();
+        for chunk in data.chunks(16 * 1024) {
+            encoder.write_all(chunk).unwrap();
+            encoder.flush().unwrap();
+        }
+        let compressed = encoder.finish().unwrap();
+        let cut = &compressed[..compressed.len() * 2 / 3];
+
+        let (recovered, recovery) = recover_to_vec(cut, Boundary::Lines);
+        assert!(recovery.truncated);
+        assert!(!recovered.is_empty());
+        assert!(recovered.ends_with(b"\n"));
+        assert!(data.starts_with(&recovered));
+        assert_eq!(recovery.bytes, recovered.len() as u64);
+    }
+}
@@ -0,0 +1,134 @@
+// Versions of the layouts of tetrad's outputs, so a script can tell when a log
+// isn't laid out the way it expects instead of silently reading the wrong
+// columns. Every output is stamped with its schema: the CSV logs start with a
+// `# tetrad schema <name>/<version>` comment row, the Parquet files carry it in
+// their key-value metadata under `tetrad.schema`, the SQLite databases in
+// `PRAGMA user_version`, and the session manifest lists all of them.
+//
+// Bump a version whenever columns are added, removed or reordered, and say
+// what changed next to it. Logs without a stamp predate this and are version
+// 0.
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Schema {
+    pub name: &'static str,
+    pub version: u32,
+}
+
+/// The CSV frame log. 2 added `epoch_ms` and `session_id` after
+/// `ballistics_east`, 3 `dcs_cpu` and `total_cpu` after those.
+pub const FRAMES: Schema = Schema {
+    name: "frames",
+    version: 3,
+};
+
+/// The CSV object log. Its columns can be picked with `object_log_columns`,
+/// logs with a header row say which. 2 added `epoch_ms` and `session_id` at
+/// the end.
+pub const OBJECTS: Schema = Schema {
+    name: "objects",
+    version: 2,
+};
+
+pub const HITCHES: Schema = Schema {
+    name: "hitches",
+    version: 1,
+};
+
+pub const ROSTER: Schema = Schema {
+    name: "roster",
+    version: 1,
+};
+
+/// 2 added `epoch_ms` and `session_id` at the end.
+pub const EVENTS: Schema = Schema {
+    name: "events",
+    version: 2,
+};
+
+pub const NETWORK: Schema = Schema {
+    name: "network",
+    version: 1,
+};
+
+pub const CHAT: Schema = Schema {
+    name: "chat",
+    version: 1,
+};
+
+/// 2 added `dcs_cpu` and `total_cpu` at the end.
+pub const PARQUET_FRAMES: Schema = Schema {
+    name: "parquet_frames",
+    version: 2,
+};
+
+pub const PARQUET_OBJECTS: Schema = Schema {
+    name: "parquet_objects",
+    version: 1,
+};
+
+/// The `sessions`, `frames` and `objects` tables of the SQLite databases.
+pub const SQLITE: Schema = Schema {
+    name: "sqlite",
+    version: 1,
+};
+
+/// The "bincode" export format, see `bincode_log`.
+pub const BINCODE: Schema = Schema {
+    name: "bincode",
+    version: crate::bincode_log::FORMAT_VERSION,
+};
+
+/// Flight recordings, see `recording`.
+pub const RECORDING: Schema = Schema {
+    name: "recording",
+    version: crate::recording::FORMAT_VERSION,
+};
+
+pub const ALL: [Schema; 12] = [
+    FRAMES,
+    OBJECTS,
+    HITCHES,
+    ROSTER,
+    EVENTS,
+    NETWORK,
+    CHAT,
+    PARQUET_FRAMES,
+    PARQUET_OBJECTS,
+    SQLITE,
+    BINCODE,
+    RECORDING,
+];
+
+/// Key of the schema in the Parquet files' metadata.
+pub const METADATA_KEY: &str = "tetrad.schema";
+
+const COMMENT_PREFIX: &str = "# tetrad schema ";
+
+impl Schema {
+    /// `<name>/<version>`, e.g. "frames/1".
+    pub fn id(&self) -> String {
+        format!("{}/{}", self.name, self.version)
+    }
+
+    /// The first row of a CSV log, without the line ending.
+    pub fn comment(&self) -> String {
+        format!("{}{}", COMMENT_PREFIX, self.id())
+    }
+}
+
+/// The schema name and version of a CSV log, from its first line, `None` if
+/// it isn't a schema comment.
+pub fn parse_comment(line: &str) -> Option<(&str, u32)> {
+    let (name, version) = line
+        .trim_end()
+        .strip_prefix(COMMENT_PREFIX)?
+        .rsplit_once('/')?;
+    Some((name, version.parse().ok()?))
+}
+
+/// The current version of the schema called `name`.
+pub fn find(name: &str) -> Option<Schema> {
+    ALL.into_iter().find(|schema| schema.name == name)
+}